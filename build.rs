@@ -0,0 +1,73 @@
+/// Writes the V8 startup snapshot of the Rode globals to OUT_DIR
+/// The globals are installed exactly as at runtime, from `globals` and the
+/// `natives` table, but the table is expanded over placeholder functions:
+/// the snapshot only records each native function's position in the table,
+/// and `startup` supplies the real ones in the same order.
+use rusty_v8 as v8;
+
+#[path = "src/globals.rs"]
+mod globals;
+#[path = "src/natives.rs"]
+mod natives;
+
+use natives::{natives_api, natives_table};
+
+/// A module per table entry with a stand-in for each of its functions,
+/// which never runs; each panics differently so none share an address
+macro_rules! placeholders {
+    ($($module:ident => $object:literal { $($name:literal => $callback:ident,)* })*) => {
+        $(
+            mod $module {
+                use rusty_v8 as v8;
+                $(
+                    pub fn $callback(
+                        _: &mut v8::HandleScope,
+                        _: v8::FunctionCallbackArguments,
+                        _: v8::ReturnValue,
+                    ) {
+                        unreachable!(concat!(stringify!($module), "::", stringify!($callback)));
+                    }
+                )*
+            }
+        )*
+    };
+}
+
+natives_table!(placeholders);
+natives_table!(natives_api);
+
+fn main() {
+    for path in [
+        "src/globals.rs",
+        "src/natives.rs",
+        "src/utils/assert.js",
+        "src/utils/mock.js",
+    ] {
+        println!("cargo:rerun-if-changed={}", path);
+    }
+
+    let platform = v8::new_default_platform(0, false).make_shared();
+    v8::V8::initialize_platform(platform);
+    v8::V8::initialize();
+
+    let references = Box::leak(Box::new(v8::ExternalReferences::new(&native_references())));
+    let mut creator = v8::SnapshotCreator::new(Some(references));
+    // SAFETY: the only isolate taken from this creator
+    let mut isolate = unsafe { creator.get_owned_isolate() };
+    {
+        let scope = &mut v8::HandleScope::new(&mut isolate);
+        let context = v8::Context::new(scope);
+        let scope = &mut v8::ContextScope::new(scope, context);
+        globals::install(scope, install_natives);
+        creator.set_default_context(context);
+    }
+    // The creator disposes of its isolate
+    std::mem::forget(isolate);
+    let blob = creator
+        .create_blob(v8::FunctionCodeHandling::Keep)
+        .expect("the startup snapshot is created");
+
+    let out_dir = std::env::var("OUT_DIR").expect("cargo sets OUT_DIR");
+    let path = std::path::Path::new(&out_dir).join("snapshot.bin");
+    std::fs::write(&path, &*blob).expect("the startup snapshot is written");
+}
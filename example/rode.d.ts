@@ -452,3 +452,21 @@ interface FetchResponse {
   /** Parse response body as JSON */
   json(): any
 }
+
+/**
+ * Console output with colored levels
+ */
+interface Console {
+  log(...data: any[]): void
+  error(...data: any[]): void
+  warn(...data: any[]): void
+  info(...data: any[]): void
+  table(data: any): void
+  dir(item: any): void
+  clear(): void
+  count(label?: string): void
+  time(label?: string): void
+  timeEnd(label?: string): void
+}
+
+declare var console: Console
@@ -0,0 +1,276 @@
+/// Statistics and reports for `rode bench`
+/// Every sample is the mean time per iteration of one timed batch. Results
+/// can be saved as JSON and used as the baseline of a later run, where a
+/// change only counts when the means are further apart than twice their
+/// combined standard error.
+use colored::*;
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::fs;
+
+/// Bumped when the results file changes shape
+const RESULTS_VERSION: u64 = 1;
+
+/// Times are in nanoseconds per iteration
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stats {
+    pub samples: usize,
+    pub iterations: u64,
+    pub mean: f64,
+    pub stddev: f64,
+    pub min: f64,
+    pub max: f64,
+    pub p75: f64,
+    pub p99: f64,
+}
+
+impl Stats {
+    pub fn from_samples(samples: &[f64], iterations: u64) -> Self {
+        let mut sorted = samples.to_vec();
+        sorted.sort_by(f64::total_cmp);
+        let n = sorted.len().max(1) as f64;
+        let mean = sorted.iter().sum::<f64>() / n;
+        let variance = match sorted.len() {
+            0 | 1 => 0.0,
+            len => sorted.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (len - 1) as f64,
+        };
+        Self {
+            samples: sorted.len(),
+            iterations,
+            mean,
+            stddev: variance.sqrt(),
+            min: sorted.first().copied().unwrap_or_default(),
+            max: sorted.last().copied().unwrap_or_default(),
+            p75: percentile(&sorted, 75.0),
+            p99: percentile(&sorted, 99.0),
+        }
+    }
+
+    pub fn ops_per_sec(&self) -> f64 {
+        if self.mean > 0.0 {
+            1e9 / self.mean
+        } else {
+            0.0
+        }
+    }
+
+    fn standard_error(&self) -> f64 {
+        self.stddev / (self.samples.max(1) as f64).sqrt()
+    }
+}
+
+/// Nearest-rank percentile of sorted samples
+fn percentile(sorted: &[f64], p: f64) -> f64 {
+    if sorted.is_empty() {
+        return 0.0;
+    }
+    let rank = (p / 100.0 * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+pub struct BenchResult {
+    pub file: String,
+    pub name: String,
+    /// The stats, or the error the benchmark threw
+    pub outcome: Result<Stats, String>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Change {
+    /// How many times faster or slower than the baseline
+    Faster(f64),
+    Slower(f64),
+    /// Within the noise
+    Same,
+}
+
+pub fn compare(current: &Stats, baseline: &Stats) -> Change {
+    let noise = 2.0 * current.standard_error().hypot(baseline.standard_error());
+    if (current.mean - baseline.mean).abs() <= noise || current.mean <= 0.0 {
+        Change::Same
+    } else if current.mean < baseline.mean {
+        Change::Faster(baseline.mean / current.mean)
+    } else {
+        Change::Slower(current.mean / baseline.mean)
+    }
+}
+
+/// A time per iteration in the largest unit that keeps it above 1
+pub fn format_duration(ns: f64) -> String {
+    if ns < 1e3 {
+        format!("{:.2} ns", ns)
+    } else if ns < 1e6 {
+        format!("{:.2} µs", ns / 1e3)
+    } else if ns < 1e9 {
+        format!("{:.2} ms", ns / 1e6)
+    } else {
+        format!("{:.2} s", ns / 1e9)
+    }
+}
+
+/// A count with thousands separators
+fn format_count(value: f64) -> String {
+    let digits = format!("{:.0}", value);
+    let mut out = String::new();
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i) % 3 == 0 {
+            out.push(',');
+        }
+        out.push(digit);
+    }
+    out
+}
+
+pub fn print_file(file: &str, name_width: usize) {
+    println!("{} {}", "▶".cyan(), file.cyan().bold());
+    println!(
+        "{}",
+        format!(
+            "  {:<name_width$}  {:>12}  {:>14}  {:>11}  {:>11}  {:>8}",
+            "benchmark", "time/iter", "ops/sec", "p75", "p99", "stddev"
+        )
+        .dimmed()
+    );
+}
+
+pub fn print_result(result: &BenchResult, name_width: usize, baseline: Option<&Stats>) {
+    let stats = match &result.outcome {
+        Ok(stats) => stats,
+        Err(error) => {
+            println!(
+                "  {:<name_width$}  {}",
+                result.name.red(),
+                "failed".red().bold()
+            );
+            for line in error.lines() {
+                println!("    {}", line);
+            }
+            return;
+        }
+    };
+
+    let relative = if stats.mean > 0.0 {
+        stats.stddev / stats.mean * 100.0
+    } else {
+        0.0
+    };
+    let change = match baseline.map(|baseline| compare(stats, baseline)) {
+        Some(Change::Faster(times)) => format!("{:.2}x faster", times).green().to_string(),
+        Some(Change::Slower(times)) => format!("{:.2}x slower", times).red().to_string(),
+        Some(Change::Same) => "~ same".dimmed().to_string(),
+        None => String::new(),
+    };
+    println!(
+        "  {:<name_width$}  {:>12}  {:>14}  {:>11}  {:>11}  {:>8}  {}",
+        result.name,
+        format_duration(stats.mean).bold(),
+        format_count(stats.ops_per_sec()),
+        format_duration(stats.p75),
+        format_duration(stats.p99),
+        format!("±{:.2}%", relative).dimmed(),
+        change
+    );
+}
+
+/// The results file written by `--save`
+pub fn to_json(results: &[BenchResult]) -> String {
+    let benchmarks: Vec<Value> = results
+        .iter()
+        .filter_map(|result| {
+            let stats = result.outcome.as_ref().ok()?;
+            Some(json!({
+                "file": result.file,
+                "name": result.name,
+                "samples": stats.samples,
+                "iterations": stats.iterations,
+                "mean_ns": stats.mean,
+                "stddev_ns": stats.stddev,
+                "min_ns": stats.min,
+                "max_ns": stats.max,
+                "p75_ns": stats.p75,
+                "p99_ns": stats.p99,
+                "ops_per_sec": stats.ops_per_sec(),
+            }))
+        })
+        .collect();
+    let results = json!({ "version": RESULTS_VERSION, "benchmarks": benchmarks });
+    serde_json::to_string_pretty(&results).unwrap_or_default() + "\n"
+}
+
+/// Baseline stats by file and benchmark name from a `--save`d results file
+pub fn load_baseline(path: &str) -> Result<HashMap<(String, String), Stats>, String> {
+    let contents =
+        fs::read_to_string(path).map_err(|e| format!("Cannot read baseline {}: {}", path, e))?;
+    let results: Value =
+        serde_json::from_str(&contents).map_err(|e| format!("Invalid baseline {}: {}", path, e))?;
+    if results["version"].as_u64() != Some(RESULTS_VERSION) {
+        return Err(format!(
+            "Baseline {} is not a rode bench results file (version {})",
+            path, RESULTS_VERSION
+        ));
+    }
+
+    let mut baseline = HashMap::new();
+    for benchmark in results["benchmarks"].as_array().into_iter().flatten() {
+        let number = |key: &str| benchmark[key].as_f64().unwrap_or_default();
+        let stats = Stats {
+            samples: number("samples") as usize,
+            iterations: number("iterations") as u64,
+            mean: number("mean_ns"),
+            stddev: number("stddev_ns"),
+            min: number("min_ns"),
+            max: number("max_ns"),
+            p75: number("p75_ns"),
+            p99: number("p99_ns"),
+        };
+        let file = benchmark["file"].as_str().unwrap_or_default().to_string();
+        let name = benchmark["name"].as_str().unwrap_or_default().to_string();
+        baseline.insert((file, name), stats);
+    }
+    Ok(baseline)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stats() {
+        let samples: Vec<f64> = (1..=100).map(f64::from).collect();
+        let stats = Stats::from_samples(&samples, 1000);
+        assert_eq!(stats.mean, 50.5);
+        assert_eq!((stats.min, stats.max), (1.0, 100.0));
+        assert_eq!((stats.p75, stats.p99), (75.0, 99.0));
+        assert!((stats.stddev - 29.011).abs() < 0.001);
+        assert_eq!(format_duration(stats.mean * 1e3), "50.50 µs");
+        assert_eq!(format_count(1234567.4), "1,234,567");
+
+        let shifted: Vec<f64> = samples.iter().map(|x| x * 2.0).collect();
+        let slower = Stats::from_samples(&shifted, 1000);
+        assert!(matches!(compare(&slower, &stats), Change::Slower(t) if (t - 2.0).abs() < 1e-9));
+        assert!(matches!(compare(&stats, &slower), Change::Faster(_)));
+        let nudged: Vec<f64> = samples.iter().map(|x| x + 0.5).collect();
+        assert_eq!(
+            compare(&Stats::from_samples(&nudged, 1000), &stats),
+            Change::Same
+        );
+    }
+
+    #[test]
+    fn test_results_round_trip() {
+        let stats = Stats::from_samples(&[10.0, 12.0, 14.0], 30);
+        let results = [BenchResult {
+            file: "sort.bench.ts".to_string(),
+            name: "sort".to_string(),
+            outcome: Ok(stats.clone()),
+        }];
+        let path = std::env::temp_dir().join(format!("rode-bench-{}.json", std::process::id()));
+        fs::write(&path, to_json(&results)).unwrap();
+        let baseline = load_baseline(&path.to_string_lossy()).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(
+            baseline[&("sort.bench.ts".to_string(), "sort".to_string())],
+            stats
+        );
+    }
+}
@@ -0,0 +1,312 @@
+/// `rode bench`: find benchmark files and measure what they register
+/// Each file runs in its own isolate, where `Rode.bench(name, fn)` only
+/// records the benchmark. Each one is then warmed up while the batch size
+/// doubles until a batch takes long enough to time reliably, and sampled
+/// batch by batch for the measuring time. Timing uses the monotonic
+/// nanosecond clock, and async benchmarks are awaited through microtasks.
+use crate::bench_report::{self, BenchResult, Stats};
+use crate::cli::BenchArgs;
+use crate::runtime::Runtime;
+use crate::test_runner;
+use regex::Regex;
+use rusty_v8 as v8;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+const DEFAULT_WARMUP_MS: u64 = 100;
+const DEFAULT_TIME_MS: u64 = 500;
+
+/// Batches shorter than this are dominated by timer and call overhead
+const MIN_BATCH_TIME: Duration = Duration::from_micros(100);
+const MIN_SAMPLES: usize = 10;
+const MAX_SAMPLES: usize = 100_000;
+
+const BENCH_EXTENSIONS: [&str; 4] = ["js", "ts", "jsx", "tsx"];
+
+/// Calls the benchmark `n` times, so a batch costs one call from Rust
+const SYNC_BATCH: &str = "(function (fn, n) { for (let i = 0; i < n; i++) fn() })";
+const ASYNC_BATCH: &str = "(async function (fn, n) { for (let i = 0; i < n; i++) await fn() })";
+
+struct Bench {
+    name: String,
+    function: v8::Global<v8::Function>,
+    warmup: Option<u64>,
+    time: Option<u64>,
+}
+
+thread_local! {
+    /// Benchmarks of the file being loaded; `None` once it has loaded
+    static REGISTERING: RefCell<Option<Vec<Bench>>> = const { RefCell::new(None) };
+}
+
+/// Run the benchmarks in `args.paths`, returning the exit code
+pub fn run(args: &BenchArgs) -> Result<i32, String> {
+    let files = test_runner::discover(&args.paths, is_bench_file)?;
+    if files.is_empty() {
+        return Err("No benchmark files found (*.bench.{js,ts,jsx,tsx} or *_bench.*)".to_string());
+    }
+    let filter = match &args.filter {
+        Some(pattern) => Some(test_runner::name_filter(pattern)?),
+        None => None,
+    };
+    let baseline = match &args.compare {
+        Some(path) => bench_report::load_baseline(path)?,
+        None => HashMap::new(),
+    };
+
+    let mut results = Vec::new();
+    for path in &files {
+        run_file(path, args, filter.as_ref(), &baseline, &mut results);
+    }
+
+    if let Some(path) = &args.save {
+        fs::write(path, bench_report::to_json(&results))
+            .map_err(|e| format!("Failed to write results to {}: {}", path, e))?;
+    }
+    let failed = results.iter().any(|result| result.outcome.is_err());
+    Ok(if failed { 1 } else { 0 })
+}
+
+fn is_bench_file(name: &str) -> bool {
+    let Some((stem, extension)) = name.rsplit_once('.') else {
+        return false;
+    };
+    BENCH_EXTENSIONS.contains(&extension) && (stem.ends_with(".bench") || stem.ends_with("_bench"))
+}
+
+fn run_file(
+    path: &Path,
+    args: &BenchArgs,
+    filter: Option<&Regex>,
+    baseline: &HashMap<(String, String), Stats>,
+    results: &mut Vec<BenchResult>,
+) {
+    let cwd = std::env::current_dir().unwrap_or_default();
+    let file = path
+        .strip_prefix(".")
+        .ok()
+        .or_else(|| path.strip_prefix(&cwd).ok())
+        .unwrap_or(path)
+        .display()
+        .to_string();
+
+    let filename = path.to_string_lossy().to_string();
+    let loaded = fs::read_to_string(path)
+        .map_err(|e| format!("Cannot read {}: {}", filename, e))
+        .map(|source| {
+            let options = crate::tsconfig::transpile_options_for(path);
+            let transpiled = crate::cache::transpile(&filename, &source, &options);
+            (source, transpiled)
+        });
+
+    let mut runtime = Runtime::new();
+    let context = runtime.create_context();
+    runtime.with_context(&context, setup_bench_api);
+    REGISTERING.with(|benches| *benches.borrow_mut() = Some(Vec::new()));
+    let loaded = loaded.and_then(|(source, transpiled)| {
+        runtime.execute_transpiled_in(&context, transpiled, &source, &filename)
+    });
+    let benches = REGISTERING
+        .with(|benches| benches.borrow_mut().take())
+        .unwrap_or_default();
+
+    let benches: Vec<Bench> = benches
+        .into_iter()
+        .filter(|bench| filter.is_none_or(|filter| filter.is_match(&bench.name)))
+        .collect();
+    let name_width = benches
+        .iter()
+        .map(|bench| bench.name.chars().count())
+        .chain([9])
+        .max()
+        .unwrap_or(9);
+    if loaded.is_err() || !benches.is_empty() {
+        bench_report::print_file(&file, name_width);
+    }
+    if let Err(error) = loaded {
+        let result = BenchResult {
+            file,
+            name: "(loading the file)".to_string(),
+            outcome: Err(error.trim().to_string()),
+        };
+        bench_report::print_result(&result, name_width, None);
+        results.push(result);
+        return;
+    }
+
+    for bench in &benches {
+        let warmup = Duration::from_millis(bench.warmup.unwrap_or(DEFAULT_WARMUP_MS));
+        let time = Duration::from_millis(bench.time.or(args.time).unwrap_or(DEFAULT_TIME_MS));
+        let outcome = runtime.with_context(&context, |scope| measure(scope, bench, warmup, time));
+        let result = BenchResult {
+            file: file.clone(),
+            name: bench.name.clone(),
+            outcome,
+        };
+        let key = (result.file.clone(), result.name.clone());
+        bench_report::print_result(&result, name_width, baseline.get(&key));
+        results.push(result);
+    }
+    println!();
+}
+
+/// Warm `bench` up, then sample it for `time`
+fn measure(
+    scope: &mut v8::HandleScope,
+    bench: &Bench,
+    warmup: Duration,
+    time: Duration,
+) -> Result<Stats, String> {
+    let try_catch = &mut v8::TryCatch::new(scope);
+    let function = v8::Local::new(try_catch, &bench.function);
+
+    // A first call shows whether it is async, and fails early
+    let recv = v8::undefined(try_catch).into();
+    let first = function.call(try_catch, recv, &[]);
+    let is_async = match first {
+        Some(value) => match v8::Local::<v8::Promise>::try_from(value) {
+            Ok(promise) => {
+                settle(try_catch, promise)?;
+                true
+            }
+            Err(_) => false,
+        },
+        None => return Err(exception(try_catch)),
+    };
+
+    let source = if is_async { ASYNC_BATCH } else { SYNC_BATCH };
+    let source = v8::String::new(try_catch, source).unwrap();
+    let batch_function = v8::Script::compile(try_catch, source, None)
+        .and_then(|script| script.run(try_catch))
+        .and_then(|batch| v8::Local::<v8::Function>::try_from(batch).ok())
+        .ok_or("Failed to compile the benchmark loop")?;
+
+    let run_batch = |try_catch: &mut v8::TryCatch<v8::HandleScope>, n: u64| {
+        let count = v8::Number::new(try_catch, n as f64);
+        let recv = v8::undefined(try_catch).into();
+        let started = Instant::now();
+        let result = batch_function.call(try_catch, recv, &[function.into(), count.into()]);
+        if is_async && let Some(promise) = result {
+            let promise = v8::Local::<v8::Promise>::try_from(promise).unwrap();
+            settle(try_catch, promise)?;
+        } else if result.is_none() {
+            return Err(exception(try_catch));
+        }
+        Ok(started.elapsed())
+    };
+
+    // Warm up, finding a batch size that takes long enough to time
+    let mut batch = 1u64;
+    let warmup_end = Instant::now() + warmup;
+    loop {
+        let elapsed = run_batch(try_catch, batch)?;
+        if elapsed < MIN_BATCH_TIME {
+            batch *= 2;
+        } else if Instant::now() >= warmup_end {
+            break;
+        }
+    }
+
+    let mut samples = Vec::new();
+    let mut iterations = 0;
+    let end = Instant::now() + time;
+    while samples.len() < MIN_SAMPLES || (Instant::now() < end && samples.len() < MAX_SAMPLES) {
+        let elapsed = run_batch(try_catch, batch)?;
+        samples.push(elapsed.as_nanos() as f64 / batch as f64);
+        iterations += batch;
+    }
+    Ok(Stats::from_samples(&samples, iterations))
+}
+
+/// Run microtasks until `promise` settles. Nothing else could settle it
+/// without an event loop, so a promise still pending is an error.
+fn settle(
+    try_catch: &mut v8::TryCatch<v8::HandleScope>,
+    promise: v8::Local<v8::Promise>,
+) -> Result<(), String> {
+    try_catch.perform_microtask_checkpoint();
+    match promise.state() {
+        v8::PromiseState::Fulfilled => Ok(()),
+        v8::PromiseState::Rejected => {
+            let reason = promise.result(try_catch);
+            Err(crate::runtime::format_exception(try_catch, reason))
+        }
+        v8::PromiseState::Pending => {
+            Err("The benchmark returned a promise that never settles".to_string())
+        }
+    }
+}
+
+fn exception(try_catch: &mut v8::TryCatch<v8::HandleScope>) -> String {
+    match try_catch.exception() {
+        Some(exception) => crate::runtime::format_exception(try_catch, exception),
+        None => "Benchmark failed".to_string(),
+    }
+}
+
+/// `Rode.bench` for benchmark files
+fn setup_bench_api(scope: &mut v8::HandleScope) {
+    let global = scope.get_current_context().global(scope);
+    let rode_key = v8::String::new(scope, "Rode").unwrap();
+    if let Some(rode) = global
+        .get(scope, rode_key.into())
+        .and_then(|rode| rode.to_object(scope))
+    {
+        let key = v8::String::new(scope, "bench").unwrap();
+        let bench = v8::Function::new(scope, register_bench).unwrap();
+        rode.set(scope, key.into(), bench.into());
+    }
+}
+
+/// `Rode.bench(name, fn, { warmup, time }?)`, with the phases in milliseconds
+fn register_bench(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    _rv: v8::ReturnValue,
+) {
+    let name = args.get(0).to_rust_string_lossy(scope);
+    let Ok(function) = v8::Local::<v8::Function>::try_from(args.get(1)) else {
+        let message = v8::String::new(scope, "Rode.bench() needs a name and a function").unwrap();
+        let error = v8::Exception::type_error(scope, message);
+        scope.throw_exception(error);
+        return;
+    };
+
+    let mut option = |key: &str| -> Option<u64> {
+        let options = v8::Local::<v8::Object>::try_from(args.get(2)).ok()?;
+        let key = v8::String::new(scope, key).unwrap();
+        let value = options.get(scope, key.into())?;
+        if !value.is_number() {
+            return None;
+        }
+        value.integer_value(scope).map(|ms| ms.max(0) as u64)
+    };
+    let warmup = option("warmup");
+    let time = option("time");
+    let bench = Bench {
+        name,
+        function: v8::Global::new(scope, function),
+        warmup,
+        time,
+    };
+
+    let registered = REGISTERING.with(|benches| match benches.borrow_mut().as_mut() {
+        Some(benches) => {
+            benches.push(bench);
+            true
+        }
+        None => false,
+    });
+    if !registered {
+        let message = v8::String::new(
+            scope,
+            "Rode.bench() can only be called while the file loads",
+        )
+        .unwrap();
+        let error = v8::Exception::type_error(scope, message);
+        scope.throw_exception(error);
+    }
+}
@@ -0,0 +1,151 @@
+/// Persistent transpile cache
+/// Transformed code and its source map are stored in the rode cache dir under
+/// a hash of the source, the transform options and the rode build, so
+/// unchanged files are never transpiled twice.
+use crate::source_map::SourceMap;
+use crate::typescript::{self, TranspileOptions, Transpiled};
+use serde_json::json;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::UNIX_EPOCH;
+
+static BUILD_ID: OnceLock<String> = OnceLock::new();
+
+const FNV_OFFSET: u128 = 0x6c62272e07bb014262b821756295c58d;
+const FNV_PRIME: u128 = 0x0000000001000000000000000000013b;
+
+/// Root of rode's cache, `RODE_CACHE_DIR` or the platform cache directory
+pub fn cache_dir() -> PathBuf {
+    if let Some(dir) = env::var_os("RODE_CACHE_DIR") {
+        return PathBuf::from(dir);
+    }
+
+    let platform_dir = if cfg!(windows) {
+        env::var_os("LOCALAPPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        env::var_os("HOME").map(|home| Path::new(&home).join("Library/Caches"))
+    } else {
+        env::var_os("XDG_CACHE_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".cache")))
+    };
+
+    platform_dir.unwrap_or_else(env::temp_dir).join("rode")
+}
+
+fn transpile_dir() -> PathBuf {
+    cache_dir().join("transpile")
+}
+
+/// `typescript::transpile`, reusing an earlier result for identical input
+pub fn transpile(filename: &str, source: &str, options: &TranspileOptions) -> Transpiled {
+    let entry_path = transpile_dir().join(cache_key(filename, source, options));
+
+    if let Some(transpiled) = read_entry(&entry_path) {
+        return transpiled;
+    }
+
+    let transpiled = typescript::transpile(filename, source, options);
+    // The cache is best effort; a read-only cache dir just means no reuse
+    let _ = write_entry(&entry_path, &transpiled);
+    transpiled
+}
+
+/// Delete everything in the cache dir, returning the path that was removed
+pub fn clean() -> Result<PathBuf, String> {
+    let dir = cache_dir();
+    if dir.exists() {
+        fs::remove_dir_all(&dir)
+            .map_err(|e| format!("Failed to remove {}: {}", dir.display(), e))?;
+    }
+    Ok(dir)
+}
+
+fn cache_key(filename: &str, source: &str, options: &TranspileOptions) -> String {
+    // The transform depends on the extension, not on where the file lives
+    let extension = Path::new(filename)
+        .extension()
+        .map(|e| e.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    let hash = hash(&[
+        env!("CARGO_PKG_VERSION"),
+        build_id(),
+        &extension,
+        &format!("{:?}", options),
+        source,
+    ]);
+    format!("{:032x}.json", hash)
+}
+
+/// Size and modification time of the running executable, so a rebuilt rode
+/// with a changed transform never reads entries an older build wrote
+pub fn build_id() -> &'static str {
+    BUILD_ID.get_or_init(|| {
+        let Some(metadata) = env::current_exe().and_then(fs::metadata).ok() else {
+            return String::new();
+        };
+        let modified = metadata
+            .modified()
+            .ok()
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map(|time| time.as_nanos())
+            .unwrap_or_default();
+        format!("{}:{}", metadata.len(), modified)
+    })
+}
+
+/// FNV-1a of `parts`, each followed by a zero byte so they can't run together
+pub fn hash(parts: &[&str]) -> u128 {
+    let mut hash = FNV_OFFSET;
+    for part in parts {
+        for byte in part.bytes().chain(std::iter::once(0)) {
+            hash ^= byte as u128;
+            hash = hash.wrapping_mul(FNV_PRIME);
+        }
+    }
+    hash
+}
+
+fn read_entry(path: &Path) -> Option<Transpiled> {
+    let content = fs::read_to_string(path).ok()?;
+    let entry: serde_json::Value = serde_json::from_str(&content).ok()?;
+
+    Some(Transpiled {
+        code: entry["code"].as_str()?.to_string(),
+        source_map: SourceMap::from_mappings(entry["mappings"].as_str()?)?,
+    })
+}
+
+fn write_entry(path: &Path, transpiled: &Transpiled) -> std::io::Result<()> {
+    let dir = path.parent().unwrap_or(Path::new("."));
+    fs::create_dir_all(dir)?;
+
+    let entry = json!({
+        "code": transpiled.code,
+        "mappings": transpiled.source_map.encode_mappings(),
+    });
+
+    // Write then rename so concurrent runs never see half an entry
+    let temp_path = path.with_extension(format!("{}.tmp", std::process::id()));
+    fs::write(&temp_path, entry.to_string())?;
+    fs::rename(&temp_path, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cache_key() {
+        let options = TranspileOptions::default();
+        let key = cache_key("a.ts", "let x: number = 1", &options);
+
+        assert_eq!(key, cache_key("src/b.ts", "let x: number = 1", &options));
+        assert_ne!(key, cache_key("a.ts", "let x: number = 2", &options));
+        assert_ne!(key, cache_key("a.js", "let x: number = 1", &options));
+        assert_eq!(key.len(), "0123456789abcdef0123456789abcdef.json".len());
+    }
+}
@@ -0,0 +1,203 @@
+/// Type checking for `rode check` and `--check`
+/// Runs the TypeScript compiler from the project's node_modules (or PATH) in
+/// `--noEmit` mode over the entry file and everything it imports, with the
+/// Rode API definitions added as a built-in lib.
+use regex::Regex;
+use serde_json::json;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+const RODE_LIB: &str = include_str!("../example/rode.d.ts");
+
+pub struct Diagnostic {
+    pub file: Option<String>,
+    pub line: usize,
+    pub column: usize,
+    pub code: String,
+    pub message: String,
+}
+
+impl Diagnostic {
+    /// Location followed by the surrounding lines, in the runtime error style
+    pub fn code_frame(&self) -> String {
+        let Some(file) = &self.file else {
+            return String::new();
+        };
+
+        let mut result = format!("    at {}:{}:{}\n\n", file, self.line, self.column);
+        let Ok(source) = fs::read_to_string(file) else {
+            return result;
+        };
+        let lines: Vec<&str> = source.lines().collect();
+
+        let start_line = self.line.saturating_sub(2).max(1);
+        let end_line = (self.line + 2).min(lines.len());
+        for i in start_line..=end_line {
+            let line_indicator = if i == self.line { ">" } else { " " };
+            result.push_str(&format!(
+                "  {} {:3} | {}\n",
+                line_indicator,
+                i,
+                lines[i - 1]
+            ));
+            if i == self.line {
+                result.push_str(&format!(
+                    "        | {}^\n",
+                    " ".repeat(self.column.saturating_sub(1))
+                ));
+            }
+        }
+
+        result
+    }
+}
+
+/// Type-check `filename` and the modules it imports
+pub fn check(filename: &str) -> Result<Vec<Diagnostic>, String> {
+    let entry = fs::canonicalize(filename)
+        .map_err(|e| format!("Cannot read file '{}': {}", filename, e))?;
+    let entry_dir = entry.parent().unwrap_or(Path::new(".")).to_path_buf();
+    let tsc = find_tool(&entry_dir, "tsc").ok_or_else(|| {
+        "TypeScript compiler not found. Install it with `npm install --save-dev typescript`"
+            .to_string()
+    })?;
+
+    let work_dir = std::env::temp_dir().join(format!("rode-check-{}", std::process::id()));
+    fs::create_dir_all(&work_dir)
+        .map_err(|e| format!("Failed to create {}: {}", work_dir.display(), e))?;
+    let result = run_tsc(&tsc, &entry, &work_dir);
+    let _ = fs::remove_dir_all(&work_dir);
+    result
+}
+
+fn run_tsc(tsc: &Path, entry: &Path, work_dir: &Path) -> Result<Vec<Diagnostic>, String> {
+    let lib_path = work_dir.join("rode.d.ts");
+    fs::write(&lib_path, RODE_LIB).map_err(|e| format!("Failed to write Rode lib: {}", e))?;
+
+    let entry_name = entry.to_string_lossy();
+    let is_js = entry_name.ends_with(".js") || entry_name.ends_with(".jsx");
+    let mut config = json!({
+        "compilerOptions": {
+            "noEmit": true,
+            "allowJs": true,
+            "checkJs": is_js,
+        },
+        "files": [entry_name, lib_path.to_string_lossy()],
+    });
+
+    // Layer on top of the project's own settings when it has any
+    match crate::tsconfig::find_path(entry.parent().unwrap_or(Path::new("."))) {
+        Some(project) => config["extends"] = json!(project.to_string_lossy()),
+        None => {
+            let defaults = json!({
+                "target": "ES2022",
+                "lib": ["ES2022"],
+                "module": "commonjs",
+                "moduleResolution": "node",
+                "jsx": "react",
+                "strict": true,
+                "skipLibCheck": true,
+                "types": [],
+            });
+            for (key, value) in defaults.as_object().unwrap() {
+                config["compilerOptions"][key] = value.clone();
+            }
+        }
+    }
+
+    let config_path = work_dir.join("tsconfig.json");
+    fs::write(&config_path, config.to_string())
+        .map_err(|e| format!("Failed to write {}: {}", config_path.display(), e))?;
+
+    let output = Command::new(tsc)
+        .arg("--project")
+        .arg(&config_path)
+        .arg("--pretty")
+        .arg("false")
+        .output()
+        .map_err(|e| format!("Failed to run {}: {}", tsc.display(), e))?;
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let diagnostics = parse_diagnostics(&stdout);
+    if diagnostics.is_empty() && !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!(
+            "Type checker failed: {}{}",
+            stdout.trim(),
+            stderr.trim()
+        ));
+    }
+
+    Ok(diagnostics)
+}
+
+/// Find a Node tool in the nearest `node_modules/.bin`, falling back to PATH
+pub fn find_tool(start: &Path, name: &str) -> Option<PathBuf> {
+    let local = start
+        .ancestors()
+        .map(|dir| dir.join("node_modules").join(".bin").join(name))
+        .find(|candidate| candidate.is_file());
+
+    local.or_else(|| {
+        let path = std::env::var_os("PATH")?;
+        std::env::split_paths(&path)
+            .map(|dir| dir.join(name))
+            .find(|candidate| candidate.is_file())
+    })
+}
+
+/// Parse `tsc --pretty false` output; indented lines continue a message
+fn parse_diagnostics(output: &str) -> Vec<Diagnostic> {
+    let located = Regex::new(r"^(.+)\((\d+),(\d+)\): error (TS\d+): (.*)$").unwrap();
+    let global = Regex::new(r"^error (TS\d+): (.*)$").unwrap();
+    let mut diagnostics: Vec<Diagnostic> = Vec::new();
+
+    for line in output.lines() {
+        if let Some(caps) = located.captures(line) {
+            diagnostics.push(Diagnostic {
+                file: Some(caps[1].to_string()),
+                line: caps[2].parse().unwrap_or(1),
+                column: caps[3].parse().unwrap_or(1),
+                code: caps[4].to_string(),
+                message: caps[5].to_string(),
+            });
+        } else if let Some(caps) = global.captures(line) {
+            diagnostics.push(Diagnostic {
+                file: None,
+                line: 0,
+                column: 0,
+                code: caps[1].to_string(),
+                message: caps[2].to_string(),
+            });
+        } else if line.starts_with(' ')
+            && let Some(last) = diagnostics.last_mut()
+        {
+            last.message.push('\n');
+            last.message.push_str(line);
+        }
+    }
+
+    diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_diagnostics() {
+        let output = "src/app.ts(3,7): error TS2322: Type 'string' is not assignable to type 'number'.\n\
+                      src/app.ts(9,1): error TS2345: Argument of type 'A' is not assignable.\n  \
+                      Property 'x' is missing.\n\
+                      error TS5058: The specified path does not exist.\n";
+        let diagnostics = parse_diagnostics(output);
+
+        assert_eq!(diagnostics.len(), 3);
+        assert_eq!(diagnostics[0].file.as_deref(), Some("src/app.ts"));
+        assert_eq!((diagnostics[0].line, diagnostics[0].column), (3, 7));
+        assert_eq!(diagnostics[0].code, "TS2322");
+        assert!(diagnostics[1].message.ends_with("Property 'x' is missing."));
+        assert_eq!(diagnostics[2].file, None);
+    }
+}
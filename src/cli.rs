@@ -0,0 +1,1100 @@
+/// Command line parsing
+/// `rode [global flags] <command> [flags] [script] [script args...]`, where
+/// `rode script.js` is short for `rode run script.js` and a bare `rode`
+/// starts the REPL. Rode's own flags go before the script; everything after
+/// the script (or after `--`) is left for the script in `Rode.args`.
+use colored::*;
+
+pub struct Cli {
+    pub command: Command,
+    pub no_color: bool,
+    /// Things that parsed but probably aren't what the user meant
+    pub warnings: Vec<String>,
+}
+
+pub enum Command {
+    Run(RunArgs),
+    /// `rode serve`: run a script with `Rode.http.serve` listening on `host:port`
+    Serve {
+        run: RunArgs,
+        host: Option<String>,
+        port: Option<u16>,
+    },
+    Repl,
+    Test(TestArgs),
+    Bench(BenchArgs),
+    Check {
+        files: Vec<String>,
+    },
+    Fmt {
+        paths: Vec<String>,
+        check: bool,
+    },
+    Compile {
+        script: String,
+        output: Option<String>,
+    },
+    Cache {
+        action: String,
+    },
+    /// `rode task <name>`: run a task from rode.json
+    Task {
+        name: Option<String>,
+    },
+    /// Help for one command, or the overview
+    Help(Option<String>),
+    Version,
+}
+
+#[derive(Default)]
+pub struct RunArgs {
+    pub script: Script,
+    pub watch: bool,
+    /// Swap changed modules in the running script (`--hot`)
+    pub hot: bool,
+    /// Extra files or directories that restart watch mode (`--watch-path`)
+    pub watch_paths: Vec<String>,
+    /// Globs for changes watch mode ignores (`--watch-ignore`)
+    pub watch_ignore: Vec<String>,
+    /// Milliseconds to wait for changes to settle (`--debounce`)
+    pub debounce: Option<u64>,
+    /// Keep the output of earlier runs on screen (`--no-clear-screen`)
+    pub no_clear_screen: bool,
+    /// Type-check before running (`--check`)
+    pub check: bool,
+    /// Print the completion value (`-p`)
+    pub print: bool,
+    /// Extension choosing the transform for `-e`, `-p` and stdin
+    pub ext: Option<String>,
+    /// Listen for a debugger (`--inspect`, `--inspect-brk`, `--inspect-wait`)
+    pub inspect: Option<Inspect>,
+    /// Write a CPU profile on exit (`--cpu-prof`)
+    pub cpu_prof: Option<CpuProf>,
+    /// Signal that writes a heap snapshot (`--heap-snapshot-signal`)
+    pub heap_snapshot_signal: Option<String>,
+    /// Megabytes the script's heap may grow to (`--max-heap-size`)
+    pub max_heap_size: Option<u64>,
+    /// Milliseconds the script may run for (`--timeout`)
+    pub timeout: Option<u64>,
+    /// Everything after the script, exposed as `Rode.args`
+    pub script_args: Vec<String>,
+}
+
+impl RunArgs {
+    /// The diagnostics and limit flags again, for watch mode's children
+    pub fn child_flags(&self) -> Vec<String> {
+        let mut flags = Vec::new();
+        if let Some(inspect) = &self.inspect {
+            flags.push(inspect.flag());
+        }
+        if let Some(cpu_prof) = &self.cpu_prof {
+            flags.push("--cpu-prof".to_string());
+            if let Some(dir) = &cpu_prof.dir {
+                flags.push(format!("--cpu-prof-dir={}", dir));
+            }
+            if let Some(name) = &cpu_prof.name {
+                flags.push(format!("--cpu-prof-name={}", name));
+            }
+        }
+        if let Some(signal) = &self.heap_snapshot_signal {
+            flags.push(format!("--heap-snapshot-signal={}", signal));
+        }
+        if let Some(size) = self.max_heap_size {
+            flags.push(format!("--max-heap-size={}", size));
+        }
+        if let Some(timeout) = self.timeout {
+            flags.push(format!("--timeout={}", timeout));
+        }
+        flags
+    }
+}
+
+/// Where `--cpu-prof` writes the profile; a new file in the current
+/// directory by default
+#[derive(Default, Clone, PartialEq, Debug)]
+pub struct CpuProf {
+    pub dir: Option<String>,
+    pub name: Option<String>,
+}
+
+/// Where the debugger server listens, and whether the script waits for it
+#[derive(Clone, PartialEq, Debug)]
+pub struct Inspect {
+    pub mode: InspectMode,
+    /// `host:port`
+    pub address: String,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum InspectMode {
+    /// Start the script at once
+    Run,
+    /// Start once a debugger has attached
+    Wait,
+    /// Start once a debugger has attached, paused on the first statement
+    Break,
+}
+
+impl Inspect {
+    /// The flag that asks for this again, for watch mode's children
+    pub fn flag(&self) -> String {
+        let flag = match self.mode {
+            InspectMode::Run => "--inspect",
+            InspectMode::Wait => "--inspect-wait",
+            InspectMode::Break => "--inspect-brk",
+        };
+        format!("{}={}", flag, self.address)
+    }
+}
+
+#[derive(Default)]
+pub struct TestArgs {
+    /// Test files or directories to search (the current directory)
+    pub paths: Vec<String>,
+    /// Only run tests whose name matches (`--filter`)
+    pub filter: Option<String>,
+    pub reporter: TestReporter,
+    /// File to write the report to instead of stdout (`--reporter-output`)
+    pub reporter_output: Option<String>,
+    /// Default per-test timeout in milliseconds (`--timeout`)
+    pub timeout: Option<u64>,
+    /// Overwrite mismatched snapshots (`--update-snapshots`)
+    pub update_snapshots: bool,
+    /// Directory for coverage reports (`--coverage`, `--coverage-dir`)
+    pub coverage: Option<String>,
+}
+
+#[derive(Default)]
+pub struct BenchArgs {
+    /// Benchmark files or directories to search (the current directory)
+    pub paths: Vec<String>,
+    /// Only run benchmarks whose name matches (`--filter`)
+    pub filter: Option<String>,
+    /// Default measuring time per benchmark in milliseconds (`--time`)
+    pub time: Option<u64>,
+    /// File to write the results to as JSON (`--save`)
+    pub save: Option<String>,
+    /// Results file of an earlier run to compare with (`--compare`)
+    pub compare: Option<String>,
+}
+
+#[derive(Default, Clone, Copy, PartialEq, Debug)]
+pub enum TestReporter {
+    #[default]
+    Pretty,
+    Tap,
+    Junit,
+}
+
+#[derive(Default, PartialEq, Debug)]
+pub enum Script {
+    #[default]
+    None,
+    File(String),
+    /// Code given with `-e`/`-p`
+    Eval(String),
+    /// `-`: read the script from stdin
+    Stdin,
+}
+
+struct CommandHelp {
+    name: &'static str,
+    about: &'static str,
+    usage: &'static str,
+    flags: &'static [(&'static str, &'static str)],
+}
+
+const RUN_FLAGS: &[(&str, &str)] = &[
+    (
+        "-w, --watch",
+        "Restart when the script or anything it loads changes",
+    ),
+    (
+        "--watch-path <path>",
+        "Also restart when this file or directory changes",
+    ),
+    ("--watch-ignore <glob>", "Ignore changes to matching files"),
+    (
+        "--hot",
+        "Watch, swapping changed modules in place where they allow it",
+    ),
+    (
+        "--debounce <ms>",
+        "Wait for changes to settle before restarting (100)",
+    ),
+    (
+        "--no-clear-screen",
+        "Don't clear the screen when restarting",
+    ),
+    ("--check", "Type-check before running"),
+    ("-e, --eval <code>", "Evaluate code instead of a file"),
+    ("-p, --print <code>", "Evaluate code and print the result"),
+    (
+        "--ext <ext>",
+        "Transform -e, -p and stdin as this file type (ts, tsx, ...)",
+    ),
+    INSPECT_FLAGS[0],
+    INSPECT_FLAGS[1],
+    INSPECT_FLAGS[2],
+    PROFILE_FLAGS[0],
+    PROFILE_FLAGS[1],
+    PROFILE_FLAGS[2],
+    PROFILE_FLAGS[3],
+    LIMIT_FLAGS[0],
+    LIMIT_FLAGS[1],
+];
+
+const LIMIT_FLAGS: [(&str, &str); 2] = [
+    (
+        "--max-heap-size <MB>",
+        "Stop the script when its heap outgrows this",
+    ),
+    ("--timeout <ms>", "Stop the script after this long"),
+];
+
+const PROFILE_FLAGS: [(&str, &str); 4] = [
+    ("--cpu-prof", "Write a .cpuprofile of the run when it exits"),
+    (
+        "--cpu-prof-dir <dir>",
+        "Directory for the CPU profile (the current one)",
+    ),
+    (
+        "--cpu-prof-name <name>",
+        "File name for the CPU profile (CPU.<date>.<time>.<pid>...)",
+    ),
+    (
+        "--heap-snapshot-signal <signal>",
+        "Write a .heapsnapshot on SIGUSR1 or SIGUSR2",
+    ),
+];
+
+const INSPECT_FLAGS: [(&str, &str); 3] = [
+    (
+        "--inspect[=<host:port>]",
+        "Listen for a DevTools debugger (127.0.0.1:9229)",
+    ),
+    (
+        "--inspect-brk[=<host:port>]",
+        "Like --inspect, but pause on the first statement once attached",
+    ),
+    (
+        "--inspect-wait[=<host:port>]",
+        "Like --inspect, but wait for a debugger before starting",
+    ),
+];
+
+/// Where `--inspect` listens without an address, as in Node
+const DEFAULT_INSPECT_HOST: &str = "127.0.0.1";
+const DEFAULT_INSPECT_PORT: u16 = 9229;
+
+const COMMANDS: &[CommandHelp] = &[
+    CommandHelp {
+        name: "run",
+        about: "Run a script (the default command)",
+        usage: "rode run [flags] <script|-> [script args...]",
+        flags: RUN_FLAGS,
+    },
+    CommandHelp {
+        name: "repl",
+        about: "Start the interactive REPL (the default without arguments)",
+        usage: "rode repl",
+        flags: &[],
+    },
+    CommandHelp {
+        name: "test",
+        about: "Run *.test.* and *_test.* files",
+        usage: "rode test [flags] [files or directories...]",
+        flags: &[
+            (
+                "--filter <pattern>",
+                "Only run tests whose name contains this, or matches /regex/",
+            ),
+            (
+                "--timeout <ms>",
+                "Fail tests that run longer than this (5000)",
+            ),
+            ("--reporter <name>", "pretty (default), tap or junit"),
+            (
+                "--reporter-output <path>",
+                "Write the tap or junit report to a file",
+            ),
+            (
+                "-u, --update-snapshots",
+                "Rewrite snapshots that no longer match",
+            ),
+            (
+                "--coverage",
+                "Report code coverage in the terminal, as lcov and as HTML",
+            ),
+            (
+                "--coverage-dir <dir>",
+                "Where to write the coverage reports (coverage)",
+            ),
+        ],
+    },
+    CommandHelp {
+        name: "bench",
+        about: "Run Rode.bench benchmarks in *.bench.* and *_bench.* files",
+        usage: "rode bench [flags] [files or directories...]",
+        flags: &[
+            (
+                "--filter <pattern>",
+                "Only run benchmarks whose name contains this, or matches /regex/",
+            ),
+            ("--time <ms>", "How long to measure each benchmark (500)"),
+            ("--save <file>", "Write the results as JSON"),
+            (
+                "--compare <file>",
+                "Compare with the results saved by an earlier run",
+            ),
+        ],
+    },
+    CommandHelp {
+        name: "check",
+        about: "Type-check scripts without running them",
+        usage: "rode check <files...>",
+        flags: &[],
+    },
+    CommandHelp {
+        name: "fmt",
+        about: "Format source files with the project's Prettier",
+        usage: "rode fmt [flags] [paths...]",
+        flags: &[("--check", "Only report files that aren't formatted")],
+    },
+    CommandHelp {
+        name: "serve",
+        about: "Run a server script, choosing where Rode.http.serve listens",
+        usage: "rode serve [flags] <script> [script args...]",
+        flags: &[
+            (
+                "--port <port>",
+                "Default port for Rode.http.serve (rode.json, or 8000)",
+            ),
+            (
+                "--host <host>",
+                "Address to listen on (rode.json, or 127.0.0.1)",
+            ),
+            ("-w, --watch", "Restart the server when its files change"),
+            (
+                "--watch-path <path>",
+                "Also restart when this file or directory changes",
+            ),
+            ("--watch-ignore <glob>", "Ignore changes to matching files"),
+            (
+                "--hot",
+                "Watch, swapping changed modules in place where they allow it",
+            ),
+            (
+                "--debounce <ms>",
+                "Wait for changes to settle before restarting (100)",
+            ),
+            (
+                "--no-clear-screen",
+                "Don't clear the screen when restarting",
+            ),
+            ("--check", "Type-check before running"),
+            INSPECT_FLAGS[0],
+            INSPECT_FLAGS[1],
+            INSPECT_FLAGS[2],
+            PROFILE_FLAGS[0],
+            PROFILE_FLAGS[1],
+            PROFILE_FLAGS[2],
+            PROFILE_FLAGS[3],
+            LIMIT_FLAGS[0],
+            LIMIT_FLAGS[1],
+        ],
+    },
+    CommandHelp {
+        name: "compile",
+        about: "Bundle a script and the runtime into one executable",
+        usage: "rode compile [flags] <script>",
+        flags: &[(
+            "-o, --output <path>",
+            "Executable to write (the script's name)",
+        )],
+    },
+    CommandHelp {
+        name: "task",
+        about: "Run a task from rode.json, or list them without a name",
+        usage: "rode task [name]",
+        flags: &[],
+    },
+    CommandHelp {
+        name: "cache",
+        about: "Manage the transpile cache",
+        usage: "rode cache <clean|dir>",
+        flags: &[],
+    },
+];
+
+const GLOBAL_FLAGS: &[(&str, &str)] = &[
+    ("-h, --help", "Show help (also `rode <command> --help`)"),
+    ("-V, --version", "Print the version"),
+    ("--no-color", "Disable colored output"),
+];
+
+/// Parse the arguments after the program name
+pub fn parse(args: &[String]) -> Result<Cli, String> {
+    let mut cli = Cli {
+        command: Command::Repl,
+        no_color: false,
+        warnings: Vec::new(),
+    };
+
+    let mut rest = args;
+    while let Some(arg) = rest.first() {
+        match arg.as_str() {
+            "--no-color" => cli.no_color = true,
+            "-h" | "--help" => {
+                cli.command = Command::Help(None);
+                return Ok(cli);
+            }
+            "-V" | "--version" => {
+                cli.command = Command::Version;
+                return Ok(cli);
+            }
+            _ => break,
+        }
+        rest = &rest[1..];
+    }
+
+    let Some(first) = rest.first() else {
+        return Ok(cli);
+    };
+    // Anything that isn't a command name is the script for an implicit `run`
+    let (name, args) = match first.as_str() {
+        "help" => {
+            return Ok(Cli {
+                command: Command::Help(rest.get(1).cloned()),
+                ..cli
+            });
+        }
+        name if find_command(name).is_some() => (name, &rest[1..]),
+        _ => ("run", rest),
+    };
+    let wants_help = args
+        .iter()
+        .take_while(|arg| arg.starts_with('-') && *arg != "--")
+        .any(|arg| arg == "-h" || arg == "--help");
+    if wants_help {
+        cli.command = Command::Help(Some(name.to_string()));
+        return Ok(cli);
+    }
+
+    cli.command = match name {
+        "run" => Command::Run(parse_run(args, &mut cli)?),
+        "serve" => parse_serve(args, &mut cli)?,
+        "repl" => {
+            no_positionals("repl", args, 0)?;
+            Command::Repl
+        }
+        "test" => parse_test(args)?,
+        "bench" => parse_bench(args)?,
+        "check" => {
+            let files = positionals("check", args)?;
+            if files.is_empty() {
+                return Err("`rode check` needs at least one file".to_string());
+            }
+            Command::Check { files }
+        }
+        "fmt" => parse_fmt(args)?,
+        "compile" => parse_compile(args)?,
+        "task" => {
+            no_positionals("task", args, 1)?;
+            Command::Task {
+                name: args.first().cloned(),
+            }
+        }
+        _ => match args {
+            [action] if action == "clean" || action == "dir" => Command::Cache {
+                action: action.clone(),
+            },
+            _ => return Err("`rode cache` expects `clean` or `dir`".to_string()),
+        },
+    };
+
+    Ok(cli)
+}
+
+fn find_command(name: &str) -> Option<&'static CommandHelp> {
+    COMMANDS.iter().find(|command| command.name == name)
+}
+
+/// Flags up to the script, the script itself, then the script's arguments
+fn parse_run(args: &[String], cli: &mut Cli) -> Result<RunArgs, String> {
+    parse_run_with(args, cli, |_, _, _| Ok(false))
+}
+
+/// `parse_run` for a command with flags of its own: flags that are not run
+/// flags go to `extra`, which returns whether it took the flag
+fn parse_run_with<'a>(
+    args: &'a [String],
+    cli: &mut Cli,
+    mut extra: impl FnMut(&str, Option<&str>, &mut std::slice::Iter<'a, String>) -> Result<bool, String>,
+) -> Result<RunArgs, String> {
+    let mut run = RunArgs::default();
+    let mut rest = args.iter();
+
+    while let Some(arg) = rest.next() {
+        let (flag, inline) = split_flag(arg);
+        match flag {
+            "-w" | "--watch" => run.watch = true,
+            "--hot" => {
+                run.watch = true;
+                run.hot = true;
+            }
+            "--watch-path" => {
+                run.watch = true;
+                run.watch_paths.push(take_value(flag, inline, &mut rest)?);
+            }
+            "--watch-ignore" => {
+                run.watch = true;
+                run.watch_ignore.push(take_value(flag, inline, &mut rest)?);
+            }
+            "--debounce" => {
+                let value = take_value(flag, inline, &mut rest)?;
+                run.debounce = Some(
+                    value
+                        .parse()
+                        .map_err(|_| format!("Invalid --debounce '{}'", value))?,
+                );
+            }
+            "--no-clear-screen" => run.no_clear_screen = true,
+            "--check" => run.check = true,
+            "--no-color" => cli.no_color = true,
+            "-e" | "--eval" | "-p" | "--print" => {
+                run.print = flag == "-p" || flag == "--print";
+                run.script = Script::Eval(take_value(flag, inline, &mut rest)?);
+                break;
+            }
+            "--ext" => run.ext = Some(take_value(flag, inline, &mut rest)?),
+            "--inspect" | "--inspect-brk" | "--inspect-wait" => {
+                run.inspect = Some(parse_inspect(flag, inline)?)
+            }
+            "--cpu-prof" => {
+                run.cpu_prof.get_or_insert_default();
+            }
+            "--cpu-prof-dir" => {
+                run.cpu_prof.get_or_insert_default().dir =
+                    Some(take_value(flag, inline, &mut rest)?)
+            }
+            "--cpu-prof-name" => {
+                run.cpu_prof.get_or_insert_default().name =
+                    Some(take_value(flag, inline, &mut rest)?)
+            }
+            "--heap-snapshot-signal" => {
+                run.heap_snapshot_signal = Some(take_value(flag, inline, &mut rest)?)
+            }
+            "--max-heap-size" => run.max_heap_size = Some(limit(flag, inline, &mut rest)?),
+            "--timeout" => run.timeout = Some(limit(flag, inline, &mut rest)?),
+            "--" => {
+                if let Some(script) = rest.next() {
+                    run.script = Script::File(script.clone());
+                }
+                run.script_args = rest.cloned().collect();
+                return finish_run(run);
+            }
+            "-" => {
+                run.script = Script::Stdin;
+                break;
+            }
+            _ if arg.starts_with('-') => {
+                if !extra(flag, inline, &mut rest)? {
+                    return Err(format!(
+                        "Unknown flag '{}' (use `--` to pass it to the script)",
+                        arg
+                    ));
+                }
+            }
+            _ => {
+                run.script = Script::File(arg.clone());
+                break;
+            }
+        }
+    }
+
+    run.script_args = rest.cloned().collect();
+    // `rode app.js --watch` runs app.js with `--watch` in Rode.args; say so,
+    // since it's easy to mean the runtime flag
+    for arg in &run.script_args {
+        if arg == "--" {
+            break;
+        }
+        if RUN_FLAGS
+            .iter()
+            .any(|(names, _)| names.split([',', ' ', '[']).any(|name| name == arg))
+        {
+            cli.warnings.push(format!(
+                "'{}' comes after the script, so it is passed to the script. Put rode flags before the script name.",
+                arg
+            ));
+        }
+    }
+
+    finish_run(run)
+}
+
+fn finish_run(run: RunArgs) -> Result<RunArgs, String> {
+    match run.script {
+        Script::None => Err("Missing script to run".to_string()),
+        Script::File(_) if run.ext.is_some() => {
+            Err("--ext only applies to -e, -p and stdin".to_string())
+        }
+        Script::Eval(_) | Script::Stdin if run.watch || run.check => {
+            Err("--watch and --check need a script file".to_string())
+        }
+        _ => Ok(run),
+    }
+}
+
+/// A positive number for `--max-heap-size` or `--timeout`
+fn limit<'a>(
+    flag: &str,
+    inline: Option<&str>,
+    rest: &mut impl Iterator<Item = &'a String>,
+) -> Result<u64, String> {
+    let value = take_value(flag, inline, rest)?;
+    value
+        .parse()
+        .ok()
+        .filter(|&n| n > 0)
+        .ok_or(format!("Invalid {} '{}'", flag, value))
+}
+
+/// `--inspect[=[host:]port]`; the address needs `=`, like in Node
+fn parse_inspect(flag: &str, inline: Option<&str>) -> Result<Inspect, String> {
+    let mode = match flag {
+        "--inspect-brk" => InspectMode::Break,
+        "--inspect-wait" => InspectMode::Wait,
+        _ => InspectMode::Run,
+    };
+    let (host, port) = match inline {
+        None => (DEFAULT_INSPECT_HOST, DEFAULT_INSPECT_PORT.to_string()),
+        Some(address) => match address.rsplit_once(':') {
+            Some((host, port)) => (host, port.to_string()),
+            None => (DEFAULT_INSPECT_HOST, address.to_string()),
+        },
+    };
+    let port: u16 = port
+        .parse()
+        .map_err(|_| format!("Invalid {} port '{}'", flag, port))?;
+    Ok(Inspect {
+        mode,
+        address: format!("{}:{}", host, port),
+    })
+}
+
+fn parse_serve(args: &[String], cli: &mut Cli) -> Result<Command, String> {
+    let mut host = None;
+    let mut port = None;
+    // Run flags as usual, plus the serve flags
+    let run = parse_run_with(args, cli, |flag, inline, rest| {
+        match flag {
+            "--port" => {
+                let value = take_value(flag, inline, rest)?;
+                port = Some(
+                    value
+                        .parse()
+                        .map_err(|_| format!("Invalid port '{}'", value))?,
+                );
+            }
+            "--host" => host = Some(take_value(flag, inline, rest)?),
+            _ => return Ok(false),
+        }
+        Ok(true)
+    })?;
+    if !matches!(run.script, Script::File(_)) {
+        return Err("`rode serve` needs a script file".to_string());
+    }
+    Ok(Command::Serve { run, host, port })
+}
+
+fn parse_fmt(args: &[String]) -> Result<Command, String> {
+    let check = args.iter().any(|arg| arg == "--check");
+    let args: Vec<String> = args.iter().filter(|a| *a != "--check").cloned().collect();
+    let paths = positionals("fmt", &args)?;
+    Ok(Command::Fmt { paths, check })
+}
+
+fn parse_test(args: &[String]) -> Result<Command, String> {
+    let mut test = TestArgs::default();
+    let mut rest = args.iter();
+
+    while let Some(arg) = rest.next() {
+        let (flag, inline) = split_flag(arg);
+        match flag {
+            "--filter" => test.filter = Some(take_value(flag, inline, &mut rest)?),
+            "--timeout" => {
+                let value = take_value(flag, inline, &mut rest)?;
+                test.timeout = Some(
+                    value
+                        .parse()
+                        .map_err(|_| format!("Invalid --timeout '{}'", value))?,
+                );
+            }
+            "--reporter" => {
+                test.reporter = match take_value(flag, inline, &mut rest)?.as_str() {
+                    "pretty" => TestReporter::Pretty,
+                    "tap" => TestReporter::Tap,
+                    "junit" => TestReporter::Junit,
+                    other => {
+                        return Err(format!(
+                            "Unknown reporter '{}' (pretty, tap or junit)",
+                            other
+                        ));
+                    }
+                }
+            }
+            "--reporter-output" => {
+                test.reporter_output = Some(take_value(flag, inline, &mut rest)?)
+            }
+            "-u" | "--update-snapshots" => test.update_snapshots = true,
+            "--coverage" => {
+                test.coverage.get_or_insert_with(|| "coverage".to_string());
+            }
+            "--coverage-dir" => test.coverage = Some(take_value(flag, inline, &mut rest)?),
+            _ if arg.starts_with('-') => {
+                return Err(format!("Unknown flag '{}' for `rode test`", arg));
+            }
+            _ => test.paths.push(arg.clone()),
+        }
+    }
+
+    if test.reporter_output.is_some() && test.reporter == TestReporter::Pretty {
+        return Err("--reporter-output needs --reporter tap or junit".to_string());
+    }
+    Ok(Command::Test(test))
+}
+
+fn parse_bench(args: &[String]) -> Result<Command, String> {
+    let mut bench = BenchArgs::default();
+    let mut rest = args.iter();
+
+    while let Some(arg) = rest.next() {
+        let (flag, inline) = split_flag(arg);
+        match flag {
+            "--filter" => bench.filter = Some(take_value(flag, inline, &mut rest)?),
+            "--time" => {
+                let value = take_value(flag, inline, &mut rest)?;
+                bench.time = Some(
+                    value
+                        .parse()
+                        .map_err(|_| format!("Invalid --time '{}'", value))?,
+                );
+            }
+            "--save" => bench.save = Some(take_value(flag, inline, &mut rest)?),
+            "--compare" => bench.compare = Some(take_value(flag, inline, &mut rest)?),
+            _ if arg.starts_with('-') => {
+                return Err(format!("Unknown flag '{}' for `rode bench`", arg));
+            }
+            _ => bench.paths.push(arg.clone()),
+        }
+    }
+    Ok(Command::Bench(bench))
+}
+
+fn parse_compile(args: &[String]) -> Result<Command, String> {
+    let mut output = None;
+    let mut files = Vec::new();
+    let mut rest = args.iter();
+
+    while let Some(arg) = rest.next() {
+        let (flag, inline) = split_flag(arg);
+        match flag {
+            "-o" | "--output" => output = Some(take_value(flag, inline, &mut rest)?),
+            _ if arg.starts_with('-') => return Err(format!("Unknown flag '{}'", arg)),
+            _ => files.push(arg.clone()),
+        }
+    }
+
+    match files.as_slice() {
+        [script] => Ok(Command::Compile {
+            script: script.clone(),
+            output,
+        }),
+        [] => Err("Missing script to compile".to_string()),
+        _ => Err("`rode compile` takes a single entry script".to_string()),
+    }
+}
+
+fn positionals(command: &str, args: &[String]) -> Result<Vec<String>, String> {
+    no_positionals(command, args, usize::MAX)?;
+    Ok(args.to_vec())
+}
+
+/// Reject flags, and more than `max` positional arguments
+fn no_positionals(command: &str, args: &[String], max: usize) -> Result<(), String> {
+    if let Some(flag) = args.iter().find(|arg| arg.starts_with('-')) {
+        return Err(format!("Unknown flag '{}' for `rode {}`", flag, command));
+    }
+    if args.len() > max {
+        return Err(format!("Too many arguments for `rode {}`", command));
+    }
+    Ok(())
+}
+
+/// `--name=value` -> ("--name", Some("value"))
+fn split_flag(arg: &str) -> (&str, Option<&str>) {
+    match arg.split_once('=') {
+        Some((flag, value)) if flag.starts_with("--") => (flag, Some(value)),
+        _ => (arg, None),
+    }
+}
+
+fn take_value<'a>(
+    flag: &str,
+    inline: Option<&str>,
+    rest: &mut impl Iterator<Item = &'a String>,
+) -> Result<String, String> {
+    match inline {
+        Some(value) => Ok(value.to_string()),
+        None => rest
+            .next()
+            .cloned()
+            .ok_or_else(|| format!("{} requires an argument", flag)),
+    }
+}
+
+pub fn print_help(command: Option<&str>) -> Result<(), String> {
+    let Some(name) = command else {
+        print_overview();
+        return Ok(());
+    };
+    let command = find_command(name).ok_or_else(|| format!("Unknown command '{}'", name))?;
+
+    println!("{}", command.about);
+    println!();
+    println!("{} {}", "Usage:".bold(), command.usage.cyan());
+    if !command.flags.is_empty() {
+        println!();
+        println!("{}", "Flags:".bold());
+        print_flags(command.flags);
+    }
+    Ok(())
+}
+
+fn print_overview() {
+    println!(
+        "{} {}",
+        "Rode".bright_cyan().bold(),
+        format!("v{}", env!("CARGO_PKG_VERSION")).dimmed()
+    );
+    println!();
+    println!(
+        "{} {} {}",
+        "Usage:".bold(),
+        "rode [flags] <command>".cyan(),
+        "[args...]".dimmed()
+    );
+    println!(
+        "       {} {}",
+        "rode [run flags] <script>".cyan(),
+        "[script args...]".dimmed()
+    );
+    println!();
+    println!("{}", "Commands:".bold());
+    for command in COMMANDS {
+        println!("  {:10} {}", command.name.cyan(), command.about);
+    }
+    println!();
+    println!("{}", "Flags:".bold());
+    print_flags(GLOBAL_FLAGS);
+    println!();
+    println!("{}", "Examples:".bold());
+    for (example, about) in [
+        (
+            "rode app.ts --port 3000",
+            "Run app.ts with Rode.args = ['--port', '3000']",
+        ),
+        (
+            "rode --watch --check app.ts",
+            "Type-check and rerun on every change",
+        ),
+        ("rode -p \"1 + 1\"", "Evaluate code and print the result"),
+        ("cat app.ts | rode --ext ts -", "Read the script from stdin"),
+        (
+            "rode run -- -odd-name.js",
+            "Run a script whose name starts with -",
+        ),
+    ] {
+        println!("  {:32} {}", example.cyan(), about.dimmed());
+    }
+}
+
+fn print_flags(flags: &[(&str, &str)]) {
+    for (names, about) in flags {
+        println!("  {:22} {}", names.cyan(), about);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_args(args: &str) -> Result<Cli, String> {
+        let args: Vec<String> = args.split_whitespace().map(str::to_string).collect();
+        parse(&args)
+    }
+
+    fn run_args(args: &str) -> RunArgs {
+        match parse_args(args).unwrap().command {
+            Command::Run(run) => run,
+            _ => panic!("expected a run command for `{}`", args),
+        }
+    }
+
+    #[test]
+    fn test_script_args_follow_the_script() {
+        let run = run_args("--watch app.js --port 3000 -w");
+        assert!(run.watch);
+        assert_eq!(run.script, Script::File("app.js".to_string()));
+        assert_eq!(run.script_args, ["--port", "3000", "-w"]);
+        assert_eq!(parse_args("app.js -w").unwrap().warnings.len(), 1);
+
+        let run = run_args("--watch-path src --watch-ignore=*.log app.js");
+        assert!(run.watch);
+        assert_eq!(run.watch_paths, ["src"]);
+        assert_eq!(run.watch_ignore, ["*.log"]);
+
+        let run = run_args("--hot --debounce=300 --no-clear-screen app.js");
+        assert!(run.watch && run.hot);
+        assert_eq!(run.debounce, Some(300));
+        assert!(run.no_clear_screen);
+        assert!(parse_args("-w --debounce soon app.js").is_err());
+
+        let run = run_args("--max-heap-size 256 --timeout=5000 app.js");
+        assert_eq!(run.max_heap_size, Some(256));
+        assert_eq!(run.timeout, Some(5000));
+        assert_eq!(run.child_flags(), ["--max-heap-size=256", "--timeout=5000"]);
+        assert!(parse_args("--timeout 0 app.js").is_err());
+
+        let run = run_args("run --check -- -app.js x");
+        assert!(run.check);
+        assert_eq!(run.script, Script::File("-app.js".to_string()));
+        assert_eq!(run.script_args, ["x"]);
+
+        let run = run_args("--ext=ts -e 1+1 a b");
+        assert_eq!(run.script, Script::Eval("1+1".to_string()));
+        assert_eq!(run.script_args, ["a", "b"]);
+    }
+
+    #[test]
+    fn test_subcommands() {
+        assert!(matches!(parse_args("").unwrap().command, Command::Repl));
+        assert!(matches!(
+            parse_args("run --help").unwrap().command,
+            Command::Help(Some(name)) if name == "run"
+        ));
+        assert!(matches!(
+            parse_args("app.js --help").unwrap().command,
+            Command::Run(_)
+        ));
+        assert!(matches!(
+            parse_args("serve --watch --port=3000 app.js")
+                .unwrap()
+                .command,
+            Command::Serve {
+                port: Some(3000),
+                ..
+            }
+        ));
+        match parse_args("serve --watch-path src --port 3000 app.js")
+            .unwrap()
+            .command
+        {
+            Command::Serve { run, port, .. } => {
+                assert_eq!(port, Some(3000));
+                assert_eq!(run.watch_paths, ["src"]);
+                assert!(matches!(run.script, Script::File(ref file) if file == "app.js"));
+            }
+            _ => panic!("expected serve"),
+        }
+        // An optional inline value is not followed by a separate one, and
+        // flags after the script are the script's
+        match parse_args("serve --inspect --host 0.0.0.0 app.js --port 1")
+            .unwrap()
+            .command
+        {
+            Command::Serve { run, host, port } => {
+                assert_eq!(host.as_deref(), Some("0.0.0.0"));
+                assert_eq!(port, None);
+                assert!(run.inspect.is_some());
+                assert!(matches!(run.script, Script::File(ref file) if file == "app.js"));
+                assert_eq!(run.script_args, ["--port", "1"]);
+            }
+            _ => panic!("expected serve"),
+        }
+        assert!(parse_args("serve --nope app.js").is_err());
+        assert!(matches!(
+            parse_args("compile app.ts -o app").unwrap().command,
+            Command::Compile {
+                output: Some(_),
+                ..
+            }
+        ));
+        assert!(matches!(
+            parse_args("test --reporter=junit --timeout 100 -u --coverage tests")
+                .unwrap()
+                .command,
+            Command::Test(TestArgs {
+                reporter: TestReporter::Junit,
+                timeout: Some(100),
+                update_snapshots: true,
+                coverage: Some(ref dir),
+                ref paths,
+                ..
+            }) if paths == &["tests"] && dir == "coverage"
+        ));
+        assert!(parse_args("test --reporter xml").is_err());
+        let inspect = |args| match parse_args(args).unwrap().command {
+            Command::Run(run) => run.inspect,
+            _ => None,
+        };
+        assert_eq!(
+            inspect("run --inspect app.js"),
+            Some(Inspect {
+                mode: InspectMode::Run,
+                address: "127.0.0.1:9229".to_string(),
+            })
+        );
+        assert_eq!(
+            inspect("run --inspect-brk=9230 app.js").map(|inspect| inspect.flag()),
+            Some("--inspect-brk=127.0.0.1:9230".to_string())
+        );
+        assert_eq!(
+            inspect("run --inspect-wait=0.0.0.0:0 app.js").map(|inspect| inspect.address),
+            Some("0.0.0.0:0".to_string())
+        );
+        assert!(parse_args("run --inspect=localhost app.js").is_err());
+        let Command::Run(run) =
+            parse_args("--cpu-prof-dir profiles --heap-snapshot-signal SIGUSR2 --inspect app.js")
+                .unwrap()
+                .command
+        else {
+            panic!("expected a run");
+        };
+        assert_eq!(
+            run.child_flags(),
+            [
+                "--inspect=127.0.0.1:9229",
+                "--cpu-prof",
+                "--cpu-prof-dir=profiles",
+                "--heap-snapshot-signal=SIGUSR2"
+            ]
+        );
+        assert!(matches!(
+            parse_args("bench --time=200 --compare base.json benches").unwrap().command,
+            Command::Bench(BenchArgs {
+                time: Some(200),
+                compare: Some(_),
+                save: None,
+                ref paths,
+                ..
+            }) if paths == &["benches"]
+        ));
+        assert!(parse_args("bench --time fast").is_err());
+        assert!(parse_args("--bogus app.js").is_err());
+        assert!(parse_args("check").is_err());
+        assert!(parse_args("-w -e 1").is_err());
+    }
+}
@@ -0,0 +1,587 @@
+/// Project configuration from rode.json / rode.jsonc
+/// The nearest config file in the script's directory or any parent is loaded
+/// once at startup. Relative paths in it are resolved against the directory
+/// it lives in.
+use crate::glob::Glob;
+use crate::tsconfig::parse_jsonc;
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::sync::OnceLock;
+
+pub const CONFIG_FILES: [&str; 2] = ["rode.json", "rode.jsonc"];
+
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+pub struct Config {
+    pub path: PathBuf,
+    pub dir: PathBuf,
+    /// Env files to load instead of the defaults, in order
+    pub env_files: Option<Vec<PathBuf>>,
+    /// Import map: bare specifier (or prefix ending in `/`) to path
+    pub imports: BTreeMap<String, PathBuf>,
+    pub watch: WatchConfig,
+    pub permissions: Permissions,
+    pub tsconfig: Option<PathBuf>,
+    pub serve: ServeConfig,
+    pub limits: LimitsConfig,
+    pub tasks: BTreeMap<String, Task>,
+}
+
+#[derive(Default)]
+pub struct WatchConfig {
+    /// Extra files that restart watch mode when they change
+    pub include: Vec<Glob>,
+    /// Files that never restart watch mode
+    pub exclude: Vec<Glob>,
+    /// Milliseconds to wait for changes to settle before restarting
+    pub debounce: Option<u64>,
+    pub clear_screen: Option<bool>,
+}
+
+#[derive(Default)]
+pub struct ServeConfig {
+    pub host: Option<String>,
+    pub port: Option<u16>,
+}
+
+/// Defaults for `--max-heap-size` and `--timeout`
+#[derive(Default)]
+pub struct LimitsConfig {
+    /// Megabytes
+    pub max_heap_size: Option<u64>,
+    /// Milliseconds
+    pub timeout: Option<u64>,
+}
+
+#[derive(Default)]
+pub struct Task {
+    /// Shell command; empty for tasks that only group their dependencies
+    pub command: String,
+    pub description: Option<String>,
+    /// Tasks that have to finish first
+    pub depends_on: Vec<String>,
+    /// Extra environment variables, interpolated like .env values
+    pub env: BTreeMap<String, String>,
+}
+
+/// What scripts may touch; everything is allowed unless restricted
+#[derive(Default)]
+pub struct Permissions {
+    pub read: Access,
+    pub write: Access,
+    /// Hosts (optionally `host:port`) for `fetch` and `Rode.http.serve`
+    pub net: Access,
+    /// Environment variables visible in `Rode.env`
+    pub env: Access,
+}
+
+#[derive(Default, Debug, PartialEq)]
+pub enum Access {
+    #[default]
+    All,
+    Nothing,
+    Only(Vec<String>),
+}
+
+impl Config {
+    /// Load the nearest config file in `start` or any parent directory
+    pub fn find(start: &Path) -> Result<Option<Config>, String> {
+        let path = start
+            .ancestors()
+            .flat_map(|dir| CONFIG_FILES.iter().map(move |name| dir.join(name)))
+            .find(|candidate| candidate.is_file());
+
+        match path {
+            Some(path) => Self::load(&path).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Config, String> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        let json = parse_jsonc(&content).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Self::from_json(path, &json).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    fn from_json(path: &Path, json: &Value) -> Result<Config, String> {
+        let object = json.as_object().ok_or("expected an object")?;
+        let dir = path.parent().unwrap_or(Path::new(".")).to_path_buf();
+        let mut config = Config {
+            path: path.to_path_buf(),
+            dir: dir.clone(),
+            env_files: None,
+            imports: BTreeMap::new(),
+            watch: WatchConfig::default(),
+            permissions: Permissions::default(),
+            tsconfig: None,
+            serve: ServeConfig::default(),
+            limits: LimitsConfig::default(),
+            tasks: BTreeMap::new(),
+        };
+
+        for (key, value) in object {
+            match key.as_str() {
+                "$schema" => {}
+                "envFiles" => {
+                    config.env_files = Some(
+                        string_list(key, value)?
+                            .iter()
+                            .map(|file| dir.join(file))
+                            .collect(),
+                    )
+                }
+                "imports" => {
+                    for (specifier, target) in as_object(key, value)? {
+                        let target = target
+                            .as_str()
+                            .ok_or(format!("imports.{} must be a string", specifier))?;
+                        if !(target.starts_with("./")
+                            || target.starts_with("../")
+                            || Path::new(target).is_absolute())
+                        {
+                            return Err(format!(
+                                "imports.{} must be a path starting with ./ or ../",
+                                specifier
+                            ));
+                        }
+                        config.imports.insert(specifier.clone(), dir.join(target));
+                    }
+                }
+                "watch" => {
+                    for (field, value) in as_object(key, value)? {
+                        let globs = || {
+                            string_list(field, value)?
+                                .iter()
+                                .map(|pattern| Glob::new(pattern))
+                                .collect::<Result<Vec<_>, _>>()
+                        };
+                        match field.as_str() {
+                            "include" => config.watch.include = globs()?,
+                            "exclude" => config.watch.exclude = globs()?,
+                            "debounce" => {
+                                config.watch.debounce = Some(
+                                    value
+                                        .as_u64()
+                                        .ok_or("watch.debounce must be a number of milliseconds")?,
+                                )
+                            }
+                            "clearScreen" => {
+                                config.watch.clear_screen = Some(
+                                    value
+                                        .as_bool()
+                                        .ok_or("watch.clearScreen must be true or false")?,
+                                )
+                            }
+                            _ => return Err(format!("Unknown field 'watch.{}'", field)),
+                        }
+                    }
+                }
+                "permissions" => {
+                    for (field, value) in as_object(key, value)? {
+                        let access = parse_access(field, value)?;
+                        match field.as_str() {
+                            "read" => config.permissions.read = access,
+                            "write" => config.permissions.write = access,
+                            "net" => config.permissions.net = access,
+                            "env" => config.permissions.env = access,
+                            _ => return Err(format!("Unknown permission '{}'", field)),
+                        }
+                    }
+                }
+                "tsconfig" => {
+                    let file = value.as_str().ok_or("tsconfig must be a string")?;
+                    config.tsconfig = Some(dir.join(file));
+                }
+                "serve" => {
+                    for (field, value) in as_object(key, value)? {
+                        match field.as_str() {
+                            "host" => {
+                                config.serve.host = Some(
+                                    value
+                                        .as_str()
+                                        .ok_or("serve.host must be a string")?
+                                        .to_string(),
+                                )
+                            }
+                            "port" => {
+                                let port = value
+                                    .as_u64()
+                                    .and_then(|port| u16::try_from(port).ok())
+                                    .ok_or("serve.port must be a port number")?;
+                                config.serve.port = Some(port);
+                            }
+                            _ => return Err(format!("Unknown field 'serve.{}'", field)),
+                        }
+                    }
+                }
+                "limits" => {
+                    for (field, value) in as_object(key, value)? {
+                        let positive = |message: &str| {
+                            value.as_u64().filter(|&n| n > 0).ok_or(message.to_string())
+                        };
+                        match field.as_str() {
+                            "maxHeapSize" => {
+                                config.limits.max_heap_size = Some(positive(
+                                    "limits.maxHeapSize must be a number of megabytes",
+                                )?)
+                            }
+                            "timeout" => {
+                                config.limits.timeout = Some(positive(
+                                    "limits.timeout must be a number of milliseconds",
+                                )?)
+                            }
+                            _ => return Err(format!("Unknown field 'limits.{}'", field)),
+                        }
+                    }
+                }
+                "tasks" => {
+                    for (name, task) in as_object(key, value)? {
+                        config.tasks.insert(name.clone(), parse_task(name, task)?);
+                    }
+                }
+                _ => return Err(format!("Unknown field '{}'", key)),
+            }
+        }
+
+        Ok(config)
+    }
+
+    /// Target of a bare specifier in the import map; an exact entry wins,
+    /// then the longest matching `prefix/` entry
+    pub fn resolve_import(&self, specifier: &str) -> Option<PathBuf> {
+        if let Some(target) = self.imports.get(specifier) {
+            return Some(target.clone());
+        }
+
+        self.imports
+            .iter()
+            .filter(|(prefix, _)| prefix.ends_with('/') && specifier.starts_with(prefix.as_str()))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(prefix, target)| target.join(&specifier[prefix.len()..]))
+    }
+
+    /// Whether a change to `path` should restart watch mode, going by the
+    /// `watch` globs
+    pub fn watch_includes(&self, path: &Path) -> bool {
+        let relative = path.strip_prefix(&self.dir).unwrap_or(path);
+        self.watch.include.iter().any(|glob| glob.matches(relative)) && !self.watch_excludes(path)
+    }
+
+    pub fn watch_excludes(&self, path: &Path) -> bool {
+        let relative = path.strip_prefix(&self.dir).unwrap_or(path);
+        self.watch.exclude.iter().any(|glob| glob.matches(relative))
+    }
+
+    /// Check that `path` may be read (or written), as a message for scripts
+    pub fn check_path(&self, path: &str, write: bool) -> Result<(), String> {
+        let (access, kind) = if write {
+            (&self.permissions.write, "write")
+        } else {
+            (&self.permissions.read, "read")
+        };
+        let allowed = match access {
+            Access::All => true,
+            Access::Nothing => false,
+            Access::Only(paths) => {
+                let target = real_path(Path::new(path));
+                paths
+                    .iter()
+                    .any(|allowed| target.starts_with(real_path(&self.dir.join(allowed))))
+            }
+        };
+
+        if allowed {
+            Ok(())
+        } else {
+            Err(format!(
+                "Permission denied: {} access to '{}' is not allowed by {}",
+                kind,
+                path,
+                self.path.display()
+            ))
+        }
+    }
+
+    /// Check that a host (with an optional port) may be reached or listened on
+    pub fn check_net(&self, host: &str, port: Option<u16>) -> Result<(), String> {
+        // URLs write IPv6 hosts in brackets
+        let bare_host = host.trim_start_matches('[').trim_end_matches(']');
+        let allowed = match &self.permissions.net {
+            Access::All => true,
+            Access::Nothing => false,
+            Access::Only(hosts) => hosts.iter().any(|allowed| {
+                let (allowed_host, allowed_port) = split_host_port(allowed);
+                allowed_host == bare_host
+                    && allowed_port.is_none_or(|allowed_port| {
+                        Some(allowed_port) == port.map(|p| p.to_string()).as_deref()
+                    })
+            }),
+        };
+
+        if allowed {
+            Ok(())
+        } else {
+            let target = match port {
+                Some(port) => format!("{}:{}", host, port),
+                None => host.to_string(),
+            };
+            Err(format!(
+                "Permission denied: net access to '{}' is not allowed by {}",
+                target,
+                self.path.display()
+            ))
+        }
+    }
+
+    pub fn allows_env(&self, name: &str) -> bool {
+        match &self.permissions.env {
+            Access::All => true,
+            Access::Nothing => false,
+            Access::Only(names) => names.iter().any(|allowed| allowed == name),
+        }
+    }
+}
+
+/// Make `config` the project config for the rest of the process
+pub fn init(config: Config) {
+    let _ = CONFIG.set(config);
+}
+
+pub fn current() -> Option<&'static Config> {
+    CONFIG.get()
+}
+
+/// A `net` entry as host and optional port: `host`, `host:port`, `::1` or
+/// `[::1]:port`
+fn split_host_port(entry: &str) -> (&str, Option<&str>) {
+    if let Some(rest) = entry.strip_prefix('[')
+        && let Some((host, after)) = rest.split_once(']')
+    {
+        return (host, after.strip_prefix(':'));
+    }
+    match entry.split_once(':') {
+        // More than one colon is a bare IPv6 address
+        Some((_, port)) if port.contains(':') => (entry, None),
+        Some((host, port)) => (host, Some(port)),
+        None => (entry, None),
+    }
+}
+
+fn as_object<'a>(key: &str, value: &'a Value) -> Result<&'a Map<String, Value>, String> {
+    value
+        .as_object()
+        .ok_or_else(|| format!("{} must be an object", key))
+}
+
+fn string_list(key: &str, value: &Value) -> Result<Vec<String>, String> {
+    let error = || format!("{} must be a list of strings", key);
+    value
+        .as_array()
+        .ok_or_else(error)?
+        .iter()
+        .map(|item| item.as_str().map(str::to_string).ok_or_else(error))
+        .collect()
+}
+
+/// `true`, `false` or a list of allowed entries
+fn parse_access(key: &str, value: &Value) -> Result<Access, String> {
+    match value {
+        Value::Bool(true) => Ok(Access::All),
+        Value::Bool(false) => Ok(Access::Nothing),
+        _ => string_list(key, value)
+            .map(Access::Only)
+            .map_err(|_| format!("permissions.{} must be true, false or a list", key)),
+    }
+}
+
+/// A command string, or `{ "command", "description", "dependsOn", "env" }`
+fn parse_task(name: &str, value: &Value) -> Result<Task, String> {
+    if let Some(command) = value.as_str() {
+        return Ok(Task {
+            command: command.to_string(),
+            ..Task::default()
+        });
+    }
+
+    let object = as_object(&format!("tasks.{}", name), value)?;
+    let mut task = Task::default();
+    for (field, value) in object {
+        let text = || {
+            value
+                .as_str()
+                .map(str::to_string)
+                .ok_or_else(|| format!("tasks.{}.{} must be a string", name, field))
+        };
+        match field.as_str() {
+            "command" => task.command = text()?,
+            "description" => task.description = Some(text()?),
+            "dependsOn" => {
+                task.depends_on = string_list(&format!("tasks.{}.dependsOn", name), value)?
+            }
+            "env" => {
+                for (key, value) in as_object(&format!("tasks.{}.env", name), value)? {
+                    let value = value
+                        .as_str()
+                        .ok_or_else(|| format!("tasks.{}.env.{} must be a string", name, key))?;
+                    task.env.insert(key.clone(), value.to_string());
+                }
+            }
+            _ => return Err(format!("Unknown field 'tasks.{}.{}'", name, field)),
+        }
+    }
+    if task.command.is_empty() && task.depends_on.is_empty() {
+        return Err(format!("tasks.{} needs a command or dependsOn", name));
+    }
+    Ok(task)
+}
+
+/// `path` made absolute with symlinks resolved, so a link inside an allowed
+/// directory can't lead out of it. Only the part that exists can be
+/// resolved; the rest, like a file about to be written, is kept as given.
+fn real_path(path: &Path) -> PathBuf {
+    let cwd = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+    let path = cwd.join(path);
+    for existing in path.ancestors() {
+        if let Ok(real) = existing.canonicalize() {
+            let rest = path.strip_prefix(existing).unwrap_or(Path::new(""));
+            return normalize(&real.join(rest));
+        }
+    }
+    normalize(&path)
+}
+
+/// Resolve `.` and `..` without touching the file system, since the path
+/// may not exist yet
+fn normalize(path: &Path) -> PathBuf {
+    let mut result = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                result.pop();
+            }
+            other => result.push(other),
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(json: &str) -> Result<Config, String> {
+        Config::from_json(Path::new("/app/rode.json"), &parse_jsonc(json)?)
+    }
+
+    #[test]
+    fn test_parse_config() {
+        let config = config(
+            r#"{
+                // Comments are allowed
+                "envFiles": [".env", ".env.local"],
+                "imports": { "utils/": "./src/utils/", "db": "./src/db.ts" },
+                "watch": { "include": ["src/**"], "exclude": ["**/*.test.ts"], "debounce": 300 },
+                "permissions": { "read": ["./data"], "write": false, "net": ["api.example.com"] },
+                "serve": { "port": 3000 },
+                "limits": { "maxHeapSize": 512, "timeout": 30000 },
+                "tasks": { "build": "tsc", "dev": { "command": "rode -w main.ts", "description": "Run with watch" } },
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            config.env_files.as_ref().unwrap()[1],
+            Path::new("/app/.env.local")
+        );
+        assert_eq!(
+            config.resolve_import("db"),
+            Some(PathBuf::from("/app/src/db.ts"))
+        );
+        assert_eq!(
+            config.resolve_import("utils/strings"),
+            Some(PathBuf::from("/app/src/utils/strings"))
+        );
+        assert_eq!(config.resolve_import("lodash"), None);
+        assert!(config.watch_includes(Path::new("/app/src/lib/a.ts")));
+        assert!(!config.watch_includes(Path::new("/app/src/lib/a.test.ts")));
+        assert!(
+            config
+                .check_path("/app/data/../data/users.json", false)
+                .is_ok()
+        );
+        assert!(
+            config
+                .check_path("/app/data/../secrets.txt", false)
+                .is_err()
+        );
+        assert!(config.check_path("/app/data/out.json", true).is_err());
+        assert!(config.check_net("api.example.com", Some(443)).is_ok());
+        assert!(config.check_net("evil.example.com", None).is_err());
+        assert!(config.allows_env("HOME"));
+        assert_eq!(config.serve.port, Some(3000));
+        assert_eq!(config.watch.debounce, Some(300));
+        assert_eq!(config.limits.max_heap_size, Some(512));
+        assert_eq!(config.limits.timeout, Some(30000));
+        assert_eq!(config.tasks["dev"].command, "rode -w main.ts");
+    }
+
+    #[test]
+    fn test_check_net_entries() {
+        let config = config(
+            r#"{ "permissions": { "net": ["::1", "[fe80::1]:8080", "localhost:3000", "example.com"] } }"#,
+        )
+        .unwrap();
+        assert!(config.check_net("::1", Some(80)).is_ok());
+        assert!(config.check_net("[::1]", Some(443)).is_ok());
+        assert!(config.check_net("[fe80::1]", Some(8080)).is_ok());
+        assert!(config.check_net("fe80::1", Some(8081)).is_err());
+        assert!(config.check_net("localhost", Some(3000)).is_ok());
+        assert!(config.check_net("localhost", Some(3001)).is_err());
+        assert!(config.check_net("example.com", Some(8443)).is_ok());
+        assert!(config.check_net("example.org", Some(443)).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_check_path_follows_symlinks() {
+        let dir = std::env::temp_dir().join(format!("rode-config-{}", std::process::id()));
+        fs::create_dir_all(dir.join("data")).unwrap();
+        fs::write(dir.join("secrets.txt"), "").unwrap();
+        let _ = fs::remove_file(dir.join("data/up"));
+        std::os::unix::fs::symlink(&dir, dir.join("data/up")).unwrap();
+        let config = Config::from_json(
+            &dir.join("rode.json"),
+            &parse_jsonc(r#"{ "permissions": { "read": ["./data"], "write": ["./data"] } }"#)
+                .unwrap(),
+        )
+        .unwrap();
+
+        let path = |relative: &str| dir.join(relative).to_string_lossy().to_string();
+        assert!(config.check_path(&path("data/users.json"), false).is_ok());
+        assert!(
+            config
+                .check_path(&path("data/up/secrets.txt"), false)
+                .is_err()
+        );
+        assert!(config.check_path(&path("data/new/out.json"), true).is_ok());
+        assert!(
+            config
+                .check_path(&path("data/up/new/out.json"), true)
+                .is_err()
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_rejects_unknown_fields() {
+        assert!(config(r#"{ "taks": {} }"#).is_err());
+        assert!(config(r#"{ "imports": { "x": "lodash" } }"#).is_err());
+        assert!(config(r#"{ "permissions": { "read": 1 } }"#).is_err());
+        assert!(config(r#"{ "limits": { "timeout": 0 } }"#).is_err());
+    }
+}
@@ -0,0 +1,493 @@
+/// Code coverage for `rode test --coverage`
+/// Every isolate runs with V8's precise block coverage on (see
+/// `Runtime::new`). Before a test file's isolate goes away, its counts are
+/// collected per script and mapped from the compiled code back to original
+/// lines through the registered source maps. A line counts as often as the
+/// innermost block around its first token ran. The totals are printed as a
+/// table and written as `lcov.info` and an HTML page per file.
+use crate::source_map;
+use colored::*;
+use serde_json::Value;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// A script as V8 compiled it, which coverage offsets point into
+struct CompiledScript {
+    code: String,
+    /// Added to a compiled line to get the transpiled line, e.g. -1 for the
+    /// module wrapper's header line
+    line_offset: i32,
+}
+
+/// A block of code and how often it ran, as UTF-16 offsets into the script
+struct Range {
+    start: usize,
+    end: usize,
+    count: u64,
+}
+
+#[derive(Default)]
+struct FileCoverage {
+    source: String,
+    /// Hit counts by 1-based original line
+    lines: BTreeMap<usize, u64>,
+    /// Hit counts by 1-based line and name of each function
+    functions: BTreeMap<(usize, String), u64>,
+}
+
+thread_local! {
+    static SCRIPTS: RefCell<HashMap<String, CompiledScript>> = RefCell::new(HashMap::new());
+    /// Totals by path relative to the current directory
+    static FILES: RefCell<BTreeMap<PathBuf, FileCoverage>> = const { RefCell::new(BTreeMap::new()) };
+}
+
+pub fn enable() {
+    ENABLED.store(true, Ordering::SeqCst);
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::SeqCst)
+}
+
+/// Remember the code compiled for `filename` while coverage is on
+pub fn record_script(filename: &str, code: &str, line_offset: i32) {
+    if !is_enabled() {
+        return;
+    }
+    let script = CompiledScript {
+        code: code.to_string(),
+        line_offset,
+    };
+    SCRIPTS.with(|scripts| scripts.borrow_mut().insert(filename.to_string(), script));
+}
+
+/// Add the result of `Profiler.takePreciseCoverage` to the totals
+pub fn collect(coverage: &Value) {
+    let Some(scripts) = coverage["result"].as_array() else {
+        return;
+    };
+    for script in scripts {
+        let url = script["url"].as_str().unwrap_or_default();
+        let Some(path) = reported_path(url) else {
+            continue;
+        };
+        let Some(source) = source_map::original_source(url) else {
+            continue;
+        };
+        let Some(counts) = SCRIPTS.with(|scripts| {
+            let scripts = scripts.borrow();
+            let compiled = scripts.get(url)?;
+            Some(script_counts(url, compiled, &script["functions"]))
+        }) else {
+            continue;
+        };
+
+        FILES.with(|files| {
+            let mut files = files.borrow_mut();
+            let file = files.entry(path).or_default();
+            file.source = source.to_string();
+            for (line, count) in counts.lines {
+                *file.lines.entry(line).or_default() += count;
+            }
+            for (function, count) in counts.functions {
+                *file.functions.entry(function).or_default() += count;
+            }
+        });
+    }
+}
+
+/// The file to report the script `url` under, leaving out tests,
+/// dependencies and code outside the current directory
+fn reported_path(url: &str) -> Option<PathBuf> {
+    if url.is_empty() {
+        return None;
+    }
+    let path = fs::canonicalize(url).ok()?;
+    let cwd = fs::canonicalize(std::env::current_dir().ok()?).ok()?;
+    let relative = path.strip_prefix(&cwd).ok()?;
+    if relative
+        .components()
+        .any(|component| component.as_os_str() == "node_modules")
+    {
+        return None;
+    }
+    let name = relative.file_name()?.to_string_lossy();
+    if crate::test_runner::is_test_file(&name) {
+        return None;
+    }
+    Some(relative.to_path_buf())
+}
+
+/// Line and function counts of one script, on its original lines
+fn script_counts(url: &str, compiled: &CompiledScript, functions: &Value) -> FileCoverage {
+    let functions = functions.as_array().map(Vec::as_slice).unwrap_or_default();
+    let ranges = |function: &Value| -> Vec<Range> {
+        function["ranges"]
+            .as_array()
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+            .iter()
+            .map(|range| Range {
+                start: range["startOffset"].as_u64().unwrap_or(0) as usize,
+                end: range["endOffset"].as_u64().unwrap_or(0) as usize,
+                count: range["count"].as_u64().unwrap_or(0),
+            })
+            .collect()
+    };
+    let all_ranges: Vec<Range> = functions.iter().flat_map(ranges).collect();
+
+    // Several transpiled lines can come from one original line
+    let mut counts = FileCoverage::default();
+    for (line, column, count) in line_hits(&compiled.code, compiled.line_offset, &all_ranges) {
+        if let Some((original, _)) = source_map::lookup(url, line, column) {
+            let entry = counts.lines.entry(original).or_default();
+            *entry = (*entry).max(count);
+        }
+    }
+
+    let starts = line_starts(&compiled.code);
+    for function in functions {
+        let Some(range) = ranges(function).into_iter().next() else {
+            continue;
+        };
+        // The script itself
+        if range.start == 0 {
+            continue;
+        }
+        let index = starts.partition_point(|&start| start <= range.start) - 1;
+        let line = index as i64 + 1 + compiled.line_offset as i64;
+        if line < 1 {
+            continue;
+        }
+        let column = range.start - starts[index] + 1;
+        let Some((original, _)) = source_map::lookup(url, line as usize, column) else {
+            continue;
+        };
+        let name = match function["functionName"].as_str() {
+            Some(name) if !name.is_empty() => name.to_string(),
+            _ => "(anonymous)".to_string(),
+        };
+        let entry = counts.functions.entry((original, name)).or_default();
+        *entry = (*entry).max(range.count);
+    }
+    counts
+}
+
+/// Where each line of `code` starts, in the UTF-16 units V8 offsets count
+fn line_starts(code: &str) -> Vec<usize> {
+    let mut starts = vec![0];
+    let mut offset = 0;
+    for ch in code.chars() {
+        offset += ch.len_utf16();
+        if ch == '\n' {
+            starts.push(offset);
+        }
+    }
+    starts
+}
+
+/// The 1-based transpiled line, column and count of every line of code in
+/// the compiled `code`
+fn line_hits(code: &str, line_offset: i32, ranges: &[Range]) -> Vec<(usize, usize, u64)> {
+    let mut hits = Vec::new();
+    let mut offset = 0;
+    for (index, text) in code.split('\n').enumerate() {
+        let start = offset;
+        offset += text.encode_utf16().count() + 1;
+
+        let trimmed = text.trim_start();
+        if trimmed.is_empty()
+            || trimmed.starts_with("//")
+            || trimmed.starts_with("/*")
+            || trimmed.starts_with('*')
+        {
+            continue;
+        }
+        let indent = &text[..text.len() - trimmed.len()];
+        let position = start + indent.encode_utf16().count();
+        let line = index as i64 + 1 + line_offset as i64;
+        if line < 1 {
+            continue;
+        }
+
+        // The innermost block around the line's first token
+        let count = ranges
+            .iter()
+            .filter(|range| range.start <= position && position < range.end)
+            .min_by_key(|range| range.end - range.start)
+            .map(|range| range.count);
+        if let Some(count) = count {
+            hits.push((line as usize, indent.chars().count() + 1, count));
+        }
+    }
+    hits
+}
+
+/// Print the totals and write `lcov.info` and `html/` into `dir`
+pub fn report(dir: &Path, print: bool) -> Result<(), String> {
+    let files = FILES.with(|files| std::mem::take(&mut *files.borrow_mut()));
+    if print {
+        print_summary(&files);
+    }
+
+    let html_dir = dir.join("html");
+    fs::create_dir_all(&html_dir)
+        .map_err(|e| format!("Failed to create {}: {}", html_dir.display(), e))?;
+    let lcov_path = dir.join("lcov.info");
+    fs::write(&lcov_path, lcov(&files))
+        .map_err(|e| format!("Failed to write {}: {}", lcov_path.display(), e))?;
+    write_html(&html_dir, &files)?;
+
+    if print {
+        println!(
+            "{} {} {}",
+            "Coverage written to".dimmed(),
+            lcov_path.display().to_string().cyan(),
+            format!("and {}", html_dir.join("index.html").display()).dimmed()
+        );
+    }
+    Ok(())
+}
+
+fn hit_counts<'a>(counts: impl Iterator<Item = &'a u64>) -> (usize, usize) {
+    counts.fold((0, 0), |(hit, total), &count| {
+        (hit + usize::from(count > 0), total + 1)
+    })
+}
+
+fn percent((hit, total): (usize, usize)) -> f64 {
+    if total == 0 {
+        100.0
+    } else {
+        hit as f64 * 100.0 / total as f64
+    }
+}
+
+fn colored_percent(value: f64) -> ColoredString {
+    let text = format!("{:.2}%", value);
+    if value >= 80.0 {
+        text.green()
+    } else if value >= 50.0 {
+        text.yellow()
+    } else {
+        text.red()
+    }
+}
+
+/// Uncovered lines as ranges, e.g. "3, 7-9"
+fn uncovered_lines(lines: &BTreeMap<usize, u64>) -> String {
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+    for (&line, _) in lines.iter().filter(|(_, count)| **count == 0) {
+        match ranges.last_mut() {
+            Some((_, end)) if *end + 1 == line => *end = line,
+            _ => ranges.push((line, line)),
+        }
+    }
+    ranges
+        .iter()
+        .map(|&(start, end)| match start == end {
+            true => start.to_string(),
+            false => format!("{}-{}", start, end),
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn print_summary(files: &BTreeMap<PathBuf, FileCoverage>) {
+    let names: Vec<String> = files
+        .keys()
+        .map(|path| path.display().to_string())
+        .collect();
+    let width = names
+        .iter()
+        .map(|name| name.chars().count())
+        .chain([9])
+        .max()
+        .unwrap_or(9);
+
+    println!();
+    println!("{}", "Coverage".bold());
+    println!(
+        "{}",
+        format!(
+            "{:<width$}  {:>8}  {:>9}  Uncovered lines",
+            "File", "Lines", "Functions"
+        )
+        .dimmed()
+    );
+
+    let (mut lines, mut functions) = ((0, 0), (0, 0));
+    for (name, file) in names.iter().zip(files.values()) {
+        let file_lines = hit_counts(file.lines.values());
+        let file_functions = hit_counts(file.functions.values());
+        lines = (lines.0 + file_lines.0, lines.1 + file_lines.1);
+        functions = (
+            functions.0 + file_functions.0,
+            functions.1 + file_functions.1,
+        );
+        println!(
+            "{:<width$}  {:>8}  {:>9}  {}",
+            name,
+            colored_percent(percent(file_lines)),
+            colored_percent(percent(file_functions)),
+            uncovered_lines(&file.lines).red()
+        );
+    }
+    println!(
+        "{:<width$}  {:>8}  {:>9}",
+        "All files".bold(),
+        colored_percent(percent(lines)).bold(),
+        colored_percent(percent(functions)).bold()
+    );
+}
+
+fn lcov(files: &BTreeMap<PathBuf, FileCoverage>) -> String {
+    let cwd = std::env::current_dir().unwrap_or_default();
+    let mut out = String::new();
+    for (path, file) in files {
+        out.push_str(&format!("TN:\nSF:{}\n", cwd.join(path).display()));
+        for (line, name) in file.functions.keys() {
+            out.push_str(&format!("FN:{},{}\n", line, name));
+        }
+        for ((_, name), count) in &file.functions {
+            out.push_str(&format!("FNDA:{},{}\n", count, name));
+        }
+        let (hit, total) = hit_counts(file.functions.values());
+        out.push_str(&format!("FNF:{}\nFNH:{}\n", total, hit));
+        for (line, count) in &file.lines {
+            out.push_str(&format!("DA:{},{}\n", line, count));
+        }
+        let (hit, total) = hit_counts(file.lines.values());
+        out.push_str(&format!("LF:{}\nLH:{}\nend_of_record\n", total, hit));
+    }
+    out
+}
+
+const HTML_STYLE: &str = "body{font-family:system-ui,sans-serif;margin:2em;color:#222}\
+table{border-collapse:collapse}td,th{padding:2px 10px;text-align:left}\
+th{border-bottom:1px solid #ccc}.num{text-align:right;color:#888}\
+pre{margin:0}.hit{background:#e6ffed}.miss{background:#ffeef0}\
+.high{color:#1a7f37}.medium{color:#9a6700}.low{color:#cf222e}";
+
+fn html_page(title: &str, body: &str) -> String {
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n<style>{}</style>\n</head>\n<body>\n{}</body>\n</html>\n",
+        html_escape(title),
+        HTML_STYLE,
+        body
+    )
+}
+
+fn html_percent(value: f64) -> String {
+    let class = if value >= 80.0 {
+        "high"
+    } else if value >= 50.0 {
+        "medium"
+    } else {
+        "low"
+    };
+    format!("<td class=\"{}\">{:.2}%</td>", class, value)
+}
+
+/// `index.html` with a row per file, and `<file>.html` with its source
+fn write_html(dir: &Path, files: &BTreeMap<PathBuf, FileCoverage>) -> Result<(), String> {
+    let write = |path: &Path, contents: String| {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+        }
+        fs::write(path, contents).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+    };
+
+    let mut rows = String::new();
+    for (path, file) in files {
+        let name = path.display().to_string();
+        let page = format!("{}.html", name);
+        rows.push_str(&format!(
+            "<tr><td><a href=\"{}\">{}</a></td>{}{}</tr>\n",
+            html_escape(&page),
+            html_escape(&name),
+            html_percent(percent(hit_counts(file.lines.values()))),
+            html_percent(percent(hit_counts(file.functions.values())))
+        ));
+
+        let mut source = String::new();
+        for (index, text) in file.source.lines().enumerate() {
+            let (class, count) = match file.lines.get(&(index + 1)) {
+                Some(0) => ("miss", "0".to_string()),
+                Some(count) => ("hit", format!("{}×", count)),
+                None => ("", String::new()),
+            };
+            source.push_str(&format!(
+                "<tr class=\"{}\"><td class=\"num\">{}</td><td class=\"num\">{}</td><td><pre>{}</pre></td></tr>\n",
+                class,
+                index + 1,
+                count,
+                html_escape(text)
+            ));
+        }
+        let index_link = format!("{}index.html", "../".repeat(path.components().count() - 1));
+        let body = format!(
+            "<p><a href=\"{}\">All files</a></p>\n<h1>{}</h1>\n<table>\n{}</table>\n",
+            index_link,
+            html_escape(&name),
+            source
+        );
+        write(&dir.join(&page), html_page(&name, &body))?;
+    }
+
+    let body = format!(
+        "<h1>Coverage</h1>\n<table>\n<tr><th>File</th><th>Lines</th><th>Functions</th></tr>\n{}</table>\n",
+        rows
+    );
+    write(&dir.join("index.html"), html_page("Coverage", &body))
+}
+
+fn html_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_line_hits() {
+        let code = "(function(module) {\nif (a) {\n  // note\n  b()\n}\n})";
+        let header = code.find('\n').unwrap() + 1;
+        let block = code.find("{\n  //").unwrap();
+        let ranges = [
+            Range {
+                start: 1,
+                end: code.len(),
+                count: 1,
+            },
+            Range {
+                start: block,
+                end: code.rfind('}').unwrap(),
+                count: 0,
+            },
+        ];
+        assert!(header < block);
+        // The header is dropped by the -1 offset and the comment skipped
+        assert_eq!(
+            line_hits(code, -1, &ranges),
+            [(1, 1, 1), (3, 3, 0), (4, 1, 0), (5, 1, 1)]
+        );
+    }
+
+    #[test]
+    fn test_uncovered_lines() {
+        let lines = BTreeMap::from([(1, 1), (2, 0), (3, 0), (4, 0), (6, 2), (7, 0), (9, 0)]);
+        assert_eq!(uncovered_lines(&lines), "2-4, 7, 9");
+        assert_eq!(percent(hit_counts(lines.values())), 100.0 * 2.0 / 7.0);
+    }
+}
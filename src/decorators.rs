@@ -0,0 +1,1301 @@
+/// TypeScript decorator and class member transform
+/// Runs before type stripping because `emitDecoratorMetadata` needs the
+/// parameter and property types. Decorators and TypeScript-only modifiers are
+/// blanked out with spaces and the generated calls are appended after the
+/// class on its closing line, so line numbers never move.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DecoratorMode {
+    /// TC39 stage 3 decorators, the TypeScript 5 default
+    Standard,
+    /// `experimentalDecorators` semantics
+    Legacy,
+}
+
+#[derive(Clone, Debug)]
+pub struct DecoratorOptions {
+    pub mode: DecoratorMode,
+    /// Emit `design:type`, `design:paramtypes` and `design:returntype`
+    /// metadata (legacy mode only, like `tsc`)
+    pub emit_metadata: bool,
+    /// `useDefineForClassFields`: when off, fields with only a type
+    /// annotation are dropped instead of being defined as `undefined`
+    pub define_class_fields: bool,
+}
+
+impl Default for DecoratorOptions {
+    fn default() -> Self {
+        Self {
+            mode: DecoratorMode::Standard,
+            emit_metadata: false,
+            define_class_fields: true,
+        }
+    }
+}
+
+const LEGACY_HELPERS: &str = r#"
+function __decorate(decorators, target, key, desc) {
+    var c = arguments.length, r = c < 3 ? target : desc === null ? desc = Object.getOwnPropertyDescriptor(target, key) : desc, d;
+    if (typeof Reflect === "object" && typeof Reflect.decorate === "function") r = Reflect.decorate(decorators, target, key, desc);
+    else for (var i = decorators.length - 1; i >= 0; i--) if (d = decorators[i]) r = (c < 3 ? d(r) : c > 3 ? d(target, key, r) : d(target, key)) || r;
+    return c > 3 && r && Object.defineProperty(target, key, r), r;
+}
+function __metadata(key, value) {
+    if (typeof Reflect === "object" && typeof Reflect.metadata === "function") return Reflect.metadata(key, value);
+}
+function __param(index, decorator) {
+    return function (target, key) { decorator(target, key, index); };
+}
+function __type(get) {
+    try { var type = get(); return typeof type === "function" ? type : Object; } catch (e) { return Object; }
+}"#;
+
+const STANDARD_HELPERS: &str = r#"
+function __esDecorate(klass, classDecorators, members) {
+    var state = __esDecorate.state || (__esDecorate.state = new WeakMap());
+    var data = { fields: {}, instance: [] }, staticInits = [], classInits = [];
+    var metadata = Object.create(null);
+    state.set(klass, data);
+    members.forEach(function (member) {
+        var kind = member[0], name = member[1], isStatic = member[2], decorators = member[3];
+        var target = isStatic ? klass : klass.prototype;
+        var inits = isStatic ? staticInits : data.instance;
+        var desc = Object.getOwnPropertyDescriptor(target, name) || { configurable: true, writable: true };
+        var value = kind === "method" ? desc.value : kind === "getter" ? desc.get : kind === "setter" ? desc.set
+            : kind === "accessor" ? { get: desc.get, set: desc.set } : undefined;
+        var fieldInits = [];
+        for (var i = decorators.length - 1; i >= 0; i--) {
+            var context = {
+                kind: kind, name: name, static: isStatic, private: false, metadata: metadata,
+                access: {
+                    has: function (obj) { return name in obj; },
+                    get: function (obj) { return obj[name]; },
+                    set: function (obj, v) { obj[name] = v; }
+                },
+                addInitializer: function (f) { inits.push(f); }
+            };
+            var result = decorators[i](value, context);
+            if (result === undefined) continue;
+            if (kind === "field") {
+                if (typeof result !== "function") throw new TypeError("Field decorators must return a function or undefined");
+                fieldInits.push(result);
+            } else if (kind === "accessor") {
+                value = { get: result.get || value.get, set: result.set || value.set };
+                if (result.init) fieldInits.push(result.init);
+            } else {
+                if (typeof result !== "function") throw new TypeError("Decorators must return a function or undefined");
+                value = result;
+            }
+        }
+        if (kind === "field") {
+            if (isStatic) klass[name] = fieldInits.reduce(function (v, f) { return f.call(klass, v); }, klass[name]);
+            else data.fields[name] = fieldInits;
+            return;
+        }
+        if (kind === "method") desc.value = value;
+        if (kind === "getter") desc.get = value;
+        if (kind === "setter") desc.set = value;
+        if (kind === "accessor") { desc.get = value.get; desc.set = value.set; data.fields[name] = fieldInits; }
+        Object.defineProperty(target, name, desc);
+    });
+    var original = klass;
+    for (var i = classDecorators.length - 1; i >= 0; i--) {
+        var result = classDecorators[i](klass, {
+            kind: "class", name: original.name, metadata: metadata,
+            addInitializer: function (f) { classInits.push(f); }
+        });
+        if (result !== undefined) {
+            if (typeof result !== "function") throw new TypeError("Class decorators must return a function or undefined");
+            klass = result;
+        }
+    }
+    if (typeof Symbol.metadata === "symbol") Object.defineProperty(klass, Symbol.metadata, { value: metadata, configurable: true });
+    staticInits.forEach(function (f) { f.call(original); });
+    classInits.forEach(function (f) { f.call(klass); });
+    return klass;
+}
+function __esInit(instance, klass) {
+    var data = __esDecorate.state && __esDecorate.state.get(klass);
+    if (data) data.instance.forEach(function (f) { f.call(instance); });
+}
+function __esField(instance, klass, name, value) {
+    var data = __esDecorate.state && __esDecorate.state.get(klass);
+    var inits = (data && data.fields[name]) || [];
+    return inits.reduce(function (v, f) { return f.call(instance, v); }, value);
+}"#;
+
+const TS_MODIFIERS: &[&str] = &[
+    "public",
+    "private",
+    "protected",
+    "readonly",
+    "declare",
+    "abstract",
+    "override",
+];
+const JS_MODIFIERS: &[&str] = &["static", "async", "get", "set", "accessor"];
+
+/// Decorators and TypeScript class syntax rewritten; returns the code and the
+/// helper functions it calls, which must be appended after type stripping
+pub fn transform_decorators(
+    source: &str,
+    options: &DecoratorOptions,
+) -> (String, Option<&'static str>) {
+    if !source.contains("class") {
+        return (source.to_string(), None);
+    }
+
+    let mut transformer = Transformer {
+        chars: source.chars().collect(),
+        insertions: Vec::new(),
+        options,
+        uses_helpers: false,
+    };
+    let end = transformer.chars.len();
+    transformer.scan(0, end);
+
+    let mut insertions = std::mem::take(&mut transformer.insertions);
+    insertions.sort_by_key(|(pos, _)| *pos);
+
+    let mut output = String::with_capacity(source.len());
+    let mut next = insertions.iter().peekable();
+    for (i, ch) in transformer.chars.iter().enumerate() {
+        while let Some((_, text)) = next.next_if(|(pos, _)| *pos == i) {
+            output.push_str(text);
+        }
+        output.push(*ch);
+    }
+    for (_, text) in next {
+        output.push_str(text);
+    }
+
+    let helpers = match (transformer.uses_helpers, options.mode) {
+        (false, _) => None,
+        (true, DecoratorMode::Legacy) => Some(LEGACY_HELPERS),
+        (true, DecoratorMode::Standard) => Some(STANDARD_HELPERS),
+    };
+    (output, helpers)
+}
+
+struct Decorator {
+    expression: String,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum MemberKind {
+    Method,
+    Getter,
+    Setter,
+    Field,
+    Accessor,
+    Constructor,
+}
+
+impl MemberKind {
+    fn name(self) -> &'static str {
+        match self {
+            MemberKind::Method | MemberKind::Constructor => "method",
+            MemberKind::Getter => "getter",
+            MemberKind::Setter => "setter",
+            MemberKind::Field => "field",
+            MemberKind::Accessor => "accessor",
+        }
+    }
+}
+
+struct Param {
+    decorators: Vec<Decorator>,
+    type_annotation: String,
+}
+
+struct Member {
+    kind: MemberKind,
+    /// Property key as a JavaScript expression
+    key: String,
+    is_static: bool,
+    decorators: Vec<Decorator>,
+    params: Vec<Param>,
+    type_annotation: String,
+}
+
+struct Transformer<'a> {
+    chars: Vec<char>,
+    insertions: Vec<(usize, String)>,
+    options: &'a DecoratorOptions,
+    uses_helpers: bool,
+}
+
+fn is_ident_start(ch: char) -> bool {
+    ch.is_alphabetic() || ch == '_' || ch == '$'
+}
+
+fn is_ident_char(ch: char) -> bool {
+    ch.is_alphanumeric() || ch == '_' || ch == '$'
+}
+
+impl Transformer<'_> {
+    fn at(&self, pos: usize) -> char {
+        self.chars.get(pos).copied().unwrap_or('\0')
+    }
+
+    fn text(&self, start: usize, end: usize) -> String {
+        self.chars[start..end].iter().collect()
+    }
+
+    fn blank(&mut self, start: usize, end: usize) {
+        for ch in &mut self.chars[start..end] {
+            if *ch != '\n' {
+                *ch = ' ';
+            }
+        }
+    }
+
+    fn word_at(&self, pos: usize) -> String {
+        let mut end = pos;
+        while end < self.chars.len() && is_ident_char(self.chars[end]) {
+            end += 1;
+        }
+        self.text(pos, end)
+    }
+
+    /// Skip whitespace and comments
+    fn skip_trivia(&self, mut pos: usize) -> usize {
+        loop {
+            let ch = self.at(pos);
+            if ch.is_whitespace() {
+                pos += 1;
+            } else if ch == '/' && self.at(pos + 1) == '/' {
+                while pos < self.chars.len() && self.chars[pos] != '\n' {
+                    pos += 1;
+                }
+            } else if ch == '/' && self.at(pos + 1) == '*' {
+                pos += 2;
+                while pos < self.chars.len() && !(self.at(pos) == '*' && self.at(pos + 1) == '/') {
+                    pos += 1;
+                }
+                pos = (pos + 2).min(self.chars.len());
+            } else {
+                return pos;
+            }
+        }
+    }
+
+    fn skip_string(&self, mut pos: usize) -> usize {
+        let quote = self.chars[pos];
+        pos += 1;
+        while pos < self.chars.len() {
+            match self.chars[pos] {
+                '\\' => pos += 2,
+                ch if ch == quote => return pos + 1,
+                _ => pos += 1,
+            }
+        }
+        pos
+    }
+
+    /// Position after the bracket matching the one at `pos`
+    fn skip_balanced(&self, mut pos: usize) -> usize {
+        let mut depth = 0;
+        while pos < self.chars.len() {
+            let ch = self.chars[pos];
+            match ch {
+                '"' | '\'' | '`' => {
+                    pos = self.skip_string(pos);
+                    continue;
+                }
+                '/' if matches!(self.at(pos + 1), '/' | '*') => {
+                    pos = self.skip_trivia(pos);
+                    continue;
+                }
+                '(' | '[' | '{' => depth += 1,
+                ')' | ']' | '}' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return pos + 1;
+                    }
+                }
+                _ => {}
+            }
+            pos += 1;
+        }
+        pos
+    }
+
+    /// Position after `<...>` type arguments starting at `pos`
+    fn skip_angle(&self, mut pos: usize) -> usize {
+        let mut depth = 0;
+        while pos < self.chars.len() {
+            match self.chars[pos] {
+                '<' => depth += 1,
+                '>' if self.at(pos - 1) != '=' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return pos + 1;
+                    }
+                }
+                '(' | '[' | '{' => {
+                    pos = self.skip_balanced(pos);
+                    continue;
+                }
+                _ => {}
+            }
+            pos += 1;
+        }
+        pos
+    }
+
+    /// Position of the first of `stops` outside brackets, strings and generics
+    fn find_top_level(&self, mut pos: usize, stops: &[char]) -> usize {
+        let mut angle_depth = 0;
+        while pos < self.chars.len() {
+            let ch = self.chars[pos];
+            if angle_depth == 0 && stops.contains(&ch) {
+                // `=>` in a function type is not an initializer
+                if !(ch == '=' && self.at(pos + 1) == '>') {
+                    return pos;
+                }
+                pos += 2;
+                continue;
+            }
+            match ch {
+                '"' | '\'' | '`' => {
+                    pos = self.skip_string(pos);
+                    continue;
+                }
+                '(' | '[' | '{' => {
+                    pos = self.skip_balanced(pos);
+                    continue;
+                }
+                '<' => angle_depth += 1,
+                '>' if angle_depth > 0 && self.at(pos - 1) != '=' => angle_depth -= 1,
+                _ => {}
+            }
+            pos += 1;
+        }
+        pos
+    }
+
+    /// `@name`, `@a.b(args)` or `@(expr)` starting at `pos`
+    fn parse_decorator(&mut self, pos: usize) -> (Decorator, usize) {
+        let mut end = pos + 1;
+        if self.at(end) == '(' {
+            end = self.skip_balanced(end);
+        } else {
+            loop {
+                while is_ident_char(self.at(end)) {
+                    end += 1;
+                }
+                if self.at(end) == '.' && is_ident_start(self.at(end + 1)) {
+                    end += 1;
+                } else {
+                    break;
+                }
+            }
+            if self.at(end) == '<' {
+                end = self.skip_angle(end);
+            }
+            if self.at(end) == '(' {
+                end = self.skip_balanced(end);
+            }
+        }
+
+        let expression = self.text(pos + 1, end);
+        self.blank(pos, end);
+        (Decorator { expression }, end)
+    }
+
+    fn parse_decorators(&mut self, mut pos: usize) -> (Vec<Decorator>, usize) {
+        let mut decorators = Vec::new();
+        loop {
+            let next = self.skip_trivia(pos);
+            if self.at(next) == '@'
+                && (is_ident_start(self.at(next + 1)) || self.at(next + 1) == '(')
+            {
+                let (decorator, end) = self.parse_decorator(next);
+                decorators.push(decorator);
+                pos = end;
+            } else {
+                return (decorators, pos);
+            }
+        }
+    }
+
+    /// `class` followed by a name or body, and not a property like `obj.class`
+    fn is_class_keyword(&self, pos: usize) -> bool {
+        let after = self.skip_trivia(pos + "class".len());
+        let mut before = pos;
+        while before > 0 && self.chars[before - 1].is_whitespace() {
+            before -= 1;
+        }
+        let is_property = before > 0 && self.chars[before - 1] == '.';
+        !is_ident_char(self.at(pos + "class".len()))
+            && !is_property
+            && (is_ident_start(self.at(after)) || self.at(after) == '{')
+    }
+
+    fn is_statement_start(&self, pos: usize) -> bool {
+        let mut before = pos;
+        while before > 0 && self.chars[before - 1].is_whitespace() {
+            before -= 1;
+        }
+        if before == 0 {
+            return true;
+        }
+        let ch = self.chars[before - 1];
+        if matches!(ch, ';' | '}' | '{') {
+            return true;
+        }
+        let mut start = before;
+        while start > 0 && is_ident_char(self.chars[start - 1]) {
+            start -= 1;
+        }
+        matches!(
+            self.text(start, before).as_str(),
+            "export" | "default" | "abstract"
+        )
+    }
+
+    /// Find classes in `start..end`, including ones nested in function bodies
+    fn scan(&mut self, start: usize, end: usize) {
+        let mut pos = start;
+        let mut pending = Vec::new();
+
+        while pos < end {
+            let ch = self.chars[pos];
+            match ch {
+                '"' | '\'' | '`' => {
+                    pos = self.skip_string(pos);
+                    pending.clear();
+                }
+                '/' if matches!(self.at(pos + 1), '/' | '*') => pos = self.skip_trivia(pos),
+                '@' if is_ident_start(self.at(pos + 1)) || self.at(pos + 1) == '(' => {
+                    let (decorators, next) = self.parse_decorators(pos);
+                    pending.extend(decorators);
+                    pos = next;
+                }
+                ch if is_ident_start(ch) && (pos == 0 || !is_ident_char(self.chars[pos - 1])) => {
+                    let word = self.word_at(pos);
+                    let word_end = pos + word.len();
+                    match word.as_str() {
+                        "class" if self.is_class_keyword(pos) => {
+                            let is_statement = self.is_statement_start(pos);
+                            pos = self.class(pos, std::mem::take(&mut pending), is_statement);
+                        }
+                        "abstract" if self.word_at(self.skip_trivia(word_end)) == "class" => {
+                            self.blank(pos, word_end);
+                            pos = word_end;
+                        }
+                        "export" | "default" => pos = word_end,
+                        _ => {
+                            pending.clear();
+                            pos = word_end;
+                        }
+                    }
+                }
+                ch => {
+                    if !ch.is_whitespace() {
+                        pending.clear();
+                    }
+                    pos += 1;
+                }
+            }
+        }
+    }
+
+    /// Transform the class whose `class` keyword is at `pos`, returning the
+    /// position after its body
+    fn class(&mut self, pos: usize, decorators: Vec<Decorator>, is_statement: bool) -> usize {
+        let mut cursor = self.skip_trivia(pos + "class".len());
+
+        let mut name = None;
+        let word = self.word_at(cursor);
+        if !word.is_empty() && word != "extends" && word != "implements" {
+            name = Some(word.clone());
+            cursor += word.len();
+        }
+        let name_end = cursor;
+
+        // Header: generics, `extends Base<T>` and `implements ...`
+        let mut has_extends = false;
+        while cursor < self.chars.len() && self.chars[cursor] != '{' {
+            let ch = self.chars[cursor];
+            if ch == '<' {
+                let end = self.skip_angle(cursor);
+                self.blank(cursor, end);
+                cursor = end;
+            } else if ch == '(' || ch == '[' {
+                cursor = self.skip_balanced(cursor);
+            } else if is_ident_start(ch) {
+                let word = self.word_at(cursor);
+                if word == "extends" {
+                    has_extends = true;
+                }
+                if word == "implements" {
+                    let end = self.find_top_level(cursor, &['{']);
+                    self.blank(cursor, end);
+                    cursor = end;
+                } else {
+                    cursor += word.len();
+                }
+            } else {
+                cursor += 1;
+            }
+        }
+
+        // Class expressions can't be followed by statements, so their
+        // decorators are left in place for V8 to report
+        let class_name = name.clone().unwrap_or_else(|| "_default".to_string());
+        let decorate = if is_statement {
+            Some(class_name.as_str())
+        } else {
+            None
+        };
+
+        let body_start = cursor;
+        let (members, body_end) = self.class_body(body_start, has_extends, decorate);
+
+        let needs_decorating = !decorators.is_empty() || members.iter().any(|m| m.is_decorated());
+        if !needs_decorating || !is_statement {
+            return body_end;
+        }
+
+        if name.is_none() {
+            self.insertions.push((name_end, " _default".to_string()));
+        }
+        let name = class_name;
+
+        self.uses_helpers = true;
+        let statements = match self.options.mode {
+            DecoratorMode::Legacy => self.legacy_statements(&name, &decorators, &members),
+            DecoratorMode::Standard => self.standard_statements(&name, &decorators, &members),
+        };
+        self.insertions.push((body_end, format!(" {}", statements)));
+
+        body_end
+    }
+
+    fn class_body(
+        &mut self,
+        body_start: usize,
+        has_extends: bool,
+        class_name: Option<&str>,
+    ) -> (Vec<Member>, usize) {
+        let mut members = Vec::new();
+        let mut pos = body_start + 1;
+        let body_end;
+        let mut needs_instance_init = false;
+
+        loop {
+            pos = self.skip_trivia(pos);
+            if pos >= self.chars.len() {
+                body_end = pos;
+                break;
+            }
+            match self.chars[pos] {
+                '}' => {
+                    body_end = pos + 1;
+                    break;
+                }
+                ';' => {
+                    pos += 1;
+                    continue;
+                }
+                _ => {}
+            }
+
+            let (member, end) = self.member(pos, has_extends, class_name);
+            pos = end.max(pos + 1);
+            if let Some(member) = member {
+                if !member.is_static
+                    && !member.decorators.is_empty()
+                    && member.kind != MemberKind::Field
+                    && self.options.mode == DecoratorMode::Standard
+                {
+                    needs_instance_init = true;
+                }
+                members.push(member);
+            }
+        }
+
+        if let (true, Some(class_name)) = (needs_instance_init, class_name) {
+            // Runs before any other field initializer of an instance
+            self.insertions.push((
+                body_start + 1,
+                format!(" #__esInit = __esInit(this, {});", class_name),
+            ));
+        }
+
+        (members, body_end)
+    }
+
+    /// Parse one class member starting at `start`; returns the member if it
+    /// can carry decorators and the position after it
+    fn member(
+        &mut self,
+        start: usize,
+        has_extends: bool,
+        class_name: Option<&str>,
+    ) -> (Option<Member>, usize) {
+        let (decorators, mut pos) = self.parse_decorators(start);
+        pos = self.skip_trivia(pos);
+
+        let mut is_static = false;
+        let mut is_declare = false;
+        let mut is_abstract = false;
+        let mut kind = MemberKind::Method;
+        let mut accessor_pos = None;
+
+        // Modifiers, unless the word is really the member name
+        loop {
+            let word = self.word_at(pos);
+            let after = self.skip_trivia(pos + word.len());
+            let is_name = word.is_empty()
+                || matches!(
+                    self.at(after),
+                    '(' | '=' | ';' | ':' | '?' | '!' | '<' | '}'
+                )
+                || self.at(after) == '\0';
+            if is_name
+                || !(TS_MODIFIERS.contains(&word.as_str()) || JS_MODIFIERS.contains(&word.as_str()))
+            {
+                break;
+            }
+
+            match word.as_str() {
+                "static" => {
+                    is_static = true;
+                    if self.at(after) == '{' {
+                        // Static initialization block
+                        let end = self.skip_balanced(after);
+                        self.scan(after + 1, end - 1);
+                        return (None, end);
+                    }
+                }
+                "get" => kind = MemberKind::Getter,
+                "set" => kind = MemberKind::Setter,
+                "accessor" => {
+                    kind = MemberKind::Accessor;
+                    accessor_pos = Some(pos);
+                }
+                "declare" => is_declare = true,
+                "abstract" => is_abstract = true,
+                "async" => {}
+                _ => self.blank(pos, pos + word.len()),
+            }
+            pos = after;
+        }
+
+        if self.at(pos) == '*' {
+            pos = self.skip_trivia(pos + 1);
+        }
+
+        // Member name
+        let name_start = pos;
+        let key = match self.at(pos) {
+            '"' | '\'' => {
+                pos = self.skip_string(pos);
+                self.text(name_start, pos)
+            }
+            '[' => {
+                pos = self.skip_balanced(pos);
+                let after = self.skip_trivia(pos);
+                let inner = self.text(name_start + 1, pos - 1);
+                if self.at(after) == ':' && inner.contains(':') {
+                    // Index signature `[key: string]: T`
+                    let end = self.find_top_level(after, &[';', '\n', '}']);
+                    self.blank(name_start, end);
+                    return (None, end);
+                }
+                inner
+            }
+            '#' => {
+                pos += 1;
+                let word = self.word_at(pos);
+                pos += word.len();
+                format!("#{}", word)
+            }
+            _ => {
+                let word = self.word_at(pos);
+                pos += word.len();
+                if word.chars().all(|c| c.is_ascii_digit()) {
+                    word
+                } else {
+                    format!("\"{}\"", word)
+                }
+            }
+        };
+        let name_end = pos;
+        pos = self.skip_trivia(pos);
+
+        // Optional and definite assignment markers
+        if matches!(self.at(pos), '?' | '!') {
+            self.blank(pos, pos + 1);
+            pos = self.skip_trivia(pos + 1);
+        }
+        if self.at(pos) == '<' {
+            let end = self.skip_angle(pos);
+            self.blank(pos, end);
+            pos = self.skip_trivia(end);
+        }
+
+        if self.at(pos) == '(' {
+            if key == "\"constructor\"" {
+                kind = MemberKind::Constructor;
+            } else if kind == MemberKind::Accessor {
+                kind = MemberKind::Method;
+            }
+            return self.method(start, decorators, kind, key, is_static, pos, has_extends);
+        }
+
+        // Field or auto-accessor
+        if kind != MemberKind::Accessor {
+            kind = MemberKind::Field;
+        }
+        let mut type_annotation = String::new();
+        if self.at(pos) == ':' {
+            let end = self.find_top_level(pos + 1, &['=', ';', '\n', '}']);
+            type_annotation = self.text(pos + 1, end).trim().to_string();
+            pos = end;
+        }
+
+        let has_initializer = self.at(pos) == '=';
+        let mut end = pos;
+        if has_initializer {
+            end = self.find_top_level(pos + 1, &[';', '\n', '}']);
+            // An initializer continues onto the next line while it is open
+            // (e.g. a trailing operator), which find_top_level handles via
+            // bracket skipping
+            self.scan(pos + 1, end);
+        }
+
+        if is_declare || is_abstract {
+            self.blank(start, end);
+            return (None, end);
+        }
+
+        let is_private = key.starts_with('#');
+        if !self.options.define_class_fields
+            && !has_initializer
+            && !type_annotation.is_empty()
+            && kind == MemberKind::Field
+        {
+            // Type-only fields are not emitted, so they can't shadow
+            // prototype properties
+            let blank_end = if self.at(end) == ';' { end + 1 } else { end };
+            self.blank(start, blank_end);
+        }
+
+        if kind == MemberKind::Accessor {
+            // `accessor x = 1` -> private storage plus a getter/setter pair
+            if let Some(accessor_pos) = accessor_pos {
+                self.blank(accessor_pos, accessor_pos + "accessor".len());
+            }
+            let storage = format!(
+                "#{}_accessor",
+                key.trim_matches('"').trim_start_matches('#')
+            );
+            self.blank(name_start, name_end);
+            self.insertions.push((name_start, storage.clone()));
+            let static_prefix = if is_static { "static " } else { "" };
+            let property = if key.starts_with('"') {
+                key.trim_matches('"').to_string()
+            } else {
+                format!("[{}]", key)
+            };
+            let accessors = format!(
+                " {s}get {p}() {{ return this.{f}; }} {s}set {p}(value) {{ this.{f} = value; }}",
+                s = static_prefix,
+                p = property,
+                f = storage
+            );
+            let insert_at = if self.at(end) == ';' { end + 1 } else { end };
+            self.insertions.push((insert_at, accessors));
+        }
+
+        let wraps_initializer = self.options.mode == DecoratorMode::Standard
+            && !is_static
+            && !is_private
+            && !decorators.is_empty();
+        if let Some(class_name) = class_name.filter(|_| wraps_initializer) {
+            // Route the initial value through the decorators' initializers
+            let call = format!("__esField(this, {}, {}, ", class_name, key);
+            if has_initializer {
+                let value_start = self.skip_trivia(pos + 1);
+                self.insertions.push((value_start, call));
+                self.insertions.push((end, ")".to_string()));
+            } else {
+                self.insertions.push((end, format!(" = {}void 0)", call)));
+            }
+        }
+
+        if is_private && !decorators.is_empty() {
+            return (None, end);
+        }
+
+        (
+            Some(Member {
+                kind,
+                key,
+                is_static,
+                decorators,
+                params: Vec::new(),
+                type_annotation,
+            }),
+            end,
+        )
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn method(
+        &mut self,
+        start: usize,
+        decorators: Vec<Decorator>,
+        kind: MemberKind,
+        key: String,
+        is_static: bool,
+        params_start: usize,
+        has_extends: bool,
+    ) -> (Option<Member>, usize) {
+        let params_end = self.skip_balanced(params_start);
+        let (params, properties) = self.params(
+            params_start + 1,
+            params_end - 1,
+            kind == MemberKind::Constructor,
+        );
+
+        let mut pos = self.skip_trivia(params_end);
+        let mut type_annotation = String::new();
+        if self.at(pos) == ':' {
+            let end = self.find_top_level(pos + 1, &['{', ';', '\n', '}']);
+            type_annotation = self.text(pos + 1, end).trim().to_string();
+            pos = self.skip_trivia(end);
+        }
+
+        if self.at(pos) != '{' {
+            // Overload signature or abstract method
+            let end = if self.at(pos) == ';' { pos + 1 } else { pos };
+            self.blank(start, end);
+            return (None, end);
+        }
+
+        let body_end = self.skip_balanced(pos);
+        self.scan(pos + 1, body_end - 1);
+
+        if !properties.is_empty() {
+            let assignments: String = properties
+                .iter()
+                .map(|p| format!(" this.{} = {};", p, p))
+                .collect();
+            let insert_at = if has_extends {
+                self.find_super_call(pos + 1, body_end - 1)
+                    .unwrap_or(pos + 1)
+            } else {
+                pos + 1
+            };
+            self.insertions.push((insert_at, assignments));
+        }
+
+        if key.starts_with('#') {
+            return (None, body_end);
+        }
+
+        (
+            Some(Member {
+                kind,
+                key,
+                is_static,
+                decorators,
+                params,
+                type_annotation,
+            }),
+            body_end,
+        )
+    }
+
+    /// Position after the `super(...)` call statement in a constructor body
+    fn find_super_call(&self, start: usize, end: usize) -> Option<usize> {
+        let mut pos = start;
+        while pos < end {
+            match self.chars[pos] {
+                '"' | '\'' | '`' => pos = self.skip_string(pos),
+                's' if self.word_at(pos) == "super"
+                    && !is_ident_char(self.at(pos.wrapping_sub(1))) =>
+                {
+                    let call = self.skip_trivia(pos + 5);
+                    if self.at(call) != '(' {
+                        pos += 5;
+                        continue;
+                    }
+                    let mut after = self.skip_balanced(call);
+                    let next = self.skip_trivia(after);
+                    if self.at(next) == ';' {
+                        after = next + 1;
+                    }
+                    return Some(after);
+                }
+                _ => pos += 1,
+            }
+        }
+        None
+    }
+
+    /// Parse a parameter list, returning parameter info and the names of
+    /// constructor parameter properties
+    fn params(
+        &mut self,
+        start: usize,
+        end: usize,
+        is_constructor: bool,
+    ) -> (Vec<Param>, Vec<String>) {
+        let mut params = Vec::new();
+        let mut properties = Vec::new();
+        let mut pos = start;
+
+        while pos < end {
+            let (decorators, after) = self.parse_decorators(pos);
+            pos = self.skip_trivia(after);
+            if pos >= end {
+                break;
+            }
+
+            let mut is_property = false;
+            loop {
+                let word = self.word_at(pos);
+                let after = self.skip_trivia(pos + word.len());
+                let is_modifier = matches!(
+                    word.as_str(),
+                    "public" | "private" | "protected" | "readonly" | "override"
+                ) && (is_ident_start(self.at(after))
+                    || matches!(self.at(after), '{' | '['));
+                if !is_modifier {
+                    break;
+                }
+                is_property = true;
+                self.blank(pos, pos + word.len());
+                pos = after;
+            }
+
+            let name = self.word_at(pos);
+            let param_end = self.find_top_level(pos, &[',']).min(end);
+            let mut type_annotation = String::new();
+            let colon = self.find_top_level(pos, &[':', ',', '=']).min(param_end);
+            if self.at(colon) == ':' {
+                let type_end = self.find_top_level(colon + 1, &[',', '=']).min(param_end);
+                type_annotation = self.text(colon + 1, type_end).trim().to_string();
+            }
+
+            if is_property && is_constructor && !name.is_empty() {
+                properties.push(name);
+            }
+            params.push(Param {
+                decorators,
+                type_annotation,
+            });
+            pos = param_end + 1;
+        }
+
+        (params, properties)
+    }
+
+    fn legacy_statements(
+        &self,
+        name: &str,
+        decorators: &[Decorator],
+        members: &[Member],
+    ) -> String {
+        let mut statements = Vec::new();
+
+        for member in members {
+            if !member.is_decorated() || member.kind == MemberKind::Constructor {
+                continue;
+            }
+
+            let mut list: Vec<String> = member
+                .decorators
+                .iter()
+                .map(|d| d.expression.clone())
+                .collect();
+            list.extend(param_decorators(&member.params));
+
+            if self.options.emit_metadata {
+                match member.kind {
+                    MemberKind::Field | MemberKind::Accessor => {
+                        list.push(metadata(
+                            "design:type",
+                            &serialize_type(&member.type_annotation),
+                        ));
+                    }
+                    MemberKind::Getter => {
+                        list.push(metadata(
+                            "design:type",
+                            &serialize_type(&member.type_annotation),
+                        ));
+                    }
+                    MemberKind::Setter => {
+                        let param_type = member
+                            .params
+                            .first()
+                            .map(|p| p.type_annotation.as_str())
+                            .unwrap_or("");
+                        list.push(metadata("design:type", &serialize_type(param_type)));
+                        list.push(metadata("design:paramtypes", &param_types(&member.params)));
+                    }
+                    MemberKind::Method | MemberKind::Constructor => {
+                        list.push(metadata("design:type", "Function"));
+                        list.push(metadata("design:paramtypes", &param_types(&member.params)));
+                        list.push(metadata(
+                            "design:returntype",
+                            &serialize_type(&member.type_annotation),
+                        ));
+                    }
+                }
+            }
+
+            let target = if member.is_static {
+                name.to_string()
+            } else {
+                format!("{}.prototype", name)
+            };
+            let descriptor = if member.kind == MemberKind::Field {
+                "void 0"
+            } else {
+                "null"
+            };
+            statements.push(format!(
+                "__decorate([{}], {}, {}, {});",
+                list.join(", "),
+                target,
+                member.key,
+                descriptor
+            ));
+        }
+
+        let constructor = members.iter().find(|m| m.kind == MemberKind::Constructor);
+        let mut class_list: Vec<String> = decorators.iter().map(|d| d.expression.clone()).collect();
+        if let Some(constructor) = constructor {
+            class_list.extend(param_decorators(&constructor.params));
+        }
+        if !class_list.is_empty() {
+            if self.options.emit_metadata {
+                let params = constructor
+                    .map(|c| param_types(&c.params))
+                    .unwrap_or_else(|| "[]".to_string());
+                class_list.push(metadata("design:paramtypes", &params));
+            }
+            statements.push(format!(
+                "{} = __decorate([{}], {});",
+                name,
+                class_list.join(", "),
+                name
+            ));
+        }
+
+        statements.join(" ")
+    }
+
+    fn standard_statements(
+        &self,
+        name: &str,
+        decorators: &[Decorator],
+        members: &[Member],
+    ) -> String {
+        let member_list: Vec<String> = members
+            .iter()
+            .filter(|m| !m.decorators.is_empty() && m.kind != MemberKind::Constructor)
+            .map(|m| {
+                let list: Vec<String> = m.decorators.iter().map(|d| d.expression.clone()).collect();
+                format!(
+                    "[\"{}\", {}, {}, [{}]]",
+                    m.kind.name(),
+                    m.key,
+                    m.is_static,
+                    list.join(", ")
+                )
+            })
+            .collect();
+        let class_list: Vec<String> = decorators.iter().map(|d| d.expression.clone()).collect();
+
+        format!(
+            "{} = __esDecorate({}, [{}], [{}]);",
+            name,
+            name,
+            class_list.join(", "),
+            member_list.join(", ")
+        )
+    }
+}
+
+impl Member {
+    fn is_decorated(&self) -> bool {
+        !self.decorators.is_empty() || self.params.iter().any(|p| !p.decorators.is_empty())
+    }
+}
+
+fn param_decorators(params: &[Param]) -> Vec<String> {
+    params
+        .iter()
+        .enumerate()
+        .flat_map(|(i, param)| {
+            param
+                .decorators
+                .iter()
+                .map(move |d| format!("__param({}, {})", i, d.expression))
+        })
+        .collect()
+}
+
+fn metadata(key: &str, value: &str) -> String {
+    format!("__metadata(\"{}\", {})", key, value)
+}
+
+fn param_types(params: &[Param]) -> String {
+    let types: Vec<String> = params
+        .iter()
+        .map(|p| serialize_type(&p.type_annotation))
+        .collect();
+    format!("[{}]", types.join(", "))
+}
+
+/// Runtime value `emitDecoratorMetadata` records for a type annotation
+fn serialize_type(type_annotation: &str) -> String {
+    let t = type_annotation.trim();
+    let t = t
+        .strip_prefix('(')
+        .and_then(|t| t.strip_suffix(')'))
+        .unwrap_or(t)
+        .trim();
+
+    if t.is_empty() {
+        return "Object".to_string();
+    }
+
+    // Unions collapse to their single non-nullable member, if any
+    if t.contains('|') && !t.contains('(') && !t.contains('<') {
+        let members: Vec<&str> = t
+            .split('|')
+            .map(str::trim)
+            .filter(|m| !m.is_empty() && !matches!(*m, "null" | "undefined"))
+            .collect();
+        let serialized: Vec<String> = members.iter().map(|m| serialize_type(m)).collect();
+        return match serialized.first() {
+            Some(first) if serialized.iter().all(|s| s == first) => first.clone(),
+            _ => "Object".to_string(),
+        };
+    }
+
+    if t.ends_with("[]")
+        || t.starts_with('[')
+        || t.starts_with("Array<")
+        || t.starts_with("ReadonlyArray<")
+    {
+        return "Array".to_string();
+    }
+    if t.contains("=>") {
+        return "Function".to_string();
+    }
+    if t.starts_with('"') || t.starts_with('\'') || t.starts_with('`') {
+        return "String".to_string();
+    }
+    if t.chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_digit() || c == '-')
+    {
+        return "Number".to_string();
+    }
+
+    match t {
+        "string" => "String".to_string(),
+        "number" => "Number".to_string(),
+        "boolean" | "true" | "false" => "Boolean".to_string(),
+        "bigint" => "__type(() => BigInt)".to_string(),
+        "symbol" => "__type(() => Symbol)".to_string(),
+        "void" | "undefined" | "null" | "never" => "void 0".to_string(),
+        "any" | "unknown" | "object" => "Object".to_string(),
+        _ if t.starts_with('{')
+            || t.contains('&')
+            || t.starts_with("typeof ")
+            || t.starts_with("keyof ") =>
+        {
+            "Object".to_string()
+        }
+        _ => {
+            // A named type; it may only exist at compile time (an interface),
+            // so fall back to Object when nothing with that name exists
+            let name: String = t
+                .chars()
+                .take_while(|c| is_ident_char(*c) || *c == '.')
+                .collect();
+            match name.as_str() {
+                "" => "Object".to_string(),
+                "Function" | "Object" | "String" | "Number" | "Boolean" | "Promise" | "Date"
+                | "Map" | "Set" => name,
+                _ => format!("__type(() => {})", name),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn legacy() -> DecoratorOptions {
+        DecoratorOptions {
+            mode: DecoratorMode::Legacy,
+            emit_metadata: true,
+            define_class_fields: false,
+        }
+    }
+
+    #[test]
+    fn test_legacy_class_and_members() {
+        let source = r#"@Injectable()
+class UserService {
+    @Inject(DB) db: Database;
+    constructor(@Inject(LOGGER) private readonly logger: Logger, name: string) {}
+    @Get("/users")
+    list(limit: number): Promise<User[]> { return []; }
+}"#;
+        let (code, helpers) = transform_decorators(source, &legacy());
+        let lines: Vec<&str> = code.lines().collect();
+
+        assert_eq!(lines.len(), source.lines().count());
+        assert!(!code.contains('@'));
+        assert!(!code.contains("private") && !code.contains("readonly"));
+        assert!(!code.contains("db: Database"));
+        assert!(lines[3].contains("{ this.logger = logger;}"));
+        assert!(lines[6].contains(
+            r#"__decorate([Inject(DB), __metadata("design:type", __type(() => Database))], UserService.prototype, "db", void 0);"#
+        ));
+        assert!(lines[6].contains(
+            r#"__decorate([Get("/users"), __metadata("design:type", Function), __metadata("design:paramtypes", [Number]), __metadata("design:returntype", Promise)], UserService.prototype, "list", null);"#
+        ));
+        assert!(lines[6].contains(
+            r#"UserService = __decorate([Injectable(), __param(0, Inject(LOGGER)), __metadata("design:paramtypes", [__type(() => Logger), String])], UserService);"#
+        ));
+        assert!(helpers.unwrap().contains("function __decorate("));
+    }
+
+    #[test]
+    fn test_standard_decorators() {
+        let source = "@sealed\nexport class Counter {\n  @observable count = 0;\n  @bound increment() { this.count++; }\n}";
+        let (code, helpers) = transform_decorators(source, &DecoratorOptions::default());
+
+        assert!(code.contains("count = __esField(this, Counter, \"count\", 0);"));
+        assert!(code.contains("#__esInit = __esInit(this, Counter);"));
+        assert!(code.ends_with(
+            r#"} Counter = __esDecorate(Counter, [sealed], [["field", "count", false, [observable]], ["method", "increment", false, [bound]]]);"#
+        ));
+        assert!(helpers.unwrap().contains("function __esDecorate("));
+    }
+
+    #[test]
+    fn test_class_syntax_without_decorators() {
+        let source = "abstract class Shape<T> extends Base<T> implements Drawable {\n  abstract area(): number;\n  constructor(public name: string) { super(); }\n}";
+        let (code, helpers) = transform_decorators(source, &DecoratorOptions::default());
+
+        assert!(helpers.is_none());
+        assert!(!code.contains("abstract") && !code.contains("implements") && !code.contains('<'));
+        assert!(code.contains("{ super(); this.name = name; }"));
+    }
+}
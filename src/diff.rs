@@ -0,0 +1,111 @@
+/// Line diffs for assertion failures
+/// Lines only in the expected value are marked `-` in green and lines only
+/// in the actual value `+` in red, with long unchanged stretches collapsed.
+use colored::*;
+
+/// Unchanged lines shown around each change
+const CONTEXT: usize = 3;
+
+#[derive(Debug, PartialEq)]
+enum Line<'a> {
+    Same(&'a str),
+    Expected(&'a str),
+    Actual(&'a str),
+}
+
+/// `expected` and `actual` line by line, with a legend
+pub fn line_diff(expected: &str, actual: &str) -> String {
+    let lines = diff(expected, actual);
+    let near_change: Vec<bool> = (0..lines.len())
+        .map(|i| {
+            let start = i.saturating_sub(CONTEXT);
+            let end = (i + CONTEXT + 1).min(lines.len());
+            lines[start..end]
+                .iter()
+                .any(|line| !matches!(line, Line::Same(_)))
+        })
+        .collect();
+
+    let mut out = format!("{}\n{}\n\n", "- Expected".green(), "+ Actual".red());
+    let mut collapsed = false;
+    for (line, near) in lines.iter().zip(near_change) {
+        match line {
+            Line::Same(_) if !near => {
+                if !collapsed {
+                    out.push_str(&format!("{}\n", "  ...".dimmed()));
+                    collapsed = true;
+                }
+                continue;
+            }
+            Line::Same(text) => out.push_str(&format!("  {}\n", text.dimmed())),
+            Line::Expected(text) => out.push_str(&format!("{}\n", format!("- {}", text).green())),
+            Line::Actual(text) => out.push_str(&format!("{}\n", format!("+ {}", text).red())),
+        }
+        collapsed = false;
+    }
+    out.trim_end().to_string()
+}
+
+/// Longest common subsequence of lines, expected lines before actual ones
+fn diff<'a>(expected: &'a str, actual: &'a str) -> Vec<Line<'a>> {
+    let old: Vec<&str> = expected.lines().collect();
+    let new: Vec<&str> = actual.lines().collect();
+
+    // common[i][j]: LCS length of old[i..] and new[j..]
+    let mut common = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            common[i][j] = if old[i] == new[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+
+    let mut lines = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            lines.push(Line::Same(old[i]));
+            i += 1;
+            j += 1;
+        } else if i < old.len() && (j == new.len() || common[i + 1][j] >= common[i][j + 1]) {
+            lines.push(Line::Expected(old[i]));
+            i += 1;
+        } else {
+            lines.push(Line::Actual(new[j]));
+            j += 1;
+        }
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff() {
+        let expected = "{\n  \"a\": 1,\n  \"b\": 2\n}";
+        let actual = "{\n  \"a\": 1,\n  \"b\": 3\n}";
+        assert_eq!(
+            diff(expected, actual),
+            [
+                Line::Same("{"),
+                Line::Same("  \"a\": 1,"),
+                Line::Expected("  \"b\": 2"),
+                Line::Actual("  \"b\": 3"),
+                Line::Same("}"),
+            ]
+        );
+
+        colored::control::set_override(false);
+        let long: String = (0..20).map(|n| format!("{}\n", n)).collect();
+        let changed = long.replace("15\n", "fifteen\n");
+        let rendered = line_diff(&long, &changed);
+        assert!(rendered.starts_with("- Expected\n+ Actual\n\n  ...\n  12\n"));
+        assert!(rendered.contains("- 15\n+ fifteen\n  16\n"));
+        assert!(!rendered.contains("  19"));
+    }
+}
@@ -1,7 +1,7 @@
 use std::collections::HashMap;
 use std::env;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 pub struct EnvParser {
     vars: HashMap<String, String>,
@@ -124,7 +124,9 @@ impl EnvParser {
         self.expand_variables(&result)
     }
 
-    fn expand_variables(&self, s: &str) -> String {
+    /// Substitute `${VAR}` and `$VAR` from the parsed variables, then the
+    /// process environment; unknown variables become empty
+    pub fn expand_variables(&self, s: &str) -> String {
         let mut result = String::new();
         let mut chars = s.chars().peekable();
 
@@ -195,19 +197,26 @@ impl EnvParser {
     }
 }
 
-pub fn load_env_files() -> Result<(), String> {
+pub const DEFAULT_ENV_FILES: [&str; 4] =
+    [".env.local", ".env", ".env.development", ".env.production"];
+
+/// Load `files` (the defaults in the current directory when `None`)
+pub fn load_env_files(files: Option<&[PathBuf]>) -> Result<(), String> {
     let mut parser = EnvParser::new();
 
-    let env_files = [".env.local", ".env", ".env.development", ".env.production"];
+    let env_files: Vec<PathBuf> = match files {
+        Some(files) => files.to_vec(),
+        None => DEFAULT_ENV_FILES.iter().map(PathBuf::from).collect(),
+    };
 
     for file in &env_files {
-        if Path::new(file).exists() {
+        if file.exists() {
             match parser.load_file(file) {
                 Ok(()) => {
-                    println!("Loaded environment from {}", file);
+                    println!("Loaded environment from {}", file.display());
                 }
                 Err(e) => {
-                    eprintln!("Warning: Failed to load {}: {}", file, e);
+                    eprintln!("Warning: Failed to load {}: {}", file.display(), e);
                 }
             }
         }
@@ -0,0 +1,29 @@
+/// `rode fmt`
+/// Formats with Prettier from the project's node_modules (or PATH), so the
+/// project's own `.prettierrc` and `.prettierignore` apply.
+use crate::checker::find_tool;
+use std::env;
+use std::process::Command;
+
+/// Format `paths` (the current directory when empty) in place, or with
+/// `check` only list unformatted files. Returns whether everything was
+/// formatted.
+pub fn format(paths: &[String], check: bool) -> Result<bool, String> {
+    let cwd = env::current_dir().map_err(|e| format!("Cannot read current directory: {}", e))?;
+    let prettier = find_tool(&cwd, "prettier").ok_or_else(|| {
+        "Prettier not found. Install it with `npm install --save-dev prettier`".to_string()
+    })?;
+
+    let mut command = Command::new(&prettier);
+    command.arg(if check { "--check" } else { "--write" });
+    if paths.is_empty() {
+        command.arg(".");
+    } else {
+        command.args(paths);
+    }
+
+    let status = command
+        .status()
+        .map_err(|e| format!("Failed to run {}: {}", prettier.display(), e))?;
+    Ok(status.success())
+}
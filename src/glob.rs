@@ -0,0 +1,86 @@
+/// Glob patterns for config file paths
+/// `*` and `?` stay within one path segment, `**` spans any number of
+/// segments and `{a,b}` matches either alternative. A pattern without a `/`
+/// matches a file name in any directory, like in `.gitignore`.
+use regex::Regex;
+use std::path::Path;
+
+pub struct Glob {
+    regex: Regex,
+}
+
+impl Glob {
+    pub fn new(pattern: &str) -> Result<Glob, String> {
+        let trimmed = pattern.trim_start_matches("./");
+        let anchored = if trimmed.contains('/') {
+            trimmed.trim_start_matches('/').to_string()
+        } else {
+            format!("**/{}", trimmed)
+        };
+
+        let mut regex = String::from("^");
+        let mut chars = anchored.chars().peekable();
+        let mut in_group = false;
+        while let Some(ch) = chars.next() {
+            match ch {
+                '*' if chars.peek() == Some(&'*') => {
+                    chars.next();
+                    if chars.peek() == Some(&'/') {
+                        chars.next();
+                        regex.push_str("(?:.*/)?");
+                    } else {
+                        regex.push_str(".*");
+                    }
+                }
+                '*' => regex.push_str("[^/]*"),
+                '?' => regex.push_str("[^/]"),
+                '{' if !in_group => {
+                    in_group = true;
+                    regex.push_str("(?:");
+                }
+                '}' if in_group => {
+                    in_group = false;
+                    regex.push(')');
+                }
+                ',' if in_group => regex.push('|'),
+                _ => regex.push_str(&regex::escape(&ch.to_string())),
+            }
+        }
+        if in_group {
+            return Err(format!("Unclosed '{{' in pattern '{}'", pattern));
+        }
+        // A directory pattern also covers everything inside it
+        regex.push_str("(?:/.*)?$");
+
+        Ok(Glob {
+            regex: Regex::new(&regex)
+                .map_err(|e| format!("Invalid pattern '{}': {}", pattern, e))?,
+        })
+    }
+
+    /// Match a path relative to the directory the pattern is rooted at
+    pub fn matches(&self, relative: &Path) -> bool {
+        let path = relative.to_string_lossy().replace('\\', "/");
+        self.regex.is_match(path.trim_start_matches("./"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glob_matches() {
+        let matches =
+            |pattern: &str, path: &str| Glob::new(pattern).unwrap().matches(Path::new(path));
+
+        assert!(matches("src/**/*.ts", "src/app.ts"));
+        assert!(matches("src/**/*.ts", "src/lib/deep/util.ts"));
+        assert!(!matches("src/*.ts", "src/lib/util.ts"));
+        assert!(matches("*.{js,ts}", "lib/index.js"));
+        assert!(matches("node_modules", "node_modules/pkg/index.js"));
+        assert!(matches("./config/?.json", "config/a.json"));
+        assert!(!matches("dist/**", "src/dist.ts"));
+        assert!(Glob::new("src/{a,b").is_err());
+    }
+}
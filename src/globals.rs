@@ -0,0 +1,132 @@
+/// The Rode globals, short of the per-process `setup_process_state`
+/// Shared with build.rs, which installs them in the startup snapshot, so it
+/// only uses rusty_v8. The native functions come from the `natives` table;
+/// the JavaScript halves of `Rode.assert` and `Rode.mock` are factories
+/// compiled without an origin, so their frames stay out of error stacks.
+use rusty_v8 as v8;
+
+const ASSERT_JS: &str = include_str!("utils/assert.js");
+const MOCK_JS: &str = include_str!("utils/mock.js");
+
+/// Install the globals, with `install_natives` from an expanded `natives` table
+pub fn install(scope: &mut v8::HandleScope, install_natives: fn(&mut v8::HandleScope)) {
+    // Rode's console replaces V8's rather than adding to it
+    let global = scope.get_current_context().global(scope);
+    let console_key = v8::String::new(scope, "console").unwrap();
+    let console = v8::Object::new(scope);
+    global.set(scope, console_key.into(), console.into());
+
+    install_natives(scope);
+
+    // The assert factory takes the natives the table put on `Rode.assert`
+    let assert = object(scope, "Rode.assert");
+    let natives: Vec<_> = ["inspect", "diff", "storeSnapshot"]
+        .into_iter()
+        .map(|name| {
+            let key = v8::String::new(scope, name).unwrap();
+            assert.get(scope, key.into()).unwrap()
+        })
+        .collect();
+    let assert = run_factory(scope, ASSERT_JS, &natives);
+    set(scope, "Rode", "assert", assert);
+
+    let mock = run_factory(scope, MOCK_JS, &[]);
+    set(scope, "Rode", "mock", mock);
+
+    let sep = v8::String::new(scope, if cfg!(windows) { "\\" } else { "/" }).unwrap();
+    set(scope, "Rode.path", "sep", sep.into());
+    let delimiter = v8::String::new(scope, if cfg!(windows) { ";" } else { ":" }).unwrap();
+    set(scope, "Rode.path", "delimiter", delimiter.into());
+
+    // Loaded modules by canonical path, like Node's require.cache
+    let cache = v8::Object::new(scope);
+    set(scope, "require", "cache", cache.into());
+}
+
+/// The object at a dotted path from the global object, created where
+/// missing; `""` is the global object itself
+pub fn object<'s>(scope: &mut v8::HandleScope<'s>, path: &str) -> v8::Local<'s, v8::Object> {
+    let mut object = scope.get_current_context().global(scope);
+    for name in path.split('.').filter(|name| !name.is_empty()) {
+        let key = v8::String::new(scope, name).unwrap();
+        object = match object
+            .get(scope, key.into())
+            .filter(|value| value.is_object())
+            .and_then(|value| value.to_object(scope))
+        {
+            Some(existing) => existing,
+            None => {
+                let new_obj = v8::Object::new(scope);
+                object.set(scope, key.into(), new_obj.into());
+                new_obj
+            }
+        };
+    }
+    object
+}
+
+fn set(scope: &mut v8::HandleScope, path: &str, name: &str, value: v8::Local<v8::Value>) {
+    let object = object(scope, path);
+    let key = v8::String::new(scope, name).unwrap();
+    object.set(scope, key.into(), value);
+}
+
+/// Compile a `(function (...) { ... })` script and call it with `args`
+fn run_factory<'s>(
+    scope: &mut v8::HandleScope<'s>,
+    source: &str,
+    args: &[v8::Local<v8::Value>],
+) -> v8::Local<'s, v8::Value> {
+    let source = v8::String::new(scope, source).unwrap();
+    let factory = v8::Script::compile(scope, source, None)
+        .and_then(|script| script.run(scope))
+        .and_then(|factory| v8::Local::<v8::Function>::try_from(factory).ok())
+        .expect("the built-in module compiles");
+    let recv = v8::undefined(scope).into();
+    factory
+        .call(scope, recv, args)
+        .expect("the built-in module initializes")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::runtime::Runtime;
+
+    #[test]
+    fn test_fake_timers() {
+        let script = r#"
+            const check = (ok, what) => { if (!ok) throw new Error('failed: ' + what) }
+            const { timers } = Rode.mock
+            const RealDate = Date
+            const fired = []
+
+            timers.install({ now: 1000 })
+            setTimeout(() => fired.push('timeout'), 50)
+            const interval = setInterval(() => fired.push('interval'), 20)
+            check(Date.now() === 1000, 'Date.now() starts at the given time')
+            check(new Date().getTime() === 1000, 'new Date() reads the clock')
+            check(new Date(5).getTime() === 5, 'new Date(ms) keeps its argument')
+            check(new Date() instanceof RealDate, 'fake dates are dates')
+
+            timers.tick(45)
+            check(fired.join() === 'interval,interval', 'tick fires the timers due')
+            check(Date.now() === 1045, 'tick moves Date.now()')
+            check(Date() === new RealDate(1045).toString(), 'Date() reads the clock')
+
+            timers.tick(5)
+            check(fired.join() === 'interval,interval,timeout', 'timers fire in order')
+            clearInterval(interval)
+            timers.runAll()
+            check(timers.pending() === 0, 'cleared timers never fire')
+
+            timers.restore()
+            check(Date === RealDate, 'restore() puts back the real Date')
+            check(Math.abs(Date.now() - RealDate.now()) < 1000, 'the real clock is back')
+        "#;
+
+        let mut runtime = Runtime::new();
+        runtime
+            .execute_with_filename(script, "fake_timers.js")
+            .unwrap();
+    }
+}
@@ -0,0 +1,313 @@
+/// Hot module replacement for `--hot`
+/// The watch-mode supervisor appends changed module paths to an updates
+/// file (see `UPDATES_FILE_VAR`), and the child applies them in its isolate
+/// whenever the script is idle: after its top-level code has run, or while
+/// `Rode.http.serve` waits for requests. A change propagates from the
+/// module to the modules that required it until it reaches one that called
+/// `import.meta.hot.accept()`. Those modules run their `dispose` handlers,
+/// are dropped from `require.cache` and evaluated again. When a change
+/// reaches the entry script instead, or a module declined updates, the child
+/// exits with `RESTART_EXIT_CODE` and the supervisor restarts it.
+use crate::modules;
+use colored::*;
+use rusty_v8 as v8;
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+
+/// Set by watch mode on the child process when `--hot` is on: the file
+/// changed module paths are appended to, one per line
+pub const UPDATES_FILE_VAR: &str = "RODE_HOT_UPDATES";
+
+/// Exit code a hot child uses to ask the supervisor for a full restart
+pub const RESTART_EXIT_CODE: i32 = 75;
+
+/// What a module instance registered through `import.meta.hot`
+#[derive(Default)]
+struct HotModule {
+    accepted: bool,
+    declined: bool,
+    on_accept: Vec<v8::Global<v8::Function>>,
+    on_dispose: Vec<v8::Global<v8::Function>>,
+}
+
+thread_local! {
+    static MODULES: RefCell<HashMap<PathBuf, HotModule>> = RefCell::new(HashMap::new());
+    /// `import.meta.hot.data` handed from a disposed module to its next run
+    static DATA: RefCell<HashMap<PathBuf, v8::Global<v8::Object>>> =
+        RefCell::new(HashMap::new());
+    /// How much of the updates file has been applied
+    static UPDATES_READ: Cell<usize> = const { Cell::new(0) };
+}
+
+pub fn is_enabled() -> bool {
+    std::env::var_os(UPDATES_FILE_VAR).is_some()
+}
+
+/// `import.meta.hot` for the module at `path`
+pub fn create<'s>(scope: &mut v8::HandleScope<'s>, path: &Path) -> v8::Local<'s, v8::Object> {
+    MODULES.with(|modules| {
+        modules
+            .borrow_mut()
+            .insert(path.to_path_buf(), HotModule::default())
+    });
+
+    let hot = v8::Object::new(scope);
+    let id = v8::String::new(scope, &path.to_string_lossy()).unwrap();
+    set_method(scope, hot, "accept", hot_accept, id);
+    set_method(scope, hot, "dispose", hot_dispose, id);
+    set_method(scope, hot, "decline", hot_decline, id);
+
+    let data = match DATA.with(|data| data.borrow_mut().remove(path)) {
+        Some(data) => v8::Local::new(scope, data),
+        None => v8::Object::new(scope),
+    };
+    let data_key = v8::String::new(scope, "data").unwrap();
+    hot.set(scope, data_key.into(), data.into());
+    hot
+}
+
+/// A method on `hot` that knows which module it belongs to through `id`
+fn set_method<'s>(
+    scope: &mut v8::HandleScope<'s>,
+    hot: v8::Local<v8::Object>,
+    name: &str,
+    callback: impl v8::MapFnTo<v8::FunctionCallback>,
+    id: v8::Local<'s, v8::String>,
+) {
+    let key = v8::String::new(scope, name).unwrap();
+    let function = v8::Function::builder(callback)
+        .data(id.into())
+        .build(scope)
+        .unwrap();
+    hot.set(scope, key.into(), function.into());
+}
+
+/// `import.meta.hot.accept(callback?)`: take updates to this module and the
+/// modules it requires; the callback gets the new exports
+fn hot_accept(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    _rv: v8::ReturnValue,
+) {
+    let callback = match args.length() {
+        0 => None,
+        _ => match v8::Local::<v8::Function>::try_from(args.get(0)) {
+            Ok(callback) => Some(v8::Global::new(scope, callback)),
+            Err(_) => {
+                let error =
+                    v8::String::new(scope, "import.meta.hot.accept() takes a callback").unwrap();
+                scope.throw_exception(error.into());
+                return;
+            }
+        },
+    };
+    with_module(scope, &args, |module| {
+        module.accepted = true;
+        module.on_accept.extend(callback);
+    });
+}
+
+/// `import.meta.hot.dispose(callback)`: clean up before the module is
+/// replaced; the callback gets `data` to hand state to the next instance
+fn hot_dispose(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    _rv: v8::ReturnValue,
+) {
+    let Ok(callback) = v8::Local::<v8::Function>::try_from(args.get(0)) else {
+        let error = v8::String::new(scope, "import.meta.hot.dispose() takes a callback").unwrap();
+        scope.throw_exception(error.into());
+        return;
+    };
+    let callback = v8::Global::new(scope, callback);
+    with_module(scope, &args, |module| module.on_dispose.push(callback));
+}
+
+/// `import.meta.hot.decline()`: changes to this module always restart
+fn hot_decline(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    _rv: v8::ReturnValue,
+) {
+    with_module(scope, &args, |module| module.declined = true);
+}
+
+fn with_module(
+    scope: &mut v8::HandleScope,
+    args: &v8::FunctionCallbackArguments,
+    update: impl FnOnce(&mut HotModule),
+) {
+    let Some(id) = args.data() else {
+        return;
+    };
+    let path = PathBuf::from(id.to_rust_string_lossy(scope));
+    MODULES.with(|modules| {
+        if let Some(module) = modules.borrow_mut().get_mut(&path) {
+            update(module);
+        }
+    });
+}
+
+/// Apply the updates the supervisor sent since the last call. When they
+/// can't be applied, exit so the supervisor restarts the script.
+pub fn poll(scope: &mut v8::HandleScope) {
+    let changed = take_updates();
+    if changed.is_empty() {
+        return;
+    }
+
+    match update(scope, &changed) {
+        Ok(updated) => {
+            for path in updated {
+                println!(
+                    "{} {}",
+                    "[hot]".bright_green().bold(),
+                    display(&path).cyan()
+                );
+            }
+        }
+        Err(reason) => {
+            println!("{} {}", "[hot]".bright_yellow().bold(), reason.yellow());
+            crate::profiler::finish_cpu_profile();
+            process::exit(RESTART_EXIT_CODE);
+        }
+    }
+}
+
+/// Module paths appended to the updates file since the last call
+fn take_updates() -> BTreeSet<PathBuf> {
+    let Some(file) = std::env::var_os(UPDATES_FILE_VAR) else {
+        return BTreeSet::new();
+    };
+    let Ok(contents) = fs::read_to_string(file) else {
+        return BTreeSet::new();
+    };
+
+    // Only complete lines, in case the supervisor is still writing
+    let read = UPDATES_READ.with(Cell::get).min(contents.len());
+    let end = contents.rfind('\n').map_or(0, |end| end + 1).max(read);
+    UPDATES_READ.with(|updates_read| updates_read.set(end));
+    contents[read..end].lines().map(PathBuf::from).collect()
+}
+
+/// Replace the changed modules, returning the modules evaluated again, or
+/// why a full restart is needed
+fn update(
+    scope: &mut v8::HandleScope,
+    changed: &BTreeSet<PathBuf>,
+) -> Result<Vec<PathBuf>, String> {
+    // Walk up from each changed module to the modules accepting the change
+    let mut invalidated = BTreeSet::new();
+    let mut boundaries = BTreeSet::new();
+    let mut pending: Vec<PathBuf> = changed.iter().cloned().collect();
+    while let Some(path) = pending.pop() {
+        if !invalidated.insert(path.clone()) {
+            continue;
+        }
+        let (accepted, declined) = MODULES.with(|modules| {
+            modules
+                .borrow()
+                .get(&path)
+                .map_or((false, false), |module| (module.accepted, module.declined))
+        });
+        if declined {
+            return Err(format!(
+                "{} declined hot updates, restarting",
+                display(&path)
+            ));
+        }
+        if accepted {
+            boundaries.insert(path);
+            continue;
+        }
+
+        let importers = modules::importers(&path);
+        if importers.is_empty() || importers.contains(&None) {
+            return Err(format!(
+                "No module accepts the update to {}, restarting",
+                display(&path)
+            ));
+        }
+        pending.extend(importers.into_iter().flatten());
+    }
+
+    // Dispose every module that holds on to the old code
+    let mut on_accept = HashMap::new();
+    for path in &invalidated {
+        let module = MODULES.with(|modules| modules.borrow_mut().remove(path));
+        let module = module.unwrap_or_default();
+
+        let data = v8::Object::new(scope);
+        let recv = v8::undefined(scope).into();
+        for callback in &module.on_dispose {
+            let callback = v8::Local::new(scope, callback);
+            let mut try_catch = v8::TryCatch::new(scope);
+            if callback
+                .call(&mut try_catch, recv, &[data.into()])
+                .is_none()
+            {
+                let error = exception_message(&mut try_catch);
+                return Err(format!(
+                    "Dispose handler of {} threw {}, restarting",
+                    display(path),
+                    error
+                ));
+            }
+        }
+        let data = v8::Global::new(scope, data);
+        DATA.with(|saved| saved.borrow_mut().insert(path.clone(), data));
+
+        modules::invalidate(scope, path);
+        on_accept.insert(path.clone(), module.on_accept);
+    }
+
+    // Evaluate the accepting modules again, which requires the invalidated
+    // modules again too, and hand the new exports to their callbacks
+    for path in &boundaries {
+        let mut try_catch = v8::TryCatch::new(scope);
+        // Another accepting module may have required it again already
+        let exports = match modules::cached_exports(&mut try_catch, path) {
+            Some(exports) => exports,
+            None => match modules::reload(&mut try_catch, path) {
+                Some(exports) => exports,
+                None => {
+                    let error = exception_message(&mut try_catch);
+                    return Err(format!("{} threw {}, restarting", display(path), error));
+                }
+            },
+        };
+        let recv = v8::undefined(&mut try_catch).into();
+        for callback in on_accept.remove(path).unwrap_or_default() {
+            let callback = v8::Local::new(&mut try_catch, callback);
+            if callback.call(&mut try_catch, recv, &[exports]).is_none() {
+                let error = exception_message(&mut try_catch);
+                return Err(format!(
+                    "Accept handler of {} threw {}, restarting",
+                    display(path),
+                    error
+                ));
+            }
+        }
+    }
+
+    Ok(boundaries.into_iter().collect())
+}
+
+fn exception_message(try_catch: &mut v8::TryCatch<v8::HandleScope>) -> String {
+    try_catch
+        .exception()
+        .map(|exception| exception.to_rust_string_lossy(try_catch))
+        .unwrap_or_else(|| "an error".to_string())
+}
+
+/// `path` relative to the current directory when it is inside it
+fn display(path: &Path) -> String {
+    let cwd = std::env::current_dir().unwrap_or_default();
+    path.strip_prefix(&cwd)
+        .unwrap_or(path)
+        .display()
+        .to_string()
+}
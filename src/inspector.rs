@@ -0,0 +1,686 @@
+/// V8's inspector: in-process protocol sessions and the debugger server
+/// The runtime speaks the DevTools protocol to its own isolate through
+/// `call`, e.g. to collect precise coverage, and gets answers directly.
+/// With `--inspect` a DevTools client connects over a local WebSocket as
+/// well. Its messages are dispatched from an isolate interrupt while
+/// JavaScript runs, from `poll` while the runtime idles, and from a blocking
+/// loop while the debugger is paused.
+use crate::cli::{Inspect, InspectMode};
+use crate::websocket::{self, Message, MessageReader};
+use colored::*;
+use rusty_v8 as v8;
+use serde_json::{Value, json};
+use std::cell::Cell;
+use std::collections::{HashMap, VecDeque};
+use std::ffi::c_void;
+use std::io::BufReader;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use v8::inspector::{
+    ChannelBase, ChannelImpl, StringBuffer, StringView, V8Inspector, V8InspectorClientBase,
+    V8InspectorClientImpl, V8InspectorSession,
+};
+
+/// Every context of an isolate joins this group
+const CONTEXT_GROUP_ID: i32 = 1;
+
+/// Where the next runtime listens for a debugger, and the script's name
+static LISTEN: Mutex<Option<(Inspect, String)>> = Mutex::new(None);
+
+thread_local! {
+    /// The debugger session of the runtime on this thread, for V8's
+    /// callbacks and interrupts to reach
+    static REMOTE: Cell<*const Remote> = const { Cell::new(std::ptr::null()) };
+}
+
+/// Have the next runtime created listen for a debugger
+pub fn set_listen(inspect: Inspect, script: &str) {
+    *LISTEN.lock().unwrap() = Some((inspect, script.to_string()));
+}
+
+pub fn take_listen() -> Option<(Inspect, String)> {
+    LISTEN.lock().unwrap().take()
+}
+
+/// Dispatch the debugger's messages while the runtime idles
+pub fn poll() {
+    with_remote(Remote::dispatch_pending);
+}
+
+fn with_remote(f: impl FnOnce(&Remote)) {
+    let remote = REMOTE.with(Cell::get);
+    // SAFETY: the pointer is set while the boxed `Remote` lives, and only
+    // used on the thread that owns it
+    if let Some(remote) = unsafe { remote.as_ref() } {
+        f(remote)
+    }
+}
+
+struct Client {
+    base: V8InspectorClientBase,
+}
+
+impl V8InspectorClientImpl for Client {
+    fn base(&self) -> &V8InspectorClientBase {
+        &self.base
+    }
+
+    fn base_mut(&mut self) -> &mut V8InspectorClientBase {
+        &mut self.base
+    }
+
+    fn run_message_loop_on_pause(&mut self, _context_group_id: i32) {
+        with_remote(Remote::run_paused);
+    }
+
+    fn quit_message_loop_on_pause(&mut self) {
+        with_remote(|remote| remote.paused.set(false));
+    }
+
+    fn run_if_waiting_for_debugger(&mut self, _context_group_id: i32) {
+        with_remote(|remote| remote.waiting.set(false));
+    }
+}
+
+/// Where the inspector delivers responses for `call`
+struct Channel {
+    base: ChannelBase,
+    responses: HashMap<i32, String>,
+}
+
+impl ChannelImpl for Channel {
+    fn base(&self) -> &ChannelBase {
+        &self.base
+    }
+
+    fn base_mut(&mut self) -> &mut ChannelBase {
+        &mut self.base
+    }
+
+    fn send_response(&mut self, call_id: i32, message: v8::UniquePtr<StringBuffer>) {
+        if let Some(message) = message.as_ref() {
+            self.responses.insert(call_id, message.string().to_string());
+        }
+    }
+
+    fn send_notification(&mut self, _message: v8::UniquePtr<StringBuffer>) {}
+
+    fn flush_protocol_notifications(&mut self) {}
+}
+
+/// Sends everything the inspector says to the debugger
+struct RemoteChannel {
+    base: ChannelBase,
+    shared: Arc<Shared>,
+}
+
+impl ChannelImpl for RemoteChannel {
+    fn base(&self) -> &ChannelBase {
+        &self.base
+    }
+
+    fn base_mut(&mut self) -> &mut ChannelBase {
+        &mut self.base
+    }
+
+    fn send_response(&mut self, _call_id: i32, message: v8::UniquePtr<StringBuffer>) {
+        if let Some(message) = message.as_ref() {
+            self.shared.send(&message.string().to_string());
+        }
+    }
+
+    fn send_notification(&mut self, message: v8::UniquePtr<StringBuffer>) {
+        if let Some(message) = message.as_ref() {
+            self.shared.send(&message.string().to_string());
+        }
+    }
+
+    fn flush_protocol_notifications(&mut self) {}
+}
+
+enum Incoming {
+    Message(String),
+    Disconnected,
+}
+
+/// State shared between the runtime and the server's threads
+#[derive(Default)]
+struct Shared {
+    state: Mutex<State>,
+    arrived: Condvar,
+}
+
+#[derive(Default)]
+struct State {
+    incoming: VecDeque<Incoming>,
+    /// The attached debugger's connection
+    writer: Option<TcpStream>,
+}
+
+impl Shared {
+    fn push(&self, incoming: Incoming) {
+        self.state.lock().unwrap().incoming.push_back(incoming);
+        self.arrived.notify_all();
+    }
+
+    fn try_next(&self) -> Option<Incoming> {
+        self.state.lock().unwrap().incoming.pop_front()
+    }
+
+    /// Block until the debugger says something
+    fn next(&self) -> Incoming {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if let Some(incoming) = state.incoming.pop_front() {
+                return incoming;
+            }
+            state = self.arrived.wait(state).unwrap();
+        }
+    }
+
+    fn send(&self, message: &str) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(writer) = &mut state.writer
+            && websocket::write_text(writer, message).is_err()
+        {
+            state.writer = None;
+        }
+    }
+}
+
+/// The debugger's session; the flags are cells because V8 calls back into
+/// it while it dispatches
+struct Remote {
+    _session: v8::UniqueRef<V8InspectorSession>,
+    /// The session, for dispatching from inside V8's callbacks
+    session_ptr: *mut V8InspectorSession,
+    channel: Box<RemoteChannel>,
+    /// Set while the pause loop runs
+    paused: Cell<bool>,
+    /// Set until a debugger sends `Runtime.runIfWaitingForDebugger`
+    waiting: Cell<bool>,
+    /// Pause on the first statement once the debugger has attached
+    break_on_start: Cell<bool>,
+    /// Set while pending messages are dispatched, so interrupts don't nest
+    dispatching: Cell<bool>,
+}
+
+impl Remote {
+    fn dispatch(&self, message: &str) {
+        let message: Vec<u16> = message.encode_utf16().collect();
+        // SAFETY: the session lives as long as `self`, and V8 allows
+        // dispatching from its own callbacks, e.g. while paused inside
+        // another message
+        unsafe { (*self.session_ptr).dispatch_protocol_message(StringView::from(&message[..])) };
+    }
+
+    fn handle(&self, incoming: Incoming) {
+        match incoming {
+            Incoming::Message(message) => self.dispatch(&message),
+            // Forget the debugger's breakpoints, which also resumes, so the
+            // script doesn't stop with nobody to continue it
+            Incoming::Disconnected => {
+                self.dispatch(r#"{"id":-1,"method":"Debugger.disable"}"#);
+                self.dispatch(r#"{"id":-2,"method":"Runtime.disable"}"#);
+            }
+        }
+    }
+
+    fn dispatch_pending(&self) {
+        if self.dispatching.replace(true) {
+            return;
+        }
+        while let Some(incoming) = self.channel.shared.try_next() {
+            self.handle(incoming);
+        }
+        self.dispatching.set(false);
+    }
+
+    fn run_paused(&self) {
+        self.paused.set(true);
+        while self.paused.get() {
+            let incoming = self.channel.shared.next();
+            self.handle(incoming);
+        }
+    }
+}
+
+impl Drop for Remote {
+    fn drop(&mut self) {
+        REMOTE.with(|remote| remote.set(std::ptr::null()));
+    }
+}
+
+/// Fields drop in order: the sessions before the inspector, all of them
+/// before the client and channel they point to
+pub struct Inspector {
+    session: v8::UniqueRef<V8InspectorSession>,
+    remote: Option<Box<Remote>>,
+    inspector: v8::UniqueRef<V8Inspector>,
+    channel: Box<Channel>,
+    _client: Box<Client>,
+    isolate: v8::IsolateHandle,
+    next_id: i32,
+}
+
+impl Inspector {
+    pub fn new(isolate: &mut v8::Isolate) -> Self {
+        let mut client = Box::new(Client {
+            base: V8InspectorClientBase::new::<Client>(),
+        });
+        let mut channel = Box::new(Channel {
+            base: ChannelBase::new::<Channel>(),
+            responses: HashMap::new(),
+        });
+        let mut inspector = V8Inspector::create(isolate, &mut *client);
+        let session = inspector.connect(CONTEXT_GROUP_ID, &mut *channel, StringView::empty());
+        Self {
+            session,
+            remote: None,
+            inspector,
+            channel,
+            _client: client,
+            isolate: isolate.thread_safe_handle(),
+            next_id: 1,
+        }
+    }
+
+    /// Make `context` visible to the protocol, e.g. to `Runtime.evaluate`
+    pub fn context_created(&mut self, context: v8::Local<v8::Context>, name: &str) {
+        let name: Vec<u16> = name.encode_utf16().collect();
+        self.inspector
+            .context_created(context, CONTEXT_GROUP_ID, StringView::from(&name[..]));
+    }
+
+    /// Call a protocol method and return its result
+    pub fn call(&mut self, method: &str, params: Value) -> Result<Value, String> {
+        let id = self.next_id;
+        self.next_id += 1;
+        let message = json!({ "id": id, "method": method, "params": params }).to_string();
+        // 8-bit string views are Latin-1, so send UTF-16
+        let message: Vec<u16> = message.encode_utf16().collect();
+        self.session
+            .dispatch_protocol_message(StringView::from(&message[..]));
+
+        let response = self
+            .channel
+            .responses
+            .remove(&id)
+            .ok_or_else(|| format!("{} did not respond", method))?;
+        let mut response: Value = serde_json::from_str(&response)
+            .map_err(|e| format!("Invalid response to {}: {}", method, e))?;
+        if let Some(error) = response.get("error") {
+            let message = error["message"].as_str().unwrap_or("unknown error");
+            return Err(format!("{} failed: {}", method, message));
+        }
+        Ok(response["result"].take())
+    }
+
+    pub fn is_listening(&self) -> bool {
+        self.remote.is_some()
+    }
+
+    /// Serve the DevTools protocol on `inspect.address` for `script`,
+    /// returning the address, whose port the system picks for port 0
+    pub fn listen(&mut self, inspect: &Inspect, script: &str) -> Result<SocketAddr, String> {
+        let listener = TcpListener::bind(&inspect.address)
+            .map_err(|e| format!("Cannot listen on {}: {}", inspect.address, e))?;
+        let address = listener
+            .local_addr()
+            .map_err(|e| format!("Cannot listen on {}: {}", inspect.address, e))?;
+
+        let shared = Arc::new(Shared::default());
+        let mut channel = Box::new(RemoteChannel {
+            base: ChannelBase::new::<RemoteChannel>(),
+            shared: shared.clone(),
+        });
+        let mut session =
+            self.inspector
+                .connect(CONTEXT_GROUP_ID, &mut *channel, StringView::empty());
+        let session_ptr: *mut V8InspectorSession = &mut *session;
+        let remote = Box::new(Remote {
+            _session: session,
+            session_ptr,
+            channel,
+            paused: Cell::new(false),
+            waiting: Cell::new(inspect.mode != InspectMode::Run),
+            break_on_start: Cell::new(inspect.mode == InspectMode::Break),
+            dispatching: Cell::new(false),
+        });
+        REMOTE.with(|pointer| pointer.set(&*remote));
+        self.remote = Some(remote);
+
+        let target = Target::new(address.to_string(), script);
+        eprintln!(
+            "{} {}",
+            "Debugger listening on".cyan(),
+            target.websocket_url().bold()
+        );
+        eprintln!(
+            "{}",
+            "Open chrome://inspect in Chrome, or attach another DevTools client".dimmed()
+        );
+        let isolate = self.isolate.clone();
+        thread::spawn(move || serve(listener, target, shared, isolate));
+        Ok(address)
+    }
+
+    /// With `--inspect-brk` and `--inspect-wait`, block until a debugger
+    /// has attached and told the script to run; once per runtime
+    pub fn wait_for_debugger(&mut self) {
+        let Some(remote) = &self.remote else {
+            return;
+        };
+        if remote.waiting.get() {
+            eprintln!("{}", "Waiting for the debugger to attach...".dimmed());
+        }
+        while remote.waiting.get() {
+            let incoming = remote.channel.shared.next();
+            remote.handle(incoming);
+        }
+        if remote.break_on_start.replace(false) {
+            let reason: Vec<u16> = "Break on start".encode_utf16().collect();
+            // SAFETY: nothing else uses the session while the runtime waits here
+            unsafe {
+                (*remote.session_ptr).schedule_pause_on_next_statement(
+                    StringView::from(&reason[..]),
+                    StringView::empty(),
+                )
+            };
+        }
+    }
+}
+
+/// Wrap the Rode console in `context` so every call also reaches V8's own
+/// console, which reports it to the debugger
+pub fn forward_console(scope: &mut v8::HandleScope, v8_console: v8::Local<v8::Value>) {
+    let global = scope.get_current_context().global(scope);
+    let key = v8::String::new(scope, "console").unwrap();
+    let Some(console) = global.get(scope, key.into()) else {
+        return;
+    };
+    let source = v8::String::new(scope, FORWARD_CONSOLE_JS).unwrap();
+    let Some(forward) = v8::Script::compile(scope, source, None)
+        .and_then(|script| script.run(scope))
+        .and_then(|forward| v8::Local::<v8::Function>::try_from(forward).ok())
+    else {
+        return;
+    };
+    let recv = v8::undefined(scope).into();
+    forward.call(scope, recv, &[v8_console, console]);
+}
+
+const FORWARD_CONSOLE_JS: &str = r#"(function (v8Console, console) {
+  if (!v8Console || !console) return;
+  for (const key of Object.keys(console)) {
+    const own = console[key];
+    const report = v8Console[key];
+    if (typeof own !== 'function' || typeof report !== 'function') continue;
+    console[key] = function (...args) {
+      report.apply(v8Console, args);
+      return own.apply(this, args);
+    };
+  }
+})"#;
+
+/// What the HTTP endpoints describe, for DevTools to find the script
+struct Target {
+    id: String,
+    address: String,
+    title: String,
+    url: String,
+}
+
+impl Target {
+    fn new(address: String, script: &str) -> Self {
+        let path = std::env::current_dir()
+            .map(|cwd| cwd.join(script))
+            .unwrap_or_else(|_| script.into());
+        Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            address,
+            title: script.to_string(),
+            url: format!("file://{}", path.display()),
+        }
+    }
+
+    fn websocket_url(&self) -> String {
+        format!("ws://{}/{}", self.address, self.id)
+    }
+
+    fn to_json(&self) -> Value {
+        let frontend = format!(
+            "devtools://devtools/bundled/js_app.html?experiments=true&v8only=true&ws={}/{}",
+            self.address, self.id
+        );
+        json!({
+            "description": "rode instance",
+            "devtoolsFrontendUrl": frontend,
+            "devtoolsFrontendUrlCompat": frontend,
+            "id": self.id,
+            "title": self.title,
+            "type": "node",
+            "url": self.url,
+            "webSocketDebuggerUrl": self.websocket_url(),
+        })
+    }
+}
+
+fn serve(listener: TcpListener, target: Target, shared: Arc<Shared>, isolate: v8::IsolateHandle) {
+    let target = Arc::new(target);
+    for stream in listener.incoming().flatten() {
+        let target = target.clone();
+        let shared = shared.clone();
+        let isolate = isolate.clone();
+        thread::spawn(move || {
+            let _ = handle_connection(stream, &target, &shared, &isolate);
+        });
+    }
+}
+
+/// Answer the `/json` endpoints, or attach a debugger over a WebSocket
+fn handle_connection(
+    mut stream: TcpStream,
+    target: &Target,
+    shared: &Shared,
+    isolate: &v8::IsolateHandle,
+) -> std::io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let request = websocket::read_request(&mut reader)?;
+    // A page on another site can resolve its own name to this address
+    // (DNS rebinding), but can't make its requests say `localhost`
+    if !is_allowed_host(request.header("host")) {
+        let message = "Host header must be localhost or an IP address";
+        return websocket::write_response(&mut stream, "403 Forbidden", "text/plain", message);
+    }
+    let json = "application/json; charset=UTF-8";
+    let key = match (request.path.as_str(), request.websocket_key()) {
+        ("/json" | "/json/list", _) => {
+            let body = Value::Array(vec![target.to_json()]).to_string();
+            return websocket::write_response(&mut stream, "200 OK", json, &body);
+        }
+        ("/json/version", _) => {
+            let body = json!({
+                "Browser": format!("rode/{}", env!("CARGO_PKG_VERSION")),
+                "Protocol-Version": "1.3",
+            });
+            return websocket::write_response(&mut stream, "200 OK", json, &body.to_string());
+        }
+        (path, Some(key)) if path.strip_prefix('/') == Some(target.id.as_str()) => key,
+        _ => {
+            return websocket::write_response(&mut stream, "404 Not Found", "text/plain", "");
+        }
+    };
+
+    {
+        let mut state = shared.state.lock().unwrap();
+        if state.writer.is_some() {
+            let message = "A debugger is already attached";
+            return websocket::write_response(&mut stream, "409 Conflict", "text/plain", message);
+        }
+        websocket::write_handshake(&mut stream, key)?;
+        state.writer = Some(stream.try_clone()?);
+    }
+    eprintln!("{}", "Debugger attached.".cyan());
+
+    let mut reader = MessageReader::new(reader);
+    loop {
+        match reader.read_message() {
+            Ok(Message::Text(message)) => {
+                shared.push(Incoming::Message(message));
+                isolate.request_interrupt(on_interrupt, std::ptr::null_mut());
+            }
+            Ok(Message::Ping(payload)) => {
+                let mut state = shared.state.lock().unwrap();
+                if let Some(writer) = &mut state.writer {
+                    let _ = websocket::write_pong(writer, &payload);
+                }
+            }
+            Ok(Message::Binary(_) | Message::Pong) => {}
+            Ok(Message::Close) | Err(_) => break,
+        }
+    }
+
+    let mut state = shared.state.lock().unwrap();
+    if let Some(mut writer) = state.writer.take() {
+        let _ = websocket::write_close(&mut writer);
+    }
+    drop(state);
+    shared.push(Incoming::Disconnected);
+    isolate.request_interrupt(on_interrupt, std::ptr::null_mut());
+    eprintln!("{}", "Debugger detached.".dimmed());
+    Ok(())
+}
+
+/// Whether a request's Host header names this machine directly: `localhost`
+/// or an IP address, with or without a port
+fn is_allowed_host(host: Option<&str>) -> bool {
+    let Some(host) = host else {
+        return false;
+    };
+    let name = match host.strip_prefix('[') {
+        Some(rest) => match rest.split_once(']') {
+            Some((address, port)) if port.is_empty() || port.starts_with(':') => {
+                return address.parse::<Ipv6Addr>().is_ok();
+            }
+            _ => return false,
+        },
+        None => host.split_once(':').map_or(host, |(name, _)| name),
+    };
+    name.eq_ignore_ascii_case("localhost") || name.parse::<Ipv4Addr>().is_ok()
+}
+
+/// Runs on the isolate's thread while JavaScript runs
+extern "C" fn on_interrupt(_isolate: &mut v8::Isolate, _data: *mut c_void) {
+    poll();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::Runtime;
+    use std::io::{BufRead, Read, Write};
+    use std::time::Duration;
+
+    const CLIENT_TIMEOUT: Duration = Duration::from_secs(10);
+
+    #[test]
+    fn test_is_allowed_host() {
+        assert!(is_allowed_host(Some("localhost:9229")));
+        assert!(is_allowed_host(Some("127.0.0.1:9229")));
+        assert!(is_allowed_host(Some("[::1]:9229")));
+        assert!(is_allowed_host(Some("192.168.1.20")));
+        assert!(!is_allowed_host(Some("evil.example.com:9229")));
+        assert!(!is_allowed_host(Some("localhost.evil.example.com")));
+        assert!(!is_allowed_host(Some("[::1]evil")));
+        assert!(!is_allowed_host(None));
+    }
+
+    /// What a DevTools client does: find the target on `/json/list`,
+    /// upgrade to its WebSocket and evaluate `1 + 2` over the protocol
+    fn devtools_client(address: SocketAddr) -> Value {
+        let mut stream = TcpStream::connect(address).unwrap();
+        stream.set_read_timeout(Some(CLIENT_TIMEOUT)).unwrap();
+        write!(
+            stream,
+            "GET /json/list HTTP/1.1\r\nHost: {}\r\n\r\n",
+            address
+        )
+        .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        let (_, body) = response.split_once("\r\n\r\n").unwrap();
+        let targets: Value = serde_json::from_str(body).unwrap();
+        assert_eq!(targets[0]["title"], "app.js");
+        let url = targets[0]["webSocketDebuggerUrl"].as_str().unwrap();
+        let path = url.strip_prefix(&format!("ws://{}", address)).unwrap();
+
+        let stream = TcpStream::connect(address).unwrap();
+        stream.set_read_timeout(Some(CLIENT_TIMEOUT)).unwrap();
+        let mut writer = stream.try_clone().unwrap();
+        write!(
+            writer,
+            "GET {} HTTP/1.1\r\nHost: {}\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\r\n",
+            path, address
+        )
+        .unwrap();
+        let mut reader = BufReader::new(stream);
+        let mut head = String::new();
+        while !head.ends_with("\r\n\r\n") {
+            assert!(reader.read_line(&mut head).unwrap() > 0);
+        }
+        assert!(head.starts_with("HTTP/1.1 101"));
+
+        let mut messages = MessageReader::new(reader);
+        let mut until = |done: &dyn Fn(&Value) -> bool| loop {
+            match messages.read_message().unwrap() {
+                Message::Text(text) => {
+                    let message: Value = serde_json::from_str(&text).unwrap();
+                    if done(&message) {
+                        return message;
+                    }
+                }
+                other => panic!("unexpected {:?}", other),
+            }
+        };
+
+        websocket::write_text(&mut writer, r#"{"id":1,"method":"Runtime.enable"}"#).unwrap();
+        let created = until(&|message| message["method"] == "Runtime.executionContextCreated");
+        let context_id = created["params"]["context"]["id"].clone();
+        let evaluate = json!({
+            "id": 2,
+            "method": "Runtime.evaluate",
+            "params": { "expression": "1 + 2", "contextId": context_id },
+        });
+        websocket::write_text(&mut writer, &evaluate.to_string()).unwrap();
+        until(&|message| message["id"] == 2)
+    }
+
+    #[test]
+    fn test_devtools_client_evaluates() {
+        drop(Runtime::new());
+        let isolate = &mut v8::Isolate::new(Default::default());
+        let mut inspector = Inspector::new(isolate);
+        let scope = &mut v8::HandleScope::new(isolate);
+        let context = v8::Context::new(scope);
+        inspector.context_created(context, "rode");
+
+        let inspect = Inspect {
+            mode: InspectMode::Run,
+            address: "127.0.0.1:0".to_string(),
+        };
+        let address = inspector.listen(&inspect, "app.js").unwrap();
+        assert_ne!(address.port(), 0);
+
+        // The runtime idles here, so it dispatches the client's messages
+        let client = thread::spawn(move || devtools_client(address));
+        while !client.is_finished() {
+            poll();
+            thread::sleep(Duration::from_millis(5));
+        }
+        let response = client.join().unwrap();
+        assert_eq!(response["result"]["result"]["value"], 3);
+    }
+}
@@ -0,0 +1,670 @@
+/// JSX transform - turns JSX elements into factory calls
+/// Supports the classic runtime (`React.createElement`, `h`, ...) and the
+/// automatic `jsx-runtime`. Newlines inside elements are kept so line numbers
+/// and source maps of the surrounding code stay valid.
+use regex::Regex;
+use std::sync::OnceLock;
+
+static PRAGMA_REGEX: OnceLock<Regex> = OnceLock::new();
+
+/// Stands in for JSX text while other transforms run, followed by its index
+const TEXT_MASK: &str = "__jsx_text_";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum JsxRuntime {
+    Classic,
+    Automatic,
+}
+
+#[derive(Clone, Debug)]
+pub struct JsxOptions {
+    pub runtime: JsxRuntime,
+    /// Element factory for the classic runtime
+    pub factory: String,
+    /// Fragment component for the classic runtime
+    pub fragment: String,
+    /// Module providing `<import_source>/jsx-runtime` for the automatic runtime
+    pub import_source: String,
+}
+
+impl Default for JsxOptions {
+    fn default() -> Self {
+        Self {
+            runtime: JsxRuntime::Classic,
+            factory: "React.createElement".to_string(),
+            fragment: "React.Fragment".to_string(),
+            import_source: "react".to_string(),
+        }
+    }
+}
+
+impl JsxOptions {
+    /// Apply `@jsx`, `@jsxFrag`, `@jsxRuntime` and `@jsxImportSource` comments
+    pub fn with_pragmas(mut self, source: &str) -> Self {
+        let pragma_regex = PRAGMA_REGEX.get_or_init(|| {
+            Regex::new(r"@(jsx|jsxFrag|jsxRuntime|jsxImportSource)\s+([A-Za-z0-9_$.@/-]+)").unwrap()
+        });
+
+        for comment in comments(source) {
+            for captures in pragma_regex.captures_iter(comment) {
+                let value = captures[2].to_string();
+                match &captures[1] {
+                    "jsx" => self.factory = value,
+                    "jsxFrag" => self.fragment = value,
+                    "jsxRuntime" if value == "automatic" => self.runtime = JsxRuntime::Automatic,
+                    "jsxRuntime" if value == "classic" => self.runtime = JsxRuntime::Classic,
+                    "jsxImportSource" => {
+                        self.import_source = value;
+                        self.runtime = JsxRuntime::Automatic;
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        self
+    }
+}
+
+pub fn is_jsx_file(filename: &str) -> bool {
+    filename.ends_with(".jsx") || filename.ends_with(".tsx")
+}
+
+pub fn transform_jsx(source: &str, options: &JsxOptions) -> String {
+    let mut transformer = Transformer::new(source, options);
+    let mut output = transformer.transform_code(None);
+
+    if transformer.uses_runtime {
+        // Function declarations are hoisted, so the helper can live at the end
+        // of the file without shifting any lines
+        output.push_str(&format!(
+            "\nfunction _jsxRuntime() {{ return _jsxRuntime.m || (_jsxRuntime.m = require('{}/jsx-runtime')); }}",
+            options.import_source
+        ));
+    }
+
+    output
+}
+
+/// Replace the text and quoted attribute values of JSX elements with
+/// placeholders, for transforms that would read them as code. Newlines are
+/// kept; `unmask_text` puts the text back.
+pub fn mask_text(source: &str) -> (String, Vec<String>) {
+    let options = JsxOptions::default();
+    let mut transformer = Transformer::new(source, &options);
+    transformer.transform_code(None);
+
+    let chars = &transformer.chars;
+    let mut masked = String::new();
+    let mut texts = Vec::new();
+    let mut last = 0;
+    for &(start, end) in &transformer.texts {
+        masked.extend(&chars[last..start]);
+        let text: String = chars[start..end].iter().collect();
+        for (i, line) in text.split('\n').enumerate() {
+            if i != 0 {
+                masked.push('\n');
+            }
+            if line.trim().is_empty() {
+                masked.push_str(line);
+            } else {
+                masked.push_str(&format!("{}{}__", TEXT_MASK, texts.len()));
+                texts.push(line.to_string());
+            }
+        }
+        last = end;
+    }
+    masked.extend(&chars[last..]);
+
+    (masked, texts)
+}
+
+/// Put back the text set aside by `mask_text`
+pub fn unmask_text(code: &str, texts: &[String]) -> String {
+    let mut output = String::new();
+    let mut rest = code;
+
+    while let Some(start) = rest.find(TEXT_MASK) {
+        output.push_str(&rest[..start]);
+        let after = &rest[start + TEXT_MASK.len()..];
+        let digits = after
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(after.len());
+        let text = after[..digits]
+            .parse::<usize>()
+            .ok()
+            .and_then(|index| texts.get(index))
+            .filter(|_| after[digits..].starts_with("__"));
+        match text {
+            Some(text) => {
+                output.push_str(text);
+                rest = &after[digits + 2..];
+            }
+            None => {
+                output.push_str(TEXT_MASK);
+                rest = after;
+            }
+        }
+    }
+    output.push_str(rest);
+
+    output
+}
+
+fn comments(source: &str) -> Vec<&str> {
+    let mut comments = Vec::new();
+    let mut rest = source;
+
+    while let Some(start) = rest.find("/*") {
+        let after = &rest[start + 2..];
+        match after.find("*/") {
+            Some(end) => {
+                comments.push(&after[..end]);
+                rest = &after[end + 2..];
+            }
+            None => break,
+        }
+    }
+    for line in source.lines() {
+        if let Some(start) = line.find("//") {
+            comments.push(&line[start + 2..]);
+        }
+    }
+
+    comments
+}
+
+struct Transformer<'a> {
+    chars: Vec<char>,
+    pos: usize,
+    options: &'a JsxOptions,
+    uses_runtime: bool,
+    /// Character ranges of the JSX text and quoted attribute values read
+    texts: Vec<(usize, usize)>,
+}
+
+impl<'a> Transformer<'a> {
+    fn new(source: &str, options: &'a JsxOptions) -> Self {
+        Self {
+            chars: source.chars().collect(),
+            pos: 0,
+            options,
+            uses_runtime: false,
+            texts: Vec::new(),
+        }
+    }
+
+    fn peek(&self, offset: usize) -> Option<char> {
+        self.chars.get(self.pos + offset).copied()
+    }
+
+    fn starts_with(&self, text: &str) -> bool {
+        text.chars()
+            .enumerate()
+            .all(|(i, ch)| self.peek(i) == Some(ch))
+    }
+
+    /// Copy JavaScript through, replacing any JSX elements found in
+    /// expression position. Stops at an unbalanced `close` when given.
+    fn transform_code(&mut self, close: Option<char>) -> String {
+        let mut output = String::new();
+        let mut depth = 0;
+        let mut last_significant: Option<char> = None;
+        let mut last_word = String::new();
+
+        while let Some(ch) = self.peek(0) {
+            if Some(ch) == close && depth == 0 {
+                break;
+            }
+
+            if ch == '/' && self.peek(1) == Some('/') {
+                while let Some(c) = self.peek(0) {
+                    if c == '\n' {
+                        break;
+                    }
+                    output.push(c);
+                    self.pos += 1;
+                }
+                continue;
+            }
+
+            if ch == '/' && self.peek(1) == Some('*') {
+                output.push_str("/*");
+                self.pos += 2;
+                while self.peek(0).is_some() && !self.starts_with("*/") {
+                    output.push(self.chars[self.pos]);
+                    self.pos += 1;
+                }
+                if self.peek(0).is_some() {
+                    output.push_str("*/");
+                    self.pos += 2;
+                }
+                continue;
+            }
+
+            if ch == '"' || ch == '\'' || ch == '`' {
+                output.push_str(&self.read_string(ch));
+                last_significant = Some(ch);
+                last_word.clear();
+                continue;
+            }
+
+            if ch == '<' && self.jsx_allowed(last_significant, &last_word) {
+                let (start, texts) = (self.pos, self.texts.len());
+                match self.parse_element() {
+                    Some(element) => {
+                        output.push_str(&element);
+                        last_significant = Some(')');
+                        last_word.clear();
+                        continue;
+                    }
+                    None => {
+                        self.pos = start;
+                        self.texts.truncate(texts);
+                    }
+                }
+            }
+
+            match ch {
+                '(' | '[' | '{' => depth += 1,
+                ')' | ']' | '}' => depth -= 1,
+                _ => {}
+            }
+
+            if ch.is_alphanumeric() || ch == '_' || ch == '$' {
+                if !last_significant.is_some_and(|c| c.is_alphanumeric() || c == '_' || c == '$') {
+                    last_word.clear();
+                }
+                last_word.push(ch);
+            } else if !ch.is_whitespace() {
+                last_word.clear();
+            }
+            if !ch.is_whitespace() {
+                last_significant = Some(ch);
+            }
+
+            output.push(ch);
+            self.pos += 1;
+        }
+
+        output
+    }
+
+    fn jsx_allowed(&self, last_significant: Option<char>, last_word: &str) -> bool {
+        let next = match self.peek(1) {
+            Some(next) => next,
+            None => return false,
+        };
+        if !(next.is_alphabetic() || next == '_' || next == '$' || next == '>') {
+            return false;
+        }
+
+        match last_significant {
+            None => true,
+            Some(c) if "(,=:?[{};!&|>".contains(c) => true,
+            Some(_) => matches!(
+                last_word,
+                "return" | "yield" | "default" | "case" | "await" | "typeof" | "void"
+            ),
+        }
+    }
+
+    fn read_string(&mut self, quote: char) -> String {
+        let mut output = String::new();
+        output.push(quote);
+        self.pos += 1;
+
+        while let Some(ch) = self.peek(0) {
+            output.push(ch);
+            self.pos += 1;
+            if ch == '\\' {
+                if let Some(escaped) = self.peek(0) {
+                    output.push(escaped);
+                    self.pos += 1;
+                }
+            } else if ch == quote {
+                break;
+            }
+        }
+
+        output
+    }
+
+    fn skip_whitespace(&mut self) -> usize {
+        let mut newlines = 0;
+        while let Some(ch) = self.peek(0) {
+            if !ch.is_whitespace() {
+                break;
+            }
+            if ch == '\n' {
+                newlines += 1;
+            }
+            self.pos += 1;
+        }
+        newlines
+    }
+
+    fn read_name(&mut self) -> String {
+        let mut name = String::new();
+        while let Some(ch) = self.peek(0) {
+            if ch.is_alphanumeric() || "_$.-:".contains(ch) {
+                name.push(ch);
+                self.pos += 1;
+            } else {
+                break;
+            }
+        }
+        name
+    }
+
+    /// `{expr}` with any nested JSX transformed
+    fn read_expression(&mut self) -> Option<String> {
+        self.pos += 1;
+        let expression = self.transform_code(Some('}'));
+        if self.peek(0) != Some('}') {
+            return None;
+        }
+        self.pos += 1;
+        Some(expression)
+    }
+
+    fn parse_element(&mut self) -> Option<String> {
+        self.pos += 1;
+        let mut newlines = self.skip_whitespace();
+
+        let name = if self.peek(0) == Some('>') {
+            String::new()
+        } else {
+            self.read_name()
+        };
+
+        let mut props = Vec::new();
+        let mut key = None;
+        let mut self_closing = false;
+
+        // Attributes
+        loop {
+            newlines += self.skip_whitespace();
+            match self.peek(0)? {
+                '/' => {
+                    self.pos += 1;
+                    if self.peek(0)? != '>' {
+                        return None;
+                    }
+                    self.pos += 1;
+                    self_closing = true;
+                    break;
+                }
+                '>' => {
+                    self.pos += 1;
+                    break;
+                }
+                '{' => {
+                    let start = self.pos;
+                    self.pos += 1;
+                    self.skip_whitespace();
+                    if !self.starts_with("...") {
+                        return None;
+                    }
+                    self.pos = start;
+                    let spread = self.read_expression()?;
+                    props.push(format!("{}{}", "\n".repeat(newlines), spread.trim()));
+                    newlines = 0;
+                }
+                _ => {
+                    let attribute = self.read_name();
+                    if attribute.is_empty() {
+                        return None;
+                    }
+                    newlines += self.skip_whitespace();
+
+                    let value = if self.peek(0) == Some('=') {
+                        self.pos += 1;
+                        newlines += self.skip_whitespace();
+                        match self.peek(0)? {
+                            quote @ ('"' | '\'') => {
+                                let start = self.pos + 1;
+                                let raw = self.read_string(quote);
+                                self.texts.push((start, self.pos - 1));
+                                let inner = &raw[1..raw.len() - 1];
+                                newlines += inner.matches('\n').count();
+                                js_string(&decode_entities(inner))
+                            }
+                            '{' => self.read_expression()?,
+                            '<' => self.parse_element()?,
+                            _ => return None,
+                        }
+                    } else {
+                        "true".to_string()
+                    };
+
+                    if attribute == "key" && self.options.runtime == JsxRuntime::Automatic {
+                        key = Some(value);
+                    } else {
+                        props.push(format!(
+                            "{}{}: {}",
+                            "\n".repeat(newlines),
+                            property_key(&attribute),
+                            value
+                        ));
+                        newlines = 0;
+                    }
+                }
+            }
+        }
+
+        // Children
+        let mut children = Vec::new();
+        if !self_closing {
+            loop {
+                match self.peek(0)? {
+                    '<' if self.peek(1) == Some('/') => {
+                        self.pos += 2;
+                        newlines += self.skip_whitespace();
+                        let closing = self.read_name();
+                        newlines += self.skip_whitespace();
+                        if closing != name || self.peek(0)? != '>' {
+                            return None;
+                        }
+                        self.pos += 1;
+                        break;
+                    }
+                    '<' => {
+                        let child = self.parse_element()?;
+                        children.push(format!("{}{}", "\n".repeat(newlines), child));
+                        newlines = 0;
+                    }
+                    '{' => {
+                        let expression = self.read_expression()?;
+                        let trimmed = expression.trim();
+                        // `{/* comment */}` and `{}` produce no child
+                        let is_comment = trimmed.starts_with("/*") && trimmed.ends_with("*/");
+                        if trimmed.is_empty() || is_comment {
+                            newlines += expression.matches('\n').count();
+                        } else {
+                            children.push(format!("{}{}", "\n".repeat(newlines), expression));
+                            newlines = 0;
+                        }
+                    }
+                    _ => {
+                        let start = self.pos;
+                        let mut text = String::new();
+                        while let Some(ch) = self.peek(0) {
+                            if ch == '<' || ch == '{' {
+                                break;
+                            }
+                            text.push(ch);
+                            self.pos += 1;
+                        }
+                        self.texts.push((start, self.pos));
+                        if let Some(text_child) = jsx_text(&text) {
+                            children.push(format!("{}{}", "\n".repeat(newlines), text_child));
+                            newlines = 0;
+                        }
+                        newlines += text.matches('\n').count();
+                    }
+                }
+            }
+        }
+
+        Some(self.build_element(&name, props, key, children, newlines))
+    }
+
+    fn build_element(
+        &mut self,
+        name: &str,
+        mut props: Vec<String>,
+        key: Option<String>,
+        children: Vec<String>,
+        trailing_newlines: usize,
+    ) -> String {
+        let trailing = "\n".repeat(trailing_newlines);
+        let element_type = if name.is_empty() {
+            match self.options.runtime {
+                JsxRuntime::Classic => self.options.fragment.clone(),
+                JsxRuntime::Automatic => "_jsxRuntime().Fragment".to_string(),
+            }
+        } else if name.starts_with(|c: char| c.is_lowercase()) || name.contains('-') {
+            js_string(name)
+        } else {
+            name.to_string()
+        };
+
+        match self.options.runtime {
+            JsxRuntime::Classic => {
+                let props = if props.is_empty() {
+                    "null".to_string()
+                } else {
+                    format!("{{ {} }}", props.join(", "))
+                };
+                let children: String = children.iter().map(|c| format!(", {}", c)).collect();
+                format!(
+                    "{}({}, {}{}{})",
+                    self.options.factory, element_type, props, children, trailing
+                )
+            }
+            JsxRuntime::Automatic => {
+                self.uses_runtime = true;
+                let function = if children.len() > 1 { "jsxs" } else { "jsx" };
+                match children.len() {
+                    0 => {}
+                    1 => props.push(format!("children: {}", children[0])),
+                    _ => props.push(format!("children: [{}]", children.join(", "))),
+                }
+                let key = key.map(|k| format!(", {}", k)).unwrap_or_default();
+                format!(
+                    "_jsxRuntime().{}({}, {{ {} }}{}{})",
+                    function,
+                    element_type,
+                    props.join(", "),
+                    key,
+                    trailing
+                )
+            }
+        }
+    }
+}
+
+/// Collapse JSX text the way React does: lines are trimmed and joined with a
+/// single space, and whitespace-only lines disappear
+fn jsx_text(text: &str) -> Option<String> {
+    let lines: Vec<&str> = text.split('\n').collect();
+    let last = lines.len() - 1;
+    let mut parts = Vec::new();
+
+    for (i, line) in lines.iter().enumerate() {
+        let mut line = line.replace('\t', " ");
+        if i != 0 {
+            line = line.trim_start().to_string();
+        }
+        if i != last {
+            line = line.trim_end().to_string();
+        }
+        if !line.is_empty() {
+            parts.push(line);
+        }
+    }
+
+    let text = parts.join(" ");
+    if text.trim().is_empty() {
+        None
+    } else {
+        Some(js_string(&decode_entities(&text)))
+    }
+}
+
+fn decode_entities(text: &str) -> String {
+    text.replace("&nbsp;", "\u{a0}")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+fn js_string(text: &str) -> String {
+    serde_json::Value::String(text.to_string()).to_string()
+}
+
+fn property_key(name: &str) -> String {
+    if name
+        .chars()
+        .all(|c| c.is_alphanumeric() || c == '_' || c == '$')
+    {
+        name.to_string()
+    } else {
+        js_string(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classic_runtime() {
+        let source = r#"const el = <div className="app" {...rest}>Hello {name}</div>;"#;
+        let result = transform_jsx(source, &JsxOptions::default());
+        assert_eq!(
+            result,
+            r#"const el = React.createElement("div", { className: "app", ...rest }, "Hello ", name);"#
+        );
+    }
+
+    #[test]
+    fn test_pragma_and_fragment() {
+        let source = "/** @jsx h @jsxFrag Fragment */\nconst el = <><App count={1} /></>;";
+        let options = JsxOptions::default().with_pragmas(source);
+        let result = transform_jsx(source, &options);
+        assert!(result.contains("h(Fragment, null, h(App, { count: 1 }))"));
+    }
+
+    #[test]
+    fn test_automatic_runtime_keeps_lines() {
+        let source = "/* @jsxRuntime automatic */\nreturn (\n  <ul>\n    <li key={id}>{a < b}</li>\n  </ul>\n);";
+        let options = JsxOptions::default().with_pragmas(source);
+        let result = transform_jsx(source, &options);
+        assert!(result.contains("_jsxRuntime().jsx(\"li\", { children: a < b }, id)"));
+        assert!(result.contains("require('react/jsx-runtime')"));
+        // The helper is appended on a new line after the original code
+        assert_eq!(result.lines().count(), source.lines().count() + 1);
+    }
+
+    #[test]
+    fn test_mask_text() {
+        let source = "f(<a href=\"x: y\">\n  Note: done\n</a>, a < b);";
+        let (masked, texts) = mask_text(source);
+        assert_eq!(
+            masked,
+            "f(<a href=\"__jsx_text_0__\">\n__jsx_text_1__\n</a>, a < b);"
+        );
+        assert_eq!(texts, ["x: y", "  Note: done"]);
+        assert_eq!(unmask_text(&masked, &texts), source);
+    }
+
+    #[test]
+    fn test_leaves_comparisons_alone() {
+        let source = "if (a < b && c > d) { x = y <z; }";
+        assert_eq!(transform_jsx(source, &JsxOptions::default()), source);
+    }
+}
@@ -0,0 +1,396 @@
+/// Heap and time limits for scripts
+/// `--max-heap-size` caps the isolate's heap; near the cap V8 asks for more
+/// room, which is granted once, just enough to stop the script cleanly
+/// instead of crashing the process. `--timeout` arms a watchdog thread for
+/// each script run, and for each request once the script serves them.
+/// Either way the script is interrupted to note where it was, then
+/// terminated, and the runtime reports why.
+use colored::*;
+use rusty_v8 as v8;
+use std::cell::{Cell, RefCell};
+use std::ffi::c_void;
+use std::sync::{Arc, Condvar, Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+static LIMITS: OnceLock<Limits> = OnceLock::new();
+
+/// How long a timed out script gets to stop before the process exits, or a
+/// request before its handler is abandoned
+const STOP_GRACE: Duration = Duration::from_secs(2);
+
+/// The heap a script that reached `--max-heap-size` gets to unwind in
+const UNWIND_HEAP: usize = 16 * 1024 * 1024;
+
+/// Frames shown under the limit that was hit
+const MAX_FRAMES: i32 = 10;
+
+thread_local! {
+    /// The context of the script being watched, to find where it is
+    static WATCHED: RefCell<Option<v8::Global<v8::Context>>> = const { RefCell::new(None) };
+}
+
+#[derive(Default, Clone, Copy, PartialEq, Debug)]
+pub struct Limits {
+    /// Megabytes
+    pub max_heap_size: Option<u64>,
+    /// Milliseconds
+    pub timeout: Option<u64>,
+}
+
+/// Where an isolate's script stands with its limits, shared with the
+/// watchdog thread and the heap callback
+#[derive(Default)]
+struct State {
+    /// Why the script is being stopped, until the runtime reports it
+    reason: Option<Reason>,
+    /// Set by the watchdog, for idle loops that never take interrupts
+    timed_out: bool,
+    /// Set once the script serves requests, which are timed one by one
+    /// instead of the script
+    serving: bool,
+}
+
+type SharedState = Arc<Mutex<State>>;
+
+/// Kept in an isolate slot, so it is freed with the isolate
+struct IsolateLimits {
+    state: SharedState,
+    /// Data for `near_heap_limit`, which V8 holds a pointer to
+    heap_watch: Option<Box<HeapWatch>>,
+}
+
+struct HeapWatch {
+    isolate: v8::IsolateHandle,
+    state: SharedState,
+    /// Whether the heap got its room to unwind, which it only gets once
+    raised: Cell<bool>,
+}
+
+/// A limit the script hit, and where it was when it was stopped
+struct Reason {
+    limit: Limit,
+    /// Filled in by `stop`, once
+    frames: Option<Vec<String>>,
+}
+
+enum Limit {
+    /// Megabytes
+    HeapSize(u64),
+    /// Milliseconds
+    Timeout(u64),
+}
+
+impl Reason {
+    fn new(limit: Limit) -> Self {
+        Self {
+            limit,
+            frames: None,
+        }
+    }
+
+    fn message(&self) -> String {
+        let text = match self.limit {
+            Limit::HeapSize(size) => {
+                format!("Out of memory: the heap reached its {} MB limit", size)
+            }
+            Limit::Timeout(timeout) => format!("Timed out after {} ms", timeout),
+        };
+        let mut message = format!("\n{}\n", text.red().bold());
+        for frame in self.frames.iter().flatten() {
+            message.push_str(frame);
+            message.push('\n');
+        }
+        message
+    }
+}
+
+/// Limit the runtimes created from now on
+pub fn set(limits: Limits) {
+    let _ = LIMITS.set(limits);
+}
+
+fn get() -> Limits {
+    LIMITS.get().copied().unwrap_or_default()
+}
+
+/// Parameters for a new isolate, with the heap capped if asked
+pub fn create_params() -> v8::CreateParams {
+    let params = v8::CreateParams::default();
+    match get().max_heap_size {
+        Some(size) => params.heap_limits(0, size as usize * 1024 * 1024),
+        None => params,
+    }
+}
+
+/// The limit state of `isolate`, set up on first use
+fn state(isolate: &mut v8::Isolate) -> SharedState {
+    if isolate.get_slot::<IsolateLimits>().is_none() {
+        isolate.set_slot(IsolateLimits {
+            state: SharedState::default(),
+            heap_watch: None,
+        });
+    }
+    isolate.get_slot::<IsolateLimits>().unwrap().state.clone()
+}
+
+/// Stop scripts in `isolate` cleanly when its heap nears the cap
+pub fn watch_heap(isolate: &mut v8::Isolate) {
+    let state = state(isolate);
+    if get().max_heap_size.is_none() {
+        return;
+    }
+    let mut heap_watch = Box::new(HeapWatch {
+        isolate: isolate.thread_safe_handle(),
+        state,
+        raised: Cell::new(false),
+    });
+    let data = &mut *heap_watch as *mut HeapWatch as *mut c_void;
+    isolate.add_near_heap_limit_callback(near_heap_limit, data);
+    // The slot is dropped with the isolate, which makes no calls after that
+    isolate.get_slot_mut::<IsolateLimits>().unwrap().heap_watch = Some(heap_watch);
+}
+
+extern "C" fn near_heap_limit(data: *mut c_void, current_heap_limit: usize, _: usize) -> usize {
+    // SAFETY: `watch_heap` keeps the data in the isolate's slot
+    let heap_watch = unsafe { &*(data as *const HeapWatch) };
+    let mut state = heap_watch.state.lock().unwrap();
+    if state.reason.is_none() {
+        let limit = get().max_heap_size.unwrap_or_default();
+        state.reason = Some(Reason::new(Limit::HeapSize(limit)));
+        // Nothing may run during GC, so stop at the next safe point
+        heap_watch
+            .isolate
+            .request_interrupt(on_interrupt, std::ptr::null_mut());
+    }
+    drop(state);
+    // Room to unwind; V8 crashes if the limit stays where it is, so it is
+    // raised the first time only, and the heap can't keep growing
+    if heap_watch.raised.replace(true) {
+        current_heap_limit
+    } else {
+        current_heap_limit + UNWIND_HEAP
+    }
+}
+
+/// Stops the script run when `--timeout` passes, until dropped
+pub struct Watchdog {
+    /// Whether the run finished, and the way to tell the thread
+    done: Arc<(Mutex<bool>, Condvar)>,
+    state: SharedState,
+}
+
+impl Watchdog {
+    /// Watch a script about to run in `context`. One blocked outside
+    /// JavaScript past the timeout takes the process down with it.
+    pub fn arm(isolate: &mut v8::Isolate, context: &v8::Global<v8::Context>) -> Self {
+        Self::start(isolate, context, true)
+    }
+
+    /// Watch a request handler about to run in `context`. One blocked
+    /// outside JavaScript is abandoned, so the server keeps going.
+    pub fn arm_request(isolate: &mut v8::Isolate, context: &v8::Global<v8::Context>) -> Self {
+        Self::start(isolate, context, false)
+    }
+
+    fn start(
+        isolate: &mut v8::Isolate,
+        context: &v8::Global<v8::Context>,
+        exit_when_stuck: bool,
+    ) -> Self {
+        WATCHED.with(|watched| *watched.borrow_mut() = Some(context.clone()));
+        let state = state(isolate);
+        let done = Arc::new((Mutex::new(false), Condvar::new()));
+        if let Some(timeout) = get().timeout {
+            let handle = isolate.thread_safe_handle();
+            let (thread_state, thread_done) = (state.clone(), done.clone());
+            thread::spawn(move || {
+                watch(
+                    timeout,
+                    handle,
+                    &thread_state,
+                    &thread_done,
+                    exit_when_stuck,
+                )
+            });
+        }
+        Self { done, state }
+    }
+}
+
+impl Drop for Watchdog {
+    fn drop(&mut self) {
+        WATCHED.with(|watched| watched.borrow_mut().take());
+        let (done, finished) = &*self.done;
+        *done.lock().unwrap() = true;
+        finished.notify_all();
+        // A limit hit just as the script finished doesn't carry over to the
+        // next script; one that stopped it has been reported by now
+        *self.state.lock().unwrap() = State::default();
+    }
+}
+
+fn watch(
+    timeout: u64,
+    isolate: v8::IsolateHandle,
+    state: &Mutex<State>,
+    done: &(Mutex<bool>, Condvar),
+    exit_when_stuck: bool,
+) {
+    let (done, finished) = done;
+    let wait_until = |deadline: Instant| {
+        let mut done = done.lock().unwrap();
+        while !*done {
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                return false;
+            }
+            done = finished.wait_timeout(done, left).unwrap().0;
+        }
+        true
+    };
+
+    if wait_until(Instant::now() + Duration::from_millis(timeout)) {
+        return;
+    }
+    {
+        let mut state = state.lock().unwrap();
+        if state.serving {
+            return;
+        }
+        state
+            .reason
+            .get_or_insert(Reason::new(Limit::Timeout(timeout)));
+        state.timed_out = true;
+    }
+    isolate.request_interrupt(on_interrupt, std::ptr::null_mut());
+
+    // A script blocked outside JavaScript never takes the interrupt
+    if wait_until(Instant::now() + STOP_GRACE) {
+        return;
+    }
+    if exit_when_stuck {
+        let reason = state.lock().unwrap().reason.take();
+        eprintln!("{}", reason.map(|r| r.message()).unwrap_or_default());
+        std::process::exit(1);
+    }
+    // Stops the handler if it ever gets back to JavaScript
+    isolate.terminate_execution();
+}
+
+extern "C" fn on_interrupt(isolate: &mut v8::Isolate, _data: *mut c_void) {
+    stop(isolate);
+}
+
+/// The script in `isolate` serves requests from now on, so its run is no
+/// longer timed; each request is
+pub fn serving(isolate: &mut v8::Isolate) {
+    state(isolate).lock().unwrap().serving = true;
+}
+
+/// Stop the script if the watchdog fired; for idle loops inside native
+/// calls, which return when this is true so the script can unwind
+pub fn poll(isolate: &mut v8::Isolate) -> bool {
+    if !state(isolate).lock().unwrap().timed_out {
+        return false;
+    }
+    stop(isolate);
+    true
+}
+
+/// Note where the script is, then terminate it; once per limit hit
+fn stop(isolate: &mut v8::Isolate) {
+    let state = state(isolate);
+    // Not held while looking, which allocates and may hit the heap limit
+    match state.lock().unwrap().reason.as_mut() {
+        Some(reason) if reason.frames.is_none() => reason.frames = Some(Vec::new()),
+        _ => return,
+    }
+    let frames = WATCHED
+        .with(|watched| watched.borrow().clone())
+        .map(|context| {
+            let scope = &mut v8::HandleScope::with_context(isolate, &context);
+            current_frames(scope)
+        })
+        .unwrap_or_default();
+    if let Some(reason) = state.lock().unwrap().reason.as_mut() {
+        reason.frames = Some(frames);
+    }
+    isolate.terminate_execution();
+}
+
+/// The JavaScript stack right now, formatted like an error's frames
+fn current_frames(scope: &mut v8::HandleScope) -> Vec<String> {
+    // Only a detailed trace can be read without running JavaScript
+    scope.set_capture_stack_trace_for_uncaught_exceptions(true, MAX_FRAMES);
+    let message = v8::String::empty(scope);
+    let error = v8::Exception::error(scope, message);
+    let trace = v8::Exception::get_stack_trace(scope, error);
+    scope.set_capture_stack_trace_for_uncaught_exceptions(false, 0);
+    let Some(trace) = trace else {
+        return Vec::new();
+    };
+
+    let mut frames = Vec::new();
+    for i in 0..trace.get_frame_count() {
+        let Some(frame) = trace.get_frame(scope, i) else {
+            continue;
+        };
+        let Some(file) = frame
+            .get_script_name_or_source_url(scope)
+            .map(|name| name.to_rust_string_lossy(scope))
+            .filter(|name| !name.is_empty())
+        else {
+            continue;
+        };
+        let (line, column) = (frame.get_line_number(), frame.get_column());
+        let (line, column) =
+            crate::source_map::lookup(&file, line, column).unwrap_or((line, column));
+        let location = format!("{}:{}:{}", file, line, column);
+        let function = frame
+            .get_function_name(scope)
+            .map(|name| name.to_rust_string_lossy(scope))
+            .filter(|name| !name.is_empty());
+        frames.push(match function {
+            Some(function) => format!(
+                "    {} {} {}",
+                "at".dimmed(),
+                function,
+                format!("({})", location).cyan()
+            ),
+            None => format!("    {} {}", "at".dimmed(), location.cyan()),
+        });
+    }
+    frames
+}
+
+/// Why the script in `isolate` was terminated, for the runtime to report
+/// once it is back from V8; the isolate can then run scripts again
+pub fn take_reason(isolate: &mut v8::Isolate) -> Option<String> {
+    let state = state(isolate);
+    let mut state = state.lock().unwrap();
+    state.timed_out = false;
+    state.reason.take().map(|reason| reason.message())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_heap_limit_is_raised_once() {
+        drop(crate::runtime::Runtime::new());
+        let isolate = &mut v8::Isolate::new(Default::default());
+        let mut heap_watch = HeapWatch {
+            isolate: isolate.thread_safe_handle(),
+            state: SharedState::default(),
+            raised: Cell::new(false),
+        };
+        let data = &mut heap_watch as *mut HeapWatch as *mut c_void;
+        let limit = 64 * 1024 * 1024;
+        assert_eq!(near_heap_limit(data, limit, limit), limit + UNWIND_HEAP);
+        let raised = limit + UNWIND_HEAP;
+        assert_eq!(near_heap_limit(data, raised, limit), raised);
+        assert!(heap_watch.state.lock().unwrap().reason.is_some());
+    }
+}
@@ -0,0 +1,314 @@
+/// Minimal line editor for the REPL
+/// Switches the terminal to raw mode with `stty` while a line is being read,
+/// so arrow keys, history and tab completion work without extra dependencies.
+/// When stdin is not a terminal (or `stty` is unavailable) lines are read
+/// as-is.
+use std::io::{self, BufRead, IsTerminal, Read, Write};
+use std::process::{Command, Stdio};
+
+pub enum ReadResult {
+    Line(String),
+    /// Ctrl+C
+    Interrupted,
+    /// Ctrl+D on an empty line, or end of input
+    Eof,
+}
+
+/// Returns where the word being completed starts in the line and the
+/// candidates that could replace it
+pub type Completer<'a> = dyn FnMut(&str) -> (usize, Vec<String>) + 'a;
+
+pub struct LineEditor {
+    history: Vec<String>,
+}
+
+struct RawMode {
+    saved: String,
+}
+
+impl RawMode {
+    fn enable() -> Option<RawMode> {
+        if !io::stdin().is_terminal() || !io::stdout().is_terminal() {
+            return None;
+        }
+        let saved = stty(&["-g"])?;
+        stty(&["raw", "-echo"])?;
+        Some(RawMode {
+            saved: saved.trim().to_string(),
+        })
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        let _ = stty(&[&self.saved]);
+    }
+}
+
+fn stty(args: &[&str]) -> Option<String> {
+    let output = Command::new("stty")
+        .args(args)
+        .stdin(Stdio::inherit())
+        .output()
+        .ok()?;
+    if output.status.success() {
+        Some(String::from_utf8_lossy(&output.stdout).to_string())
+    } else {
+        None
+    }
+}
+
+enum Key {
+    Char(char),
+    Enter,
+    Backspace,
+    Delete,
+    Tab,
+    Left,
+    Right,
+    Up,
+    Down,
+    Home,
+    End,
+    Interrupt,
+    EndOfFile,
+    KillLine,
+    KillToEnd,
+    ClearScreen,
+    Unknown,
+}
+
+impl LineEditor {
+    pub fn new(history: Vec<String>) -> Self {
+        Self { history }
+    }
+
+    pub fn history(&self) -> &[String] {
+        &self.history
+    }
+
+    pub fn add_history(&mut self, entry: &str) {
+        if entry.trim().is_empty() || self.history.last().map(String::as_str) == Some(entry) {
+            return;
+        }
+        self.history.push(entry.to_string());
+    }
+
+    pub fn read_line(&mut self, prompt: &str, complete: &mut Completer) -> io::Result<ReadResult> {
+        let Some(_raw) = RawMode::enable() else {
+            return read_plain_line(prompt);
+        };
+
+        let mut stdout = io::stdout();
+        let mut buffer: Vec<char> = Vec::new();
+        let mut cursor = 0;
+        // Index into history while browsing with the arrow keys
+        let mut history_index = self.history.len();
+        let mut draft = String::new();
+
+        redraw(&mut stdout, prompt, &buffer, cursor)?;
+        loop {
+            match read_key()? {
+                Key::Char(ch) => {
+                    buffer.insert(cursor, ch);
+                    cursor += 1;
+                }
+                Key::Enter => {
+                    write!(stdout, "\r\n")?;
+                    stdout.flush()?;
+                    return Ok(ReadResult::Line(buffer.iter().collect()));
+                }
+                Key::Interrupt => {
+                    write!(stdout, "^C\r\n")?;
+                    stdout.flush()?;
+                    return Ok(ReadResult::Interrupted);
+                }
+                Key::EndOfFile if buffer.is_empty() => {
+                    write!(stdout, "\r\n")?;
+                    stdout.flush()?;
+                    return Ok(ReadResult::Eof);
+                }
+                Key::EndOfFile | Key::Delete => {
+                    if cursor < buffer.len() {
+                        buffer.remove(cursor);
+                    }
+                }
+                Key::Backspace => {
+                    if cursor > 0 {
+                        cursor -= 1;
+                        buffer.remove(cursor);
+                    }
+                }
+                Key::Left => cursor = cursor.saturating_sub(1),
+                Key::Right => cursor = (cursor + 1).min(buffer.len()),
+                Key::Home => cursor = 0,
+                Key::End => cursor = buffer.len(),
+                Key::KillLine => {
+                    buffer.drain(..cursor);
+                    cursor = 0;
+                }
+                Key::KillToEnd => buffer.truncate(cursor),
+                Key::ClearScreen => write!(stdout, "\x1B[2J\x1B[1;1H")?,
+                Key::Up => {
+                    if history_index > 0 {
+                        if history_index == self.history.len() {
+                            draft = buffer.iter().collect();
+                        }
+                        history_index -= 1;
+                        buffer = self.history[history_index].chars().collect();
+                        cursor = buffer.len();
+                    }
+                }
+                Key::Down => {
+                    if history_index < self.history.len() {
+                        history_index += 1;
+                        let entry = self.history.get(history_index).unwrap_or(&draft);
+                        buffer = entry.chars().collect();
+                        cursor = buffer.len();
+                    }
+                }
+                Key::Tab => {
+                    let line: String = buffer[..cursor].iter().collect();
+                    let (start, candidates) = complete(&line);
+                    let word_len = line[start..].chars().count();
+                    match candidates.as_slice() {
+                        [] => {}
+                        [only] => {
+                            buffer.splice(cursor - word_len..cursor, only.chars());
+                            cursor = cursor - word_len + only.chars().count();
+                        }
+                        _ => {
+                            let prefix = common_prefix(&candidates);
+                            if prefix.chars().count() > word_len {
+                                buffer.splice(cursor - word_len..cursor, prefix.chars());
+                                cursor = cursor - word_len + prefix.chars().count();
+                            } else {
+                                write!(stdout, "\r\n{}\r\n", format_columns(&candidates))?;
+                            }
+                        }
+                    }
+                }
+                Key::Unknown => {}
+            }
+
+            redraw(&mut stdout, prompt, &buffer, cursor)?;
+        }
+    }
+}
+
+fn read_plain_line(prompt: &str) -> io::Result<ReadResult> {
+    print!("{}", prompt);
+    io::stdout().flush()?;
+
+    let mut line = String::new();
+    if io::stdin().lock().read_line(&mut line)? == 0 {
+        return Ok(ReadResult::Eof);
+    }
+    Ok(ReadResult::Line(
+        line.trim_end_matches(['\n', '\r']).to_string(),
+    ))
+}
+
+fn redraw(stdout: &mut io::Stdout, prompt: &str, buffer: &[char], cursor: usize) -> io::Result<()> {
+    let line: String = buffer.iter().collect();
+    write!(stdout, "\r{}{}\x1B[K", prompt, line)?;
+    let back = buffer.len() - cursor;
+    if back > 0 {
+        write!(stdout, "\x1B[{}D", back)?;
+    }
+    stdout.flush()
+}
+
+fn read_byte() -> io::Result<Option<u8>> {
+    let mut byte = [0u8; 1];
+    match io::stdin().lock().read(&mut byte)? {
+        0 => Ok(None),
+        _ => Ok(Some(byte[0])),
+    }
+}
+
+fn read_key() -> io::Result<Key> {
+    let Some(byte) = read_byte()? else {
+        return Ok(Key::EndOfFile);
+    };
+
+    Ok(match byte {
+        0x01 => Key::Home,
+        0x03 => Key::Interrupt,
+        0x04 => Key::EndOfFile,
+        0x05 => Key::End,
+        0x09 => Key::Tab,
+        0x0b => Key::KillToEnd,
+        0x0c => Key::ClearScreen,
+        b'\r' | b'\n' => Key::Enter,
+        0x15 => Key::KillLine,
+        0x08 | 0x7f => Key::Backspace,
+        0x1b => match (read_byte()?, read_byte()?) {
+            (Some(b'[') | Some(b'O'), Some(b'A')) => Key::Up,
+            (Some(b'[') | Some(b'O'), Some(b'B')) => Key::Down,
+            (Some(b'[') | Some(b'O'), Some(b'C')) => Key::Right,
+            (Some(b'[') | Some(b'O'), Some(b'D')) => Key::Left,
+            (Some(b'[') | Some(b'O'), Some(b'H')) => Key::Home,
+            (Some(b'[') | Some(b'O'), Some(b'F')) => Key::End,
+            (Some(b'['), Some(b'3')) => {
+                read_byte()?; // trailing `~`
+                Key::Delete
+            }
+            _ => Key::Unknown,
+        },
+        byte if byte < 0x20 => Key::Unknown,
+        byte => {
+            // Reassemble multi-byte UTF-8 sequences
+            let len = match byte {
+                0xf0..=0xff => 4,
+                0xe0..=0xef => 3,
+                0xc0..=0xdf => 2,
+                _ => 1,
+            };
+            let mut bytes = vec![byte];
+            for _ in 1..len {
+                if let Some(next) = read_byte()? {
+                    bytes.push(next);
+                }
+            }
+            match String::from_utf8_lossy(&bytes).chars().next() {
+                Some(ch) => Key::Char(ch),
+                None => Key::Unknown,
+            }
+        }
+    })
+}
+
+fn common_prefix(candidates: &[String]) -> String {
+    let mut prefix: Vec<char> = candidates[0].chars().collect();
+    for candidate in &candidates[1..] {
+        let matching = prefix
+            .iter()
+            .zip(candidate.chars())
+            .take_while(|(a, b)| **a == *b)
+            .count();
+        prefix.truncate(matching);
+    }
+    prefix.into_iter().collect()
+}
+
+fn format_columns(candidates: &[String]) -> String {
+    let width = candidates
+        .iter()
+        .map(|c| c.chars().count())
+        .max()
+        .unwrap_or(0)
+        + 2;
+    let per_row = (80 / width).max(1);
+    candidates
+        .chunks(per_row)
+        .map(|row| {
+            row.iter()
+                .map(|c| format!("{:width$}", c, width = width))
+                .collect::<String>()
+                .trim_end()
+                .to_string()
+        })
+        .collect::<Vec<_>>()
+        .join("\r\n")
+}
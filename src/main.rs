@@ -1,75 +1,334 @@
+mod bench_report;
+mod bench_runner;
+mod cache;
+mod checker;
+mod cli;
+mod config;
+mod coverage;
+mod decorators;
+mod diff;
 mod env_parser;
+mod formatter;
+mod glob;
+mod globals;
+mod hot;
+mod inspector;
+mod jsx;
+mod limits;
+mod line_editor;
 mod modules;
+mod natives;
+mod profiler;
+mod repl;
 mod runtime;
+mod snapshot;
+mod source_map;
+mod standalone;
+mod startup;
+mod supervisor;
+mod tasks;
+mod test_report;
+mod test_runner;
+mod tsconfig;
 mod typescript;
 mod utils;
+mod watch;
+mod websocket;
 
 use chrono::Local;
+use cli::{Command, RunArgs, Script};
 use colored::*;
-use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use notify::{Event, RecommendedWatcher, Watcher};
 use runtime::Runtime;
+use std::collections::BTreeSet;
 use std::env;
 use std::fs;
-use std::io::{self, Write};
-use std::path::Path;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
 use std::process;
-use std::sync::mpsc::channel;
+use std::sync::mpsc::{RecvTimeoutError, channel};
+use std::thread;
 use std::time::Duration;
+use supervisor::Supervisor;
+use watch::WatchSet;
+
+/// How often watch mode picks up modules a running script has loaded
+const GRAPH_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// How often a `--hot` child checks for module updates once the script is idle
+const HOT_POLL_INTERVAL: Duration = Duration::from_millis(100);
 
 fn main() {
-    // Load environment files before doing anything else
-    if let Err(e) = env_parser::load_env_files() {
-        eprintln!("Error loading environment files: {}", e);
-        process::exit(1);
+    let args: Vec<String> = env::args().collect();
+    supervisor::save_base_env();
+
+    // A binary built by `rode compile` runs its script with every argument
+    if let Some(script) = standalone::embedded_script() {
+        load_env_files();
+        utils::process::set_script_args(args[1..].to_vec());
+        let mut runtime = Runtime::new();
+        if let Err(err) =
+            runtime.execute_transpiled(script.transpiled, &script.source, &script.filename)
+        {
+            println!();
+            print_error(&err);
+            process::exit(1);
+        }
+        return;
     }
 
-    let args: Vec<String> = env::args().collect();
+    let cli = match cli::parse(&args[1..]) {
+        Ok(cli) => cli,
+        Err(err) => {
+            print_error(&err);
+            println!("Run {} for usage", "rode --help".cyan());
+            process::exit(1);
+        }
+    };
 
-    let (watch_mode, filename) = parse_args(&args);
+    if cli.no_color {
+        colored::control::set_override(false);
+    }
+    for warning in &cli.warnings {
+        println!("{} {}", "WARN".yellow().bold(), warning.yellow());
+    }
 
-    if watch_mode {
-        run_with_watch(filename);
-    } else {
-        run_once(filename);
+    // The project config decides which env files to load, so it comes first
+    match config::Config::find(&config_start(&cli.command)) {
+        Ok(Some(config)) => config::init(config),
+        Ok(None) => {}
+        Err(err) => {
+            print_error(&err);
+            process::exit(1);
+        }
+    }
+    load_env_files();
+
+    match cli.command {
+        Command::Run(run) => {
+            set_serve_defaults(None, None);
+            run_command(run);
+        }
+        Command::Serve { run, host, port } => {
+            set_serve_defaults(host, port);
+            run_command(run);
+        }
+        Command::Repl => repl::run(),
+        Command::Test(test) => match test_runner::run(&test) {
+            Ok(code) => process::exit(code),
+            Err(err) => {
+                print_error(&err);
+                process::exit(1);
+            }
+        },
+        Command::Bench(bench) => match bench_runner::run(&bench) {
+            Ok(code) => process::exit(code),
+            Err(err) => {
+                print_error(&err);
+                process::exit(1);
+            }
+        },
+        Command::Check { files } => {
+            let mut ok = true;
+            for file in &files {
+                ok &= type_check(file);
+            }
+            process::exit(if ok { 0 } else { 1 });
+        }
+        Command::Fmt { paths, check } => match formatter::format(&paths, check) {
+            Ok(formatted) => process::exit(if formatted { 0 } else { 1 }),
+            Err(err) => {
+                print_error(&err);
+                process::exit(1);
+            }
+        },
+        Command::Compile { script, output } => {
+            match standalone::compile(&script, output.as_deref()) {
+                Ok(path) => println!(
+                    "{} {} {}",
+                    "✓".green().bold(),
+                    "Compiled".green(),
+                    path.display().to_string().cyan()
+                ),
+                Err(err) => {
+                    print_error(&err);
+                    process::exit(1);
+                }
+            }
+        }
+        Command::Cache { action } => run_cache_command(&action),
+        Command::Task { name } => run_task(name.as_deref()),
+        Command::Help(command) => {
+            if let Err(err) = cli::print_help(command.as_deref()) {
+                print_error(&err);
+                process::exit(1);
+            }
+        }
+        Command::Version => println!("rode {}", env!("CARGO_PKG_VERSION")),
     }
 }
 
-fn parse_args(args: &[String]) -> (bool, String) {
-    if args.len() < 2 {
-        print_error("Invalid arguments");
-        println!(
-            "Usage: {} {} <javascript_file> [script_args...]",
-            "rode".bold(),
-            "[--watch, -w]".dimmed()
-        );
-        println!("  {} Run script once", "rode script.js".cyan());
-        println!(
-            "  {} Run script and watch for changes",
-            "rode --watch script.js".cyan()
-        );
-        println!(
-            "  {} Pass arguments to script",
-            "rode script.js arg1 arg2".cyan()
-        );
+/// Where to look for rode.json: the script's directory, else the current one
+fn config_start(command: &Command) -> PathBuf {
+    let cwd = env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+    let script = match command {
+        Command::Run(run) | Command::Serve { run, .. } => match &run.script {
+            Script::File(file) => Some(file.as_str()),
+            _ => None,
+        },
+        Command::Check { files } => files.first().map(String::as_str),
+        Command::Compile { script, .. } => Some(script.as_str()),
+        _ => None,
+    };
+
+    match script.and_then(|file| cwd.join(file).parent().map(Path::to_path_buf)) {
+        Some(dir) => dir,
+        None => cwd,
+    }
+}
+
+fn load_env_files() {
+    let files = config::current().and_then(|config| config.env_files.as_deref());
+    if let Err(e) = env_parser::load_env_files(files) {
+        eprintln!("Error loading environment files: {}", e);
         process::exit(1);
     }
+}
 
-    // Check for watch flag
-    if args.len() >= 3 && (args[1] == "--watch" || args[1] == "-w") {
-        (true, args[2].clone())
-    } else if args.len() >= 2 {
-        (false, args[1].clone())
-    } else {
-        print_error("Invalid arguments");
-        println!(
-            "Usage: {} {} <javascript_file> [script_args...]",
-            "rode".bold(),
-            "[--watch, -w]".dimmed()
+/// Default address for `Rode.http.serve`: flags, then rode.json, then
+/// 127.0.0.1:8000
+fn set_serve_defaults(host: Option<String>, port: Option<u16>) {
+    let serve = config::current().map(|config| &config.serve);
+    let host = host.or_else(|| serve.and_then(|serve| serve.host.clone()));
+    let port = port.or_else(|| serve.and_then(|serve| serve.port));
+    if host.is_some() || port.is_some() {
+        utils::http::set_default_listen(
+            host.unwrap_or_else(|| "127.0.0.1".to_string()),
+            port.unwrap_or(8000),
         );
+    }
+}
+
+fn run_command(run: RunArgs) {
+    if run.watch
+        && let Script::File(filename) = &run.script
+    {
+        run_with_watch(filename, &run);
+        return;
+    }
+    utils::process::set_script_args(run.script_args);
+    if let Some(inspect) = run.inspect {
+        let script = match &run.script {
+            Script::File(filename) => filename.as_str(),
+            Script::Eval(_) => "[eval]",
+            _ => "[stdin]",
+        };
+        inspector::set_listen(inspect, script);
+    }
+    if let Some(cpu_prof) = run.cpu_prof {
+        profiler::enable_cpu_prof(cpu_prof);
+    }
+    if let Some(signal) = &run.heap_snapshot_signal
+        && let Err(err) = profiler::set_heap_snapshot_signal(signal)
+    {
+        print_error(&err);
+        process::exit(1);
+    }
+    let config_limits = config::current().map(|config| &config.limits);
+    limits::set(limits::Limits {
+        max_heap_size: run
+            .max_heap_size
+            .or_else(|| config_limits.and_then(|limits| limits.max_heap_size)),
+        timeout: run
+            .timeout
+            .or_else(|| config_limits.and_then(|limits| limits.timeout)),
+    });
+
+    let filename = match run.script {
+        Script::File(filename) => filename,
+        Script::Eval(code) => return run_inline(Some(code), run.print, run.ext.as_deref()),
+        Script::Stdin => return run_inline(None, run.print, run.ext.as_deref()),
+        Script::None => unreachable!("the parser requires a script"),
+    };
+
+    if run.check && !type_check(&filename) {
+        process::exit(1);
+    }
+    run_once(filename);
+}
+
+fn run_cache_command(action: &str) {
+    if action == "dir" {
+        println!("{}", cache::cache_dir().display());
+        return;
+    }
+
+    match cache::clean() {
+        Ok(dir) => println!(
+            "{} {} {}",
+            "✓".green().bold(),
+            "Removed cache at".green(),
+            dir.display().to_string().cyan()
+        ),
+        Err(err) => {
+            print_error(&err);
+            process::exit(1);
+        }
+    }
+}
+
+fn run_task(name: Option<&str>) {
+    let Some(config) = config::current() else {
+        print_error("No rode.json or rode.jsonc found in this directory or its parents");
         process::exit(1);
+    };
+    let Some(name) = name else {
+        tasks::list(config);
+        return;
+    };
+
+    match tasks::run(config, name) {
+        Ok(code) => process::exit(code),
+        Err(err) => {
+            print_error(&err);
+            process::exit(1);
+        }
     }
 }
 
+/// Run the type checker and print its diagnostics; true when there are none
+fn type_check(filename: &str) -> bool {
+    let diagnostics = match checker::check(filename) {
+        Ok(diagnostics) => diagnostics,
+        Err(err) => {
+            print_error(&err);
+            return false;
+        }
+    };
+
+    if diagnostics.is_empty() {
+        println!("{} {}", "✓".green().bold(), "No type errors".green());
+        return true;
+    }
+
+    for diagnostic in &diagnostics {
+        print_error(&format!("{} {}", diagnostic.code, diagnostic.message));
+        println!("{}", diagnostic.code_frame());
+    }
+    let noun = if diagnostics.len() == 1 {
+        "error"
+    } else {
+        "errors"
+    };
+    println!(
+        "{}",
+        format!("Found {} type {}", diagnostics.len(), noun)
+            .red()
+            .bold()
+    );
+    false
+}
+
 fn run_once(filename: String) {
     let code = match fs::read_to_string(&filename) {
         Ok(content) => content,
@@ -80,104 +339,180 @@ fn run_once(filename: String) {
         }
     };
 
-    // Strip TypeScript if it's a .ts file
-    let processed_code = if typescript::is_typescript_file(&filename) {
-        typescript::strip_typescript(&code)
-    } else if filename.ends_with(".js") {
-        // Process JS files for ES6 imports but skip TypeScript stripping
-        typescript::convert_es6_imports(&code)
-    } else {
-        code
-    };
+    // Strip TypeScript if it's a .ts file, honouring the nearest tsconfig.json
+    let options = tsconfig::transpile_options_for(Path::new(&filename));
+    let transpiled = cache::transpile(&filename, &code, &options);
 
     let mut runtime = Runtime::new();
 
-    match runtime.execute_with_filename(&processed_code, &filename) {
+    // With --hot the isolate stays alive after the script to take updates
+    if hot::is_enabled() {
+        let context = runtime.create_context();
+        if let Err(err) = runtime.execute_transpiled_in(&context, transpiled, &code, &filename) {
+            println!();
+            print_error(&err);
+            profiler::finish_cpu_profile();
+            process::exit(1);
+        }
+        loop {
+            thread::sleep(HOT_POLL_INTERVAL);
+            runtime.poll_hot_updates(&context);
+        }
+    }
+
+    match runtime.execute_transpiled(transpiled, &code, &filename) {
         Ok(_) => {}
         Err(err) => {
             println!();
             print_error(&err);
+            profiler::finish_cpu_profile();
+            process::exit(1);
+        }
+    }
+}
+
+/// Run code from `-e`/`-p` or stdin, transpiled as if it came from a file
+/// with the `--ext` extension (JavaScript by default)
+fn run_inline(eval: Option<String>, print: bool, ext: Option<&str>) {
+    let (code, name) = match eval {
+        Some(code) => (code, "[eval]"),
+        None => {
+            let mut code = String::new();
+            if let Err(err) = io::stdin().read_to_string(&mut code) {
+                print_error("Cannot read script from stdin");
+                println!("  {}", err.to_string().red());
+                process::exit(1);
+            }
+            (code, "[stdin]")
+        }
+    };
+
+    let ext = ext.unwrap_or("js").trim_start_matches('.');
+    let filename = format!("{}.{}", name, ext);
+    let options = tsconfig::transpile_options_for(Path::new(&filename));
+    let transpiled = cache::transpile(&filename, &code, &options);
+
+    let mut runtime = Runtime::new();
+    match runtime.evaluate_transpiled(transpiled, &code, &filename) {
+        Ok(result) => {
+            if print {
+                println!("{}", result);
+            }
+        }
+        Err(err) => {
+            println!();
+            print_error(&err);
+            profiler::finish_cpu_profile();
             process::exit(1);
         }
     }
 }
 
-fn run_with_watch(filename: String) {
-    let path = Path::new(&filename);
+fn run_with_watch(filename: &str, run: &RunArgs) {
+    let path = Path::new(filename);
     if !path.exists() {
         print_error(&format!("File '{}' does not exist", filename));
         process::exit(1);
     }
+    let mut watch = match WatchSet::new(path, &run.watch_paths, &run.watch_ignore) {
+        Ok(watch) => watch,
+        Err(err) => {
+            print_error(&err);
+            process::exit(1);
+        }
+    };
 
-    clear_screen();
-
-    print_watch_banner(&filename);
+    let watch_config = config::current().map(|config| &config.watch);
+    let clear =
+        !run.no_clear_screen && watch_config.and_then(|watch| watch.clear_screen) != Some(false);
+    let debounce = run
+        .debounce
+        .or_else(|| watch_config.and_then(|watch| watch.debounce))
+        .unwrap_or(100);
+    let debounce = Duration::from_millis(debounce);
 
-    // Initial run
-    run_script(&filename);
+    print_watch_banner(filename, clear);
 
     // Set up file watcher
     let (tx, rx) = channel();
     let mut watcher: RecommendedWatcher = Watcher::new(
         move |res: Result<Event, notify::Error>| {
-            if let Ok(event) = res {
-                if matches!(event.kind, EventKind::Modify(_)) {
-                    let _ = tx.send(());
-                }
+            if let Ok(event) = res
+                && watch::is_change(&event.kind)
+            {
+                let _ = tx.send(event.paths);
             }
         },
         notify::Config::default(),
     )
     .unwrap();
+    watch.subscribe(&mut watcher);
 
-    watcher.watch(path, RecursiveMode::NonRecursive).unwrap();
-
-    // Watch for changes
-    while rx.recv().is_ok() {
-        // Small delay to avoid multiple rapid triggers
-        std::thread::sleep(Duration::from_millis(100));
+    let mut supervisor = Supervisor::new(filename, &run.script_args, run.hot, run.child_flags());
+    start_run(&mut supervisor, filename, run.check);
 
-        // Drain any additional events
-        while rx.try_recv().is_ok() {}
-
-        clear_screen();
-        print_restart_banner(&filename);
-        run_script(&filename);
-    }
-}
+    loop {
+        // Wait for a change to something the script depends on, following
+        // the modules the run loads while it goes on
+        let mut changed = BTreeSet::new();
+        let mut hot_restart = false;
+        while changed.is_empty() && !hot_restart {
+            match rx.recv_timeout(GRAPH_POLL_INTERVAL) {
+                Ok(paths) => {
+                    changed.extend(paths.into_iter().filter(|path| watch.is_relevant(path)))
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => return,
+            }
+            if let Some(status) = supervisor.try_finished() {
+                // A --hot run that couldn't apply an update asks to restart
+                hot_restart = status.code() == Some(hot::RESTART_EXIT_CODE);
+                if !hot_restart {
+                    if status.success() {
+                        println!();
+                    }
+                    print_separator();
+                }
+            }
+            watch.set_loaded_files(supervisor.loaded_files());
+            watch.subscribe(&mut watcher);
+        }
 
-fn run_script(filename: &str) {
-    let code = match fs::read_to_string(filename) {
-        Ok(content) => content,
-        Err(err) => {
-            print_error(&format!("Cannot read file '{}'", filename));
-            println!("  {}", err.to_string().red());
-            return;
+        // Wait until changes settle, e.g. while a formatter rewrites files
+        while let Ok(paths) = rx.recv_timeout(debounce) {
+            changed.extend(paths.into_iter().filter(|path| watch.is_relevant(path)));
         }
-    };
 
-    // Strip TypeScript if it's a .ts file
-    let processed_code = if typescript::is_typescript_file(filename) {
-        typescript::strip_typescript(&code)
-    } else if filename.ends_with(".js") {
-        // Process JS files for ES6 imports but skip TypeScript stripping
-        typescript::convert_es6_imports(&code)
-    } else {
-        code
-    };
+        // Swap changed modules into a running --hot script; it exits
+        // with RESTART_EXIT_CODE when no module accepts them
+        if run.hot
+            && !hot_restart
+            && supervisor.is_running()
+            && changed.iter().all(|path| watch.is_hot_swappable(path))
+        {
+            let paths: Vec<&Path> = changed.iter().map(PathBuf::as_path).collect();
+            match supervisor.send_update(&paths) {
+                Ok(()) => continue,
+                Err(err) => print_error(&err),
+            }
+        }
 
-    let mut runtime = Runtime::new();
+        supervisor.stop();
+        print_restart_banner(filename, clear);
+        start_run(&mut supervisor, filename, run.check);
+    }
+}
 
-    match runtime.execute_with_filename(&processed_code, &filename) {
-        Ok(_) => {
-            println!();
-            print_separator();
-        }
-        Err(err) => {
-            println!();
-            print_error(&err);
-            print_separator();
-        }
+/// Type-check if asked to, then start the script in a child process
+fn start_run(supervisor: &mut Supervisor, filename: &str, check: bool) {
+    if check && !type_check(filename) {
+        println!();
+        print_separator();
+        return;
+    }
+    if let Err(err) = supervisor.start() {
+        print_error(&err);
+        print_separator();
     }
 }
 
@@ -187,9 +522,10 @@ fn clear_screen() {
     io::stdout().flush().unwrap();
 }
 
-fn print_watch_banner(filename: &str) {
-    // Clear console
-    clear_screen();
+fn print_watch_banner(filename: &str, clear: bool) {
+    if clear {
+        clear_screen();
+    }
     let now = Local::now().format("%H:%M:%S");
     println!(
         "{} {} {} {}",
@@ -203,8 +539,10 @@ fn print_watch_banner(filename: &str) {
     print_separator();
 }
 
-fn print_restart_banner(filename: &str) {
-    clear_screen();
+fn print_restart_banner(filename: &str, clear: bool) {
+    if clear {
+        clear_screen();
+    }
     let now = Local::now().format("%H:%M:%S");
     println!(
         "{} {} {}",
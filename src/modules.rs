@@ -1,17 +1,79 @@
+use crate::source_map::SourceMap;
 use rusty_v8 as v8;
+use std::cell::RefCell;
+use std::collections::{BTreeSet, HashMap};
 use std::fs;
-use std::path::PathBuf;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
 
-pub fn setup_module_system(scope: &mut v8::HandleScope) {
-    // Add a simple require function for basic module loading
-    let global = scope.get_current_context().global(scope);
+/// Set by watch mode on the child process running the script: a file to
+/// append the path of every loaded module to, so the supervisor can watch it
+pub const GRAPH_FILE_VAR: &str = "RODE_WATCH_GRAPH";
 
-    let require_key = v8::String::new(scope, "require").unwrap();
-    let require_func = v8::Function::new(scope, module_require).unwrap();
-    global.set(scope, require_key.into(), require_func.into());
+/// Every file read through require() so far
+static LOADED_FILES: Mutex<BTreeSet<PathBuf>> = Mutex::new(BTreeSet::new());
+
+fn record_loaded_file(path: &Path) {
+    let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let Ok(mut loaded) = LOADED_FILES.lock() else {
+        return;
+    };
+    if !loaded.insert(path.clone()) {
+        return;
+    }
+
+    if let Some(graph_file) = std::env::var_os(GRAPH_FILE_VAR)
+        && let Ok(mut file) = fs::OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(graph_file)
+    {
+        let _ = writeln!(file, "{}", path.display());
+    }
+}
+
+/// `rode:` modules that are not named after their `Rode` member
+const BUILTIN_ALIASES: [(&str, &str); 1] = [("test/mock", "mock")];
+
+thread_local! {
+    /// For every module loaded through require(), the modules that required
+    /// it. `None` stands for the entry script or code running outside any
+    /// module, like a function calling require() later on.
+    static IMPORTERS: RefCell<HashMap<PathBuf, BTreeSet<Option<PathBuf>>>> =
+        RefCell::new(HashMap::new());
+    /// Modules being evaluated, innermost last
+    static EVALUATING: RefCell<Vec<PathBuf>> = const { RefCell::new(Vec::new()) };
+    /// Directory of the script the runtime last started, which requires
+    /// from outside any module resolve from
+    static ENTRY_DIR: RefCell<Option<PathBuf>> = const { RefCell::new(None) };
+}
+
+/// Note the script a runtime is about to run, for `require` to find the
+/// tsconfig.json that applies to it
+pub fn set_entry(filename: &str) {
+    let dir = Path::new(filename)
+        .parent()
+        .filter(|dir| dir.is_dir())
+        .and_then(|dir| fs::canonicalize(dir).ok());
+    ENTRY_DIR.with(|entry| *entry.borrow_mut() = dir);
+}
+
+/// Directory `require` resolves from: the module being evaluated, or else
+/// the entry script, or else the current directory
+fn importer_dir() -> PathBuf {
+    let module_dir = EVALUATING.with(|evaluating| {
+        let evaluating = evaluating.borrow();
+        evaluating
+            .last()
+            .and_then(|module| module.parent().map(Path::to_path_buf))
+    });
+    module_dir
+        .or_else(|| ENTRY_DIR.with(|entry| entry.borrow().clone()))
+        .unwrap_or_else(|| std::env::current_dir().unwrap_or_else(|_| PathBuf::from(".")))
 }
 
-fn module_require(
+pub fn module_require(
     scope: &mut v8::HandleScope,
     args: v8::FunctionCallbackArguments,
     mut rv: v8::ReturnValue,
@@ -31,19 +93,77 @@ fn module_require(
         }
     };
 
-    // Resolve module path
-    let current_dir = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
-    let mut resolved_path = if module_path.starts_with("./") || module_path.starts_with("../") {
-        // For relative paths, resolve relative to current directory
-        current_dir.join(&module_path)
-    } else {
-        // For absolute module names, look in current directory
-        current_dir.join(&module_path)
+    // Built-in modules are the members of `Rode`, e.g. `rode:assert`
+    if let Some(name) = module_path.strip_prefix("rode:") {
+        let name = BUILTIN_ALIASES
+            .iter()
+            .find(|(alias, _)| *alias == name)
+            .map_or(name, |(_, member)| member);
+        let global = scope.get_current_context().global(scope);
+        let rode_key = v8::String::new(scope, "Rode").unwrap();
+        let builtin = global
+            .get(scope, rode_key.into())
+            .and_then(|rode| rode.to_object(scope))
+            .and_then(|rode| {
+                let key = v8::String::new(scope, name).unwrap();
+                rode.get(scope, key.into())
+            })
+            .filter(|builtin| !builtin.is_undefined());
+        match builtin {
+            Some(builtin) => rv.set(builtin),
+            None => {
+                let error_msg = format!("Module not found: {}", module_path);
+                let error = v8::String::new(scope, &error_msg).unwrap();
+                scope.throw_exception(error.into());
+            }
+        }
+        return;
+    }
+
+    let resolved_path = match resolve_module(&module_path, &importer_dir()) {
+        Some(path) => path,
+        None => {
+            let error_msg = format!("Module not found: {}", module_path);
+            let error = v8::String::new(scope, &error_msg).unwrap();
+            scope.throw_exception(error.into());
+            return;
+        }
     };
 
-    // Add .js extension if not present
-    if resolved_path.extension().is_none() {
-        resolved_path.set_extension("js");
+    let Some(cache) = module_cache(scope) else {
+        return;
+    };
+
+    let key = fs::canonicalize(&resolved_path).unwrap_or_else(|_| resolved_path.clone());
+    let importer = EVALUATING.with(|evaluating| evaluating.borrow().last().cloned());
+    IMPORTERS.with(|importers| {
+        importers
+            .borrow_mut()
+            .entry(key.clone())
+            .or_default()
+            .insert(importer);
+    });
+
+    // A cached module, or one still being evaluated in a require() cycle
+    let cache_key = v8::String::new(scope, &key.to_string_lossy()).unwrap();
+    if let Some(module) = cache
+        .get(scope, cache_key.into())
+        .and_then(|module| v8::Local::<v8::Object>::try_from(module).ok())
+    {
+        let exports_key = v8::String::new(scope, "exports").unwrap();
+        if let Some(exports) = module.get(scope, exports_key.into()) {
+            rv.set(exports);
+        }
+        return;
+    }
+
+    // Loading a module reads it, so the rode.json read permissions apply
+    if let Some(config) = crate::config::current()
+        && let Err(message) = config.check_path(&resolved_path.to_string_lossy(), false)
+    {
+        let error = v8::String::new(scope, &message).unwrap();
+        scope.throw_exception(error.into());
+        return;
     }
 
     let source = match fs::read_to_string(&resolved_path) {
@@ -56,34 +176,314 @@ fn module_require(
         }
     };
 
+    if let Some(exports) =
+        evaluate_module(scope, cache, &resolved_path, &key, &module_path, &source)
+    {
+        rv.set(exports);
+    }
+}
+
+/// Evaluate the module at `path`, stored in `cache` under `key`, and return
+/// its exports. Returns `None` with an exception pending when it throws.
+fn evaluate_module<'s>(
+    scope: &mut v8::HandleScope<'s>,
+    cache: v8::Local<v8::Object>,
+    path: &Path,
+    key: &Path,
+    specifier: &str,
+    source: &str,
+) -> Option<v8::Local<'s, v8::Value>> {
+    record_loaded_file(path);
+
     // Transform ES6 imports/exports to CommonJS
-    let transformed_source = if module_path.ends_with(".js") {
-        crate::typescript::convert_es6_imports(&source)
-    } else if crate::typescript::is_typescript_file(&module_path) {
-        crate::typescript::strip_typescript(&source)
+    let resolved_name = path.to_string_lossy();
+    let explicit_extension = Path::new(specifier).extension().is_some();
+    let transpiled = if (explicit_extension && resolved_name.ends_with(".js"))
+        || resolved_name.ends_with(".jsx")
+        || crate::typescript::is_typescript_file(&resolved_name)
+    {
+        let options = crate::tsconfig::transpile_options_for(path);
+        crate::cache::transpile(&resolved_name, source, &options)
     } else {
-        transform_module_source(&source)
+        let code = transform_module_source(source);
+        let source_map = SourceMap::identity(&code);
+        crate::typescript::Transpiled { code, source_map }
     };
 
-    // Execute the module and return its exports
+    let filename = path.to_string_lossy().to_string();
+    crate::source_map::register(&filename, source, transpiled.source_map);
+
+    // The module is cached before it runs, so require() cycles see its
+    // partial exports instead of loading it again
+    let module = v8::Object::new(scope);
+    let exports = v8::Object::new(scope);
+    let key_str = v8::String::new(scope, &key.to_string_lossy()).unwrap();
+    set_value(scope, module, "id", key_str.into());
+    set_value(scope, module, "exports", exports.into());
+    cache.set(scope, key_str.into(), module.into());
+    let meta = import_meta(scope, key);
+
+    // Compile the module as a function. The wrapper header sits on its own
+    // line and the origin is offset by one so lines match the module.
     let wrapped_source = format!(
-        r#"
-        (function() {{
-            const module = {{ exports: {{}} }};
-            const exports = module.exports;
-            {}
-            return module.exports;
-        }})()
-        "#,
-        transformed_source
+        "(function(module, exports, {}) {{\n{}\n}})",
+        IMPORT_META_NAME,
+        rewrite_import_meta(&transpiled.code)
     );
-
     let code = v8::String::new(scope, &wrapped_source).unwrap();
-    if let Some(script) = v8::Script::compile(scope, code, None) {
-        if let Some(result) = script.run(scope) {
-            rv.set(result);
+    let origin = crate::runtime::script_origin(scope, &filename, -1);
+    crate::coverage::record_script(&filename, &wrapped_source, -1);
+    let function = v8::Script::compile(scope, code, Some(&origin))
+        .and_then(|script| script.run(scope))
+        .and_then(|function| v8::Local::<v8::Function>::try_from(function).ok());
+
+    EVALUATING.with(|evaluating| evaluating.borrow_mut().push(key.to_path_buf()));
+    let recv = v8::undefined(scope).into();
+    let result = function
+        .and_then(|function| function.call(scope, recv, &[module.into(), exports.into(), meta]));
+    EVALUATING.with(|evaluating| evaluating.borrow_mut().pop());
+
+    if result.is_none() {
+        // Like Node, a module that threw is loaded again next time
+        cache.delete(scope, key_str.into());
+        return None;
+    }
+    let exports_key = v8::String::new(scope, "exports").unwrap();
+    module.get(scope, exports_key.into())
+}
+
+/// The identifier `import.meta` is rewritten to inside modules, which are
+/// classic scripts where `import.meta` is a syntax error. It has the same
+/// length so source map columns stay correct.
+const IMPORT_META_NAME: &str = "__rode_meta";
+
+/// Replace `import.meta` in code, leaving strings, template text and
+/// comments alone. Regex literals are not told apart from division.
+fn rewrite_import_meta(code: &str) -> String {
+    const IMPORT_META: &str = "import.meta";
+    let bytes = code.as_bytes();
+    let is_ident = |byte: u8| byte.is_ascii_alphanumeric() || byte == b'_' || byte == b'$';
+
+    let mut result = String::with_capacity(code.len());
+    let mut copied = 0;
+    let mut i = 0;
+    let mut depth = 0;
+    // Brace depth at each open `${`, innermost last
+    let mut templates: Vec<usize> = Vec::new();
+    let mut in_template = false;
+
+    while i < bytes.len() {
+        if in_template {
+            match bytes[i] {
+                b'\\' => i += 2,
+                b'`' => {
+                    in_template = false;
+                    i += 1;
+                }
+                b'$' if bytes.get(i + 1) == Some(&b'{') => {
+                    templates.push(depth);
+                    depth += 1;
+                    in_template = false;
+                    i += 2;
+                }
+                _ => i += 1,
+            }
+            continue;
+        }
+
+        match bytes[i] {
+            quote @ (b'"' | b'\'') => {
+                i += 1;
+                while i < bytes.len() && bytes[i] != quote && bytes[i] != b'\n' {
+                    i += if bytes[i] == b'\\' { 2 } else { 1 };
+                }
+                i += 1;
+            }
+            b'`' => {
+                in_template = true;
+                i += 1;
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'/') => {
+                i = code[i..].find('\n').map_or(bytes.len(), |end| i + end);
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                i = code[i + 2..]
+                    .find("*/")
+                    .map_or(bytes.len(), |end| i + 2 + end + 2);
+            }
+            b'{' => {
+                depth += 1;
+                i += 1;
+            }
+            b'}' => {
+                depth = depth.saturating_sub(1);
+                if templates.last() == Some(&depth) {
+                    templates.pop();
+                    in_template = true;
+                }
+                i += 1;
+            }
+            b'i' if code[i..].starts_with(IMPORT_META)
+                && (i == 0 || !is_ident(bytes[i - 1]))
+                && !bytes
+                    .get(i + IMPORT_META.len())
+                    .is_some_and(|&byte| is_ident(byte)) =>
+            {
+                result.push_str(&code[copied..i]);
+                result.push_str(IMPORT_META_NAME);
+                i += IMPORT_META.len();
+                copied = i;
+            }
+            _ => i += 1,
         }
     }
+
+    result.push_str(&code[copied..]);
+    result
+}
+
+/// `import.meta` for the module at `path`: its `url`, `filename`, `dirname`
+/// and, with `--hot`, the `hot` API
+fn import_meta<'s>(scope: &mut v8::HandleScope<'s>, path: &Path) -> v8::Local<'s, v8::Value> {
+    let meta = v8::Object::new(scope);
+    let url = format!("file://{}", path.to_string_lossy().replace('\\', "/"));
+    let url = v8::String::new(scope, &url).unwrap();
+    set_value(scope, meta, "url", url.into());
+    let filename = v8::String::new(scope, &path.to_string_lossy()).unwrap();
+    set_value(scope, meta, "filename", filename.into());
+    let dirname = path.parent().unwrap_or(path).to_string_lossy();
+    let dirname = v8::String::new(scope, &dirname).unwrap();
+    set_value(scope, meta, "dirname", dirname.into());
+    if crate::hot::is_enabled() {
+        let hot = crate::hot::create(scope, path);
+        set_value(scope, meta, "hot", hot.into());
+    }
+    meta.into()
+}
+
+fn set_value(
+    scope: &mut v8::HandleScope,
+    object: v8::Local<v8::Object>,
+    key: &str,
+    value: v8::Local<v8::Value>,
+) {
+    let key = v8::String::new(scope, key).unwrap();
+    object.set(scope, key.into(), value);
+}
+
+/// The modules that required the module at `path` (see `IMPORTERS`)
+pub fn importers(path: &Path) -> BTreeSet<Option<PathBuf>> {
+    IMPORTERS.with(|importers| importers.borrow().get(path).cloned().unwrap_or_default())
+}
+
+/// Drop the module at `path` from the cache, so the next require() runs it
+/// again, and forget what it required itself
+pub fn invalidate(scope: &mut v8::HandleScope, path: &Path) {
+    if let Some(cache) = module_cache(scope) {
+        let key = v8::String::new(scope, &path.to_string_lossy()).unwrap();
+        cache.delete(scope, key.into());
+    }
+    let importer = Some(path.to_path_buf());
+    IMPORTERS.with(|importers| {
+        for modules in importers.borrow_mut().values_mut() {
+            modules.remove(&importer);
+        }
+    });
+}
+
+/// Evaluate the module at `path` again, after `invalidate`, and return its
+/// new exports. Returns `None` with an exception pending when it throws.
+pub fn reload<'s>(
+    scope: &mut v8::HandleScope<'s>,
+    path: &Path,
+) -> Option<v8::Local<'s, v8::Value>> {
+    let cache = module_cache(scope)?;
+    let source = match fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(e) => {
+            let message = format!("Cannot read {}: {}", path.display(), e);
+            let error = v8::String::new(scope, &message).unwrap();
+            scope.throw_exception(error.into());
+            return None;
+        }
+    };
+    let specifier = path.to_string_lossy().to_string();
+    evaluate_module(scope, cache, path, path, &specifier, &source)
+}
+
+/// The exports of the module at `path` if it is in the cache
+pub fn cached_exports<'s>(
+    scope: &mut v8::HandleScope<'s>,
+    path: &Path,
+) -> Option<v8::Local<'s, v8::Value>> {
+    let cache = module_cache(scope)?;
+    let key = v8::String::new(scope, &path.to_string_lossy()).unwrap();
+    let module = cache.get(scope, key.into())?;
+    let module = v8::Local::<v8::Object>::try_from(module).ok()?;
+    let exports_key = v8::String::new(scope, "exports").unwrap();
+    module.get(scope, exports_key.into())
+}
+
+/// `require.cache` of the current context
+fn module_cache<'s>(scope: &mut v8::HandleScope<'s>) -> Option<v8::Local<'s, v8::Object>> {
+    let global = scope.get_current_context().global(scope);
+    let require_key = v8::String::new(scope, "require").unwrap();
+    let require = global.get(scope, require_key.into())?.to_object(scope)?;
+    let cache_key = v8::String::new(scope, "cache").unwrap();
+    require.get(scope, cache_key.into())?.to_object(scope)
+}
+
+/// Resolve a require() specifier to a file on disk
+/// Bare specifiers go through the rode.json import map, then `paths` and
+/// `baseUrl` of the tsconfig.json nearest to `importer_dir`, then everything
+/// falls back to the current directory.
+fn resolve_module(specifier: &str, importer_dir: &Path) -> Option<PathBuf> {
+    let current_dir = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+    let is_relative = specifier.starts_with("./") || specifier.starts_with("../");
+
+    let mut candidates = Vec::new();
+    if !is_relative && !Path::new(specifier).is_absolute() {
+        if let Some(target) = crate::config::current().and_then(|c| c.resolve_import(specifier)) {
+            candidates.push(target);
+        }
+        if let Some(config) = crate::tsconfig::nearest(importer_dir) {
+            candidates.extend(config.resolve_alias(specifier));
+        }
+    }
+    candidates.push(current_dir.join(specifier));
+
+    candidates
+        .iter()
+        .find_map(|candidate| probe_module_file(candidate))
+}
+
+const MODULE_EXTENSIONS: [&str; 4] = ["js", "ts", "tsx", "jsx"];
+
+fn probe_module_file(path: &Path) -> Option<PathBuf> {
+    if path.extension().is_some() && path.is_file() {
+        return Some(path.to_path_buf());
+    }
+
+    for extension in MODULE_EXTENSIONS {
+        let mut with_extension = path.as_os_str().to_owned();
+        with_extension.push(".");
+        with_extension.push(extension);
+        let candidate = PathBuf::from(with_extension);
+        if candidate.is_file() {
+            return Some(candidate);
+        }
+    }
+
+    if path.is_dir() {
+        for extension in MODULE_EXTENSIONS {
+            let candidate = path.join(format!("index.{}", extension));
+            if candidate.is_file() {
+                return Some(candidate);
+            }
+        }
+    }
+
+    None
 }
 
 fn transform_module_source(source: &str) -> String {
@@ -144,3 +544,43 @@ fn transform_module_source(source: &str) -> String {
 
     lines.join("\n")
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rewrite_import_meta_in_code_only() {
+        let code = r#"const url = import.meta.url // import.meta
+log("see import.meta.url", 'import.meta', /* import.meta */ import.meta.dirname)
+log(`import.meta ${import.meta.filename + `${ {a: import.meta}.a }`} import.meta`)
+const important = my_import.meta"#;
+        assert_eq!(
+            rewrite_import_meta(code),
+            r#"const url = __rode_meta.url // import.meta
+log("see import.meta.url", 'import.meta', /* import.meta */ __rode_meta.dirname)
+log(`import.meta ${__rode_meta.filename + `${ {a: __rode_meta}.a }`} import.meta`)
+const important = my_import.meta"#
+        );
+    }
+
+    #[test]
+    fn test_aliases_come_from_the_importers_tsconfig() {
+        let dir = std::env::temp_dir().join(format!("rode-resolve-{}", std::process::id()));
+        fs::create_dir_all(dir.join("sub/lib")).unwrap();
+        fs::write(
+            dir.join("sub/tsconfig.json"),
+            r#"{ "compilerOptions": { "paths": { "@lib/*": ["lib/*"] } } }"#,
+        )
+        .unwrap();
+        fs::write(dir.join("sub/lib/util.ts"), "export const x = 1").unwrap();
+
+        assert_eq!(
+            resolve_module("@lib/util", &dir.join("sub")),
+            Some(dir.join("sub/lib/util.ts"))
+        );
+        assert_eq!(resolve_module("@lib/util", &dir), None);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
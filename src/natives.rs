@@ -0,0 +1,109 @@
+/// The native functions of the Rode globals
+/// One table lists every function with the object it is set on. It expands
+/// both into the code that installs the functions and into the external
+/// references of the startup snapshot, which must list the same functions
+/// in the same order. build.rs expands it over placeholder functions to
+/// write the snapshot, and `startup` over the real ones to read it back.
+/// `natives_table!(then)` calls `then!` with the table, as entries of
+/// `module => "Object.path" { "name" => function, }` where `""` is the
+/// global object.
+macro_rules! natives_table {
+    ($then:ident) => {
+        $then! {
+            assert => "Rode.assert" {
+                "inspect" => assert_inspect,
+                "diff" => assert_diff,
+                "storeSnapshot" => assert_snapshot,
+            }
+            console => "console" {
+                "log" => console_log,
+                "error" => console_error,
+                "warn" => console_warn,
+                "info" => console_info,
+                "table" => console_table,
+                "dir" => console_dir,
+                "clear" => console_clear,
+                "count" => console_count,
+                "time" => console_time,
+                "timeEnd" => console_time_end,
+            }
+            fetch => "" {
+                "fetch" => fetch_function,
+            }
+            fs => "Rode.fs" {
+                "readFile" => rode_read_file,
+                "writeFile" => rode_write_file,
+                "exists" => rode_exists,
+                "mkdir" => rode_mkdir,
+                "remove" => rode_remove,
+                "readDir" => rode_read_dir,
+            }
+            http => "Rode.http" {
+                "serve" => rode_serve,
+            }
+            password => "Rode.password" {
+                "hash" => password_hash,
+                "verify" => password_verify,
+                "strength" => password_strength,
+                "generate" => password_generate,
+            }
+            path => "Rode.path" {
+                "join" => path_join,
+                "resolve" => path_resolve,
+                "dirname" => path_dirname,
+                "basename" => path_basename,
+                "extname" => path_extname,
+                "isAbsolute" => path_is_absolute,
+                "normalize" => path_normalize,
+                "relative" => path_relative,
+            }
+            process => "Rode" {
+                "exit" => rode_exit,
+                "writeHeapSnapshot" => rode_write_heap_snapshot,
+            }
+            prompt => "" {
+                "prompt" => prompt_function,
+                "alert" => alert_function,
+            }
+            uuid => "Rode.uuid" {
+                "v4" => uuid_v4,
+                "v1" => uuid_v1,
+                "v7" => uuid_v7,
+                "nil" => uuid_nil,
+                "parse" => uuid_parse,
+                "validate" => uuid_validate,
+                "version" => uuid_version,
+            }
+            modules => "" {
+                "require" => module_require,
+            }
+        }
+    };
+}
+
+/// `native_references` and `install_natives` for the table, with each
+/// `module` in scope where it is expanded
+macro_rules! natives_api {
+    ($($module:ident => $object:literal { $($name:literal => $callback:ident,)* })*) => {
+        /// Every native function in the table, in table order
+        pub fn native_references() -> Vec<rusty_v8::ExternalReference<'static>> {
+            vec![$($(rusty_v8::ExternalReference {
+                function: rusty_v8::MapFnTo::map_fn_to($module::$callback),
+            },)*)*]
+        }
+
+        /// Set every native function in the table on its object
+        pub fn install_natives(scope: &mut rusty_v8::HandleScope) {
+            $(
+                let object = crate::globals::object(scope, $object);
+                $(
+                    let key = rusty_v8::String::new(scope, $name).unwrap();
+                    let function = rusty_v8::Function::new(scope, $module::$callback).unwrap();
+                    object.set(scope, key.into(), function.into());
+                )*
+            )*
+        }
+    };
+}
+
+pub(crate) use {natives_api, natives_table};
@@ -0,0 +1,296 @@
+/// CPU profiles and heap snapshots for Chrome DevTools
+/// `--cpu-prof` samples the runtime's isolate through the inspector's
+/// Profiler domain and writes a `.cpuprofile` when the script exits, also
+/// on `Rode.exit()`, an uncaught error, or Ctrl+C. Heap snapshots come from
+/// the isolate's heap profiler, on `Rode.writeHeapSnapshot()` or when the
+/// `--heap-snapshot-signal` arrives. Signals only set a flag; a watcher
+/// thread turns that into an isolate interrupt, and idle loops `poll` it.
+use crate::cli::CpuProf;
+use crate::inspector::Inspector;
+use colored::*;
+use rusty_v8 as v8;
+use std::cell::Cell;
+use std::ffi::c_void;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicI32, AtomicU32, Ordering};
+use std::sync::{Once, OnceLock};
+use std::thread;
+use std::time::Duration;
+
+static CPU_PROF: OnceLock<CpuProf> = OnceLock::new();
+
+/// The signal that writes a heap snapshot, as a number
+static HEAP_SNAPSHOT_SIGNAL: AtomicI32 = AtomicI32::new(0);
+
+/// Set by the signal handler, cleared by whoever acts on it
+static SNAPSHOT_REQUESTED: AtomicBool = AtomicBool::new(false);
+static STOP_REQUESTED: AtomicI32 = AtomicI32::new(0);
+
+/// Numbers the files written, like Node's `.001`
+static SEQUENCE: AtomicU32 = AtomicU32::new(0);
+
+/// How often the watcher thread looks at the signal flags
+const SIGNAL_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// How long Ctrl+C waits for the profile before exiting without it
+const STOP_GRACE: Duration = Duration::from_secs(2);
+
+thread_local! {
+    /// The inspector sampling this thread's isolate, for the exit paths
+    /// that don't own the runtime
+    static PROFILED: Cell<*mut Inspector> = const { Cell::new(std::ptr::null_mut()) };
+}
+
+#[cfg(unix)]
+mod signals {
+    pub const SIGINT: i32 = 2;
+    pub const SIGTERM: i32 = 15;
+    #[cfg(target_os = "linux")]
+    pub const SIGUSR1: i32 = 10;
+    #[cfg(target_os = "linux")]
+    pub const SIGUSR2: i32 = 12;
+    #[cfg(not(target_os = "linux"))]
+    pub const SIGUSR1: i32 = 30;
+    #[cfg(not(target_os = "linux"))]
+    pub const SIGUSR2: i32 = 31;
+
+    unsafe extern "C" {
+        fn signal(signum: i32, handler: extern "C" fn(i32)) -> usize;
+    }
+
+    pub fn install(signum: i32, handler: extern "C" fn(i32)) {
+        // SAFETY: the handlers only store to atomics
+        unsafe { signal(signum, handler) };
+    }
+}
+
+/// Profile the CPU of the runtimes created from now on
+pub fn enable_cpu_prof(options: CpuProf) {
+    let _ = CPU_PROF.set(options);
+}
+
+pub fn cpu_prof_enabled() -> bool {
+    CPU_PROF.get().is_some()
+}
+
+/// Write a heap snapshot whenever `signal` (`SIGUSR1`, `SIGUSR2`) arrives
+pub fn set_heap_snapshot_signal(signal: &str) -> Result<(), String> {
+    HEAP_SNAPSHOT_SIGNAL.store(signal_number(signal)?, Ordering::SeqCst);
+    Ok(())
+}
+
+#[cfg(unix)]
+fn signal_number(name: &str) -> Result<i32, String> {
+    match name.trim_start_matches("SIG") {
+        "USR1" => Ok(signals::SIGUSR1),
+        "USR2" => Ok(signals::SIGUSR2),
+        _ => Err(format!(
+            "Unsupported heap snapshot signal '{}' (use SIGUSR1 or SIGUSR2)",
+            name
+        )),
+    }
+}
+
+#[cfg(not(unix))]
+fn signal_number(_name: &str) -> Result<i32, String> {
+    Err("--heap-snapshot-signal needs a Unix system".to_string())
+}
+
+/// Start sampling the isolate `inspector` belongs to, until
+/// `finish_cpu_profile`; `inspector` must stay where it is until then
+pub fn start_cpu_profile(inspector: &mut Inspector) {
+    let started = inspector
+        .call("Profiler.enable", serde_json::json!({}))
+        .and_then(|_| inspector.call("Profiler.start", serde_json::json!({})));
+    match started {
+        Ok(_) => PROFILED.with(|profiled| profiled.set(inspector)),
+        Err(e) => eprintln!("{} {}", "CPU profiling unavailable:".yellow(), e),
+    }
+}
+
+/// Handle the signals the flags ask for in `isolate`, the first one only
+pub fn watch_signals(isolate: &v8::Isolate) {
+    static WATCHING: Once = Once::new();
+    let snapshot_signal = HEAP_SNAPSHOT_SIGNAL.load(Ordering::SeqCst);
+    if snapshot_signal == 0 && !cpu_prof_enabled() {
+        return;
+    }
+    let isolate = isolate.thread_safe_handle();
+    WATCHING.call_once(|| spawn_watcher(snapshot_signal, isolate));
+}
+
+#[cfg(unix)]
+fn spawn_watcher(snapshot_signal: i32, isolate: v8::IsolateHandle) {
+    extern "C" fn on_snapshot_signal(_signum: i32) {
+        SNAPSHOT_REQUESTED.store(true, Ordering::SeqCst);
+    }
+    extern "C" fn on_stop_signal(signum: i32) {
+        STOP_REQUESTED.store(signum, Ordering::SeqCst);
+    }
+
+    if snapshot_signal != 0 {
+        signals::install(snapshot_signal, on_snapshot_signal);
+    }
+    // Services stop with Ctrl+C, so still write their profile
+    if cpu_prof_enabled() {
+        signals::install(signals::SIGINT, on_stop_signal);
+        signals::install(signals::SIGTERM, on_stop_signal);
+    }
+    thread::spawn(move || {
+        let mut stop_pending = Duration::ZERO;
+        loop {
+            thread::sleep(SIGNAL_POLL_INTERVAL);
+            let stop = STOP_REQUESTED.load(Ordering::SeqCst);
+            if !SNAPSHOT_REQUESTED.load(Ordering::SeqCst) && stop == 0 {
+                continue;
+            }
+            // Does nothing once the isolate is gone
+            if !isolate.request_interrupt(on_interrupt, std::ptr::null_mut()) {
+                break;
+            }
+            // A script blocked outside JavaScript never takes the interrupt,
+            // and should still stop
+            if stop != 0 {
+                stop_pending += SIGNAL_POLL_INTERVAL;
+                if stop_pending >= STOP_GRACE {
+                    std::process::exit(128 + stop);
+                }
+            }
+        }
+    });
+}
+
+#[cfg(not(unix))]
+fn spawn_watcher(_snapshot_signal: i32, _isolate: v8::IsolateHandle) {}
+
+extern "C" fn on_interrupt(isolate: &mut v8::Isolate, _data: *mut c_void) {
+    poll(isolate);
+}
+
+/// Act on signals that arrived; from interrupts and the runtime's idle loops
+pub fn poll(isolate: &mut v8::Isolate) {
+    if SNAPSHOT_REQUESTED.swap(false, Ordering::SeqCst) {
+        match write_heap_snapshot(isolate, &heap_snapshot_path(None)) {
+            Ok(path) => eprintln!(
+                "{} {}",
+                "Heap snapshot written to".cyan(),
+                path.display().to_string().bold()
+            ),
+            Err(e) => eprintln!("{} {}", "Heap snapshot failed:".red(), e),
+        }
+    }
+    let signum = STOP_REQUESTED.swap(0, Ordering::SeqCst);
+    if signum != 0 {
+        finish_cpu_profile();
+        std::process::exit(128 + signum);
+    }
+}
+
+/// Stop `--cpu-prof` sampling and write the profile; before any exit
+pub fn finish_cpu_profile() {
+    let inspector = PROFILED.with(|profiled| profiled.replace(std::ptr::null_mut()));
+    // SAFETY: the pointer is cleared before the runtime drops its inspector
+    let Some(inspector) = (unsafe { inspector.as_mut() }) else {
+        return;
+    };
+    let written = inspector
+        .call("Profiler.stop", serde_json::json!({}))
+        .and_then(|result| write_cpu_profile(&result["profile"]));
+    match written {
+        Ok(path) => eprintln!(
+            "{} {}",
+            "CPU profile written to".cyan(),
+            path.display().to_string().bold()
+        ),
+        Err(e) => eprintln!("{} {}", "CPU profile failed:".red(), e),
+    }
+}
+
+/// Whether `inspector` is the one `--cpu-prof` samples
+pub fn is_profiling(inspector: &Inspector) -> bool {
+    std::ptr::eq(PROFILED.with(Cell::get), inspector)
+}
+
+fn write_cpu_profile(profile: &serde_json::Value) -> Result<PathBuf, String> {
+    let options = CPU_PROF.get().cloned().unwrap_or_default();
+    let name = options
+        .name
+        .unwrap_or_else(|| default_filename("CPU", "cpuprofile"));
+    let path = match &options.dir {
+        Some(dir) => {
+            fs::create_dir_all(dir).map_err(|e| format!("Cannot create {}: {}", dir, e))?;
+            Path::new(dir).join(name)
+        }
+        None => PathBuf::from(name),
+    };
+    fs::write(&path, profile.to_string())
+        .map_err(|e| format!("Cannot write {}: {}", path.display(), e))?;
+    Ok(path)
+}
+
+/// `path`, or a new file in the current directory named like Node's
+pub fn heap_snapshot_path(path: Option<&str>) -> PathBuf {
+    match path {
+        Some(path) => PathBuf::from(path),
+        None => PathBuf::from(default_filename("Heap", "heapsnapshot")),
+    }
+}
+
+/// Write a snapshot of the isolate's heap to `path`, returning it
+pub fn write_heap_snapshot(isolate: &mut v8::Isolate, path: &Path) -> Result<PathBuf, String> {
+    let file = File::create(path).map_err(|e| format!("Cannot write {}: {}", path.display(), e))?;
+    let mut writer = BufWriter::new(file);
+    let mut failed = None;
+    isolate.take_heap_snapshot(|chunk| match writer.write_all(chunk) {
+        Ok(()) => true,
+        Err(e) => {
+            failed = Some(e);
+            false
+        }
+    });
+    match failed {
+        Some(e) => Err(format!("Cannot write {}: {}", path.display(), e)),
+        None => writer
+            .flush()
+            .map(|_| path.to_path_buf())
+            .map_err(|e| format!("Cannot write {}: {}", path.display(), e)),
+    }
+}
+
+/// `<prefix>.<date>.<time>.<pid>.0.<sequence>.<extension>`, as Node names them
+fn default_filename(prefix: &str, extension: &str) -> String {
+    let now = chrono::Local::now().format("%Y%m%d.%H%M%S");
+    let sequence = SEQUENCE.fetch_add(1, Ordering::SeqCst) + 1;
+    format!(
+        "{}.{}.{}.0.{:03}.{}",
+        prefix,
+        now,
+        std::process::id(),
+        sequence,
+        extension
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_filename() {
+        let first = default_filename("CPU", "cpuprofile");
+        let second = default_filename("Heap", "heapsnapshot");
+        let pattern = regex::Regex::new(r"^CPU\.\d{8}\.\d{6}\.\d+\.0\.\d{3}\.cpuprofile$").unwrap();
+        assert!(pattern.is_match(&first), "{}", first);
+        assert!(second.starts_with("Heap.") && second.ends_with(".heapsnapshot"));
+        let sequence = |name: &str| name.rsplit('.').nth(1).unwrap().parse::<u32>().unwrap();
+        assert_eq!(sequence(&second), sequence(&first) + 1);
+        #[cfg(unix)]
+        {
+            assert_eq!(signal_number("SIGUSR2"), Ok(signals::SIGUSR2));
+            assert_eq!(signal_number("USR1"), Ok(signals::SIGUSR1));
+            assert!(signal_number("SIGKILL").is_err());
+        }
+    }
+}
@@ -0,0 +1,371 @@
+/// Interactive REPL backed by one persistent isolate and context
+use crate::line_editor::{LineEditor, ReadResult};
+use crate::runtime::Runtime;
+use crate::typescript;
+use colored::*;
+use regex::Regex;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+const MAX_HISTORY: usize = 1000;
+const REPL_FILENAME: &str = "repl";
+
+const HELP: &str = "\
+.help          Show this help
+.load <file>   Evaluate a file in this session
+.save <file>   Save everything entered in this session to a file
+.exit          Exit the REPL (or press Ctrl+D)
+
+Press Tab to complete globals and properties, Ctrl+C to cancel input.
+`_` holds the last result and `await` works at the top level.";
+
+pub fn run() {
+    println!(
+        "{} {}",
+        "Rode".bright_cyan().bold(),
+        format!("v{}", env!("CARGO_PKG_VERSION")).dimmed()
+    );
+    println!(
+        "{}",
+        "Type .help for more information, .exit or Ctrl+D to leave".dimmed()
+    );
+
+    let mut runtime = Runtime::new();
+    let context = runtime.create_context();
+    let history_path = history_path();
+    let mut editor = LineEditor::new(load_history(&history_path));
+    // Complete entries entered this session, for `.save`
+    let mut session: Vec<String> = Vec::new();
+    let mut pending = String::new();
+    let mut interrupted = false;
+
+    loop {
+        let prompt = if pending.is_empty() { "> " } else { "... " };
+        let mut complete = |line: &str| {
+            let (start, path, prefix) = completion_target(line);
+            let candidates = runtime
+                .property_names(&context, &path)
+                .into_iter()
+                .filter(|name| name.starts_with(prefix) && is_identifier(name))
+                .collect();
+            (start, candidates)
+        };
+
+        let line = match editor.read_line(prompt, &mut complete) {
+            Ok(ReadResult::Line(line)) => line,
+            Ok(ReadResult::Interrupted) => {
+                if pending.is_empty() && interrupted {
+                    break;
+                }
+                if pending.is_empty() {
+                    println!("{}", "(To exit, press Ctrl+C again or Ctrl+D)".dimmed());
+                }
+                pending.clear();
+                interrupted = true;
+                continue;
+            }
+            Ok(ReadResult::Eof) | Err(_) => break,
+        };
+        interrupted = false;
+        editor.add_history(&line);
+
+        if pending.is_empty() && line.trim_start().starts_with('.') {
+            if !run_command(&mut runtime, &context, line.trim(), &session) {
+                break;
+            }
+            continue;
+        }
+
+        if !pending.is_empty() {
+            pending.push('\n');
+        }
+        pending.push_str(&line);
+        if pending.trim().is_empty() || is_incomplete(&pending) {
+            continue;
+        }
+
+        let input = std::mem::take(&mut pending);
+        match evaluate(&mut runtime, &context, &input) {
+            Err(err) if err.contains("SyntaxError: Unexpected end of input") => {
+                // Something the bracket check missed, like a trailing operator
+                pending = input;
+            }
+            result => {
+                print_result(result);
+                session.push(input);
+            }
+        }
+    }
+
+    save_history(&history_path, editor.history());
+}
+
+fn evaluate(
+    runtime: &mut Runtime,
+    context: &rusty_v8::Global<rusty_v8::Context>,
+    input: &str,
+) -> Result<String, String> {
+    // Allow `import` statements just like in scripts
+    let code = typescript::convert_es6_imports(input).code;
+    match wrap_top_level_await(&code) {
+        Some(wrapped) => runtime.evaluate(context, &wrapped, REPL_FILENAME, true),
+        None => runtime.evaluate(context, &code, REPL_FILENAME, false),
+    }
+}
+
+fn print_result(result: Result<String, String>) {
+    match result {
+        Ok(value) => println!("{}", value),
+        Err(err) => println!("{} {}", "Uncaught".red().bold(), err),
+    }
+}
+
+/// Handle a `.command`; returns false when the REPL should exit
+fn run_command(
+    runtime: &mut Runtime,
+    context: &rusty_v8::Global<rusty_v8::Context>,
+    line: &str,
+    session: &[String],
+) -> bool {
+    let (command, argument) = line.split_once(' ').unwrap_or((line, ""));
+    let argument = argument.trim();
+
+    match command {
+        ".help" => println!("{}", HELP),
+        ".exit" => return false,
+        ".load" if !argument.is_empty() => match fs::read_to_string(argument) {
+            Ok(code) => print_result(evaluate(runtime, context, &code)),
+            Err(err) => println!("{} {}: {}", "ERROR".red().bold(), argument, err),
+        },
+        ".save" if !argument.is_empty() => {
+            let mut contents = session.join("\n");
+            contents.push('\n');
+            match fs::write(argument, contents) {
+                Ok(_) => println!("{} {}", "Session saved to".green(), argument.cyan()),
+                Err(err) => println!("{} {}: {}", "ERROR".red().bold(), argument, err),
+            }
+        }
+        ".load" | ".save" => println!("{} {} <file>", "Usage:".dimmed(), command),
+        _ => println!(
+            "{} {}",
+            "Invalid REPL keyword".red(),
+            "(type .help for a list of commands)".dimmed()
+        ),
+    }
+    true
+}
+
+fn history_path() -> PathBuf {
+    if let Some(path) = env::var_os("RODE_REPL_HISTORY") {
+        return PathBuf::from(path);
+    }
+    let home = env::var_os("HOME")
+        .or_else(|| env::var_os("USERPROFILE"))
+        .map(PathBuf::from)
+        .unwrap_or_else(env::temp_dir);
+    home.join(".rode_repl_history")
+}
+
+fn load_history(path: &PathBuf) -> Vec<String> {
+    fs::read_to_string(path)
+        .map(|content| content.lines().map(str::to_string).collect())
+        .unwrap_or_default()
+}
+
+fn save_history(path: &PathBuf, history: &[String]) {
+    let start = history.len().saturating_sub(MAX_HISTORY);
+    let mut contents = history[start..].join("\n");
+    contents.push('\n');
+    let _ = fs::write(path, contents);
+}
+
+/// Split the text before the cursor into the byte offset of the word being
+/// completed, the property path leading to it, and the partial word
+/// e.g. `x = Rode.fs.re` -> (12, ["Rode", "fs"], "re")
+fn completion_target(line: &str) -> (usize, Vec<&str>, &str) {
+    let expression_start = line
+        .char_indices()
+        .rev()
+        .take_while(|(_, c)| c.is_alphanumeric() || *c == '_' || *c == '$' || *c == '.')
+        .last()
+        .map(|(i, _)| i)
+        .unwrap_or(line.len());
+    let expression = &line[expression_start..];
+
+    match expression.rsplit_once('.') {
+        Some((path, prefix)) => (line.len() - prefix.len(), path.split('.').collect(), prefix),
+        None => (expression_start, Vec::new(), expression),
+    }
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_alphabetic() || c == '_' || c == '$')
+        && chars.all(|c| c.is_alphanumeric() || c == '_' || c == '$')
+}
+
+/// Whether the input has unclosed brackets, strings or comments and the
+/// REPL should keep reading lines
+fn is_incomplete(code: &str) -> bool {
+    let mut depth: i32 = 0;
+    let mut chars = code.chars().peekable();
+    // Nesting of `${` inside template literals, as bracket depths to return to
+    let mut templates: Vec<i32> = Vec::new();
+    let mut in_template = false;
+
+    while let Some(ch) = chars.next() {
+        if in_template {
+            match ch {
+                '\\' => {
+                    chars.next();
+                }
+                '`' => in_template = false,
+                '$' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    templates.push(depth);
+                    depth += 1;
+                    in_template = false;
+                }
+                _ => {}
+            }
+            continue;
+        }
+
+        match ch {
+            '"' | '\'' => {
+                let mut closed = false;
+                while let Some(c) = chars.next() {
+                    match c {
+                        '\\' => {
+                            chars.next();
+                        }
+                        c if c == ch => {
+                            closed = true;
+                            break;
+                        }
+                        '\n' => break,
+                        _ => {}
+                    }
+                }
+                if !closed {
+                    // Unterminated string literals can't continue on the next line
+                    return false;
+                }
+            }
+            '`' => in_template = true,
+            '/' if chars.peek() == Some(&'/') => {
+                while chars.peek().is_some_and(|&c| c != '\n') {
+                    chars.next();
+                }
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut previous = ' ';
+                let mut closed = false;
+                for c in chars.by_ref() {
+                    if previous == '*' && c == '/' {
+                        closed = true;
+                        break;
+                    }
+                    previous = c;
+                }
+                if !closed {
+                    return true;
+                }
+            }
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' => {
+                depth -= 1;
+                if templates.last() == Some(&depth) {
+                    templates.pop();
+                    in_template = true;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    in_template || depth > 0
+}
+
+/// Wrap input using `await` in an async function so it can run at the top
+/// level. `const`/`let`/`var` declarations are hoisted to `var`s outside the
+/// wrapper so they stay defined for later inputs, and a lone expression is
+/// returned so its value is printed.
+fn wrap_top_level_await(code: &str) -> Option<String> {
+    let await_regex = Regex::new(r"\bawait\b").unwrap();
+    if !await_regex.is_match(code) {
+        return None;
+    }
+
+    let declaration_regex =
+        Regex::new(r"(?m)^(\s*)(?:const|let|var)\s+([A-Za-z_$][\w$]*)(\s*=)").unwrap();
+    let names: Vec<String> = declaration_regex
+        .captures_iter(code)
+        .map(|caps| caps[2].to_string())
+        .collect();
+    let body = declaration_regex.replace_all(code, "$1$2$3").to_string();
+
+    let trimmed = body.trim().trim_end_matches(';');
+    let statement_regex = Regex::new(
+        r"^(?:if|for|while|do|function|async\s+function|class|try|throw|return|switch|\{)\b",
+    )
+    .unwrap();
+    let is_expression =
+        names.is_empty() && !trimmed.contains(';') && !statement_regex.is_match(trimmed);
+
+    let hoisted = if names.is_empty() {
+        String::new()
+    } else {
+        format!("var {}; ", names.join(", "))
+    };
+    let body = if is_expression {
+        format!("return ({}\n);", trimmed)
+    } else {
+        body
+    };
+
+    Some(format!("{}(async () => {{ {}\n}})()", hoisted, body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_incomplete() {
+        assert!(is_incomplete("function f() {"));
+        assert!(is_incomplete("const s = `a ${b"));
+        assert!(is_incomplete("foo(1,\n  [2, 3]"));
+        assert!(is_incomplete("/* comment"));
+        assert!(!is_incomplete("const s = `a ${ {x: 1}.x } b`"));
+        assert!(!is_incomplete("if (a) { b('}') }"));
+        assert!(!is_incomplete("let x = 'unterminated"));
+    }
+
+    #[test]
+    fn test_wrap_top_level_await() {
+        assert_eq!(wrap_top_level_await("1 + 1"), None);
+        assert_eq!(
+            wrap_top_level_await("await fetchData()").unwrap(),
+            "(async () => { return (await fetchData()\n);\n})()"
+        );
+        assert_eq!(
+            wrap_top_level_await("const data = await load();").unwrap(),
+            "var data; (async () => { data = await load();\n})()"
+        );
+    }
+
+    #[test]
+    fn test_completion_target() {
+        assert_eq!(
+            completion_target("x = Rode.fs.re"),
+            (12, vec!["Rode", "fs"], "re")
+        );
+        assert_eq!(completion_target("cons"), (0, vec![], "cons"));
+        assert_eq!(completion_target("foo("), (4, vec![], ""));
+    }
+}
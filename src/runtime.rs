@@ -1,10 +1,18 @@
+use crate::inspector;
+use crate::source_map;
+use colored::*;
 use rusty_v8 as v8;
 use std::sync::Once;
 
 static INIT: Once = Once::new();
 
 pub struct Runtime {
+    /// Declared first so it is dropped before the isolate
+    /// Boxed so `--cpu-prof` can reach it from the exit paths
+    inspector: Option<Box<inspector::Inspector>>,
     isolate: v8::OwnedIsolate,
+    /// Whether contexts start from the startup snapshot, globals included
+    from_snapshot: bool,
 }
 
 impl Runtime {
@@ -15,60 +23,171 @@ impl Runtime {
             v8::V8::initialize();
         });
 
-        let isolate = v8::Isolate::new(Default::default());
-        Self { isolate }
+        let listen = inspector::take_listen();
+        // A debugger needs V8's own console, which the snapshot has replaced
+        let from_snapshot = listen.is_none();
+        let mut isolate = crate::startup::new_isolate(from_snapshot);
+        isolate.set_prepare_stack_trace_callback(prepare_stack_trace);
+
+        // Block coverage only counts functions compiled after it is on
+        let coverage = crate::coverage::is_enabled();
+        let cpu_prof = crate::profiler::cpu_prof_enabled();
+        let inspector = (coverage || cpu_prof || listen.is_some()).then(|| {
+            let mut inspector = Box::new(inspector::Inspector::new(&mut isolate));
+            if coverage {
+                let started = inspector
+                    .call("Profiler.enable", serde_json::json!({}))
+                    .and_then(|_| {
+                        inspector.call(
+                            "Profiler.startPreciseCoverage",
+                            serde_json::json!({ "callCount": true, "detailed": true }),
+                        )
+                    });
+                if let Err(e) = started {
+                    eprintln!("{} {}", "Coverage unavailable:".yellow(), e);
+                }
+            }
+            if let Some((inspect, script)) = listen
+                && let Err(e) = inspector.listen(&inspect, &script)
+            {
+                eprintln!("{} {}", "Debugger unavailable:".yellow(), e);
+            }
+            if cpu_prof {
+                crate::profiler::start_cpu_profile(&mut inspector);
+            }
+            inspector
+        });
+        crate::profiler::watch_signals(&isolate);
+        Self {
+            inspector,
+            isolate,
+            from_snapshot,
+        }
+    }
+
+    /// Add this isolate's coverage counts to the totals
+    pub fn collect_coverage(&mut self) {
+        let Some(inspector) = &mut self.inspector else {
+            return;
+        };
+        match inspector.call("Profiler.takePreciseCoverage", serde_json::json!({})) {
+            Ok(coverage) => crate::coverage::collect(&coverage),
+            Err(e) => eprintln!("{} {}", "Coverage unavailable:".yellow(), e),
+        }
+    }
+
+    /// Compile and run a transpiled script, mapping errors back to `source`
+    pub fn execute_transpiled(
+        &mut self,
+        transpiled: crate::typescript::Transpiled,
+        source: &str,
+        filename: &str,
+    ) -> Result<(), String> {
+        source_map::register(filename, source, transpiled.source_map);
+        self.execute_with_filename(&transpiled.code, filename)
+    }
+
+    /// Like `execute_transpiled`, but in a context that outlives the script,
+    /// for `--hot` to apply updates to afterwards
+    pub fn execute_transpiled_in(
+        &mut self,
+        context: &v8::Global<v8::Context>,
+        transpiled: crate::typescript::Transpiled,
+        source: &str,
+        filename: &str,
+    ) -> Result<(), String> {
+        source_map::register(filename, source, transpiled.source_map);
+        self.run_script(Some(context), &transpiled.code, filename, false)
+            .map(|_| ())
+    }
+
+    /// Run `f` with a scope in `context`
+    pub fn with_context<R>(
+        &mut self,
+        context: &v8::Global<v8::Context>,
+        f: impl FnOnce(&mut v8::HandleScope) -> R,
+    ) -> R {
+        let scope = &mut v8::HandleScope::with_context(&mut self.isolate, context);
+        f(scope)
+    }
+
+    /// Apply pending `--hot` module updates in `context`
+    pub fn poll_hot_updates(&mut self, context: &v8::Global<v8::Context>) {
+        let scope = &mut v8::HandleScope::with_context(&mut self.isolate, context);
+        crate::hot::poll(scope);
+        inspector::poll();
+        crate::profiler::poll(scope);
+    }
+
+    /// Like `execute_transpiled`, but returns the script's completion value
+    /// formatted the way `console.log` prints it
+    pub fn evaluate_transpiled(
+        &mut self,
+        transpiled: crate::typescript::Transpiled,
+        source: &str,
+        filename: &str,
+    ) -> Result<String, String> {
+        source_map::register(filename, source, transpiled.source_map);
+        self.run_script(None, &transpiled.code, filename, true)
+            .map(Option::unwrap_or_default)
     }
 
     pub fn execute_with_filename(&mut self, code: &str, filename: &str) -> Result<(), String> {
-        let scope = &mut v8::HandleScope::new(&mut self.isolate);
-        let context = v8::Context::new(scope);
-        let scope = &mut v8::ContextScope::new(scope, context);
+        self.run_script(None, code, filename, false).map(|_| ())
+    }
 
-        crate::utils::setup_console(scope);
-        crate::utils::setup_fetch(scope);
-        crate::utils::setup_fs(scope);
-        crate::utils::setup_http(scope);
-        crate::utils::setup_password(scope);
-        crate::utils::setup_path(scope);
-        crate::utils::setup_process(scope);
-        crate::utils::setup_prompt(scope);
-        crate::utils::setup_uuid(scope);
-        crate::modules::setup_module_system(scope);
+    /// Run a script in `context`, or in a fresh one
+    fn run_script(
+        &mut self,
+        context: Option<&v8::Global<v8::Context>>,
+        code: &str,
+        filename: &str,
+        format_result: bool,
+    ) -> Result<Option<String>, String> {
+        let context = match context {
+            Some(context) => context.clone(),
+            None => self.create_context(),
+        };
+        if let Some(inspector) = &mut self.inspector {
+            inspector.wait_for_debugger();
+        }
+        let _watchdog = crate::limits::Watchdog::arm(&mut self.isolate, &context);
+        let scope = &mut v8::HandleScope::with_context(&mut self.isolate, &context);
 
         let code_str = v8::String::new(scope, code).unwrap();
-        let filename_str = v8::String::new(scope, filename).unwrap();
-        let source_map_url = v8::undefined(scope).into();
-        let origin = v8::ScriptOrigin::new(
-            scope,
-            filename_str.into(),
-            0,
-            0,
-            false,
-            0,
-            source_map_url,
-            false,
-            false,
-            false,
-        );
+        let origin = script_origin(scope, filename, 0);
+        crate::coverage::record_script(filename, code, 0);
+        crate::modules::set_entry(filename);
 
         let mut try_catch = v8::TryCatch::new(scope);
         let script = match v8::Script::compile(&mut try_catch, code_str, Some(&origin)) {
             Some(script) => script,
             None => {
                 if let Some(exception) = try_catch.exception() {
-                    let exception_str = exception.to_rust_string_lossy(&mut try_catch);
-                    return Err(Self::format_error(&exception_str, code, filename));
+                    let error = describe_exception(&mut try_catch, exception, 0);
+                    let location = Self::message_location(&mut try_catch);
+                    return Err(Self::format_error(&error, code, filename, location));
                 }
                 return Err("Failed to compile script".to_string());
             }
         };
 
         match script.run(&mut try_catch) {
-            Some(_) => Ok(()),
+            Some(result) if format_result => Ok(Some(crate::utils::console::format_value(
+                &mut try_catch,
+                result,
+            ))),
+            Some(_) => Ok(None),
+            None if try_catch.has_terminated() => {
+                try_catch.cancel_terminate_execution();
+                Err(crate::limits::take_reason(&mut try_catch)
+                    .unwrap_or_else(|| "Script execution was terminated".to_string()))
+            }
             None => {
                 if let Some(exception) = try_catch.exception() {
-                    let exception_str = exception.to_rust_string_lossy(&mut try_catch);
-                    Err(Self::format_error(&exception_str, code, filename))
+                    let error = describe_exception(&mut try_catch, exception, 0);
+                    let location = Self::message_location(&mut try_catch);
+                    Err(Self::format_error(&error, code, filename, location))
                 } else {
                     Err("Script execution failed".to_string())
                 }
@@ -76,86 +195,523 @@ impl Runtime {
         }
     }
 
-    fn format_error(error: &str, source_code: &str, filename: &str) -> String {
-        // Parse the error to extract line number and message
-        let error_line = Self::find_syntax_error_line(source_code, error);
-        if let Some((_, message)) = Self::parse_v8_error(error) {
-            let lines: Vec<&str> = source_code.lines().collect();
-            let mut result = String::new();
-
-            result.push_str(&format!("\n{}\n", message));
-            result.push_str(&format!("    at {}:{}\n\n", filename, error_line));
-
-            // Show context around the error line
-            let start_line = if error_line > 2 { error_line - 2 } else { 1 };
-            let end_line = std::cmp::min(error_line + 2, lines.len());
-
-            for i in start_line..=end_line {
-                if i <= lines.len() {
-                    let line_content = if i == 0 { "" } else { lines[i - 1] };
-                    let line_indicator = if i == error_line { ">" } else { " " };
-                    result.push_str(&format!(
-                        "  {} {:3} | {}\n",
-                        line_indicator, i, line_content
-                    ));
-
-                    // Add arrow pointer for the error line
-                    if i == error_line {
-                        let indent = "      | ";
-                        let pointer = if line_content.trim().is_empty() {
-                            "^".to_string()
-                        } else {
-                            "^".repeat(line_content.trim_start().len().max(1))
-                        };
-                        result.push_str(&format!("{}{}\n", indent, pointer));
-                    }
-                }
-            }
-
-            result
+    /// A context with the Rode globals that outlives a single script
+    pub fn create_context(&mut self) -> v8::Global<v8::Context> {
+        let scope = &mut v8::HandleScope::new(&mut self.isolate);
+        let context = v8::Context::new(scope);
+        if let Some(inspector) = &mut self.inspector {
+            inspector.context_created(context, "rode");
+        }
+        let scope = &mut v8::ContextScope::new(scope, context);
+        // V8's own console reports to the debugger, so keep it to forward to
+        let listening = self.inspector.as_ref().is_some_and(|i| i.is_listening());
+        let v8_console = if listening {
+            let global = context.global(scope);
+            let key = v8::String::new(scope, "console").unwrap();
+            global.get(scope, key.into())
         } else {
-            format!("Runtime error: {}", error)
+            None
+        };
+        if !self.from_snapshot {
+            crate::startup::install_globals(scope);
         }
+        crate::utils::setup_process_state(scope);
+        if let Some(v8_console) = v8_console {
+            inspector::forward_console(scope, v8_console);
+        }
+        v8::Global::new(scope, context)
     }
 
-    fn parse_v8_error(error: &str) -> Option<(usize, String)> {
-        // Just return the error message without line parsing for now
-        // We'll let find_syntax_error_line handle the detection
-        Some((1, error.to_string()))
+    /// Evaluate code in a persistent context and return the inspected result,
+    /// which is also stored in `_`. With `await_result` the completion value
+    /// is a promise that is settled before returning.
+    pub fn evaluate(
+        &mut self,
+        context: &v8::Global<v8::Context>,
+        code: &str,
+        filename: &str,
+        await_result: bool,
+    ) -> Result<String, String> {
+        let scope = &mut v8::HandleScope::with_context(&mut self.isolate, context);
+        let code_str = v8::String::new(scope, code).unwrap();
+        let origin = script_origin(scope, filename, 0);
+
+        let mut try_catch = v8::TryCatch::new(scope);
+        let mut result = v8::Script::compile(&mut try_catch, code_str, Some(&origin))
+            .and_then(|script| script.run(&mut try_catch));
+        try_catch.perform_microtask_checkpoint();
+
+        if await_result
+            && let Some(promise) = result.and_then(|r| v8::Local::<v8::Promise>::try_from(r).ok())
+        {
+            match promise.state() {
+                v8::PromiseState::Fulfilled => result = Some(promise.result(&mut try_catch)),
+                v8::PromiseState::Rejected => {
+                    let reason = promise.result(&mut try_catch);
+                    return Err(describe_exception(&mut try_catch, reason, 0).text);
+                }
+                v8::PromiseState::Pending => {}
+            }
+        }
+
+        let Some(result) = result else {
+            return match try_catch.exception() {
+                Some(exception) => Err(describe_exception(&mut try_catch, exception, 0).text),
+                None => Err("Script execution failed".to_string()),
+            };
+        };
+
+        let global = try_catch.get_current_context().global(&mut try_catch);
+        let last_key = v8::String::new(&mut try_catch, "_").unwrap();
+        global.set(&mut try_catch, last_key.into(), result);
+
+        Ok(crate::utils::console::inspect_value(&mut try_catch, result))
     }
 
-    fn find_syntax_error_line(source_code: &str, error: &str) -> usize {
-        let lines: Vec<&str> = source_code.lines().collect();
+    /// Property names reachable from `globalThis` through `path`, own and
+    /// inherited, for completion
+    pub fn property_names(
+        &mut self,
+        context: &v8::Global<v8::Context>,
+        path: &[&str],
+    ) -> Vec<String> {
+        let scope = &mut v8::HandleScope::with_context(&mut self.isolate, context);
+        let mut try_catch = v8::TryCatch::new(scope);
 
-        // Look for common syntax issues
-        if error.contains("Unexpected token") {
-            // Look for unclosed braces
-            let mut brace_count = 0;
-            for (i, line) in lines.iter().enumerate() {
-                for ch in line.chars() {
-                    match ch {
-                        '{' => brace_count += 1,
-                        '}' => brace_count -= 1,
-                        _ => {}
+        let mut value: v8::Local<v8::Value> = try_catch
+            .get_current_context()
+            .global(&mut try_catch)
+            .into();
+        for key in path {
+            let Some(object) = value.to_object(&mut try_catch) else {
+                return Vec::new();
+            };
+            let key = v8::String::new(&mut try_catch, key).unwrap();
+            match object.get(&mut try_catch, key.into()) {
+                Some(next) if !next.is_null_or_undefined() => value = next,
+                _ => return Vec::new(),
+            }
+        }
+
+        let mut names = Vec::new();
+        let mut current = value.to_object(&mut try_catch);
+        while let Some(object) = current {
+            if let Some(own) = object.get_own_property_names(&mut try_catch) {
+                for i in 0..own.length() {
+                    if let Some(name) = own.get_index(&mut try_catch, i) {
+                        names.push(name.to_rust_string_lossy(&mut try_catch));
                     }
                 }
+            }
+            current = object
+                .get_prototype(&mut try_catch)
+                .and_then(|proto| v8::Local::<v8::Object>::try_from(proto).ok());
+        }
 
-                // If we have an unmatched opening brace, the error is likely on the next meaningful line
-                if brace_count > 0 && i + 1 < lines.len() {
-                    let next_line = lines[i + 1].trim();
-                    if !next_line.is_empty()
-                        && (next_line.starts_with("return")
-                            || next_line.starts_with("}")
-                            || next_line.contains("const")
-                            || next_line.contains("function"))
-                    {
-                        return i + 2; // Return line number (1-indexed)
-                    }
+        names.sort();
+        names.dedup();
+        names
+    }
+
+    fn message_location(try_catch: &mut v8::TryCatch<v8::HandleScope>) -> Option<ErrorLocation> {
+        let message = try_catch.message()?;
+        let resource = message
+            .get_script_resource_name(try_catch)
+            .filter(|name| !name.is_null_or_undefined())
+            .map(|name| name.to_rust_string_lossy(try_catch));
+        let source_line = message
+            .get_source_line(try_catch)
+            .map(|line| line.to_rust_string_lossy(try_catch));
+
+        Some(ErrorLocation {
+            resource,
+            line: message.get_line_number(try_catch)?,
+            start_column: message.get_start_column(),
+            end_column: message.get_end_column(),
+            source_line,
+        })
+    }
+
+    fn format_error(
+        error: &DescribedException,
+        generated_code: &str,
+        filename: &str,
+        location: Option<ErrorLocation>,
+    ) -> String {
+        let Some(location) = location else {
+            return format!("\n{}\n", error.text);
+        };
+
+        // Errors from required modules are reported against the module
+        let resource = location.resource.as_deref().unwrap_or(filename);
+        let span = location.end_column.max(location.start_column + 1) - location.start_column;
+
+        // Show the frame from the user's file when the script was transpiled
+        let original = source_map::original_source(resource);
+        let start = source_map::lookup(resource, location.line, location.start_column + 1);
+        let end = source_map::lookup(resource, location.line, location.start_column + span);
+        let mut context = 2;
+        let (lines, error_line, column, width): (Vec<&str>, usize, usize, usize) =
+            match (&original, start) {
+                (Some(original), Some((line, column))) => {
+                    // Stripped types can sit inside the span, so measure it
+                    // in the original unless it ends on another line
+                    let width = match end {
+                        Some((end_line, end_column))
+                            if end_line == line && end_column >= column =>
+                        {
+                            end_column - column + 1
+                        }
+                        _ => span,
+                    };
+                    (original.lines().collect(), line, column - 1, width)
                 }
+                _ if resource == filename => (
+                    generated_code.lines().collect(),
+                    location.line,
+                    location.start_column,
+                    span,
+                ),
+                _ => match &location.source_line {
+                    // Only the offending line is known, so show it alone
+                    Some(source_line) => {
+                        context = 0;
+                        let mut lines = vec![""; location.line.saturating_sub(1)];
+                        lines.push(source_line);
+                        (lines, location.line, location.start_column, span)
+                    }
+                    None => (Vec::new(), location.line, location.start_column, span),
+                },
+            };
+
+        let mut result = String::new();
+        result.push_str(&format!("\n{}\n", error.text));
+        // Compile errors have no frames, so say where they are
+        if !error.has_frames {
+            result.push_str(&format!(
+                "    at {}:{}:{}\n",
+                resource,
+                error_line,
+                column + 1
+            ));
+        }
+        result.push('\n');
+
+        // Show context around the error line
+        let start_line = error_line.saturating_sub(context).max(1);
+        let end_line = std::cmp::min(error_line + context, lines.len());
+
+        for i in start_line..=end_line {
+            let line_content = lines[i - 1];
+            let line_indicator = if i == error_line { ">" } else { " " };
+            result.push_str(&format!(
+                "  {} {:3} | {}\n",
+                line_indicator, i, line_content
+            ));
+
+            // Underline the offending span, keeping tabs so it lines up
+            if i == error_line {
+                let indent: String = line_content
+                    .chars()
+                    .take(column)
+                    .map(|c| if c == '\t' { '\t' } else { ' ' })
+                    .collect();
+                result.push_str(&format!(
+                    "        | {}{}\n",
+                    indent,
+                    "^".repeat(width.max(1))
+                ));
             }
         }
 
-        // Default to line 1 if we can't detect
-        1
+        result
+    }
+}
+
+impl Drop for Runtime {
+    fn drop(&mut self) {
+        // The profile needs the inspector, which is about to go
+        if let Some(inspector) = &self.inspector
+            && crate::profiler::is_profiling(inspector)
+        {
+            crate::profiler::finish_cpu_profile();
+        }
+    }
+}
+
+/// Where V8 reported an error, taken from the TryCatch message
+struct ErrorLocation {
+    /// Script the error came from, which may be a required module
+    resource: Option<String>,
+    /// 1-based line in the generated code
+    line: usize,
+    /// 0-based columns of the offending span, end exclusive
+    start_column: usize,
+    end_column: usize,
+    source_line: Option<String>,
+}
+
+/// Script origin for `filename`, carrying its registered source map if any
+pub fn script_origin<'s>(
+    scope: &mut v8::HandleScope<'s>,
+    filename: &str,
+    line_offset: i32,
+) -> v8::ScriptOrigin<'s> {
+    let filename_str = v8::String::new(scope, filename).unwrap();
+    let source_map_url = match source_map::data_url(filename) {
+        Some(url) => v8::String::new(scope, &url).unwrap().into(),
+        None => v8::undefined(scope).into(),
+    };
+
+    v8::ScriptOrigin::new(
+        scope,
+        filename_str.into(),
+        line_offset,
+        0,
+        false,
+        0,
+        source_map_url,
+        false,
+        false,
+        false,
+    )
+}
+
+/// Builds `error.stack` with every frame mapped through registered source maps
+/// A user-defined `Error.prepareStackTrace` takes over, like in Node.
+fn prepare_stack_trace<'s>(
+    scope: &mut v8::HandleScope<'s>,
+    error: v8::Local<v8::Value>,
+    sites: v8::Local<v8::Array>,
+) -> v8::Local<'s, v8::Value> {
+    if let Some(custom) = user_prepare_stack_trace(scope) {
+        let recv = v8::undefined(scope).into();
+        if let Some(stack) = custom.call(scope, recv, &[error, sites.into()]) {
+            return stack;
+        }
+        return v8::undefined(scope).into();
+    }
+
+    let mut stack = error_header(scope, error);
+
+    for i in 0..sites.length() {
+        let site = match sites.get_index(scope, i).and_then(|s| s.to_object(scope)) {
+            Some(site) => site,
+            None => continue,
+        };
+        stack.push_str(&format!("\n    at {}", format_call_site(scope, site)));
     }
+
+    v8::String::new(scope, &stack).unwrap().into()
+}
+
+fn user_prepare_stack_trace<'s>(
+    scope: &mut v8::HandleScope<'s>,
+) -> Option<v8::Local<'s, v8::Function>> {
+    let global = scope.get_current_context().global(scope);
+    let error_class = get_property(scope, global, "Error")?.to_object(scope)?;
+    let custom = get_property(scope, error_class, "prepareStackTrace")?;
+    v8::Local::<v8::Function>::try_from(custom).ok()
+}
+
+/// `Error.prototype.toString` semantics, so `Error.captureStackTrace` on a
+/// plain object gets a sensible first line too
+fn error_header(scope: &mut v8::HandleScope, error: v8::Local<v8::Value>) -> String {
+    let Ok(object) = v8::Local::<v8::Object>::try_from(error) else {
+        return error.to_rust_string_lossy(scope);
+    };
+
+    let name = get_property(scope, object, "name")
+        .map(|name| name.to_rust_string_lossy(scope))
+        .unwrap_or_else(|| "Error".to_string());
+    let message = get_property(scope, object, "message")
+        .map(|message| message.to_rust_string_lossy(scope))
+        .unwrap_or_default();
+
+    match (name.is_empty(), message.is_empty()) {
+        (_, true) => name,
+        (true, false) => message,
+        (false, false) => format!("{}: {}", name, message),
+    }
+}
+
+fn format_call_site(scope: &mut v8::HandleScope, site: v8::Local<v8::Object>) -> String {
+    if call_site_bool(scope, site, "isNative") {
+        return "native".to_string();
+    }
+
+    let file =
+        call_site_string(scope, site, "getFileName").unwrap_or_else(|| "<anonymous>".to_string());
+    let line = call_site_number(scope, site, "getLineNumber");
+    let column = call_site_number(scope, site, "getColumnNumber");
+    let (line, column) = source_map::lookup(&file, line, column).unwrap_or((line, column));
+    let location = format!("{}:{}:{}", file, line, column);
+
+    let function = call_site_string(scope, site, "getFunctionName");
+    let is_async = call_site_bool(scope, site, "isAsync");
+    let prefix = if is_async { "async " } else { "" };
+
+    let name = if call_site_bool(scope, site, "isConstructor") {
+        Some(format!(
+            "new {}",
+            function.unwrap_or_else(|| "<anonymous>".to_string())
+        ))
+    } else if !call_site_bool(scope, site, "isToplevel") {
+        // Method calls read as `Type.method`
+        let type_name = call_site_string(scope, site, "getTypeName");
+        let method = call_site_string(scope, site, "getMethodName");
+        match (type_name, function.or(method)) {
+            (Some(type_name), Some(function)) if !function.starts_with(&type_name) => {
+                Some(format!("{}.{}", type_name, function))
+            }
+            (_, Some(function)) => Some(function),
+            (Some(type_name), None) => Some(format!("{}.<anonymous>", type_name)),
+            (None, None) => None,
+        }
+    } else {
+        function
+    };
+
+    match name {
+        Some(name) => format!("{}{} ({})", prefix, name, location),
+        None => format!("{}{}", prefix, location),
+    }
+}
+
+const MAX_NESTED_ERRORS: usize = 8;
+
+/// An uncaught exception rendered for the terminal
+struct DescribedException {
+    text: String,
+    /// Whether any stack frames were printed for the top-level error
+    has_frames: bool,
+}
+
+/// `error.stack` with frames colored and frames without a script hidden,
+/// followed by the members of an AggregateError and the `cause` chain
+fn describe_exception(
+    scope: &mut v8::HandleScope,
+    exception: v8::Local<v8::Value>,
+    depth: usize,
+) -> DescribedException {
+    let object = v8::Local::<v8::Object>::try_from(exception).ok();
+    let stack = object
+        .and_then(|object| get_property(scope, object, "stack"))
+        .filter(|stack| stack.is_string())
+        .map(|stack| stack.to_rust_string_lossy(scope))
+        .unwrap_or_else(|| exception.to_rust_string_lossy(scope));
+
+    let mut lines = Vec::new();
+    let mut has_frames = false;
+    for line in stack.lines() {
+        let Some(frame) = line.trim_start().strip_prefix("at ") else {
+            lines.push(line.to_string());
+            continue;
+        };
+        if is_internal_frame(frame) {
+            continue;
+        }
+        has_frames = true;
+        lines.push(
+            match frame.strip_suffix(')').and_then(|f| f.rsplit_once(" (")) {
+                Some((name, location)) => format!(
+                    "    {} {} {}",
+                    "at".dimmed(),
+                    name,
+                    format!("({})", location).cyan()
+                ),
+                None => format!("    {} {}", "at".dimmed(), frame.cyan()),
+            },
+        );
+    }
+
+    if let Some(object) = object
+        && depth < MAX_NESTED_ERRORS
+    {
+        if let Some(errors) = get_property(scope, object, "errors")
+            && let Ok(errors) = v8::Local::<v8::Array>::try_from(errors)
+        {
+            for i in 0..errors.length() {
+                let Some(error) = errors.get_index(scope, i) else {
+                    continue;
+                };
+                let nested = describe_exception(scope, error, depth + 1);
+                lines.push(format!(
+                    "    {} {}",
+                    format!("[errors[{}]]", i).dimmed(),
+                    nested.text.replace('\n', "\n    ")
+                ));
+            }
+        }
+
+        if let Some(cause) = get_property(scope, object, "cause") {
+            let nested = describe_exception(scope, cause, depth + 1);
+            lines.push(format!("{} {}", "Caused by:".dimmed(), nested.text));
+        }
+    }
+
+    DescribedException {
+        text: lines.join("\n"),
+        has_frames,
+    }
+}
+
+/// An exception as it is printed when uncaught, with its stack and causes
+pub fn format_exception(scope: &mut v8::HandleScope, exception: v8::Local<v8::Value>) -> String {
+    describe_exception(scope, exception, 0).text
+}
+
+fn is_internal_frame(frame: &str) -> bool {
+    // Built-ins and frames with no script behind them
+    frame == "native" || frame.contains("<anonymous>:")
+}
+
+fn get_property<'s>(
+    scope: &mut v8::HandleScope<'s>,
+    object: v8::Local<v8::Object>,
+    key: &str,
+) -> Option<v8::Local<'s, v8::Value>> {
+    let key = v8::String::new(scope, key).unwrap();
+    object
+        .get(scope, key.into())
+        .filter(|value| !value.is_undefined())
+}
+
+fn call_site_value<'s>(
+    scope: &mut v8::HandleScope<'s>,
+    site: v8::Local<v8::Object>,
+    method: &str,
+) -> Option<v8::Local<'s, v8::Value>> {
+    let key = v8::String::new(scope, method).unwrap();
+    let function = site.get(scope, key.into())?;
+    let function = v8::Local::<v8::Function>::try_from(function).ok()?;
+    let value = function.call(scope, site.into(), &[])?;
+    if value.is_null_or_undefined() {
+        None
+    } else {
+        Some(value)
+    }
+}
+
+fn call_site_string(
+    scope: &mut v8::HandleScope,
+    site: v8::Local<v8::Object>,
+    method: &str,
+) -> Option<String> {
+    let value = call_site_value(scope, site, method)?;
+    Some(value.to_rust_string_lossy(scope))
+}
+
+fn call_site_bool(scope: &mut v8::HandleScope, site: v8::Local<v8::Object>, method: &str) -> bool {
+    call_site_value(scope, site, method).is_some_and(|value| value.is_true())
+}
+
+fn call_site_number(
+    scope: &mut v8::HandleScope,
+    site: v8::Local<v8::Object>,
+    method: &str,
+) -> usize {
+    call_site_value(scope, site, method)
+        .and_then(|value| value.uint32_value(scope))
+        .unwrap_or(0) as usize
 }
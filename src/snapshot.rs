@@ -0,0 +1,236 @@
+/// Snapshot files for `Rode.assert.snapshot`
+/// Snapshots of a test file live next to it in `<file>.snap`, one template
+/// literal per snapshot keyed by the test's full name and a counter (or the
+/// hint given to the assertion), so the file reads and diffs like code.
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const HEADER: &str = "// Rode snapshot v1\n";
+
+/// The outcome of comparing a value with its snapshot
+pub enum Check {
+    Matched,
+    /// There was no snapshot (or snapshots are being updated), so it was stored
+    Written,
+    Mismatched {
+        name: String,
+        expected: String,
+    },
+}
+
+/// The test currently running, for naming its snapshots
+struct CurrentTest {
+    file: PathBuf,
+    name: String,
+    count: usize,
+}
+
+#[derive(Default)]
+struct SnapshotFile {
+    entries: BTreeMap<String, String>,
+    changed: bool,
+}
+
+thread_local! {
+    static CURRENT: RefCell<Option<CurrentTest>> = const { RefCell::new(None) };
+    static FILES: RefCell<HashMap<PathBuf, SnapshotFile>> = RefCell::new(HashMap::new());
+    /// `rode test --update-snapshots`: overwrite instead of comparing
+    static UPDATE: Cell<bool> = const { Cell::new(false) };
+}
+
+pub fn set_update(update: bool) {
+    UPDATE.with(|flag| flag.set(update));
+}
+
+/// Name the snapshots taken until `end_test` after the test `name` in `file`
+pub fn start_test(file: &Path, name: &str) {
+    let test = CurrentTest {
+        file: file.to_path_buf(),
+        name: name.to_string(),
+        count: 0,
+    };
+    CURRENT.with(|current| *current.borrow_mut() = Some(test));
+}
+
+pub fn end_test() {
+    CURRENT.with(|current| *current.borrow_mut() = None);
+}
+
+/// Compare `value` with the current test's next snapshot, or the one
+/// named by `hint`
+pub fn check(value: &str, hint: Option<&str>) -> Result<Check, String> {
+    let (file, name) = CURRENT
+        .with(|current| {
+            let mut current = current.borrow_mut();
+            let test = current.as_mut()?;
+            test.count += 1;
+            let name = match hint {
+                Some(hint) => format!("{}: {}", test.name, hint),
+                None => format!("{} {}", test.name, test.count),
+            };
+            Some((snap_path(&test.file), name))
+        })
+        .ok_or("Snapshots can only be taken inside a test run by `rode test`")?;
+    let update = UPDATE.with(Cell::get);
+
+    FILES.with(|files| {
+        let mut files = files.borrow_mut();
+        if !files.contains_key(&file) {
+            let entries = match fs::read_to_string(&file) {
+                Ok(contents) => parse(&contents)
+                    .map_err(|e| format!("Invalid snapshot file {}: {}", file.display(), e))?,
+                Err(_) => BTreeMap::new(),
+            };
+            let snapshots = SnapshotFile {
+                entries,
+                changed: false,
+            };
+            files.insert(file.clone(), snapshots);
+        }
+        let snapshots = files.get_mut(&file).unwrap();
+
+        match snapshots.entries.get(&name) {
+            Some(expected) if expected == value => Ok(Check::Matched),
+            Some(expected) if !update => Ok(Check::Mismatched {
+                name,
+                expected: expected.clone(),
+            }),
+            _ => {
+                snapshots.entries.insert(name, value.to_string());
+                snapshots.changed = true;
+                Ok(Check::Written)
+            }
+        }
+    })
+}
+
+/// Write the snapshot files that changed, returning how many were written
+pub fn save() -> Result<usize, String> {
+    FILES.with(|files| {
+        let mut written = 0;
+        for (path, snapshots) in files.borrow_mut().iter_mut() {
+            if !snapshots.changed {
+                continue;
+            }
+            fs::write(path, serialize(&snapshots.entries))
+                .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+            snapshots.changed = false;
+            written += 1;
+        }
+        Ok(written)
+    })
+}
+
+fn snap_path(test_file: &Path) -> PathBuf {
+    let mut path = test_file.as_os_str().to_owned();
+    path.push(".snap");
+    PathBuf::from(path)
+}
+
+fn serialize(entries: &BTreeMap<String, String>) -> String {
+    let mut out = String::from(HEADER);
+    for (name, value) in entries {
+        out.push_str(&format!(
+            "\nexports[`{}`] = `\n{}\n`;\n",
+            escape(name),
+            escape(value)
+        ));
+    }
+    out
+}
+
+fn parse(contents: &str) -> Result<BTreeMap<String, String>, String> {
+    let mut entries = BTreeMap::new();
+    let mut rest = contents;
+    while let Some(start) = rest.find("exports[`") {
+        rest = &rest[start + "exports[`".len()..];
+        let (name, after_name) = read_template(rest)?;
+        rest = after_name
+            .strip_prefix("] = `")
+            .ok_or("expected '] = `' after a snapshot name")?;
+        let (value, after_value) = read_template(rest)?;
+        rest = after_value;
+
+        // Values sit on their own lines between the backticks
+        let value = value
+            .strip_prefix('\n')
+            .and_then(|value| value.strip_suffix('\n'))
+            .unwrap_or(&value)
+            .to_string();
+        entries.insert(name, value);
+    }
+    Ok(entries)
+}
+
+/// Unescape a template literal up to its closing backtick, returning the
+/// text and what follows the backtick
+fn read_template(input: &str) -> Result<(String, &str), String> {
+    let mut out = String::new();
+    let mut chars = input.char_indices();
+    while let Some((i, ch)) = chars.next() {
+        match ch {
+            '`' => return Ok((out, &input[i + 1..])),
+            '\\' => match chars.next() {
+                Some((_, escaped)) => out.push(escaped),
+                None => break,
+            },
+            ch => out.push(ch),
+        }
+    }
+    Err("unterminated template literal".to_string())
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('`', "\\`")
+        .replace("${", "\\${")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let mut entries = BTreeMap::new();
+        entries.insert(
+            "user › renders 1".to_string(),
+            "{\n  \"a\": 1\n}".to_string(),
+        );
+        entries.insert("odd `name`".to_string(), "\\ and ${x} and `".to_string());
+        let serialized = serialize(&entries);
+        assert!(serialized.contains("exports[`user › renders 1`] = `\n{\n  \"a\": 1\n}\n`;\n"));
+        assert_eq!(parse(&serialized).unwrap(), entries);
+        assert!(parse("exports[`a`] = `never closed").is_err());
+    }
+
+    #[test]
+    fn test_check() {
+        let dir = std::env::temp_dir().join(format!("rode-snapshot-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("math.test.ts");
+
+        assert!(check("1", None).is_err());
+        start_test(&file, "adds");
+        assert!(matches!(check("3", None).unwrap(), Check::Written));
+        assert!(matches!(check("4", Some("big")).unwrap(), Check::Written));
+        end_test();
+        assert_eq!(save().unwrap(), 1);
+        FILES.with(|files| files.borrow_mut().clear());
+
+        start_test(&file, "adds");
+        assert!(matches!(check("3", None).unwrap(), Check::Matched));
+        match check("5", Some("big")).unwrap() {
+            Check::Mismatched { name, expected } => {
+                assert_eq!(name, "adds: big");
+                assert_eq!(expected, "4");
+            }
+            _ => panic!("expected a mismatch"),
+        }
+        end_test();
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
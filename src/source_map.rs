@@ -0,0 +1,346 @@
+/// Source maps for transpiled scripts
+/// The TypeScript stripper and ES module converter only ever drop characters
+/// from a line or swap a whole line for a generated one, so a map is built by
+/// aligning every generated line against the original line it came from.
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+const BASE64_CHARS: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Segment {
+    generated_column: usize,
+    original_line: usize,
+    original_column: usize,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct SourceMap {
+    lines: Vec<Vec<Segment>>,
+}
+
+impl SourceMap {
+    /// Map where every generated position is the same position in the original
+    pub fn identity(source: &str) -> Self {
+        let origins: Vec<usize> = (0..source.lines().count()).collect();
+        Self::from_line_origins(source, source, &origins)
+    }
+
+    /// Build a map from generated code where `origins[i]` is the 0-based
+    /// original line that generated line `i` was produced from
+    pub fn from_line_origins(original: &str, generated: &str, origins: &[usize]) -> Self {
+        let original_lines: Vec<&str> = original.lines().collect();
+        let lines = generated
+            .lines()
+            .zip(origins)
+            .map(|(generated_line, &origin)| {
+                let original_line = original_lines.get(origin).copied().unwrap_or("");
+                align_line(generated_line, original_line, origin)
+            })
+            .collect();
+
+        Self { lines }
+    }
+
+    /// Look up the original position of a 1-based generated line and column
+    pub fn original_position(&self, line: usize, column: usize) -> Option<(usize, usize)> {
+        let segments = self.lines.get(line.checked_sub(1)?)?;
+        let column = column.saturating_sub(1);
+        let segment = segments
+            .iter()
+            .rev()
+            .find(|s| s.generated_column <= column)
+            .or_else(|| segments.first())?;
+
+        let offset = column.saturating_sub(segment.generated_column);
+        Some((
+            segment.original_line + 1,
+            segment.original_column + offset + 1,
+        ))
+    }
+
+    /// Serialize as a version 3 source map with the original source embedded
+    pub fn to_json(&self, file: &str, source: &str) -> String {
+        serde_json::json!({
+            "version": 3,
+            "file": file,
+            "sources": [file],
+            "sourcesContent": [source],
+            "names": [],
+            "mappings": self.encode_mappings(),
+        })
+        .to_string()
+    }
+
+    /// Serialize as a `data:` URL suitable for `ScriptOrigin`'s source map URL
+    pub fn to_data_url(&self, file: &str, source: &str) -> String {
+        format!(
+            "data:application/json;base64,{}",
+            base64_encode(self.to_json(file, source).as_bytes())
+        )
+    }
+
+    /// Rebuild a map from the `mappings` field of a version 3 source map
+    pub fn from_mappings(mappings: &str) -> Option<Self> {
+        let mut lines = Vec::new();
+        let mut original_line = 0i64;
+        let mut original_column = 0i64;
+
+        for line in mappings.split(';') {
+            let mut segments = Vec::new();
+            let mut generated_column = 0i64;
+            for segment in line.split(',').filter(|s| !s.is_empty()) {
+                let values = decode_vlq(segment)?;
+                if values.len() < 4 {
+                    return None;
+                }
+                generated_column += values[0];
+                original_line += values[2];
+                original_column += values[3];
+                segments.push(Segment {
+                    generated_column: usize::try_from(generated_column).ok()?,
+                    original_line: usize::try_from(original_line).ok()?,
+                    original_column: usize::try_from(original_column).ok()?,
+                });
+            }
+            lines.push(segments);
+        }
+
+        Some(Self { lines })
+    }
+
+    pub fn encode_mappings(&self) -> String {
+        let mut mappings = String::new();
+        let mut previous_line = 0i64;
+        let mut previous_column = 0i64;
+
+        for (i, segments) in self.lines.iter().enumerate() {
+            if i > 0 {
+                mappings.push(';');
+            }
+
+            let mut previous_generated = 0i64;
+            for (j, segment) in segments.iter().enumerate() {
+                if j > 0 {
+                    mappings.push(',');
+                }
+                encode_vlq(
+                    &mut mappings,
+                    segment.generated_column as i64 - previous_generated,
+                );
+                encode_vlq(&mut mappings, 0);
+                encode_vlq(&mut mappings, segment.original_line as i64 - previous_line);
+                encode_vlq(
+                    &mut mappings,
+                    segment.original_column as i64 - previous_column,
+                );
+
+                previous_generated = segment.generated_column as i64;
+                previous_line = segment.original_line as i64;
+                previous_column = segment.original_column as i64;
+            }
+        }
+
+        mappings
+    }
+}
+
+fn align_line(generated: &str, original: &str, origin: usize) -> Vec<Segment> {
+    let original_chars: Vec<char> = original.chars().collect();
+    let mut segments = Vec::new();
+    let mut original_index = 0;
+    let mut previous_delta = None;
+
+    for (generated_index, ch) in generated.chars().enumerate() {
+        while original_index < original_chars.len() && original_chars[original_index] != ch {
+            original_index += 1;
+        }
+
+        if original_index == original_chars.len() {
+            // Not a pure deletion (e.g. an import rewritten to require), so
+            // point the whole line at the start of its original
+            return vec![Segment {
+                generated_column: 0,
+                original_line: origin,
+                original_column: 0,
+            }];
+        }
+
+        let delta = original_index - generated_index;
+        if previous_delta != Some(delta) {
+            segments.push(Segment {
+                generated_column: generated_index,
+                original_line: origin,
+                original_column: original_index,
+            });
+            previous_delta = Some(delta);
+        }
+        original_index += 1;
+    }
+
+    if segments.is_empty() {
+        segments.push(Segment {
+            generated_column: 0,
+            original_line: origin,
+            original_column: 0,
+        });
+    }
+
+    segments
+}
+
+fn encode_vlq(out: &mut String, value: i64) {
+    let mut vlq = if value < 0 {
+        ((-value) << 1) | 1
+    } else {
+        value << 1
+    };
+
+    loop {
+        let mut digit = (vlq & 0b11111) as usize;
+        vlq >>= 5;
+        if vlq > 0 {
+            digit |= 0b100000;
+        }
+        out.push(BASE64_CHARS[digit] as char);
+        if vlq == 0 {
+            break;
+        }
+    }
+}
+
+fn decode_vlq(segment: &str) -> Option<Vec<i64>> {
+    let mut values = Vec::new();
+    let mut value = 0i64;
+    let mut shift = 0;
+
+    for byte in segment.bytes() {
+        let digit = BASE64_CHARS.iter().position(|&c| c == byte)? as i64;
+        value |= (digit & 0b11111) << shift;
+        if digit & 0b100000 != 0 {
+            shift += 5;
+            continue;
+        }
+        values.push(if value & 1 == 1 {
+            -(value >> 1)
+        } else {
+            value >> 1
+        });
+        value = 0;
+        shift = 0;
+    }
+
+    Some(values)
+}
+
+pub fn base64_encode(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+
+    for chunk in bytes.chunks(3) {
+        let b = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+        let n = ((b[0] as u32) << 16) | ((b[1] as u32) << 8) | b[2] as u32;
+
+        out.push(BASE64_CHARS[(n >> 18) as usize & 63] as char);
+        out.push(BASE64_CHARS[(n >> 12) as usize & 63] as char);
+        if chunk.len() > 1 {
+            out.push(BASE64_CHARS[(n >> 6) as usize & 63] as char);
+        } else {
+            out.push('=');
+        }
+        if chunk.len() > 2 {
+            out.push(BASE64_CHARS[n as usize & 63] as char);
+        } else {
+            out.push('=');
+        }
+    }
+
+    out
+}
+
+struct Registered {
+    source: Rc<str>,
+    map: SourceMap,
+}
+
+thread_local! {
+    static REGISTRY: RefCell<HashMap<String, Rc<Registered>>> = RefCell::new(HashMap::new());
+}
+
+/// Remember the original source and map for a script about to be compiled
+pub fn register(filename: &str, source: &str, map: SourceMap) {
+    let entry = Rc::new(Registered {
+        source: Rc::from(source),
+        map,
+    });
+    REGISTRY.with(|registry| {
+        registry.borrow_mut().insert(filename.to_string(), entry);
+    });
+}
+
+fn registered(filename: &str) -> Option<Rc<Registered>> {
+    REGISTRY.with(|registry| registry.borrow().get(filename).cloned())
+}
+
+/// Map a 1-based generated line and column in `filename` back to the original
+pub fn lookup(filename: &str, line: usize, column: usize) -> Option<(usize, usize)> {
+    registered(filename)?.map.original_position(line, column)
+}
+
+/// The untransformed source registered for `filename`
+pub fn original_source(filename: &str) -> Option<Rc<str>> {
+    registered(filename).map(|entry| entry.source.clone())
+}
+
+/// The `data:` source map URL registered for `filename`
+pub fn data_url(filename: &str) -> Option<String> {
+    let entry = registered(filename)?;
+    Some(entry.map.to_data_url(filename, &entry.source))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_maps_stripped_columns() {
+        let original = "function add(a: number, b: number): number {";
+        let generated = "function add(a, b) {";
+        let map = SourceMap::from_line_origins(original, generated, &[0]);
+
+        // `b` moved from column 25 to column 17
+        assert_eq!(map.original_position(1, 17), Some((1, 25)));
+        assert_eq!(map.original_position(1, 1), Some((1, 1)));
+    }
+
+    #[test]
+    fn test_maps_removed_lines() {
+        let original = "interface A {\n  x: number;\n}\nthrow new Error('x');";
+        let generated = "throw new Error('x');";
+        let map = SourceMap::from_line_origins(original, generated, &[3]);
+
+        assert_eq!(map.original_position(1, 7), Some((4, 7)));
+        assert_eq!(map.original_position(2, 1), None);
+    }
+
+    #[test]
+    fn test_encodes_mappings() {
+        let map = SourceMap::identity("a\nb");
+        assert_eq!(map.encode_mappings(), "AAAA;AACA");
+        assert_eq!(base64_encode(b"rode"), "cm9kZQ==");
+    }
+
+    #[test]
+    fn test_decodes_mappings() {
+        let original = "const x: number = -1;\n\nlet y: string = 'a';";
+        let generated = "const x = -1;\nlet y = 'a';";
+        let map = SourceMap::from_line_origins(original, generated, &[0, 2]);
+
+        let decoded = SourceMap::from_mappings(&map.encode_mappings()).unwrap();
+        assert_eq!(decoded.lines, map.lines);
+    }
+}
@@ -0,0 +1,146 @@
+/// Standalone executables for `rode compile`
+/// The output is a copy of the rode binary with the transpiled entry script
+/// appended, followed by a trailer holding its length and a magic marker that
+/// the binary checks for at startup. Modules the script imports are still
+/// loaded from disk when it runs.
+use crate::source_map::SourceMap;
+use crate::typescript::Transpiled;
+use serde_json::json;
+use std::env;
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+const MAGIC: &[u8; 8] = b"RODESCR1";
+const TRAILER_LEN: u64 = 16;
+
+pub struct EmbeddedScript {
+    pub filename: String,
+    pub source: String,
+    pub transpiled: Transpiled,
+}
+
+/// Write an executable running `script` to `output`, by default the script's
+/// name without extension in the current directory
+pub fn compile(script: &str, output: Option<&str>) -> Result<PathBuf, String> {
+    let source =
+        fs::read_to_string(script).map_err(|e| format!("Cannot read file '{}': {}", script, e))?;
+    let options = crate::tsconfig::transpile_options_for(Path::new(script));
+    let transpiled = crate::cache::transpile(script, &source, &options);
+
+    let output = match output {
+        Some(output) => PathBuf::from(output),
+        None => {
+            let stem = Path::new(script)
+                .file_stem()
+                .ok_or_else(|| format!("Cannot name an executable after '{}'", script))?;
+            PathBuf::from(format!(
+                "{}{}",
+                stem.to_string_lossy(),
+                env::consts::EXE_SUFFIX
+            ))
+        }
+    };
+
+    let exe = env::current_exe().map_err(|e| format!("Cannot locate the rode binary: {}", e))?;
+    let mut binary = fs::read(&exe).map_err(|e| format!("Cannot read {}: {}", exe.display(), e))?;
+    // Compiling from a compiled binary replaces its script rather than stacking
+    if let Some((start, _)) = payload_range(
+        &binary[binary.len().saturating_sub(TRAILER_LEN as usize)..],
+        binary.len() as u64,
+    ) {
+        binary.truncate(start as usize);
+    }
+
+    let filename = Path::new(script)
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| script.to_string());
+    let payload = json!({
+        "filename": filename,
+        "source": source,
+        "code": transpiled.code,
+        "mappings": transpiled.source_map.encode_mappings(),
+    })
+    .to_string();
+    append_payload(&mut binary, payload.as_bytes());
+
+    fs::write(&output, binary)
+        .map_err(|e| format!("Failed to write {}: {}", output.display(), e))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&output, fs::Permissions::from_mode(0o755))
+            .map_err(|e| format!("Failed to make {} executable: {}", output.display(), e))?;
+    }
+
+    Ok(output)
+}
+
+/// The script compiled into the running binary, if any
+pub fn embedded_script() -> Option<EmbeddedScript> {
+    let mut file = File::open(env::current_exe().ok()?).ok()?;
+    let len = file.metadata().ok()?.len();
+    if len < TRAILER_LEN {
+        return None;
+    }
+
+    let mut trailer = [0u8; TRAILER_LEN as usize];
+    file.seek(SeekFrom::End(-(TRAILER_LEN as i64))).ok()?;
+    file.read_exact(&mut trailer).ok()?;
+    let (start, payload_len) = payload_range(&trailer, len)?;
+
+    let mut payload = vec![0u8; payload_len as usize];
+    file.seek(SeekFrom::Start(start)).ok()?;
+    file.read_exact(&mut payload).ok()?;
+
+    let entry: serde_json::Value = serde_json::from_slice(&payload).ok()?;
+    Some(EmbeddedScript {
+        filename: entry["filename"].as_str()?.to_string(),
+        source: entry["source"].as_str()?.to_string(),
+        transpiled: Transpiled {
+            code: entry["code"].as_str()?.to_string(),
+            source_map: SourceMap::from_mappings(entry["mappings"].as_str()?)?,
+        },
+    })
+}
+
+fn append_payload(binary: &mut Vec<u8>, payload: &[u8]) {
+    binary.extend_from_slice(payload);
+    binary.extend_from_slice(&(payload.len() as u64).to_le_bytes());
+    binary.extend_from_slice(MAGIC);
+}
+
+/// Start and length of the payload described by the last 16 bytes of a
+/// `file_len` byte file
+fn payload_range(trailer: &[u8], file_len: u64) -> Option<(u64, u64)> {
+    if trailer.len() != TRAILER_LEN as usize || &trailer[8..] != MAGIC {
+        return None;
+    }
+    let payload_len = u64::from_le_bytes(trailer[..8].try_into().ok()?);
+    let start = file_len
+        .checked_sub(TRAILER_LEN)?
+        .checked_sub(payload_len)?;
+    Some((start, payload_len))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_payload_round_trip() {
+        let mut binary = b"\x7fELF binary".to_vec();
+        let exe_len = binary.len() as u64;
+        append_payload(&mut binary, b"{\"code\":\"1\"}");
+
+        let trailer = &binary[binary.len() - 16..];
+        let (start, len) = payload_range(trailer, binary.len() as u64).unwrap();
+        assert_eq!(start, exe_len);
+        assert_eq!(
+            &binary[start as usize..(start + len) as usize],
+            b"{\"code\":\"1\"}"
+        );
+        assert_eq!(payload_range(&binary[..16], 16), None);
+    }
+}
@@ -0,0 +1,134 @@
+/// V8 startup snapshot of the Rode globals
+/// Building the global object, and running the JavaScript half of the
+/// standard library, is most of the cost of a new runtime. build.rs does it
+/// once in a snapshot isolate at compile time and the blob is embedded in
+/// the executable, so isolates deserialize their contexts from it. The blob
+/// refers to native functions by their position in the `natives` table,
+/// which is expanded here over the real functions in the same order.
+use crate::globals;
+use crate::modules;
+use crate::natives::{natives_api, natives_table};
+use crate::utils::{assert, console, fetch, fs, http, password, path, process, prompt, uuid};
+use rusty_v8 as v8;
+use std::sync::OnceLock;
+
+static SNAPSHOT: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/snapshot.bin"));
+static EXTERNAL_REFERENCES: OnceLock<v8::ExternalReferences> = OnceLock::new();
+
+natives_table!(natives_api);
+
+/// The native functions in the snapshot, which isolates created from it
+/// need in the same order
+pub fn external_references() -> &'static v8::ExternalReferences {
+    EXTERNAL_REFERENCES.get_or_init(|| v8::ExternalReferences::new(&native_references()))
+}
+
+/// The Rode globals, for contexts that don't come from the snapshot
+pub fn install_globals(scope: &mut v8::HandleScope) {
+    globals::install(scope, install_natives);
+}
+
+/// An isolate under the resource limits, whose contexts start with the
+/// Rode globals when `from_snapshot` is set
+pub fn new_isolate(from_snapshot: bool) -> v8::OwnedIsolate {
+    let mut params = crate::limits::create_params();
+    if from_snapshot {
+        params = params
+            .snapshot_blob(SNAPSHOT)
+            .external_references(&**external_references());
+    }
+    let mut isolate = v8::Isolate::new(params);
+    crate::limits::watch_heap(&mut isolate);
+    isolate
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime;
+
+    /// Whether a new context of `isolate` has working globals, JavaScript
+    /// factories and natives alike
+    fn natives_work(isolate: &mut v8::Isolate, from_snapshot: bool) -> bool {
+        let scope = &mut v8::HandleScope::new(isolate);
+        let context = v8::Context::new(scope);
+        let scope = &mut v8::ContextScope::new(scope, context);
+        if !from_snapshot {
+            install_globals(scope);
+        }
+        let code = v8::String::new(
+            scope,
+            "typeof Rode.assert.equal === 'function' && typeof Rode.mock.fn === 'function' \
+             && Rode.path.join('a', 'b') === ['a', 'b'].join(Rode.path.sep) \
+             && Rode.uuid.validate(Rode.uuid.nil()) && typeof require.cache === 'object'",
+        )
+        .unwrap();
+        let script = v8::Script::compile(scope, code, None).unwrap();
+        script.run(scope).unwrap().is_true()
+    }
+
+    #[test]
+    fn test_embedded_snapshot() {
+        drop(runtime::Runtime::new());
+        assert!(natives_work(&mut new_isolate(true), true));
+        assert!(natives_work(&mut new_isolate(false), false));
+    }
+
+    #[test]
+    fn test_create_and_read_snapshot() {
+        drop(runtime::Runtime::new());
+        let mut creator = v8::SnapshotCreator::new(Some(external_references()));
+        // SAFETY: the only isolate taken from this creator
+        let mut isolate = unsafe { creator.get_owned_isolate() };
+        {
+            let scope = &mut v8::HandleScope::new(&mut isolate);
+            let context = v8::Context::new(scope);
+            let scope = &mut v8::ContextScope::new(scope, context);
+            install_globals(scope);
+            creator.set_default_context(context);
+        }
+        // The creator disposes of its isolate
+        std::mem::forget(isolate);
+        let blob = creator.create_blob(v8::FunctionCodeHandling::Keep).unwrap();
+        let blob: &'static [u8] = Box::leak(blob.to_vec().into_boxed_slice());
+
+        let params = v8::CreateParams::default()
+            .snapshot_blob(blob)
+            .external_references(&**external_references());
+        assert!(natives_work(&mut v8::Isolate::new(params), true));
+    }
+
+    /// Contexts per second with and without the snapshot:
+    /// `cargo test --release startup -- --ignored --nocapture`
+    #[test]
+    #[ignore = "benchmark"]
+    fn bench_startup() {
+        const RUNS: u32 = 50;
+        drop(runtime::Runtime::new());
+
+        let time = |snapshot: bool| {
+            let start = std::time::Instant::now();
+            for _ in 0..RUNS {
+                let isolate = &mut new_isolate(snapshot);
+                let scope = &mut v8::HandleScope::new(isolate);
+                let context = v8::Context::new(scope);
+                let scope = &mut v8::ContextScope::new(scope, context);
+                if !snapshot {
+                    install_globals(scope);
+                }
+                crate::utils::setup_process_state(scope);
+            }
+            start.elapsed() / RUNS
+        };
+
+        let installed = time(false);
+        let snapshot = time(true);
+        println!(
+            "runtime startup: {:?} installing globals, {:?} from the snapshot ({:.1}x)",
+            installed,
+            snapshot,
+            installed.as_secs_f64() / snapshot.as_secs_f64()
+        );
+        assert!(snapshot < installed);
+    }
+}
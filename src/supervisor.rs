@@ -0,0 +1,180 @@
+/// Child processes for watch mode
+/// Each run of the script is a separate rode process, so a script that never
+/// returns (like a server) can be stopped when files change, and the ports it
+/// bound are released with it. The child reports the modules it loads
+/// through a graph file (see `modules::GRAPH_FILE_VAR`), and with `--hot`
+/// takes changed modules through an updates file (see `hot`).
+use crate::hot;
+use crate::modules::GRAPH_FILE_VAR;
+use std::collections::BTreeSet;
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus};
+use std::sync::OnceLock;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long a run gets to exit after `SIGTERM` before it is killed
+const STOP_GRACE: Duration = Duration::from_secs(2);
+const STOP_POLL: Duration = Duration::from_millis(10);
+
+/// The environment rode started with, before any env files were loaded
+static BASE_ENV: OnceLock<Vec<(OsString, OsString)>> = OnceLock::new();
+
+/// Remember the environment before env files change it, so children load
+/// the env files afresh and removed variables really disappear
+pub fn save_base_env() {
+    let _ = BASE_ENV.set(env::vars_os().collect());
+}
+
+pub struct Supervisor {
+    args: Vec<String>,
+    graph_path: PathBuf,
+    /// Set with `--hot`
+    updates_path: Option<PathBuf>,
+    child: Option<Child>,
+}
+
+impl Supervisor {
+    /// `flags` are rode flags every run gets, like `--inspect`
+    pub fn new(
+        filename: &str,
+        script_args: &[String],
+        hot: bool,
+        flags: Vec<String>,
+    ) -> Supervisor {
+        let mut args = Vec::new();
+        if !colored::control::SHOULD_COLORIZE.should_colorize() {
+            args.push("--no-color".to_string());
+        }
+        match crate::utils::http::default_listen() {
+            Some((host, port)) => {
+                args.extend(["serve".to_string(), "--host".to_string(), host]);
+                args.extend(["--port".to_string(), port.to_string()]);
+            }
+            None => args.push("run".to_string()),
+        }
+        args.extend(flags);
+        args.push("--".to_string());
+        args.push(filename.to_string());
+        args.extend(script_args.iter().cloned());
+
+        let temp_path = |extension: &str| {
+            env::temp_dir().join(format!("rode-watch-{}.{}", std::process::id(), extension))
+        };
+        Supervisor {
+            args,
+            graph_path: temp_path("graph"),
+            updates_path: hot.then(|| temp_path("updates")),
+            child: None,
+        }
+    }
+
+    /// Start a new run, stopping the current one first
+    pub fn start(&mut self) -> Result<(), String> {
+        self.stop();
+        fs::write(&self.graph_path, "")
+            .map_err(|e| format!("Failed to write {}: {}", self.graph_path.display(), e))?;
+
+        let exe =
+            env::current_exe().map_err(|e| format!("Cannot locate the rode binary: {}", e))?;
+        let mut command = Command::new(exe);
+        command.args(&self.args);
+        if let Some(base_env) = BASE_ENV.get() {
+            command.env_clear().envs(base_env.iter().cloned());
+        }
+        command.env(GRAPH_FILE_VAR, &self.graph_path);
+        if let Some(updates_path) = &self.updates_path {
+            fs::write(updates_path, "")
+                .map_err(|e| format!("Failed to write {}: {}", updates_path.display(), e))?;
+            command.env(hot::UPDATES_FILE_VAR, updates_path);
+        }
+
+        let child = command
+            .spawn()
+            .map_err(|e| format!("Failed to start the script: {}", e))?;
+        self.child = Some(child);
+        Ok(())
+    }
+
+    /// The exit status of the current run, once, when it has finished by itself
+    pub fn try_finished(&mut self) -> Option<ExitStatus> {
+        let status = self.child.as_mut()?.try_wait().ok()??;
+        self.child = None;
+        Some(status)
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.child.is_some()
+    }
+
+    /// Hand changed modules to a `--hot` run to swap in
+    pub fn send_update(&self, paths: &[&Path]) -> Result<(), String> {
+        let Some(updates_path) = &self.updates_path else {
+            return Err("Hot updates are off".to_string());
+        };
+        let lines: String = paths
+            .iter()
+            .map(|path| format!("{}\n", path.display()))
+            .collect();
+        fs::OpenOptions::new()
+            .append(true)
+            .open(updates_path)
+            .and_then(|mut file| file.write_all(lines.as_bytes()))
+            .map_err(|e| format!("Failed to write {}: {}", updates_path.display(), e))
+    }
+
+    /// Stop the current run and wait for it, so its ports are free again.
+    /// It gets `SIGTERM` and a moment to clean up before it is killed.
+    pub fn stop(&mut self) {
+        if let Some(mut child) = self.child.take() {
+            if terminate(&child) {
+                let deadline = Instant::now() + STOP_GRACE;
+                while Instant::now() < deadline {
+                    if let Ok(Some(_)) = child.try_wait() {
+                        return;
+                    }
+                    thread::sleep(STOP_POLL);
+                }
+            }
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+    }
+
+    /// Modules loaded by the current (or last) run so far
+    pub fn loaded_files(&self) -> BTreeSet<PathBuf> {
+        fs::read_to_string(&self.graph_path)
+            .map(|graph| graph.lines().map(PathBuf::from).collect())
+            .unwrap_or_default()
+    }
+}
+
+/// Ask `child` to exit, where the platform has a way to ask
+#[cfg(unix)]
+fn terminate(child: &Child) -> bool {
+    const SIGTERM: i32 = 15;
+    unsafe extern "C" {
+        fn kill(pid: i32, signal: i32) -> i32;
+    }
+    // SAFETY: the child has not been waited for, so the pid is still its own
+    unsafe { kill(child.id() as i32, SIGTERM) == 0 }
+}
+
+#[cfg(not(unix))]
+fn terminate(_child: &Child) -> bool {
+    false
+}
+
+impl Drop for Supervisor {
+    fn drop(&mut self) {
+        self.stop();
+        let _ = fs::remove_file(&self.graph_path);
+        if let Some(updates_path) = &self.updates_path {
+            let _ = fs::remove_file(updates_path);
+        }
+    }
+}
@@ -0,0 +1,316 @@
+/// `rode task`: named commands from rode.json
+/// Tasks run through the system shell in the directory of the config file,
+/// so they behave the same from anywhere inside the project. Dependencies
+/// run first, and tasks that don't depend on each other run in parallel.
+use crate::config::{Config, Task};
+use crate::env_parser::EnvParser;
+use colored::*;
+use std::collections::{BTreeMap, HashSet};
+use std::process::Command;
+use std::sync::mpsc::channel;
+use std::thread;
+
+/// Run task `name` after its dependencies, returning the exit code of the
+/// first task that failed (0 when all succeeded)
+pub fn run(config: &Config, name: &str) -> Result<i32, String> {
+    if !config.tasks.contains_key(name) {
+        return Err(format!(
+            "Task '{}' not found in {}",
+            name,
+            config.path.display()
+        ));
+    }
+    let plan = plan(&config.tasks, name)?;
+
+    let (tx, rx) = channel();
+    let mut started: HashSet<&str> = HashSet::new();
+    let mut finished: HashSet<String> = HashSet::new();
+    let mut running = 0;
+    let mut exit_code = 0;
+
+    loop {
+        // Start everything whose dependencies are done, unless a task failed
+        for task_name in plan.iter().filter(|_| exit_code == 0) {
+            let task = &config.tasks[task_name];
+            if started.contains(task_name.as_str())
+                || !task.depends_on.iter().all(|dep| finished.contains(dep))
+            {
+                continue;
+            }
+            started.insert(task_name);
+
+            // Tasks without a command only group their dependencies
+            if task.command.is_empty() {
+                finished.insert(task_name.clone());
+                continue;
+            }
+
+            let (command, env) = interpolate(task);
+            println!(
+                "{} {} {}",
+                "▶".cyan(),
+                task_name.cyan().bold(),
+                command.dimmed()
+            );
+            let mut child = shell(&command);
+            child.current_dir(&config.dir).envs(env);
+
+            let tx = tx.clone();
+            let task_name = task_name.clone();
+            thread::spawn(move || {
+                let result = child
+                    .status()
+                    .map(|status| status.code().unwrap_or(1))
+                    .map_err(|e| format!("Failed to run task '{}': {}", task_name, e));
+                let _ = tx.send((task_name, result));
+            });
+            running += 1;
+        }
+
+        if running == 0 {
+            break;
+        }
+        // `tx` is still alive here, so this only returns with a result
+        let Ok((task_name, result)) = rx.recv() else {
+            break;
+        };
+        running -= 1;
+
+        match result {
+            Ok(0) => {
+                finished.insert(task_name);
+            }
+            Ok(code) => {
+                print_failure(&format!(
+                    "Task '{}' failed with exit code {}",
+                    task_name, code
+                ));
+                if exit_code == 0 {
+                    exit_code = code;
+                }
+            }
+            Err(err) => {
+                print_failure(&err);
+                if exit_code == 0 {
+                    exit_code = 1;
+                }
+            }
+        }
+    }
+
+    Ok(exit_code)
+}
+
+/// Print the tasks in `config` for `rode task` without a name
+pub fn list(config: &Config) {
+    if config.tasks.is_empty() {
+        println!("No tasks defined in {}", config.path.display());
+        return;
+    }
+
+    println!(
+        "{} {}",
+        "Available tasks".bold(),
+        format!("({})", config.path.display()).dimmed()
+    );
+    let width = config
+        .tasks
+        .keys()
+        .map(|name| name.len())
+        .max()
+        .unwrap_or(0);
+    for (name, task) in &config.tasks {
+        let about = task.description.as_deref().unwrap_or(&task.command);
+        println!("  {:width$}  {}", name.cyan(), about, width = width);
+        if task.description.is_some() && !task.command.is_empty() {
+            println!(
+                "  {:width$}  {}",
+                "",
+                format!("$ {}", task.command).dimmed(),
+                width = width
+            );
+        }
+        if !task.depends_on.is_empty() {
+            println!(
+                "  {:width$}  {}",
+                "",
+                format!("depends on {}", task.depends_on.join(", ")).dimmed(),
+                width = width
+            );
+        }
+    }
+}
+
+/// `name` and everything it depends on, dependencies first
+fn plan(tasks: &BTreeMap<String, Task>, name: &str) -> Result<Vec<String>, String> {
+    fn visit(
+        tasks: &BTreeMap<String, Task>,
+        name: &str,
+        stack: &mut Vec<String>,
+        order: &mut Vec<String>,
+    ) -> Result<(), String> {
+        if order.iter().any(|done| done == name) {
+            return Ok(());
+        }
+        if let Some(start) = stack.iter().position(|pending| pending == name) {
+            let mut cycle = stack[start..].to_vec();
+            cycle.push(name.to_string());
+            return Err(format!("Task dependency cycle: {}", cycle.join(" -> ")));
+        }
+        let task = tasks.get(name).ok_or_else(|| match stack.last() {
+            Some(parent) => format!("Task '{}' depends on unknown task '{}'", parent, name),
+            None => format!("Task '{}' not found", name),
+        })?;
+
+        stack.push(name.to_string());
+        for dependency in &task.depends_on {
+            visit(tasks, dependency, stack, order)?;
+        }
+        stack.pop();
+        order.push(name.to_string());
+        Ok(())
+    }
+
+    let mut order = Vec::new();
+    visit(tasks, name, &mut Vec::new(), &mut order)?;
+    Ok(order)
+}
+
+/// Expand `$VAR` and `${VAR}`: env entries with .env semantics from the
+/// process environment, and the command from those entries only. Anything
+/// else in the command, like `$f` in a shell loop, `$?` or `${1:-x}`, is
+/// left for the shell, which sees the process environment and the entries.
+fn interpolate(task: &Task) -> (String, Vec<(String, String)>) {
+    let environment = EnvParser::new();
+    let mut env = Vec::new();
+    for (key, value) in &task.env {
+        let value = environment.expand_variables(value);
+        env.push((key.clone(), value));
+    }
+    (expand_env_entries(&task.command, &env), env)
+}
+
+fn expand_env_entries(command: &str, env: &[(String, String)]) -> String {
+    let lookup = |name: &str| {
+        env.iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    };
+    let mut result = String::new();
+    let mut rest = command;
+    while let Some(start) = rest.find('$') {
+        result.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let (name, len) = if let Some(braced) = after.strip_prefix('{') {
+            match braced.find('}') {
+                Some(end) => (&braced[..end], end + 2),
+                None => ("", 0),
+            }
+        } else {
+            let end = after
+                .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+                .unwrap_or(after.len());
+            let starts_like_a_name = after
+                .chars()
+                .next()
+                .is_some_and(|c| c.is_alphabetic() || c == '_');
+            if starts_like_a_name {
+                (&after[..end], end)
+            } else {
+                ("", 0)
+            }
+        };
+        match lookup(name).filter(|_| !name.is_empty()) {
+            Some(value) => {
+                result.push_str(value);
+                rest = &after[len..];
+            }
+            None => {
+                result.push('$');
+                rest = after;
+            }
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+fn shell(command: &str) -> Command {
+    if cfg!(windows) {
+        let mut shell = Command::new("cmd");
+        shell.arg("/C").arg(command);
+        shell
+    } else {
+        let mut shell = Command::new("sh");
+        shell.arg("-c").arg(command);
+        shell
+    }
+}
+
+fn print_failure(message: &str) {
+    println!("{} {}", "ERROR".red().bold(), message.red().bold());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn task(command: &str, depends_on: &[&str]) -> Task {
+        Task {
+            command: command.to_string(),
+            depends_on: depends_on.iter().map(|name| name.to_string()).collect(),
+            ..Task::default()
+        }
+    }
+
+    #[test]
+    fn test_plan() {
+        let mut tasks = BTreeMap::new();
+        tasks.insert("lint".to_string(), task("eslint .", &[]));
+        tasks.insert("build".to_string(), task("tsc", &["lint"]));
+        tasks.insert("test".to_string(), task("rode test", &["build", "lint"]));
+        tasks.insert("ci".to_string(), task("", &["test", "build"]));
+        assert_eq!(plan(&tasks, "ci").unwrap(), ["lint", "build", "test", "ci"]);
+
+        tasks.insert("lint".to_string(), task("eslint .", &["test"]));
+        assert_eq!(
+            plan(&tasks, "build").unwrap_err(),
+            "Task dependency cycle: build -> lint -> test -> build"
+        );
+
+        tasks.insert("lint".to_string(), task("eslint .", &["format"]));
+        assert!(
+            plan(&tasks, "build")
+                .unwrap_err()
+                .contains("unknown task 'format'")
+        );
+    }
+
+    #[test]
+    fn test_interpolate() {
+        let mut deploy = task("echo ${TARGET}/$NAME $1 $RODE_TASK_UNSET", &[]);
+        deploy.env.insert("NAME".to_string(), "app".to_string());
+        deploy
+            .env
+            .insert("TARGET".to_string(), "dist${RODE_TASK_UNSET}".to_string());
+
+        let (command, env) = interpolate(&deploy);
+        assert_eq!(command, "echo dist/app $1 $RODE_TASK_UNSET");
+        assert_eq!(env[1], ("TARGET".to_string(), "dist".to_string()));
+    }
+
+    #[test]
+    fn test_interpolate_leaves_shell_variables() {
+        let mut list = task(
+            "for f in *; do echo $f $NAME; done; echo $? ${1:-x} '$HOME' ${NAME}s $",
+            &[],
+        );
+        list.env.insert("NAME".to_string(), "app".to_string());
+
+        let (command, _) = interpolate(&list);
+        assert_eq!(
+            command,
+            "for f in *; do echo $f app; done; echo $? ${1:-x} '$HOME' apps $"
+        );
+    }
+}
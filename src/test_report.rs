@@ -0,0 +1,278 @@
+/// Results and reporters for `rode test`
+/// The pretty reporter prints each test as it finishes; TAP and JUnit XML
+/// are built from the collected results once every file has run.
+use colored::*;
+use std::time::Duration;
+
+pub enum Outcome {
+    Passed,
+    Failed(String),
+    Skipped,
+}
+
+pub struct TestResult {
+    pub file: String,
+    /// Enclosing describe() names, outermost first
+    pub suites: Vec<String>,
+    pub name: String,
+    pub outcome: Outcome,
+    pub duration: Duration,
+}
+
+impl TestResult {
+    pub fn full_name(&self) -> String {
+        let mut parts: Vec<&str> = self.suites.iter().map(String::as_str).collect();
+        parts.push(&self.name);
+        parts.join(" › ")
+    }
+
+    fn is_failed(&self) -> bool {
+        matches!(self.outcome, Outcome::Failed(_))
+    }
+}
+
+pub fn print_file(file: &str) {
+    println!("{} {}", "▶".cyan(), file.cyan().bold());
+}
+
+pub fn print_result(result: &TestResult) {
+    let name = result.full_name();
+    let time = format!("({}ms)", result.duration.as_millis()).dimmed();
+    match &result.outcome {
+        Outcome::Passed => println!("  {} {} {}", "✓".green(), name, time),
+        Outcome::Failed(_) => println!("  {} {} {}", "✗".red().bold(), name.red(), time),
+        Outcome::Skipped => println!("  {} {}", "○".yellow(), name.dimmed()),
+    }
+}
+
+/// The failures again with their errors, then the totals
+pub fn print_summary(results: &[TestResult], snapshots_written: usize, elapsed: Duration) {
+    let failures: Vec<&TestResult> = results.iter().filter(|r| r.is_failed()).collect();
+    if !failures.is_empty() {
+        println!();
+        println!("{}", "Failures".red().bold());
+        for result in &failures {
+            if let Outcome::Failed(error) = &result.outcome {
+                println!();
+                println!(
+                    "{} {} {}",
+                    "●".red(),
+                    result.file.dimmed(),
+                    result.full_name().red().bold()
+                );
+                for line in error.lines() {
+                    println!("    {}", line);
+                }
+            }
+        }
+    }
+
+    let (passed, failed, skipped) = counts(results);
+    let mut parts = Vec::new();
+    if passed > 0 {
+        parts.push(format!("{} passed", passed).green().bold().to_string());
+    }
+    if failed > 0 {
+        parts.push(format!("{} failed", failed).red().bold().to_string());
+    }
+    if skipped > 0 {
+        parts.push(format!("{} skipped", skipped).yellow().to_string());
+    }
+    if parts.is_empty() {
+        parts.push("no tests".yellow().to_string());
+    }
+
+    println!();
+    println!("{}", "─".repeat(60).dimmed());
+    println!(
+        "{} {} {}",
+        "Tests:".bold(),
+        parts.join(", "),
+        format!("({} total, {}ms)", results.len(), elapsed.as_millis()).dimmed()
+    );
+    if snapshots_written > 0 {
+        let files = if snapshots_written == 1 {
+            "file"
+        } else {
+            "files"
+        };
+        println!(
+            "{} {}",
+            "Snapshots:".bold(),
+            format!("{} {} written", snapshots_written, files).cyan()
+        );
+    }
+}
+
+fn counts(results: &[TestResult]) -> (usize, usize, usize) {
+    let mut counts = (0, 0, 0);
+    for result in results {
+        match result.outcome {
+            Outcome::Passed => counts.0 += 1,
+            Outcome::Failed(_) => counts.1 += 1,
+            Outcome::Skipped => counts.2 += 1,
+        }
+    }
+    counts
+}
+
+/// Test Anything Protocol, version 13
+pub fn tap(results: &[TestResult]) -> String {
+    let mut out = format!("TAP version 13\n1..{}\n", results.len());
+    for (i, result) in results.iter().enumerate() {
+        let description = format!("{} › {}", result.file, result.full_name()).replace('#', "\\#");
+        match &result.outcome {
+            Outcome::Passed => out.push_str(&format!("ok {} - {}\n", i + 1, description)),
+            Outcome::Skipped => out.push_str(&format!("ok {} - {} # SKIP\n", i + 1, description)),
+            Outcome::Failed(error) => {
+                out.push_str(&format!("not ok {} - {}\n", i + 1, description));
+                out.push_str("  ---\n  message: |-\n");
+                for line in error.lines() {
+                    out.push_str(&format!("    {}\n", line));
+                }
+                out.push_str(&format!(
+                    "  duration_ms: {}\n  ...\n",
+                    result.duration.as_millis()
+                ));
+            }
+        }
+    }
+    out
+}
+
+/// JUnit XML with one `<testsuite>` per file, as CI servers expect
+pub fn junit(results: &[TestResult], elapsed: Duration) -> String {
+    let (_, failed, skipped) = counts(results);
+    let mut out = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    out.push_str(&format!(
+        "<testsuites name=\"rode test\" tests=\"{}\" failures=\"{}\" skipped=\"{}\" time=\"{:.3}\">\n",
+        results.len(),
+        failed,
+        skipped,
+        elapsed.as_secs_f64()
+    ));
+
+    let mut files: Vec<&str> = Vec::new();
+    for result in results {
+        if !files.contains(&result.file.as_str()) {
+            files.push(&result.file);
+        }
+    }
+    for file in files {
+        let tests: Vec<&TestResult> = results.iter().filter(|r| r.file == file).collect();
+        let time: Duration = tests.iter().map(|r| r.duration).sum();
+        out.push_str(&format!(
+            "  <testsuite name=\"{}\" tests=\"{}\" failures=\"{}\" skipped=\"{}\" time=\"{:.3}\">\n",
+            xml_escape(file),
+            tests.len(),
+            tests.iter().filter(|r| r.is_failed()).count(),
+            tests
+                .iter()
+                .filter(|r| matches!(r.outcome, Outcome::Skipped))
+                .count(),
+            time.as_secs_f64()
+        ));
+        for result in tests {
+            let mut classname = vec![result.file.as_str()];
+            classname.extend(result.suites.iter().map(String::as_str));
+            out.push_str(&format!(
+                "    <testcase classname=\"{}\" name=\"{}\" time=\"{:.3}\"",
+                xml_escape(&classname.join(" › ")),
+                xml_escape(&result.name),
+                result.duration.as_secs_f64()
+            ));
+            match &result.outcome {
+                Outcome::Passed => out.push_str("/>\n"),
+                Outcome::Skipped => out.push_str(">\n      <skipped/>\n    </testcase>\n"),
+                Outcome::Failed(error) => {
+                    let message = error.lines().next().unwrap_or_default();
+                    out.push_str(&format!(
+                        ">\n      <failure message=\"{}\">{}</failure>\n    </testcase>\n",
+                        xml_escape(message),
+                        xml_escape(error)
+                    ));
+                }
+            }
+        }
+        out.push_str("  </testsuite>\n");
+    }
+    out.push_str("</testsuites>\n");
+    out
+}
+
+fn xml_escape(text: &str) -> String {
+    // Error messages carry terminal colors, which are invalid in XML
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '\x1b' => {
+                // Skip `ESC [ ... letter`
+                if chars.peek() == Some(&'[') {
+                    for next in chars.by_ref() {
+                        if next.is_ascii_alphabetic() {
+                            break;
+                        }
+                    }
+                }
+            }
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            ch if ch.is_control() && ch != '\n' && ch != '\t' => {}
+            ch => out.push(ch),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn results() -> Vec<TestResult> {
+        let result = |name: &str, outcome| TestResult {
+            file: "math.test.ts".to_string(),
+            suites: vec!["add".to_string()],
+            name: name.to_string(),
+            outcome,
+            duration: Duration::from_millis(2),
+        };
+        vec![
+            result("adds", Outcome::Passed),
+            result(
+                "overflows #1",
+                Outcome::Failed("Error: 1 < 2\n    at x".into()),
+            ),
+            result("later", Outcome::Skipped),
+        ]
+    }
+
+    #[test]
+    fn test_tap() {
+        let tap = tap(&results());
+        assert!(tap.starts_with("TAP version 13\n1..3\nok 1 - math.test.ts › add › adds\n"));
+        assert!(tap.contains("not ok 2 - math.test.ts › add › overflows \\#1\n  ---\n"));
+        assert!(tap.contains("    Error: 1 < 2\n"));
+        assert!(tap.contains("ok 3 - math.test.ts › add › later # SKIP\n"));
+    }
+
+    #[test]
+    fn test_junit() {
+        let xml = junit(&results(), Duration::from_millis(10));
+        assert!(xml.contains("tests=\"3\" failures=\"1\" skipped=\"1\" time=\"0.010\""));
+        assert!(
+            xml.contains(
+                "<testcase classname=\"math.test.ts › add\" name=\"adds\" time=\"0.002\"/>"
+            )
+        );
+        assert!(xml.contains("<failure message=\"Error: 1 &lt; 2\">"));
+        assert!(xml.contains("<skipped/>"));
+        assert_eq!(
+            xml_escape("\x1b[31mred\x1b[0m & \"q\""),
+            "red &amp; &quot;q&quot;"
+        );
+    }
+}
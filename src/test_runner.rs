@@ -0,0 +1,896 @@
+/// `rode test`: find test files and run the tests they register
+/// Each file runs in its own isolate. While the file is evaluated,
+/// `describe`, `it`/`test` (also `Rode.test`) and the hooks only record what
+/// to run; the tests run afterwards with their hooks around them. A test
+/// that runs past its timeout is stopped by terminating the isolate, and an
+/// async test is awaited by running microtasks until its promise settles.
+/// Snapshots taken by `Rode.assert.snapshot` are saved after each file, and
+/// with `--coverage` the isolate's coverage is collected before it goes away.
+use crate::cli::{TestArgs, TestReporter};
+use crate::coverage;
+use crate::runtime::Runtime;
+use crate::snapshot;
+use crate::test_report::{self, Outcome, TestResult};
+use regex::Regex;
+use rusty_v8 as v8;
+use std::cell::RefCell;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{RecvTimeoutError, channel};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const DEFAULT_TIMEOUT_MS: u64 = 5000;
+
+const TEST_EXTENSIONS: [&str; 4] = ["js", "ts", "jsx", "tsx"];
+
+#[derive(Default, Clone, Copy, PartialEq)]
+enum Mode {
+    #[default]
+    Normal,
+    Only,
+    Skip,
+}
+
+impl Mode {
+    /// The mode of a suite marked `own` inside one in this mode; skipping wins
+    fn within(self, own: Mode) -> Mode {
+        match (self, own) {
+            (Mode::Skip, _) | (_, Mode::Skip) => Mode::Skip,
+            (Mode::Only, _) | (_, Mode::Only) => Mode::Only,
+            _ => Mode::Normal,
+        }
+    }
+}
+
+#[derive(Default)]
+struct Suite {
+    name: String,
+    mode: Mode,
+    before_all: Vec<v8::Global<v8::Function>>,
+    after_all: Vec<v8::Global<v8::Function>>,
+    before_each: Vec<v8::Global<v8::Function>>,
+    after_each: Vec<v8::Global<v8::Function>>,
+    children: Vec<Entry>,
+}
+
+enum Entry {
+    Test(Test),
+    Suite(Suite),
+}
+
+struct Test {
+    name: String,
+    /// `None` for `it.skip(name)` without a body
+    function: Option<v8::Global<v8::Function>>,
+    mode: Mode,
+    timeout: Option<u64>,
+}
+
+thread_local! {
+    /// The file's root suite, then the describe() blocks being evaluated.
+    /// Empty while tests run, so registering then is an error.
+    static REGISTERING: RefCell<Vec<Suite>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Run the tests in `args.paths`, returning the exit code
+pub fn run(args: &TestArgs) -> Result<i32, String> {
+    let files = discover(&args.paths, is_test_file)?;
+    if files.is_empty() {
+        return Err("No test files found (*.test.{js,ts,jsx,tsx} or *_test.*)".to_string());
+    }
+    let filter = match &args.filter {
+        Some(pattern) => Some(name_filter(pattern)?),
+        None => None,
+    };
+
+    snapshot::set_update(args.update_snapshots);
+    if args.coverage.is_some() {
+        coverage::enable();
+    }
+    let mut runner = Runner {
+        file: String::new(),
+        filter,
+        timeout: args.timeout.unwrap_or(DEFAULT_TIMEOUT_MS),
+        has_only: false,
+        // The terminal still shows progress when the report goes to a file
+        pretty: args.reporter == TestReporter::Pretty || args.reporter_output.is_some(),
+        results: Vec::new(),
+        snapshots_written: 0,
+    };
+    let started = Instant::now();
+    for file in &files {
+        runner.run_file(file);
+    }
+    let elapsed = started.elapsed();
+
+    if runner.pretty {
+        test_report::print_summary(&runner.results, runner.snapshots_written, elapsed);
+    }
+    if let Some(dir) = &args.coverage {
+        coverage::report(Path::new(dir), runner.pretty)?;
+    }
+    let report = match args.reporter {
+        TestReporter::Pretty => None,
+        TestReporter::Tap => Some(test_report::tap(&runner.results)),
+        TestReporter::Junit => Some(test_report::junit(&runner.results, elapsed)),
+    };
+    match (report, &args.reporter_output) {
+        (Some(report), Some(path)) => fs::write(path, report)
+            .map_err(|e| format!("Failed to write report to {}: {}", path, e))?,
+        (Some(report), None) => print!("{}", report),
+        (None, _) => {}
+    }
+
+    let failed = runner
+        .results
+        .iter()
+        .any(|result| matches!(result.outcome, Outcome::Failed(_)));
+    Ok(if failed { 1 } else { 0 })
+}
+
+/// Files under `paths` whose name `matches`, and the files `paths` names
+/// directly; also finds `rode bench` files
+pub fn discover(paths: &[String], matches: fn(&str) -> bool) -> Result<Vec<PathBuf>, String> {
+    fn walk(dir: &Path, matches: fn(&str) -> bool, files: &mut Vec<PathBuf>) {
+        let Ok(entries) = fs::read_dir(dir) else {
+            return;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let name = entry.file_name().to_string_lossy().to_string();
+            if name.starts_with('.') || name == "node_modules" {
+                continue;
+            }
+            if path.is_dir() {
+                walk(&path, matches, files);
+            } else if matches(&name) {
+                files.push(path);
+            }
+        }
+    }
+
+    let roots: Vec<&str> = match paths {
+        [] => vec!["."],
+        paths => paths.iter().map(String::as_str).collect(),
+    };
+    let mut files = Vec::new();
+    for root in roots {
+        let path = Path::new(root);
+        if path.is_file() {
+            files.push(path.to_path_buf());
+        } else if path.is_dir() {
+            walk(path, matches, &mut files);
+        } else {
+            return Err(format!("No such file or directory: {}", root));
+        }
+    }
+    files.sort();
+    files.dedup();
+    Ok(files)
+}
+
+pub fn is_test_file(name: &str) -> bool {
+    let Some((stem, extension)) = name.rsplit_once('.') else {
+        return false;
+    };
+    TEST_EXTENSIONS.contains(&extension) && (stem.ends_with(".test") || stem.ends_with("_test"))
+}
+
+/// `--filter`: a substring of the test's full name, or `/regex/`
+pub fn name_filter(pattern: &str) -> Result<Regex, String> {
+    let regex = match pattern
+        .strip_prefix('/')
+        .and_then(|rest| rest.strip_suffix('/'))
+    {
+        Some(regex) if !regex.is_empty() => regex.to_string(),
+        _ => regex::escape(pattern),
+    };
+    Regex::new(&regex).map_err(|e| format!("Invalid --filter '{}': {}", pattern, e))
+}
+
+struct Runner {
+    /// The current file, as shown in reports
+    file: String,
+    filter: Option<Regex>,
+    timeout: u64,
+    /// Whether the current file uses `.only`
+    has_only: bool,
+    pretty: bool,
+    results: Vec<TestResult>,
+    snapshots_written: usize,
+}
+
+impl Runner {
+    fn run_file(&mut self, path: &Path) {
+        let cwd = std::env::current_dir().unwrap_or_default();
+        self.file = path
+            .strip_prefix(".")
+            .ok()
+            .or_else(|| path.strip_prefix(&cwd).ok())
+            .unwrap_or(path)
+            .display()
+            .to_string();
+        if self.pretty {
+            test_report::print_file(&self.file);
+        }
+
+        let filename = path.to_string_lossy().to_string();
+        let source = match fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(e) => {
+                self.file_error(format!("Cannot read {}: {}", filename, e));
+                return;
+            }
+        };
+        let options = crate::tsconfig::transpile_options_for(path);
+        let transpiled = crate::cache::transpile(&filename, &source, &options);
+
+        let mut runtime = Runtime::new();
+        let context = runtime.create_context();
+        runtime.with_context(&context, setup_test_api);
+        REGISTERING.with(|suites| *suites.borrow_mut() = vec![Suite::default()]);
+        let loaded = runtime.execute_transpiled_in(&context, transpiled, &source, &filename);
+        let root = REGISTERING.with(|suites| suites.borrow_mut().drain(..).next());
+        if let Err(err) = loaded {
+            self.file_error(err);
+            return;
+        }
+        let Some(root) = root else {
+            return;
+        };
+
+        self.has_only = has_only(&root);
+        runtime.with_context(&context, |scope| {
+            self.run_suite(scope, &root, &[], Mode::Normal, None);
+        });
+        match snapshot::save() {
+            Ok(written) => self.snapshots_written += written,
+            Err(err) => self.file_error(err),
+        }
+        runtime.collect_coverage();
+    }
+
+    /// A file that failed to load counts as one failed test
+    fn file_error(&mut self, error: String) {
+        let result = TestResult {
+            file: self.file.clone(),
+            suites: Vec::new(),
+            name: "(loading the file)".to_string(),
+            outcome: Outcome::Failed(error.trim().to_string()),
+            duration: Duration::ZERO,
+        };
+        self.report(result);
+    }
+
+    fn report(&mut self, result: TestResult) {
+        if self.pretty {
+            test_report::print_result(&result);
+        }
+        self.results.push(result);
+    }
+
+    /// Run `suite` below the enclosing suites `parents`. `inherited` is the
+    /// strongest `.only`/`.skip` of the parents, and `setup_error` a failed
+    /// beforeAll hook of a parent, which fails every test below it.
+    fn run_suite(
+        &mut self,
+        scope: &mut v8::HandleScope,
+        suite: &Suite,
+        parents: &[&Suite],
+        inherited: Mode,
+        setup_error: Option<&str>,
+    ) {
+        let mode = inherited.within(suite.mode);
+        let mut suites: Vec<&Suite> = parents.to_vec();
+        suites.push(suite);
+        let runs_tests = self.runs_tests(suite, &suites, mode);
+
+        let mut own_error = None;
+        if runs_tests && setup_error.is_none() {
+            for hook in &suite.before_all {
+                if let Err(err) = self.call(scope, hook, self.timeout) {
+                    own_error = Some(format!("beforeAll hook failed: {}", err));
+                    break;
+                }
+            }
+        }
+        let setup_error = setup_error.or(own_error.as_deref());
+
+        for child in &suite.children {
+            match child {
+                Entry::Test(test) => self.run_test(scope, test, &suites, mode, setup_error),
+                Entry::Suite(child) => self.run_suite(scope, child, &suites, mode, setup_error),
+            }
+        }
+
+        if runs_tests {
+            for hook in &suite.after_all {
+                if let Err(err) = self.call(scope, hook, self.timeout) {
+                    let result = TestResult {
+                        file: self.file.clone(),
+                        suites: suite_names(&suites),
+                        name: "(afterAll hook)".to_string(),
+                        outcome: Outcome::Failed(err),
+                        duration: Duration::ZERO,
+                    };
+                    self.report(result);
+                    break;
+                }
+            }
+        }
+    }
+
+    fn run_test(
+        &mut self,
+        scope: &mut v8::HandleScope,
+        test: &Test,
+        suites: &[&Suite],
+        inherited: Mode,
+        setup_error: Option<&str>,
+    ) {
+        if !self.matches_filter(suites, test) {
+            return;
+        }
+
+        let started = Instant::now();
+        let outcome = match &test.function {
+            Some(function) if self.selected(test, inherited) => match setup_error {
+                Some(error) => Outcome::Failed(error.to_string()),
+                None => {
+                    let mut names = suite_names(suites);
+                    names.push(test.name.clone());
+                    snapshot::start_test(Path::new(&self.file), &names.join(" › "));
+                    let outcome = self.run_body(scope, function, test.timeout, suites);
+                    snapshot::end_test();
+                    outcome
+                }
+            },
+            _ => Outcome::Skipped,
+        };
+
+        let result = TestResult {
+            file: self.file.clone(),
+            suites: suite_names(suites),
+            name: test.name.clone(),
+            outcome,
+            duration: started.elapsed(),
+        };
+        self.report(result);
+    }
+
+    /// The beforeEach hooks, the test and the afterEach hooks
+    fn run_body(
+        &self,
+        scope: &mut v8::HandleScope,
+        function: &v8::Global<v8::Function>,
+        timeout: Option<u64>,
+        suites: &[&Suite],
+    ) -> Outcome {
+        let mut error = None;
+        for hook in suites.iter().flat_map(|suite| &suite.before_each) {
+            if let Err(err) = self.call(scope, hook, self.timeout) {
+                error = Some(format!("beforeEach hook failed: {}", err));
+                break;
+            }
+        }
+        if error.is_none() {
+            error = self
+                .call(scope, function, timeout.unwrap_or(self.timeout))
+                .err();
+        }
+        // afterEach hooks run even when the test failed, innermost first
+        for hook in suites.iter().rev().flat_map(|suite| &suite.after_each) {
+            if let Err(err) = self.call(scope, hook, self.timeout) {
+                error.get_or_insert(format!("afterEach hook failed: {}", err));
+            }
+        }
+        match error {
+            Some(error) => Outcome::Failed(error),
+            None => Outcome::Passed,
+        }
+    }
+
+    fn matches_filter(&self, suites: &[&Suite], test: &Test) -> bool {
+        let Some(filter) = &self.filter else {
+            return true;
+        };
+        let mut names = suite_names(suites);
+        names.push(test.name.clone());
+        filter.is_match(&names.join(" "))
+    }
+
+    /// Whether `test` runs rather than being skipped
+    fn selected(&self, test: &Test, inherited: Mode) -> bool {
+        if inherited == Mode::Skip || test.mode == Mode::Skip || test.function.is_none() {
+            return false;
+        }
+        !self.has_only || inherited == Mode::Only || test.mode == Mode::Only
+    }
+
+    /// Whether any test in `suite` runs, so its beforeAll/afterAll hooks do
+    fn runs_tests(&self, suite: &Suite, suites: &[&Suite], mode: Mode) -> bool {
+        suite.children.iter().any(|child| match child {
+            Entry::Test(test) => self.selected(test, mode) && self.matches_filter(suites, test),
+            Entry::Suite(child) => {
+                let mut suites = suites.to_vec();
+                suites.push(child);
+                self.runs_tests(child, &suites, mode.within(child.mode))
+            }
+        })
+    }
+
+    /// Call a test or hook and wait for the promise it returns, failing when
+    /// it throws, rejects or takes longer than `timeout` milliseconds
+    fn call(
+        &self,
+        scope: &mut v8::HandleScope,
+        function: &v8::Global<v8::Function>,
+        timeout: u64,
+    ) -> Result<(), String> {
+        let limit = Duration::from_millis(timeout);
+        let deadline = Instant::now() + limit;
+        let timed_out = Arc::new(AtomicBool::new(false));
+
+        // Stop code that never yields, like an endless loop
+        let (done, finished) = channel::<()>();
+        let handle = scope.thread_safe_handle();
+        let watchdog = {
+            let timed_out = timed_out.clone();
+            thread::spawn(move || {
+                if let Err(RecvTimeoutError::Timeout) = finished.recv_timeout(limit) {
+                    timed_out.store(true, Ordering::SeqCst);
+                    handle.terminate_execution();
+                }
+            })
+        };
+
+        // `Err(None)` when the call was stopped or its promise is still pending
+        let result: Result<(), Option<String>> = {
+            let try_catch = &mut v8::TryCatch::new(scope);
+            let function = v8::Local::new(try_catch, function);
+            let recv = v8::undefined(try_catch).into();
+            match function.call(try_catch, recv, &[]) {
+                None if try_catch.has_terminated() => Err(None),
+                None => Err(Some(match try_catch.exception() {
+                    Some(exception) => crate::runtime::format_exception(try_catch, exception),
+                    None => "Test failed".to_string(),
+                })),
+                Some(value) => match v8::Local::<v8::Promise>::try_from(value) {
+                    Ok(promise) => loop {
+                        try_catch.perform_microtask_checkpoint();
+                        match promise.state() {
+                            v8::PromiseState::Fulfilled => break Ok(()),
+                            v8::PromiseState::Rejected => {
+                                let reason = promise.result(try_catch);
+                                let error = crate::runtime::format_exception(try_catch, reason);
+                                break Err(Some(error));
+                            }
+                            v8::PromiseState::Pending => {
+                                if timed_out.load(Ordering::SeqCst) || Instant::now() >= deadline {
+                                    break Err(None);
+                                }
+                                thread::sleep(Duration::from_millis(1));
+                            }
+                        }
+                    },
+                    Err(_) => Ok(()),
+                },
+            }
+        };
+
+        let _ = done.send(());
+        let _ = watchdog.join();
+        if timed_out.load(Ordering::SeqCst) {
+            scope.cancel_terminate_execution();
+        }
+        result.map_err(|error| error.unwrap_or_else(|| format!("Timed out after {}ms", timeout)))
+    }
+}
+
+fn suite_names(suites: &[&Suite]) -> Vec<String> {
+    // The root suite has no name
+    suites
+        .iter()
+        .filter(|suite| !suite.name.is_empty())
+        .map(|suite| suite.name.clone())
+        .collect()
+}
+
+fn has_only(suite: &Suite) -> bool {
+    suite.children.iter().any(|child| match child {
+        Entry::Test(test) => test.mode == Mode::Only,
+        Entry::Suite(suite) => suite.mode == Mode::Only || has_only(suite),
+    })
+}
+
+/// `describe`, `it`, `test`, the hooks and `Rode.test` as globals
+fn setup_test_api(scope: &mut v8::HandleScope) {
+    let global = scope.get_current_context().global(scope);
+
+    let describe = api_function(scope, "describe", register_suite, true);
+    let test = api_function(scope, "test", register_test, true);
+    let it = api_function(scope, "it", register_test, true);
+    for (name, function) in [("describe", describe), ("test", test), ("it", it)] {
+        let key = v8::String::new(scope, name).unwrap();
+        global.set(scope, key.into(), function.into());
+    }
+    for hook in ["beforeAll", "afterAll", "beforeEach", "afterEach"] {
+        let function = api_function(scope, hook, register_hook, false);
+        let key = v8::String::new(scope, hook).unwrap();
+        global.set(scope, key.into(), function.into());
+    }
+
+    let rode_key = v8::String::new(scope, "Rode").unwrap();
+    if let Some(rode) = global
+        .get(scope, rode_key.into())
+        .and_then(|rode| rode.to_object(scope))
+    {
+        let key = v8::String::new(scope, "test").unwrap();
+        rode.set(scope, key.into(), test.into());
+    }
+}
+
+/// A registration function that knows what it is through its data, with
+/// `.only` and `.skip` variants when `variants` is set
+fn api_function<'s>(
+    scope: &mut v8::HandleScope<'s>,
+    name: &str,
+    callback: impl v8::MapFnTo<v8::FunctionCallback> + Copy,
+    variants: bool,
+) -> v8::Local<'s, v8::Function> {
+    let build = |scope: &mut v8::HandleScope<'s>, data: &str| {
+        let data = v8::String::new(scope, data).unwrap();
+        v8::Function::builder(callback)
+            .data(data.into())
+            .build(scope)
+            .unwrap()
+    };
+
+    let function = build(scope, name);
+    if variants {
+        for variant in ["only", "skip"] {
+            let variant_function = build(scope, &format!("{}.{}", name, variant));
+            let key = v8::String::new(scope, variant).unwrap();
+            function.set(scope, key.into(), variant_function.into());
+        }
+    }
+    function
+}
+
+/// The API name and mode a registration function was called as
+fn called_as(scope: &mut v8::HandleScope, args: &v8::FunctionCallbackArguments) -> (String, Mode) {
+    let name = args
+        .data()
+        .map(|data| data.to_rust_string_lossy(scope))
+        .unwrap_or_default();
+    let mode = if name.ends_with(".only") {
+        Mode::Only
+    } else if name.ends_with(".skip") {
+        Mode::Skip
+    } else {
+        Mode::Normal
+    };
+    (name, mode)
+}
+
+fn throw(scope: &mut v8::HandleScope, message: &str) {
+    let message = v8::String::new(scope, message).unwrap();
+    let error = v8::Exception::type_error(scope, message);
+    scope.throw_exception(error);
+}
+
+/// Add `entry` to the innermost suite being registered
+fn add_entry(scope: &mut v8::HandleScope, name: &str, entry: Entry) {
+    let added = REGISTERING.with(|suites| match suites.borrow_mut().last_mut() {
+        Some(suite) => {
+            suite.children.push(entry);
+            true
+        }
+        None => false,
+    });
+    if !added {
+        throw(
+            scope,
+            &format!("{}() can only be called while the test file loads", name),
+        );
+    }
+}
+
+/// `test(name, fn, timeout?)`, `it(...)` and `Rode.test(...)`; the third
+/// argument can also be `{ timeout, only, skip }`
+fn register_test(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    _rv: v8::ReturnValue,
+) {
+    let (api, mut mode) = called_as(scope, &args);
+    let name = args.get(0).to_rust_string_lossy(scope);
+    let function = match v8::Local::<v8::Function>::try_from(args.get(1)) {
+        Ok(function) => Some(v8::Global::new(scope, function)),
+        Err(_) if mode == Mode::Skip => None,
+        Err(_) => {
+            throw(scope, &format!("{}() needs a name and a function", api));
+            return;
+        }
+    };
+
+    let mut timeout = None;
+    let options = args.get(2);
+    if options.is_number() {
+        timeout = options.integer_value(scope).map(|ms| ms.max(0) as u64);
+    } else if let Ok(options) = v8::Local::<v8::Object>::try_from(options) {
+        if let Some(value) = get_option(scope, options, "timeout") {
+            timeout = value.integer_value(scope).map(|ms| ms.max(0) as u64);
+        }
+        if get_option(scope, options, "skip").is_some_and(|value| value.is_true()) {
+            mode = Mode::Skip;
+        } else if get_option(scope, options, "only").is_some_and(|value| value.is_true()) {
+            mode = Mode::Only;
+        }
+    }
+
+    let test = Test {
+        name,
+        function,
+        mode,
+        timeout,
+    };
+    add_entry(scope, &api, Entry::Test(test));
+}
+
+fn get_option<'s>(
+    scope: &mut v8::HandleScope<'s>,
+    options: v8::Local<v8::Object>,
+    key: &str,
+) -> Option<v8::Local<'s, v8::Value>> {
+    let key = v8::String::new(scope, key).unwrap();
+    options
+        .get(scope, key.into())
+        .filter(|value| !value.is_undefined())
+}
+
+/// `describe(name, fn)`: the tests registered while `fn` runs belong to it
+fn register_suite(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    _rv: v8::ReturnValue,
+) {
+    let (api, mode) = called_as(scope, &args);
+    let name = args.get(0).to_rust_string_lossy(scope);
+    let Ok(function) = v8::Local::<v8::Function>::try_from(args.get(1)) else {
+        throw(scope, &format!("{}() needs a name and a function", api));
+        return;
+    };
+
+    let open = REGISTERING.with(|suites| {
+        let mut suites = suites.borrow_mut();
+        if suites.is_empty() {
+            return false;
+        }
+        suites.push(Suite {
+            name,
+            mode,
+            ..Suite::default()
+        });
+        true
+    });
+    if !open {
+        throw(
+            scope,
+            &format!("{}() can only be called while the test file loads", api),
+        );
+        return;
+    }
+
+    let recv = v8::undefined(scope).into();
+    let result = function.call(scope, recv, &[]);
+    let suite = REGISTERING.with(|suites| suites.borrow_mut().pop());
+    // When the body threw, the exception propagates and fails the file
+    if result.is_some()
+        && let Some(suite) = suite
+    {
+        add_entry(scope, &api, Entry::Suite(suite));
+    }
+}
+
+/// `beforeAll(fn)`, `afterAll(fn)`, `beforeEach(fn)` and `afterEach(fn)`
+fn register_hook(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    _rv: v8::ReturnValue,
+) {
+    let (hook, _) = called_as(scope, &args);
+    let Ok(function) = v8::Local::<v8::Function>::try_from(args.get(0)) else {
+        throw(scope, &format!("{}() needs a function", hook));
+        return;
+    };
+    let function = v8::Global::new(scope, function);
+
+    let added = REGISTERING.with(|suites| {
+        let mut suites = suites.borrow_mut();
+        let Some(suite) = suites.last_mut() else {
+            return false;
+        };
+        match hook.as_str() {
+            "beforeAll" => suite.before_all.push(function),
+            "afterAll" => suite.after_all.push(function),
+            "beforeEach" => suite.before_each.push(function),
+            _ => suite.after_each.push(function),
+        }
+        true
+    });
+    if !added {
+        throw(
+            scope,
+            &format!("{}() can only be called while the test file loads", hook),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_test_file() {
+        for name in [
+            "math.test.ts",
+            "math_test.js",
+            "app.test.tsx",
+            "a.b.test.jsx",
+        ] {
+            assert!(is_test_file(name), "{}", name);
+        }
+        for name in [
+            "math.ts",
+            "test.ts",
+            "math.test.json",
+            "contest.ts",
+            "latest_test",
+            "math.test",
+        ] {
+            assert!(!is_test_file(name), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_name_filter() {
+        let substring = name_filter("adds (2)").unwrap();
+        assert!(substring.is_match("math adds (2) numbers"));
+        assert!(!substring.is_match("math adds 2 numbers"));
+
+        let regex = name_filter("/^math .*numbers$/").unwrap();
+        assert!(regex.is_match("math adds 2 numbers"));
+        assert!(!regex.is_match("string adds 2 numbers"));
+
+        // Too short to hold a regex, so taken literally
+        assert!(name_filter("/").unwrap().is_match("a/b"));
+        assert!(name_filter("//").unwrap().is_match("http://"));
+        assert!(!name_filter("//").unwrap().is_match("a/b"));
+
+        assert!(name_filter("/[/").is_err());
+    }
+
+    #[test]
+    fn test_mode_within() {
+        assert!(Mode::Normal.within(Mode::Normal) == Mode::Normal);
+        assert!(Mode::Normal.within(Mode::Only) == Mode::Only);
+        assert!(Mode::Only.within(Mode::Normal) == Mode::Only);
+        assert!(Mode::Only.within(Mode::Skip) == Mode::Skip);
+        assert!(Mode::Skip.within(Mode::Only) == Mode::Skip);
+        assert!(Mode::Skip.within(Mode::Normal) == Mode::Skip);
+    }
+
+    /// The outcome of each test in `source` run as a test file, by full name
+    fn run(name: &str, source: &str, filter: Option<&str>) -> Vec<(String, &'static str)> {
+        let dir = std::env::temp_dir().join(format!("rode-test-runner-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(format!("{}.test.js", name));
+        fs::write(&path, source).unwrap();
+
+        let mut runner = Runner {
+            file: String::new(),
+            filter: filter.map(|pattern| name_filter(pattern).unwrap()),
+            timeout: DEFAULT_TIMEOUT_MS,
+            has_only: false,
+            pretty: false,
+            results: Vec::new(),
+            snapshots_written: 0,
+        };
+        runner.run_file(&path);
+        fs::remove_file(&path).unwrap();
+
+        runner
+            .results
+            .iter()
+            .map(|result| {
+                let outcome = match &result.outcome {
+                    Outcome::Passed => "passed",
+                    Outcome::Failed(_) => "failed",
+                    Outcome::Skipped => "skipped",
+                };
+                (result.full_name(), outcome)
+            })
+            .collect()
+    }
+
+    const SELECTION: &str = r#"
+        const ran = []
+        describe('outer', () => {
+            beforeAll(() => ran.push('beforeAll'))
+            afterAll(() => {
+                if (ran.join() !== 'beforeAll,a') throw new Error(ran.join())
+            })
+            it('plain', () => ran.push('plain'))
+            describe.only('focused', () => {
+                it('a', () => ran.push('a'))
+                it.skip('b', () => ran.push('b'))
+            })
+            describe.skip('skipped', () => {
+                it.only('c', () => ran.push('c'))
+            })
+            it.skip('pending')
+        })
+        describe('other', () => {
+            beforeAll(() => { throw new Error('no test here runs') })
+            it('d', () => {})
+        })
+    "#;
+
+    #[test]
+    fn test_only_and_skip_selection() {
+        let results = run("selection", SELECTION, None);
+        let expected = [
+            ("outer › plain", "skipped"),
+            ("outer › focused › a", "passed"),
+            ("outer › focused › b", "skipped"),
+            ("outer › skipped › c", "skipped"),
+            ("outer › pending", "skipped"),
+            ("other › d", "skipped"),
+        ];
+        let expected: Vec<_> = expected
+            .iter()
+            .map(|(name, outcome)| (name.to_string(), *outcome))
+            .collect();
+        assert_eq!(results, expected);
+    }
+
+    #[test]
+    fn test_filter_selection() {
+        let source = r#"
+            describe('math', () => {
+                beforeAll(() => { throw new Error('math runs no test') })
+                it('adds', () => {})
+            })
+            describe('strings', () => {
+                it('join', () => {})
+                it('split', () => {})
+            })
+        "#;
+        // Tests the filter leaves out aren't reported at all
+        let results = run("filter", source, Some("/strings j/"));
+        assert_eq!(results, vec![("strings › join".to_string(), "passed")]);
+    }
+
+    #[test]
+    fn test_snapshots_of_different_maps_differ() {
+        let snapshot = |entries: &str| {
+            format!(
+                "it('maps', () => Rode.assert.snapshot(new Map({})))",
+                entries
+            )
+        };
+        let first = run("maps", &snapshot("[['a', 1]]"), None);
+        assert_eq!(first, vec![("maps".to_string(), "passed")]);
+        let same = run("maps", &snapshot("[['a', 1]]"), None);
+        assert_eq!(same, vec![("maps".to_string(), "passed")]);
+        let different = run("maps", &snapshot("[['a', 2]]"), None);
+        assert_eq!(different, vec![("maps".to_string(), "failed")]);
+
+        let dir = std::env::temp_dir().join(format!("rode-test-runner-{}", std::process::id()));
+        fs::remove_file(dir.join("maps.test.js.snap")).unwrap();
+    }
+}
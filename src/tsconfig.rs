@@ -0,0 +1,415 @@
+/// tsconfig.json discovery and the compiler options rode honours
+/// `extends` chains are followed and merged the way `tsc` does: compiler
+/// options are merged key by key, with relative paths resolved against the
+/// config file that declared them.
+use crate::decorators::{DecoratorMode, DecoratorOptions};
+use crate::jsx::{JsxOptions, JsxRuntime};
+use crate::typescript::TranspileOptions;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+
+const MAX_EXTENDS_DEPTH: usize = 16;
+
+/// The nearest tsconfig.json already loaded, by the directory it was looked
+/// up from. A tsconfig.json change restarts the process, so they never go
+/// stale.
+static NEAREST: OnceLock<Mutex<HashMap<PathBuf, Option<Arc<TsConfig>>>>> = OnceLock::new();
+
+pub struct TsConfig {
+    compiler_options: Map<String, Value>,
+    base_url: Option<PathBuf>,
+    /// Directory `paths` entries are relative to
+    paths_base: PathBuf,
+}
+
+impl TsConfig {
+    /// Find the nearest tsconfig.json in `start` or any parent directory
+    pub fn find(start: &Path) -> Option<TsConfig> {
+        let path = find_path(start)?;
+
+        match Self::load(&path) {
+            Ok(config) => Some(config),
+            Err(e) => {
+                eprintln!("Warning: Failed to load {}: {}", path.display(), e);
+                None
+            }
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<TsConfig, String> {
+        let dir = path.parent().unwrap_or(Path::new(".")).to_path_buf();
+        let mut config = TsConfig {
+            compiler_options: Map::new(),
+            base_url: None,
+            paths_base: dir,
+        };
+        config.merge_file(path, 0)?;
+        Ok(config)
+    }
+
+    fn merge_file(&mut self, path: &Path, depth: usize) -> Result<(), String> {
+        if depth > MAX_EXTENDS_DEPTH {
+            return Err("tsconfig extends chain is too deep".to_string());
+        }
+
+        let content = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        let json = parse_jsonc(&content).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let dir = path.parent().unwrap_or(Path::new("."));
+
+        // Parents first, so this file's options win
+        let parents = match json.get("extends") {
+            Some(Value::String(parent)) => vec![parent.clone()],
+            Some(Value::Array(parents)) => parents
+                .iter()
+                .filter_map(|p| p.as_str().map(str::to_string))
+                .collect(),
+            _ => Vec::new(),
+        };
+        for parent in parents {
+            let parent_path = resolve_extends(dir, &parent)
+                .ok_or_else(|| format!("Cannot find extended config '{}'", parent))?;
+            self.merge_file(&parent_path, depth + 1)?;
+        }
+
+        if let Some(Value::Object(options)) = json.get("compilerOptions") {
+            for (key, value) in options {
+                self.compiler_options.insert(key.clone(), value.clone());
+            }
+            if let Some(base_url) = options.get("baseUrl").and_then(Value::as_str) {
+                self.base_url = Some(dir.join(base_url));
+            }
+            if options.contains_key("paths") {
+                self.paths_base = dir.to_path_buf();
+            }
+        }
+
+        Ok(())
+    }
+
+    fn option_str(&self, key: &str) -> Option<&str> {
+        self.compiler_options.get(key).and_then(Value::as_str)
+    }
+
+    fn option_bool(&self, key: &str) -> Option<bool> {
+        self.compiler_options.get(key).and_then(Value::as_bool)
+    }
+
+    /// Transform settings derived from `jsx*`, decorator and `target` options
+    pub fn transpile_options(&self) -> TranspileOptions {
+        let mut jsx = JsxOptions::default();
+        match self.option_str("jsx").map(str::to_lowercase).as_deref() {
+            Some("react-jsx") | Some("react-jsxdev") => jsx.runtime = JsxRuntime::Automatic,
+            Some(_) | None => jsx.runtime = JsxRuntime::Classic,
+        }
+        if let Some(factory) = self.option_str("jsxFactory") {
+            jsx.factory = factory.to_string();
+        }
+        if let Some(fragment) = self.option_str("jsxFragmentFactory") {
+            jsx.fragment = fragment.to_string();
+        }
+        if let Some(source) = self.option_str("jsxImportSource") {
+            jsx.import_source = source.to_string();
+        }
+
+        let legacy = self.option_bool("experimentalDecorators").unwrap_or(false);
+        // `useDefineForClassFields` defaults on from ES2022 targets upwards
+        let modern_target = match self.option_str("target").map(str::to_lowercase) {
+            Some(target) => target == "esnext" || target_year(&target) >= 2022,
+            None => false,
+        };
+        let decorators = DecoratorOptions {
+            mode: if legacy {
+                DecoratorMode::Legacy
+            } else {
+                DecoratorMode::Standard
+            },
+            emit_metadata: legacy && self.option_bool("emitDecoratorMetadata").unwrap_or(false),
+            define_class_fields: self
+                .option_bool("useDefineForClassFields")
+                .unwrap_or(modern_target),
+        };
+
+        TranspileOptions { jsx, decorators }
+    }
+
+    /// Candidate files for a bare specifier through `paths` and `baseUrl`
+    pub fn resolve_alias(&self, specifier: &str) -> Vec<PathBuf> {
+        let mut candidates = Vec::new();
+        let paths_base = self.base_url.as_ref().unwrap_or(&self.paths_base);
+
+        if let Some(Value::Object(paths)) = self.compiler_options.get("paths") {
+            // The longest matching prefix wins, like in tsc
+            let mut best: Option<(&str, &Vec<Value>, String)> = None;
+            for (pattern, targets) in paths {
+                let Value::Array(targets) = targets else {
+                    continue;
+                };
+                let captured = match pattern.split_once('*') {
+                    Some((prefix, suffix)) => specifier
+                        .strip_prefix(prefix)
+                        .and_then(|rest| rest.strip_suffix(suffix))
+                        .map(str::to_string),
+                    None if pattern == specifier => Some(String::new()),
+                    None => None,
+                };
+                if let Some(captured) = captured {
+                    let is_better = best
+                        .as_ref()
+                        .is_none_or(|(current, _, _)| pattern.len() > current.len());
+                    if is_better {
+                        best = Some((pattern, targets, captured));
+                    }
+                }
+            }
+
+            if let Some((_, targets, captured)) = best {
+                for target in targets.iter().filter_map(Value::as_str) {
+                    candidates.push(paths_base.join(target.replace('*', &captured)));
+                }
+            }
+        }
+
+        if let Some(base_url) = &self.base_url {
+            candidates.push(base_url.join(specifier));
+        }
+
+        candidates
+    }
+}
+
+/// Path of the tsconfig.json set in rode.json, or else the nearest one in
+/// `start` or any parent directory
+pub fn find_path(start: &Path) -> Option<PathBuf> {
+    if let Some(path) = crate::config::current().and_then(|config| config.tsconfig.clone()) {
+        return Some(path);
+    }
+
+    start
+        .ancestors()
+        .map(|dir| dir.join("tsconfig.json"))
+        .find(|candidate| candidate.is_file())
+}
+
+/// The tsconfig.json nearest to `dir`, read and reported on once per directory
+pub fn nearest(dir: &Path) -> Option<Arc<TsConfig>> {
+    let cache = NEAREST.get_or_init(Default::default);
+    if let Some(config) = cache.lock().unwrap().get(dir) {
+        return config.clone();
+    }
+
+    let config = TsConfig::find(dir).map(Arc::new);
+    cache
+        .lock()
+        .unwrap()
+        .insert(dir.to_path_buf(), config.clone());
+    config
+}
+
+/// Transform settings for a file, from the tsconfig.json nearest to it
+pub fn transpile_options_for(file: &Path) -> TranspileOptions {
+    let dir = file
+        .canonicalize()
+        .ok()
+        .and_then(|p| p.parent().map(Path::to_path_buf))
+        .unwrap_or_else(|| std::env::current_dir().unwrap_or_else(|_| PathBuf::from(".")));
+
+    nearest(&dir)
+        .map(|config| config.transpile_options())
+        .unwrap_or_default()
+}
+
+fn target_year(target: &str) -> u32 {
+    match target
+        .strip_prefix("es")
+        .and_then(|t| t.parse::<u32>().ok())
+    {
+        Some(6) => 2015,
+        Some(year) if year >= 2015 => year,
+        _ => 0,
+    }
+}
+
+fn resolve_extends(dir: &Path, specifier: &str) -> Option<PathBuf> {
+    let with_json = |path: PathBuf| {
+        if path.is_file() {
+            Some(path)
+        } else if path.is_dir() {
+            Some(path.join("tsconfig.json")).filter(|p| p.is_file())
+        } else {
+            let mut json = path.into_os_string();
+            json.push(".json");
+            Some(PathBuf::from(json)).filter(|p| p.is_file())
+        }
+    };
+
+    if specifier.starts_with('.') || Path::new(specifier).is_absolute() {
+        return with_json(dir.join(specifier));
+    }
+
+    // Shared configs published as packages, e.g. "@tsconfig/node20"
+    dir.ancestors()
+        .find_map(|ancestor| with_json(ancestor.join("node_modules").join(specifier)))
+}
+
+/// Parse JSON with comments and trailing commas, as used by tsconfig.json
+pub fn parse_jsonc(text: &str) -> Result<Value, String> {
+    let mut stripped = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    let mut in_string = false;
+
+    while let Some(ch) = chars.next() {
+        if in_string {
+            stripped.push(ch);
+            if ch == '\\' {
+                if let Some(escaped) = chars.next() {
+                    stripped.push(escaped);
+                }
+            } else if ch == '"' {
+                in_string = false;
+            }
+            continue;
+        }
+
+        match (ch, chars.peek()) {
+            ('"', _) => {
+                in_string = true;
+                stripped.push(ch);
+            }
+            ('/', Some('/')) => {
+                while chars.peek().is_some_and(|&c| c != '\n') {
+                    chars.next();
+                }
+            }
+            ('/', Some('*')) => {
+                chars.next();
+                let mut previous = ' ';
+                for c in chars.by_ref() {
+                    if previous == '*' && c == '/' {
+                        break;
+                    }
+                    if c == '\n' {
+                        stripped.push('\n');
+                    }
+                    previous = c;
+                }
+            }
+            _ => stripped.push(ch),
+        }
+    }
+
+    serde_json::from_str(&remove_trailing_commas(&stripped)).map_err(|e| e.to_string())
+}
+
+fn remove_trailing_commas(json: &str) -> String {
+    let chars: Vec<char> = json.chars().collect();
+    let mut result = String::with_capacity(json.len());
+    let mut in_string = false;
+    let mut i = 0;
+
+    while i < chars.len() {
+        let ch = chars[i];
+        if in_string {
+            result.push(ch);
+            if ch == '\\' && i + 1 < chars.len() {
+                result.push(chars[i + 1]);
+                i += 1;
+            } else if ch == '"' {
+                in_string = false;
+            }
+        } else if ch == '"' {
+            in_string = true;
+            result.push(ch);
+        } else if ch == ',' {
+            let next = chars[i + 1..].iter().find(|c| !c.is_whitespace());
+            if !matches!(next, Some('}') | Some(']')) {
+                result.push(ch);
+            }
+        } else {
+            result.push(ch);
+        }
+        i += 1;
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_jsonc() {
+        let text = r#"{
+            // comment with "quotes"
+            "compilerOptions": {
+                "baseUrl": "./src", /* inline */
+                "paths": { "@app/*": ["app/*",], },
+                "url": "http://example.com/*not a comment*/",
+            },
+        }"#;
+        let json = parse_jsonc(text).unwrap();
+        assert_eq!(json["compilerOptions"]["baseUrl"], "./src");
+        assert_eq!(json["compilerOptions"]["paths"]["@app/*"][0], "app/*");
+        assert_eq!(
+            json["compilerOptions"]["url"],
+            "http://example.com/*not a comment*/"
+        );
+    }
+
+    #[test]
+    fn test_extends_and_paths() {
+        let dir = std::env::temp_dir().join(format!("rode-tsconfig-{}", std::process::id()));
+        fs::create_dir_all(dir.join("configs")).unwrap();
+        fs::write(
+            dir.join("configs/base.json"),
+            r#"{ "compilerOptions": { "experimentalDecorators": true, "baseUrl": "..", "jsx": "react-jsx" } }"#,
+        )
+        .unwrap();
+        fs::write(
+            dir.join("tsconfig.json"),
+            r#"{ "extends": "./configs/base", "compilerOptions": { "paths": { "@lib/*": ["lib/*"], "@lib/core": ["core/index"] }, "emitDecoratorMetadata": true } }"#,
+        )
+        .unwrap();
+
+        let config = TsConfig::find(&dir.join("src")).unwrap();
+        let options = config.transpile_options();
+        assert_eq!(options.decorators.mode, DecoratorMode::Legacy);
+        assert!(options.decorators.emit_metadata);
+        assert_eq!(options.jsx.runtime, JsxRuntime::Automatic);
+
+        // baseUrl from the parent is relative to configs/, so it is `dir`
+        let base = dir.join("configs").join("..");
+        assert_eq!(
+            config.resolve_alias("@lib/util"),
+            vec![base.join("lib/util"), base.join("@lib/util")]
+        );
+        assert_eq!(
+            config.resolve_alias("@lib/core")[0],
+            base.join("core/index")
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_nearest_is_loaded_once_per_directory() {
+        let dir = std::env::temp_dir().join(format!("rode-nearest-{}", std::process::id()));
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        fs::write(
+            dir.join("tsconfig.json"),
+            r#"{ "compilerOptions": { "jsx": "react-jsx" } }"#,
+        )
+        .unwrap();
+
+        let first = nearest(&dir.join("sub")).unwrap();
+        assert_eq!(first.transpile_options().jsx.runtime, JsxRuntime::Automatic);
+        // Served from memory even once the file is gone
+        fs::remove_dir_all(&dir).unwrap();
+        let second = nearest(&dir.join("sub")).unwrap();
+        assert!(Arc::ptr_eq(&first, &second));
+    }
+}
@@ -1,14 +1,76 @@
 /// Fast TypeScript stripper - removes TypeScript syntax to get pure JavaScript
 /// This is a simple regex-based approach for common TypeScript patterns
+use crate::decorators::{self, DecoratorOptions};
+use crate::jsx::{self, JsxOptions};
+use crate::source_map::SourceMap;
 use regex::Regex;
 use std::sync::OnceLock;
 
 static GENERIC_REGEX: OnceLock<Regex> = OnceLock::new();
 static AS_TYPE_REGEX: OnceLock<Regex> = OnceLock::new();
 
-pub fn strip_typescript(source: &str) -> String {
+/// Transformed code together with a source map back to the input
+pub struct Transpiled {
+    pub code: String,
+    pub source_map: SourceMap,
+}
+
+/// Settings for the transforms applied by `transpile`
+#[derive(Clone, Debug, Default)]
+pub struct TranspileOptions {
+    pub jsx: JsxOptions,
+    pub decorators: DecoratorOptions,
+}
+
+/// Apply the transform matching the file extension
+pub fn transpile(filename: &str, source: &str, options: &TranspileOptions) -> Transpiled {
+    let mut helpers = None;
+    let mut transpiled = if is_typescript_file(filename) {
+        // Decorators need the type annotations for metadata, so they go first
+        let (decorated, decorator_helpers) =
+            decorators::transform_decorators(source, &options.decorators);
+        helpers = decorator_helpers;
+        if jsx::is_jsx_file(filename) {
+            // The stripper would take a `:` or ` as ` in JSX text for a type,
+            // so the text is set aside until the types are gone
+            let (masked, texts) = jsx::mask_text(&decorated);
+            let mut stripped = strip_typescript(&masked);
+            stripped.code = jsx::unmask_text(&stripped.code, &texts);
+            stripped
+        } else {
+            strip_typescript(&decorated)
+        }
+    } else if filename.ends_with(".js") || filename.ends_with(".jsx") {
+        // Process JS files for ES6 imports but skip TypeScript stripping
+        convert_es6_imports(source)
+    } else {
+        Transpiled {
+            code: source.to_string(),
+            source_map: SourceMap::identity(source),
+        }
+    };
+
+    // JSX runs on the stripped code, since the stripper works line by line
+    // and can't tell the generated object literals from type annotations;
+    // it keeps line breaks, so the source map stays valid
+    if jsx::is_jsx_file(filename) {
+        let jsx_options = options.jsx.clone().with_pragmas(source);
+        transpiled.code = jsx::transform_jsx(&transpiled.code, &jsx_options);
+    }
+
+    // Helpers are plain JavaScript and go after everything the stripper saw
+    if let Some(helpers) = helpers {
+        transpiled.code.push_str(helpers);
+    }
+
+    transpiled
+}
+
+pub fn strip_typescript(source: &str) -> Transpiled {
     let lines: Vec<&str> = source.lines().collect();
     let mut result_lines = Vec::new();
+    // Original line index of every emitted line
+    let mut origins = Vec::new();
     let mut i = 0;
 
     while i < lines.len() {
@@ -16,7 +78,6 @@ pub fn strip_typescript(source: &str) -> String {
 
         // Skip interface declarations
         if line.starts_with("interface ") {
-            i += 1;
             let mut brace_count = 0;
             let mut found_opening = false;
 
@@ -51,7 +112,6 @@ pub fn strip_typescript(source: &str) -> String {
 
         // Skip enum declarations
         if line.starts_with("enum ") {
-            i += 1;
             let mut brace_count = 0;
             let mut found_opening = false;
 
@@ -88,6 +148,7 @@ pub fn strip_typescript(source: &str) -> String {
             let converted = convert_import_to_require(lines[i]);
             if !converted.is_empty() {
                 result_lines.push(converted);
+                origins.push(i);
             }
             i += 1;
             continue;
@@ -118,10 +179,13 @@ pub fn strip_typescript(source: &str) -> String {
         processed_line = processed_line.replace("  ", " ");
 
         result_lines.push(processed_line);
+        origins.push(i);
         i += 1;
     }
 
-    result_lines.join("\n")
+    let code = result_lines.join("\n");
+    let source_map = SourceMap::from_line_origins(source, &code, &origins);
+    Transpiled { code, source_map }
 }
 
 fn remove_type_annotations(line: &str) -> String {
@@ -129,10 +193,15 @@ fn remove_type_annotations(line: &str) -> String {
     let mut chars = line.chars().peekable();
     let mut in_string = false;
     let mut string_char = '"';
-    let mut paren_depth = 0;
+    // Open brackets on this line; a colon directly inside `{` or `[` belongs
+    // to an object literal or destructuring pattern, not a type
+    let mut brackets = Vec::new();
+    // Unanswered `?` at each bracket depth, so the `:` of an object literal
+    // inside a ternary branch isn't taken for the ternary's
+    let mut pending_ternaries = vec![0];
 
     while let Some(ch) = chars.next() {
-        if ch == '"' || ch == '\'' {
+        if ch == '"' || ch == '\'' || ch == '`' {
             if !in_string {
                 in_string = true;
                 string_char = ch;
@@ -140,52 +209,107 @@ fn remove_type_annotations(line: &str) -> String {
                 in_string = false;
             }
             result.push(ch);
-        } else if !in_string {
-            if ch == '(' {
-                paren_depth += 1;
-                result.push(ch);
-            } else if ch == ')' {
-                paren_depth -= 1;
-                result.push(ch);
-            } else if ch == ':' && paren_depth > 0 {
-                // Only remove type annotations inside parentheses (function parameters)
-                // Skip until we find a delimiter
-                while let Some(&next) = chars.peek() {
-                    if next == ',' || next == ')' {
-                        break;
+        } else if in_string {
+            result.push(ch);
+            if ch == '\\'
+                && let Some(escaped) = chars.next()
+            {
+                result.push(escaped);
+            }
+        } else {
+            let paren_depth = brackets.iter().filter(|&&b| b == '(').count();
+            match ch {
+                '(' | '[' | '{' => {
+                    brackets.push(ch);
+                    pending_ternaries.push(0);
+                    result.push(ch);
+                }
+                ')' | ']' | '}' => {
+                    brackets.pop();
+                    if pending_ternaries.len() > 1 {
+                        pending_ternaries.pop();
                     }
-                    chars.next();
+                    result.push(ch);
                 }
-                // Don't consume the delimiter
-            } else if ch == ':' && paren_depth == 0 && result.trim_end().ends_with(')') {
-                // Function return type annotation
-                while let Some(&next) = chars.peek() {
-                    if next == '{' || next == ';' || next == '=' {
-                        break;
+                '?' => {
+                    // `a ? b : c`, but not `x?: T`, `a?.b` or `a ?? b`
+                    let next = chars.peek().copied();
+                    if !matches!(next, Some(':') | Some('.') | Some('?')) && !result.ends_with('?')
+                    {
+                        *pending_ternaries.last_mut().unwrap() += 1;
                     }
-                    chars.next();
+                    result.push(ch);
                 }
-                // Don't consume the delimiter
-            } else if ch == ':' && paren_depth == 0 {
-                // Variable type annotation (const x: Type = ...)
-                while let Some(&next) = chars.peek() {
-                    if next == '=' || next == ';' || next == ',' {
-                        break;
+                ':' if pending_ternaries.last().is_some_and(|&n| n > 0) => {
+                    *pending_ternaries.last_mut().unwrap() -= 1;
+                    result.push(ch);
+                }
+                ':' if matches!(brackets.last(), Some('{') | Some('[')) => {
+                    result.push(ch);
+                }
+                ':' if paren_depth > 0 => {
+                    // Parameter type annotation, dropping an optional `?`
+                    if result.ends_with('?') {
+                        result.pop();
                     }
-                    chars.next();
+                    skip_type(&mut chars, &mut result, &[',', ')', '=']);
+                }
+                ':' if result.trim_end().ends_with(')') => {
+                    // Function return type annotation
+                    skip_type(&mut chars, &mut result, &['{', ';', '=']);
                 }
-                // Don't consume the delimiter
-            } else {
-                result.push(ch);
+                ':' => {
+                    // Variable type annotation (const x: Type = ...)
+                    skip_type(&mut chars, &mut result, &['=', ';', ',']);
+                }
+                _ => result.push(ch),
             }
-        } else {
-            result.push(ch);
         }
     }
 
     result
 }
 
+/// Skip a type up to one of `stops` outside any brackets of the type itself,
+/// keeping a single space if the type was followed by whitespace
+fn skip_type(
+    chars: &mut std::iter::Peekable<std::str::Chars>,
+    result: &mut String,
+    stops: &[char],
+) {
+    let mut depth = 0;
+    let mut skipped = String::new();
+
+    while let Some(&next) = chars.peek() {
+        if depth == 0 && stops.contains(&next) {
+            // `=>` inside a function type is not a default value
+            let mut ahead = chars.clone();
+            ahead.next();
+            let is_arrow = next == '=' && ahead.peek() == Some(&'>');
+            if !(is_arrow && skipped.trim_end().ends_with(')')) {
+                break;
+            }
+        }
+        match next {
+            '<' | '(' | '[' | '{' => depth += 1,
+            '>' if !skipped.ends_with('=') => depth -= 1,
+            ')' | ']' | '}' => {
+                if depth == 0 {
+                    break;
+                }
+                depth -= 1;
+            }
+            _ => {}
+        }
+        skipped.push(next);
+        chars.next();
+    }
+
+    if skipped.ends_with(char::is_whitespace) && !result.ends_with(' ') {
+        result.push(' ');
+    }
+}
+
 fn convert_import_to_require(line: &str) -> String {
     let line = line.trim();
 
@@ -279,9 +403,10 @@ pub fn is_typescript_file(filename: &str) -> bool {
     filename.ends_with(".ts") || filename.ends_with(".tsx")
 }
 
-pub fn convert_es6_imports(source_code: &str) -> String {
+pub fn convert_es6_imports(source_code: &str) -> Transpiled {
     let lines: Vec<&str> = source_code.lines().collect();
     let mut result_lines = Vec::new();
+    let mut origins = Vec::new();
     let mut exports = Vec::new();
     let mut i = 0;
 
@@ -293,18 +418,21 @@ pub fn convert_es6_imports(source_code: &str) -> String {
             let converted = convert_import_to_require(lines[i]);
             if !converted.is_empty() {
                 result_lines.push(converted);
+                origins.push(i);
             }
         }
         // Convert ES6 exports to CommonJS exports
         else if line.starts_with("export ") {
             let (converted_line, export_name) = convert_export_to_commonjs_with_name(lines[i]);
             result_lines.push(converted_line);
+            origins.push(i);
             if let Some(name) = export_name {
-                exports.push(name);
+                exports.push((name, i));
             }
         } else {
             // Keep regular lines as-is
             result_lines.push(lines[i].to_string());
+            origins.push(i);
         }
 
         i += 1;
@@ -312,14 +440,20 @@ pub fn convert_es6_imports(source_code: &str) -> String {
 
     // Add module.exports at the end if we have exports
     if !exports.is_empty() {
+        let last_line = lines.len().saturating_sub(1);
         result_lines.push(String::new());
         result_lines.push("// CommonJS exports".to_string());
-        for export_name in exports {
+        origins.extend([last_line, last_line]);
+        for (export_name, line) in exports {
             result_lines.push(format!("module.exports.{} = {};", export_name, export_name));
+            // Point the appended export back at the declaration it came from
+            origins.push(line);
         }
     }
 
-    result_lines.join("\n")
+    let code = result_lines.join("\n");
+    let source_map = SourceMap::from_line_origins(source_code, &code, &origins);
+    Transpiled { code, source_map }
 }
 
 #[cfg(test)]
@@ -337,7 +471,7 @@ const name: string = "test";
 let count: number = 0;
         "#;
 
-        let result = strip_typescript(ts_code);
+        let result = strip_typescript(ts_code).code;
         assert!(result.contains("function add(a, b)"));
         assert!(result.contains("const name = \"test\""));
         assert!(result.contains("let count = 0"));
@@ -354,7 +488,7 @@ interface User {
 const user = { name: "John", age: 30 };
         "#;
 
-        let result = strip_typescript(ts_code);
+        let result = strip_typescript(ts_code).code;
         assert!(!result.contains("interface User"));
         assert!(result.contains("const user = { name: \"John\", age: 30 }"));
     }
@@ -368,9 +502,62 @@ type UserID = number;
 const id: UserID = 123;
         "#;
 
-        let result = strip_typescript(ts_code);
+        let result = strip_typescript(ts_code).code;
         assert!(!result.contains("type StringOrNumber"));
         assert!(!result.contains("type UserID"));
         assert!(result.contains("const id = 123"));
     }
+
+    #[test]
+    fn test_strip_declarations_opening_on_their_first_line() {
+        let ts_code = r#"
+interface Point { x: number; y: number }
+const origin = { x: 0, y: 0 };
+enum Color { Red }
+const color = { name: "red" };
+        "#;
+
+        let result = strip_typescript(ts_code).code;
+        assert!(!result.contains("Point"));
+        assert!(!result.contains("Red"));
+        assert!(result.contains("const origin = { x: 0, y: 0 };"));
+        assert!(result.contains("const color = { name: \"red\" };"));
+    }
+
+    #[test]
+    fn test_colons_in_template_and_escaped_strings() {
+        let ts_code = r#"
+const label: string = `total: ${count}`;
+const quote: string = "say \"hi: there\"";
+        "#;
+
+        let result = strip_typescript(ts_code).code;
+        assert!(result.contains("const label = `total: ${count}`;"));
+        assert!(result.contains(r#"const quote = "say \"hi: there\"";"#));
+    }
+
+    #[test]
+    fn test_ternary_with_object_literals() {
+        let ts_code = r#"
+const u = cond ? { a: 1 } : null;
+const v = cond ? null : { b: { c: 2 } };
+const w: Item = cond ? { a: { b: 1 } } : { c: [x ? 1 : 2] };
+        "#;
+
+        let result = strip_typescript(ts_code).code;
+        assert!(result.contains("const u = cond ? { a: 1 } : null;"));
+        assert!(result.contains("const v = cond ? null : { b: { c: 2 } };"));
+        assert!(result.contains("const w = cond ? { a: { b: 1 } } : { c: [x ? 1 : 2] };"));
+    }
+
+    #[test]
+    fn test_transpile_tsx_keeps_jsx_text() {
+        let source =
+            "const el = <p title=\"a: b as c\">Note: save as draft</p>;\nconst n: number = 1;\n";
+        let result = transpile("x.tsx", source, &TranspileOptions::default()).code;
+        assert_eq!(
+            result,
+            "const el = React.createElement(\"p\", { title: \"a: b as c\" }, \"Note: save as draft\");\nconst n = 1;"
+        );
+    }
 }
@@ -0,0 +1,159 @@
+(function (inspect, diff, storeSnapshot) {
+  class AssertionError extends Error {
+    constructor(message, actual, expected, operator) {
+      super(message)
+      this.name = 'AssertionError'
+      this.actual = actual
+      this.expected = expected
+      this.operator = operator
+    }
+  }
+
+  // Strings are quoted so that '1' and 1 differ in the diff
+  const show = (value) =>
+    typeof value === 'string' && !value.includes('\n') ? JSON.stringify(value) : inspect(value)
+
+  // Errors stringify to '{}', so they are shown as 'Name: message'
+  const showError = (error) => (error instanceof Error ? String(error) : show(error))
+
+  function fail(message, header, actual, expected, operator, details) {
+    const text = message === undefined ? header : String(message)
+    throw new AssertionError(details ? text + '\n\n' + details : text, actual, expected, operator)
+  }
+
+  function compare(equal, message, header, actual, expected, operator) {
+    if (!equal) {
+      fail(message, header, actual, expected, operator, diff(show(expected), show(actual)))
+    }
+  }
+
+  function isDeepEqual(a, b, seen) {
+    if (Object.is(a, b)) return true
+    if (typeof a !== 'object' || typeof b !== 'object' || a === null || b === null) return false
+    if (Object.getPrototypeOf(a) !== Object.getPrototypeOf(b)) return false
+    if (seen.get(a) === b) return true
+    seen.set(a, b)
+
+    if (a instanceof Date) return a.getTime() === b.getTime()
+    if (a instanceof RegExp) return String(a) === String(b)
+    if (a instanceof Error && (a.name !== b.name || a.message !== b.message)) return false
+    if (a instanceof Map) {
+      if (a.size !== b.size) return false
+      for (const [key, value] of a) {
+        if (!b.has(key) || !isDeepEqual(value, b.get(key), seen)) return false
+      }
+      return true
+    }
+    if (a instanceof Set) {
+      if (a.size !== b.size) return false
+      for (const value of a) {
+        if (!b.has(value)) return false
+      }
+      return true
+    }
+
+    const keys = Object.keys(a)
+    if (keys.length !== Object.keys(b).length) return false
+    return keys.every(
+      (key) => Object.prototype.hasOwnProperty.call(b, key) && isDeepEqual(a[key], b[key], seen),
+    )
+  }
+
+  function assert(value, message) {
+    if (!value) fail(message, 'Expected a truthy value, got ' + show(value), value, true, '==')
+  }
+
+  assert.AssertionError = AssertionError
+  assert.ok = assert
+
+  assert.equal = function equal(actual, expected, message) {
+    compare(actual == expected, message, 'Expected values to be loosely equal (==)', actual, expected, '==')
+  }
+
+  assert.strictEqual = function strictEqual(actual, expected, message) {
+    compare(Object.is(actual, expected), message, 'Expected values to be strictly equal', actual, expected, 'strictEqual')
+  }
+
+  assert.deepEqual = function deepEqual(actual, expected, message) {
+    compare(isDeepEqual(actual, expected, new Map()), message, 'Expected values to be deeply equal', actual, expected, 'deepEqual')
+  }
+
+  assert.match = function match(string, regexp, message) {
+    if (!(regexp instanceof RegExp)) throw new TypeError('assert.match() takes a RegExp')
+    if (typeof string !== 'string' || !regexp.test(string)) {
+      fail(message, 'Expected ' + show(string) + ' to match ' + regexp, string, regexp, 'match')
+    }
+  }
+
+  // `expected` is a RegExp tested against the error, an Error class, a
+  // validation function or an object whose properties the error must have
+  function checkError(error, expected, message, operator) {
+    if (expected === undefined) return
+    if (expected instanceof RegExp) {
+      if (!expected.test(String(error))) {
+        fail(message, 'The error did not match ' + expected + ': ' + show(String(error)), error, expected, operator)
+      }
+    } else if (typeof expected === 'function') {
+      if (expected === Error || expected.prototype instanceof Error) {
+        if (!(error instanceof expected)) {
+          fail(message, 'Expected the error to be an instance of ' + expected.name + ', got ' + showError(error), error, expected, operator)
+        }
+      } else if (expected.call(undefined, error) !== true) {
+        fail(message, 'The validation function did not return true for ' + showError(error), error, expected, operator)
+      }
+    } else if (typeof expected === 'object' && expected !== null) {
+      const picked = {}
+      let equal = typeof error === 'object' && error !== null
+      for (const key of Object.keys(expected)) {
+        picked[key] = error?.[key]
+        const want = expected[key]
+        const ok = want instanceof RegExp && typeof picked[key] === 'string'
+          ? want.test(picked[key])
+          : isDeepEqual(picked[key], want, new Map())
+        equal = equal && ok
+      }
+      compare(equal, message, 'The error does not have the expected properties', picked, expected, operator)
+    } else {
+      throw new TypeError('assert.' + operator + '() takes a RegExp, an Error class, a function or an object to check the error against')
+    }
+  }
+
+  assert.throws = function throws(fn, expected, message) {
+    if (typeof fn !== 'function') throw new TypeError('assert.throws() takes a function')
+    if (typeof expected === 'string') [expected, message] = [undefined, expected]
+    try {
+      fn()
+    } catch (error) {
+      checkError(error, expected, message, 'throws')
+      return error
+    }
+    fail(message, 'Missing expected exception', undefined, expected, 'throws')
+  }
+
+  assert.rejects = async function rejects(promiseOrFn, expected, message) {
+    if (typeof expected === 'string') [expected, message] = [undefined, expected]
+    try {
+      await (typeof promiseOrFn === 'function' ? promiseOrFn() : promiseOrFn)
+    } catch (error) {
+      checkError(error, expected, message, 'rejects')
+      return error
+    }
+    fail(message, 'Missing expected rejection', undefined, expected, 'rejects')
+  }
+
+  assert.snapshot = function snapshot(value, hint) {
+    const serialized = inspect(value)
+    const mismatch = storeSnapshot(serialized, hint === undefined ? undefined : String(hint))
+    if (mismatch) {
+      throw new AssertionError(
+        'Snapshot "' + mismatch.name + '" does not match (rerun with --update-snapshots to update it)\n\n' +
+          diff(mismatch.expected, serialized),
+        value,
+        mismatch.expected,
+        'snapshot',
+      )
+    }
+  }
+
+  return assert
+})
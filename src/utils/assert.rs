@@ -0,0 +1,65 @@
+/// `Rode.assert` (also `require('rode:assert')`)
+/// The comparisons are plain JavaScript, so `==`, `instanceof` and Map/Set
+/// iteration behave exactly as in user code. Rust formats the values with
+/// the console's `format_value_pretty`, renders the colored diff and keeps the
+/// snapshot files. The script has no origin, so its frames are left out of
+/// error stacks and a failure points at the assertion in the test.
+use crate::diff;
+use crate::snapshot::{self, Check};
+use crate::utils::console::format_value_pretty;
+use rusty_v8 as v8;
+
+/// `inspect(value)`: the value as a multi-line string
+pub fn assert_inspect(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    mut rv: v8::ReturnValue,
+) {
+    let text = format_value_pretty(scope, args.get(0));
+    rv.set(v8::String::new(scope, &text).unwrap().into());
+}
+
+/// `diff(expected, actual)`: the colored line diff of two strings
+pub fn assert_diff(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    mut rv: v8::ReturnValue,
+) {
+    let expected = args.get(0).to_rust_string_lossy(scope);
+    let actual = args.get(1).to_rust_string_lossy(scope);
+    let text = diff::line_diff(&expected, &actual);
+    rv.set(v8::String::new(scope, &text).unwrap().into());
+}
+
+/// `storeSnapshot(serialized, hint)`: undefined when the snapshot matched or
+/// was written, `{ name, expected }` when it differs
+pub fn assert_snapshot(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    mut rv: v8::ReturnValue,
+) {
+    let value = args.get(0).to_rust_string_lossy(scope);
+    let hint = match args.get(1) {
+        hint if hint.is_string() => Some(hint.to_rust_string_lossy(scope)),
+        _ => None,
+    };
+
+    match snapshot::check(&value, hint.as_deref()) {
+        Ok(Check::Matched) | Ok(Check::Written) => {}
+        Ok(Check::Mismatched { name, expected }) => {
+            let mismatch = v8::Object::new(scope);
+            let name_key = v8::String::new(scope, "name").unwrap();
+            let name = v8::String::new(scope, &name).unwrap();
+            mismatch.set(scope, name_key.into(), name.into());
+            let expected_key = v8::String::new(scope, "expected").unwrap();
+            let expected = v8::String::new(scope, &expected).unwrap();
+            mismatch.set(scope, expected_key.into(), expected.into());
+            rv.set(mismatch.into());
+        }
+        Err(e) => {
+            let message = v8::String::new(scope, &e).unwrap();
+            let error = v8::Exception::error(scope, message);
+            scope.throw_exception(error);
+        }
+    }
+}
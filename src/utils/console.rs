@@ -1,7 +1,8 @@
 use colored::*;
 use rusty_v8 as v8;
 
-fn format_value(scope: &mut v8::HandleScope, value: v8::Local<v8::Value>) -> String {
+/// Plain representation of a value, as printed by `console.log`
+pub fn format_value(scope: &mut v8::HandleScope, value: v8::Local<v8::Value>) -> String {
     if value.is_string() {
         value
             .to_string(scope)
@@ -24,27 +25,7 @@ fn format_value(scope: &mut v8::HandleScope, value: v8::Local<v8::Value>) -> Str
         "undefined".to_string()
     } else if value.is_object() {
         // Try to stringify the object
-        let global = scope.get_current_context().global(scope);
-        let json_key = v8::String::new(scope, "JSON").unwrap();
-        if let Some(json_obj) = global
-            .get(scope, json_key.into())
-            .and_then(|v| v.to_object(scope))
-        {
-            let stringify_key = v8::String::new(scope, "stringify").unwrap();
-            if let Some(stringify_func) = json_obj
-                .get(scope, stringify_key.into())
-                .and_then(|v| v8::Local::<v8::Function>::try_from(v).ok())
-            {
-                let args = &[value];
-                if let Some(result) = stringify_func.call(scope, json_obj.into(), args) {
-                    return result
-                        .to_string(scope)
-                        .map(|s| s.to_rust_string_lossy(scope))
-                        .unwrap_or_else(|| "[object Object]".to_string());
-                }
-            }
-        }
-        "[object Object]".to_string()
+        stringify(scope, value).unwrap_or_else(|| "[object Object]".to_string())
     } else {
         value
             .to_string(scope)
@@ -53,6 +34,266 @@ fn format_value(scope: &mut v8::HandleScope, value: v8::Local<v8::Value>) -> Str
     }
 }
 
+/// Like `format_value`, but the way an inspector shows values rather than
+/// as JSON, one entry per line with two-space indents, for diffs and
+/// snapshots. Map and Set entries, undefined, NaN, -0 and class names all
+/// show, so values that differ never read the same.
+pub fn format_value_pretty(scope: &mut v8::HandleScope, value: v8::Local<v8::Value>) -> String {
+    if value.is_string() {
+        return format_value(scope, value);
+    }
+    let scope = &mut v8::HandleScope::new(scope);
+    let value = v8::Local::new(scope, value);
+    pretty(scope, value, 0, &mut Vec::new())
+}
+
+/// `value` nested `depth` levels deep inside the objects `seen`
+fn pretty<'s>(
+    scope: &mut v8::HandleScope<'s>,
+    value: v8::Local<'s, v8::Value>,
+    depth: usize,
+    seen: &mut Vec<v8::Local<'s, v8::Object>>,
+) -> String {
+    if value.is_undefined() {
+        return "undefined".to_string();
+    } else if value.is_null() {
+        return "null".to_string();
+    } else if value.is_string() {
+        return quote(&value.to_rust_string_lossy(scope));
+    } else if value.is_number() {
+        let number = value.number_value(scope).unwrap_or(f64::NAN);
+        if number == 0.0 && number.is_sign_negative() {
+            return "-0".to_string();
+        }
+        return value.to_rust_string_lossy(scope);
+    } else if value.is_big_int() {
+        return format!("{}n", value.to_rust_string_lossy(scope));
+    } else if let Ok(symbol) = v8::Local::<v8::Symbol>::try_from(value) {
+        let description = symbol.description(scope);
+        if description.is_undefined() {
+            return "Symbol()".to_string();
+        }
+        return format!("Symbol({})", description.to_rust_string_lossy(scope));
+    } else if !value.is_object() {
+        // Booleans
+        return value.to_rust_string_lossy(scope);
+    }
+
+    if let Ok(function) = v8::Local::<v8::Function>::try_from(value) {
+        let name = function.get_name(scope).to_rust_string_lossy(scope);
+        return if name.is_empty() {
+            "[Function (anonymous)]".to_string()
+        } else {
+            format!("[Function: {}]", name)
+        };
+    } else if let Ok(date) = v8::Local::<v8::Date>::try_from(value) {
+        return chrono::DateTime::from_timestamp_millis(date.value_of() as i64)
+            .filter(|_| date.value_of().is_finite())
+            .map(|date| date.format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string())
+            .unwrap_or_else(|| "Invalid Date".to_string());
+    } else if value.is_reg_exp() || value.is_native_error() {
+        return value.to_rust_string_lossy(scope);
+    } else if value.is_string_object() {
+        let string = value.to_rust_string_lossy(scope);
+        return format!("[String: {}]", quote(&string));
+    } else if value.is_number_object() || value.is_boolean_object() {
+        let kind = if value.is_number_object() {
+            "Number"
+        } else {
+            "Boolean"
+        };
+        return format!("[{}: {}]", kind, value.to_rust_string_lossy(scope));
+    }
+
+    let object = value.to_object(scope).unwrap();
+    if seen.iter().any(|parent| parent.strict_equals(value)) {
+        return "[Circular]".to_string();
+    }
+    seen.push(object);
+
+    let class = class_name(scope, object);
+    let mut entries = Vec::new();
+    let header = if value.is_map() {
+        let map = v8::Local::<v8::Map>::try_from(value).unwrap();
+        let pairs = map.as_array(scope);
+        for i in (0..pairs.length()).step_by(2) {
+            let key = pairs.get_index(scope, i).unwrap();
+            let item = pairs.get_index(scope, i + 1).unwrap();
+            let key = pretty(scope, key, depth + 1, seen);
+            let item = pretty(scope, item, depth + 1, seen);
+            entries.push(format!("{} => {}", key, item));
+        }
+        format!("{}({}) {{", class.as_deref().unwrap_or("Map"), map.size())
+    } else if value.is_set() {
+        let items = set_values(scope, value);
+        for i in 0..items.length() {
+            let item = items.get_index(scope, i).unwrap();
+            entries.push(pretty(scope, item, depth + 1, seen));
+        }
+        format!(
+            "{}({}) {{",
+            class.as_deref().unwrap_or("Set"),
+            items.length()
+        )
+    } else if value.is_array() || value.is_typed_array() {
+        let length_key = v8::String::new(scope, "length").unwrap();
+        let length = object
+            .get(scope, length_key.into())
+            .and_then(|length| length.uint32_value(scope))
+            .unwrap_or(0);
+        for i in 0..length {
+            let item = object
+                .get_index(scope, i)
+                .unwrap_or_else(|| v8::undefined(scope).into());
+            entries.push(pretty(scope, item, depth + 1, seen));
+        }
+        match class.as_deref() {
+            Some("Array") => "[".to_string(),
+            Some(name) => format!("{}({}) [", name, length),
+            None => format!("[Array({}): null prototype] [", length),
+        }
+    } else {
+        let names = object
+            .get_own_property_names(scope)
+            .unwrap_or_else(|| v8::Array::new(scope, 0));
+        for i in 0..names.length() {
+            let key = names.get_index(scope, i).unwrap();
+            let item = object
+                .get(scope, key)
+                .unwrap_or_else(|| v8::undefined(scope).into());
+            let key = property_key(&key.to_rust_string_lossy(scope));
+            let item = pretty(scope, item, depth + 1, seen);
+            entries.push(format!("{}: {}", key, item));
+        }
+        match class.as_deref() {
+            Some("Object") | Some("") => "{".to_string(),
+            Some(name) => format!("{} {{", name),
+            None => "[Object: null prototype] {".to_string(),
+        }
+    };
+    seen.pop();
+
+    let close = if header.ends_with('[') { "]" } else { "}" };
+    if entries.is_empty() {
+        return format!("{}{}", header, close);
+    }
+    let indent = "  ".repeat(depth + 1);
+    let body = entries
+        .iter()
+        .map(|entry| format!("{}{}", indent, entry))
+        .collect::<Vec<_>>()
+        .join(",\n");
+    format!("{}\n{}\n{}{}", header, body, "  ".repeat(depth), close)
+}
+
+/// The name of the class `object` is an instance of, or None when it has no
+/// prototype
+fn class_name(scope: &mut v8::HandleScope, object: v8::Local<v8::Object>) -> Option<String> {
+    let prototype = object
+        .get_prototype(scope)
+        .filter(|prototype| prototype.is_object())?
+        .to_object(scope)?;
+    let constructor_key = v8::String::new(scope, "constructor").unwrap();
+    let name = prototype
+        .get(scope, constructor_key.into())
+        .and_then(|constructor| v8::Local::<v8::Function>::try_from(constructor).ok())
+        .map(|constructor| constructor.get_name(scope).to_rust_string_lossy(scope))
+        .unwrap_or_default();
+    Some(name)
+}
+
+/// `Array.from(set)`
+fn set_values<'s>(
+    scope: &mut v8::HandleScope<'s>,
+    set: v8::Local<'s, v8::Value>,
+) -> v8::Local<'s, v8::Array> {
+    let global = scope.get_current_context().global(scope);
+    let array_key = v8::String::new(scope, "Array").unwrap();
+    let from_key = v8::String::new(scope, "from").unwrap();
+    global
+        .get(scope, array_key.into())
+        .and_then(|array| array.to_object(scope))
+        .and_then(|array| {
+            let from = array.get(scope, from_key.into())?;
+            let from = v8::Local::<v8::Function>::try_from(from).ok()?;
+            from.call(scope, array.into(), &[set])
+        })
+        .and_then(|values| v8::Local::<v8::Array>::try_from(values).ok())
+        .unwrap_or_else(|| v8::Array::new(scope, 0))
+}
+
+/// A string in double quotes, escaped as in JSON
+fn quote(string: &str) -> String {
+    serde_json::to_string(string).unwrap_or_default()
+}
+
+/// A property name, quoted unless it is an identifier
+fn property_key(name: &str) -> String {
+    let mut chars = name.chars();
+    let identifier = chars
+        .next()
+        .is_some_and(|c| c.is_alphabetic() || c == '_' || c == '$')
+        && chars.all(|c| c.is_alphanumeric() || c == '_' || c == '$');
+    if identifier {
+        name.to_string()
+    } else {
+        quote(name)
+    }
+}
+
+/// `JSON.stringify(value)`
+fn stringify(scope: &mut v8::HandleScope, value: v8::Local<v8::Value>) -> Option<String> {
+    let global = scope.get_current_context().global(scope);
+    let json_key = v8::String::new(scope, "JSON").unwrap();
+    let json_obj = global
+        .get(scope, json_key.into())
+        .and_then(|v| v.to_object(scope))?;
+    let stringify_key = v8::String::new(scope, "stringify").unwrap();
+    let stringify_func = json_obj
+        .get(scope, stringify_key.into())
+        .and_then(|v| v8::Local::<v8::Function>::try_from(v).ok())?;
+
+    let result = stringify_func.call(scope, json_obj.into(), &[value])?;
+    result
+        .to_string(scope)
+        .map(|s| s.to_rust_string_lossy(scope))
+}
+
+/// Colored representation of a value for the REPL, with strings quoted
+pub fn inspect_value(scope: &mut v8::HandleScope, value: v8::Local<v8::Value>) -> String {
+    if value.is_string() {
+        let string = value.to_rust_string_lossy(scope);
+        let escaped = string
+            .replace('\\', "\\\\")
+            .replace('\'', "\\'")
+            .replace('\n', "\\n");
+        format!("'{}'", escaped).green().to_string()
+    } else if value.is_big_int() {
+        format!("{}n", value.to_rust_string_lossy(scope))
+            .yellow()
+            .to_string()
+    } else if value.is_number() || value.is_boolean() {
+        value.to_rust_string_lossy(scope).yellow().to_string()
+    } else if value.is_undefined() {
+        "undefined".dimmed().to_string()
+    } else if value.is_null() {
+        "null".bold().to_string()
+    } else if value.is_symbol() {
+        value.to_rust_string_lossy(scope).green().to_string()
+    } else if let Ok(function) = v8::Local::<v8::Function>::try_from(value) {
+        let name = function.get_name(scope).to_rust_string_lossy(scope);
+        if name.is_empty() {
+            "[Function (anonymous)]".cyan().to_string()
+        } else {
+            format!("[Function: {}]", name).cyan().to_string()
+        }
+    } else if value.is_native_error() {
+        value.to_rust_string_lossy(scope).red().to_string()
+    } else {
+        format_value(scope, value)
+    }
+}
+
 fn format_table_data(scope: &mut v8::HandleScope, value: v8::Local<v8::Value>) -> String {
     if !value.is_object() {
         return format_value(scope, value);
@@ -268,60 +509,46 @@ pub fn console_time_end(
     println!("{}: 0.000ms", label);
 }
 
-pub fn setup_console(scope: &mut v8::HandleScope) {
-    let console_obj = v8::Object::new(scope);
-    let console_key = v8::String::new(scope, "console").unwrap();
-
-    // console.log
-    let log_key = v8::String::new(scope, "log").unwrap();
-    let log_func = v8::Function::new(scope, console_log).unwrap();
-    console_obj.set(scope, log_key.into(), log_func.into());
-
-    // console.error
-    let error_key = v8::String::new(scope, "error").unwrap();
-    let error_func = v8::Function::new(scope, console_error).unwrap();
-    console_obj.set(scope, error_key.into(), error_func.into());
-
-    // console.warn
-    let warn_key = v8::String::new(scope, "warn").unwrap();
-    let warn_func = v8::Function::new(scope, console_warn).unwrap();
-    console_obj.set(scope, warn_key.into(), warn_func.into());
-
-    // console.info
-    let info_key = v8::String::new(scope, "info").unwrap();
-    let info_func = v8::Function::new(scope, console_info).unwrap();
-    console_obj.set(scope, info_key.into(), info_func.into());
-
-    // console.table
-    let table_key = v8::String::new(scope, "table").unwrap();
-    let table_func = v8::Function::new(scope, console_table).unwrap();
-    console_obj.set(scope, table_key.into(), table_func.into());
-
-    // console.dir
-    let dir_key = v8::String::new(scope, "dir").unwrap();
-    let dir_func = v8::Function::new(scope, console_dir).unwrap();
-    console_obj.set(scope, dir_key.into(), dir_func.into());
-
-    // console.clear
-    let clear_key = v8::String::new(scope, "clear").unwrap();
-    let clear_func = v8::Function::new(scope, console_clear).unwrap();
-    console_obj.set(scope, clear_key.into(), clear_func.into());
-
-    // console.count
-    let count_key = v8::String::new(scope, "count").unwrap();
-    let count_func = v8::Function::new(scope, console_count).unwrap();
-    console_obj.set(scope, count_key.into(), count_func.into());
-
-    // console.time
-    let time_key = v8::String::new(scope, "time").unwrap();
-    let time_func = v8::Function::new(scope, console_time).unwrap();
-    console_obj.set(scope, time_key.into(), time_func.into());
-
-    // console.timeEnd
-    let time_end_key = v8::String::new(scope, "timeEnd").unwrap();
-    let time_end_func = v8::Function::new(scope, console_time_end).unwrap();
-    console_obj.set(scope, time_end_key.into(), time_end_func.into());
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::Runtime;
+
+    /// `format_value_pretty` of what `code` evaluates to
+    fn pretty(code: &str) -> String {
+        let mut runtime = Runtime::new();
+        let context = runtime.create_context();
+        runtime.with_context(&context, |scope| {
+            let code = v8::String::new(scope, code).unwrap();
+            let value = v8::Script::compile(scope, code, None)
+                .and_then(|script| script.run(scope))
+                .unwrap();
+            format_value_pretty(scope, value)
+        })
+    }
 
-    let global = scope.get_current_context().global(scope);
-    global.set(scope, console_key.into(), console_obj.into());
+    #[test]
+    fn test_format_value_pretty() {
+        assert_eq!(pretty("'top level'"), "top level");
+        assert_eq!(
+            pretty("[undefined, NaN, -0, 1n, null]"),
+            "[\n  undefined,\n  NaN,\n  -0,\n  1n,\n  null\n]"
+        );
+        assert_eq!(
+            pretty("new Map([['a', { b: [] }], [1, new Set(['x'])]])"),
+            "Map(2) {\n  \"a\" => {\n    b: []\n  },\n  1 => Set(1) {\n    \"x\"\n  }\n}"
+        );
+        assert_eq!(
+            pretty(
+                "class Point { constructor() { this.x = 1; this['my key'] = undefined } }; new Point()"
+            ),
+            "Point {\n  x: 1,\n  \"my key\": undefined\n}"
+        );
+        assert_eq!(pretty("Object.create(null)"), "[Object: null prototype] {}");
+        assert_eq!(
+            pretty("const a = { n: new Date(0) }; a.self = a; a"),
+            "{\n  n: 1970-01-01T00:00:00.000Z,\n  self: [Circular]\n}"
+        );
+        assert_ne!(pretty("new Map([['a', 1]])"), pretty("new Map([['a', 2]])"));
+    }
 }
@@ -4,14 +4,35 @@ use std::collections::HashMap;
 use std::time::Duration;
 use tokio::runtime::Runtime;
 
-pub fn setup_fetch(scope: &mut v8::HandleScope) {
-    let fetch_fn = v8::Function::new(scope, fetch_function).unwrap();
-    let global = scope.get_current_context().global(scope);
-    let key = v8::String::new(scope, "fetch").unwrap();
-    global.set(scope, key.into(), fetch_fn.into());
+/// Redirects followed before giving up, as in reqwest's default policy
+const MAX_REDIRECTS: usize = 10;
+
+/// Check a URL against the rode.json net permissions
+fn check_url(url: &reqwest::Url) -> Result<(), String> {
+    match crate::config::current() {
+        Some(config) => config.check_net(
+            url.host_str().unwrap_or_default(),
+            url.port_or_known_default(),
+        ),
+        None => Ok(()),
+    }
+}
+
+/// Follow redirects only to allowed hosts, or an allowed host could send
+/// the request anywhere
+fn redirect_policy() -> reqwest::redirect::Policy {
+    reqwest::redirect::Policy::custom(|attempt| {
+        if attempt.previous().len() >= MAX_REDIRECTS {
+            attempt.error("too many redirects")
+        } else if let Err(message) = check_url(attempt.url()) {
+            attempt.error(message)
+        } else {
+            attempt.follow()
+        }
+    })
 }
 
-fn fetch_function(
+pub fn fetch_function(
     scope: &mut v8::HandleScope,
     args: v8::FunctionCallbackArguments,
     mut retval: v8::ReturnValue,
@@ -29,6 +50,15 @@ fn fetch_function(
         return;
     };
 
+    // Only hosts allowed by rode.json permissions
+    if let Ok(parsed) = reqwest::Url::parse(&url)
+        && let Err(message) = check_url(&parsed)
+    {
+        let error = v8::String::new(scope, &message).unwrap();
+        scope.throw_exception(error.into());
+        return;
+    }
+
     // Parse options
     let mut method = "GET".to_string();
     let mut headers = HashMap::new();
@@ -97,6 +127,7 @@ fn fetch_function(
     let result = rt.block_on(async {
         let client = reqwest::Client::builder()
             .timeout(Duration::from_millis(timeout_ms))
+            .redirect(redirect_policy())
             .build()
             .map_err(|e| format!("Failed to create HTTP client: {}", e))?;
 
@@ -22,6 +22,10 @@ pub fn rode_read_file(
         }
     };
 
+    if !check_access(scope, &filename, false) {
+        return;
+    }
+
     match fs::read_to_string(&filename) {
         Ok(content) => {
             let result = v8::String::new(scope, &content).unwrap();
@@ -65,6 +69,10 @@ pub fn rode_write_file(
         }
     };
 
+    if !check_access(scope, &filename, true) {
+        return;
+    }
+
     match fs::write(&filename, &content) {
         Ok(_) => {}
         Err(err) => {
@@ -95,6 +103,10 @@ pub fn rode_exists(
         }
     };
 
+    if !check_access(scope, &path, false) {
+        return;
+    }
+
     let exists = Path::new(&path).exists();
     let result = v8::Boolean::new(scope, exists);
     rv.set(result.into());
@@ -126,6 +138,10 @@ pub fn rode_mkdir(
         false
     };
 
+    if !check_access(scope, &path, true) {
+        return;
+    }
+
     let result = if recursive {
         fs::create_dir_all(&path)
     } else {
@@ -165,6 +181,10 @@ pub fn rode_remove(
         false
     };
 
+    if !check_access(scope, &path, true) {
+        return;
+    }
+
     let path_obj = Path::new(&path);
     let result = if path_obj.is_dir() {
         if recursive {
@@ -203,6 +223,10 @@ pub fn rode_read_dir(
         }
     };
 
+    if !check_access(scope, &path, false) {
+        return;
+    }
+
     match fs::read_dir(&path) {
         Ok(entries) => {
             let array = v8::Array::new(scope, 0);
@@ -243,47 +267,17 @@ pub fn rode_read_dir(
     }
 }
 
-pub fn setup_fs(scope: &mut v8::HandleScope) {
-    let global = scope.get_current_context().global(scope);
-    let rode_key = v8::String::new(scope, "Rode").unwrap();
-
-    let rode_obj = if let Some(existing) = global
-        .get(scope, rode_key.into())
-        .and_then(|v| v.to_object(scope))
-    {
-        existing
-    } else {
-        let new_obj = v8::Object::new(scope);
-        global.set(scope, rode_key.into(), new_obj.into());
-        new_obj
+/// Throw when the rode.json permissions don't allow this access
+fn check_access(scope: &mut v8::HandleScope, path: &str, write: bool) -> bool {
+    let Some(config) = crate::config::current() else {
+        return true;
     };
-
-    let fs_obj = v8::Object::new(scope);
-    let fs_key = v8::String::new(scope, "fs").unwrap();
-
-    let read_file_key = v8::String::new(scope, "readFile").unwrap();
-    let read_file_func = v8::Function::new(scope, rode_read_file).unwrap();
-    fs_obj.set(scope, read_file_key.into(), read_file_func.into());
-
-    let write_file_key = v8::String::new(scope, "writeFile").unwrap();
-    let write_file_func = v8::Function::new(scope, rode_write_file).unwrap();
-    fs_obj.set(scope, write_file_key.into(), write_file_func.into());
-
-    let exists_key = v8::String::new(scope, "exists").unwrap();
-    let exists_func = v8::Function::new(scope, rode_exists).unwrap();
-    fs_obj.set(scope, exists_key.into(), exists_func.into());
-
-    let mkdir_key = v8::String::new(scope, "mkdir").unwrap();
-    let mkdir_func = v8::Function::new(scope, rode_mkdir).unwrap();
-    fs_obj.set(scope, mkdir_key.into(), mkdir_func.into());
-
-    let remove_key = v8::String::new(scope, "remove").unwrap();
-    let remove_func = v8::Function::new(scope, rode_remove).unwrap();
-    fs_obj.set(scope, remove_key.into(), remove_func.into());
-
-    let read_dir_key = v8::String::new(scope, "readDir").unwrap();
-    let read_dir_func = v8::Function::new(scope, rode_read_dir).unwrap();
-    fs_obj.set(scope, read_dir_key.into(), read_dir_func.into());
-
-    rode_obj.set(scope, fs_key.into(), fs_obj.into());
+    match config.check_path(path, write) {
+        Ok(()) => true,
+        Err(message) => {
+            let error = v8::String::new(scope, &message).unwrap();
+            scope.throw_exception(error.into());
+            false
+        }
+    }
 }
@@ -1,9 +1,22 @@
 use rusty_v8 as v8;
 use std::io::prelude::*;
 use std::net::{TcpListener, TcpStream};
+use std::sync::OnceLock;
 use std::thread;
 use std::time::Duration;
 
+/// Host and port `Rode.http.serve` listens on when the script passes no port
+static DEFAULT_LISTEN: OnceLock<(String, u16)> = OnceLock::new();
+
+/// Override the default listen address, used by `rode serve`
+pub fn set_default_listen(host: String, port: u16) {
+    let _ = DEFAULT_LISTEN.set((host, port));
+}
+
+pub fn default_listen() -> Option<(String, u16)> {
+    DEFAULT_LISTEN.get().cloned()
+}
+
 struct HttpHandler {
     callback_js: String,
 }
@@ -68,10 +81,16 @@ fn handle_client(mut stream: TcpStream, handler: &HttpHandler) {
             method, path, handler.callback_js
         );
 
-        let mut isolate = v8::Isolate::new(Default::default());
+        // Each request runs in its own isolate, under the same limits as
+        // the script and with the same globals from the startup snapshot
+        let mut isolate = crate::startup::new_isolate(true);
         let scope = &mut v8::HandleScope::new(&mut isolate);
         let context = v8::Context::new(scope);
         let scope = &mut v8::ContextScope::new(scope, context);
+        crate::utils::setup_process_state(scope);
+        let context = v8::Global::new(scope, context);
+        let _watchdog = crate::limits::Watchdog::arm_request(scope, &context);
+        let scope = &mut v8::TryCatch::new(scope);
 
         let code = v8::String::new(scope, &response_body).unwrap();
 
@@ -101,6 +120,11 @@ fn handle_client(mut stream: TcpStream, handler: &HttpHandler) {
                         .unwrap_or_else(|| "".to_string());
                     (200, body)
                 }
+            } else if scope.has_terminated() {
+                if let Some(reason) = crate::limits::take_reason(scope) {
+                    eprintln!("{}", reason);
+                }
+                (500, "Handler stopped by a resource limit".to_string())
             } else {
                 (500, "Script execution failed".to_string())
             }
@@ -138,15 +162,27 @@ pub fn rode_serve(
         return;
     }
 
+    let (host, default_port) = DEFAULT_LISTEN
+        .get()
+        .cloned()
+        .unwrap_or_else(|| ("127.0.0.1".to_string(), 8000));
     let port = if args.length() > 1 {
         args.get(1)
             .to_uint32(scope)
             .map(|v| v.value() as u16)
-            .unwrap_or(8000)
+            .unwrap_or(default_port)
     } else {
-        8000
+        default_port
     };
 
+    if let Some(config) = crate::config::current()
+        && let Err(message) = config.check_net(&host, Some(port))
+    {
+        let error = v8::String::new(scope, &message).unwrap();
+        scope.throw_exception(error.into());
+        return;
+    }
+
     let callback_js = handler_func
         .to_string(scope)
         .map(|s| s.to_rust_string_lossy(scope))
@@ -155,7 +191,7 @@ pub fn rode_serve(
     let handler = HttpHandler { callback_js };
 
     let server_handle = thread::spawn(move || {
-        let listener = match TcpListener::bind(format!("127.0.0.1:{}", port)) {
+        let listener = match TcpListener::bind(format!("{}:{}", host, port)) {
             Ok(listener) => listener,
             Err(e) => {
                 eprintln!("Failed to bind to port {}: {}", port, e);
@@ -163,7 +199,7 @@ pub fn rode_serve(
             }
         };
 
-        println!("Server running on http://127.0.0.1:{}", port);
+        println!("Server running on http://{}:{}", host, port);
 
         for stream in listener.incoming() {
             match stream {
@@ -182,35 +218,14 @@ pub fn rode_serve(
         }
     });
 
+    crate::limits::serving(scope);
     loop {
         thread::sleep(Duration::from_millis(100));
-        if server_handle.is_finished() {
+        crate::hot::poll(scope);
+        crate::inspector::poll();
+        crate::profiler::poll(scope);
+        if crate::limits::poll(scope) || server_handle.is_finished() {
             break;
         }
     }
 }
-
-pub fn setup_http(scope: &mut v8::HandleScope) {
-    let global = scope.get_current_context().global(scope);
-    let rode_key = v8::String::new(scope, "Rode").unwrap();
-
-    let rode_obj = if let Some(existing) = global
-        .get(scope, rode_key.into())
-        .and_then(|v| v.to_object(scope))
-    {
-        existing
-    } else {
-        let new_obj = v8::Object::new(scope);
-        global.set(scope, rode_key.into(), new_obj.into());
-        new_obj
-    };
-
-    let http_obj = v8::Object::new(scope);
-    let http_key = v8::String::new(scope, "http").unwrap();
-
-    let serve_key = v8::String::new(scope, "serve").unwrap();
-    let serve_func = v8::Function::new(scope, rode_serve).unwrap();
-    http_obj.set(scope, serve_key.into(), serve_func.into());
-
-    rode_obj.set(scope, http_key.into(), http_obj.into());
-}
@@ -0,0 +1,291 @@
+// `Rode.mock` (also `require('rode:test/mock')`)
+// Spies and stubs wrap functions and object methods, so `Rode.fs` and the
+// other built-ins can be stubbed like any object. The fetch interceptor
+// swaps `globalThis.fetch` for one that answers from registered routes and
+// never reaches the network, and the fake timers provide `setTimeout`,
+// `setInterval` and `Date` on a virtual clock that only moves when the test
+// advances it. `restore()` puts back whatever was there before.
+(function () {
+  const active = new Set()
+
+  function createSpy(implementation, restore) {
+    const calls = []
+    const results = []
+    const contexts = []
+    const once = []
+    let current = implementation
+
+    const spy = function (...args) {
+      calls.push(args)
+      contexts.push(this)
+      const fn = once.length > 0 ? once.shift() : current
+      try {
+        let value
+        if (fn && new.target) value = Reflect.construct(fn, args, new.target)
+        else if (fn) value = fn.apply(this, args)
+        results.push({ type: 'return', value })
+        return value
+      } catch (error) {
+        results.push({ type: 'throw', value: error })
+        throw error
+      }
+    }
+
+    spy.mock = {
+      calls,
+      results,
+      contexts,
+      callCount: () => calls.length,
+      lastCall: () => calls[calls.length - 1],
+      reset() {
+        calls.length = 0
+        results.length = 0
+        contexts.length = 0
+        once.length = 0
+      },
+      restore() {
+        active.delete(spy)
+        restore()
+      },
+    }
+    spy.mockImplementation = (fn) => ((current = fn), spy)
+    spy.mockImplementationOnce = (fn) => (once.push(fn), spy)
+    spy.mockReturnValue = (value) => spy.mockImplementation(() => value)
+    spy.mockReturnValueOnce = (value) => spy.mockImplementationOnce(() => value)
+    spy.mockResolvedValue = (value) => spy.mockImplementation(() => Promise.resolve(value))
+    spy.mockRejectedValue = (error) => spy.mockImplementation(() => Promise.reject(error))
+    return spy
+  }
+
+  // A spy with `implementation`, or one that returns undefined
+  function fn(implementation) {
+    return createSpy(implementation, () => {})
+  }
+
+  // Replace `object[name]` with a spy that calls the original method unless
+  // given an implementation
+  function method(object, name, implementation) {
+    const original = object?.[name]
+    if (typeof original !== 'function') {
+      throw new TypeError('mock.method(): ' + String(name) + ' is not a method')
+    }
+    const own = Object.prototype.hasOwnProperty.call(object, name)
+    const spy = createSpy(implementation ?? original, () => {
+      if (own) object[name] = original
+      else delete object[name]
+    })
+    object[name] = spy
+    active.add(spy)
+    return spy
+  }
+
+  const STATUS_TEXT = {
+    200: 'OK', 201: 'Created', 202: 'Accepted', 204: 'No Content',
+    301: 'Moved Permanently', 302: 'Found', 304: 'Not Modified',
+    400: 'Bad Request', 401: 'Unauthorized', 403: 'Forbidden', 404: 'Not Found',
+    409: 'Conflict', 422: 'Unprocessable Entity', 429: 'Too Many Requests',
+    500: 'Internal Server Error', 502: 'Bad Gateway', 503: 'Service Unavailable',
+  }
+
+  // A response shaped like the ones `fetch` returns. Objects and arrays are
+  // sent as JSON.
+  function response(body = '', init = {}) {
+    const status = init.status ?? 200
+    const headers = {}
+    for (const [name, value] of Object.entries(init.headers ?? {})) {
+      headers[name.toLowerCase()] = String(value)
+    }
+    if (typeof body !== 'string') {
+      body = JSON.stringify(body)
+      headers['content-type'] ??= 'application/json'
+    }
+    return {
+      status,
+      statusText: init.statusText ?? STATUS_TEXT[status] ?? '',
+      ok: status >= 200 && status < 300,
+      headers,
+      body,
+      text() {
+        return this.body
+      },
+      json() {
+        return JSON.parse(this.body)
+      },
+    }
+  }
+
+  const isResponse = (value) =>
+    typeof value === 'object' && value !== null && typeof value.text === 'function' && 'status' in value
+
+  // Everything after the host, e.g. '/users?page=2'
+  const pathOf = (url) => url.replace(/^[a-z][a-z0-9+.-]*:\/\/[^/?#]*/i, '') || '/'
+
+  // `pattern` is a full URL, a path starting with '/', a RegExp tested
+  // against the URL or a function of the request
+  function matches(pattern, request) {
+    if (pattern instanceof RegExp) return pattern.test(request.url)
+    if (typeof pattern === 'function') return pattern(request) === true
+    const text = String(pattern)
+    return text.startsWith('/') ? pathOf(request.url) === text : request.url === text
+  }
+
+  let fetchMock = null
+
+  // Answer `fetch` from routes registered with `on`. Requests that match no
+  // route throw, so a test never reaches the network by accident.
+  function fetch() {
+    if (fetchMock) return fetchMock
+
+    const original = globalThis.fetch
+    const routes = []
+    const calls = []
+
+    function mockFetch(url, options = {}) {
+      const request = {
+        url: String(url),
+        method: String(options.method ?? 'GET').toUpperCase(),
+        headers: { ...options.headers },
+        body: options.body,
+      }
+      calls.push(request)
+      const route = routes.find(
+        (route) => (route.method === '*' || route.method === request.method) && matches(route.pattern, request),
+      )
+      if (!route) {
+        throw new Error('No mocked fetch route for ' + request.method + ' ' + request.url)
+      }
+      if (route.times !== undefined && --route.times === 0) routes.splice(routes.indexOf(route), 1)
+
+      const reply = typeof route.reply === 'function' ? route.reply(request) : route.reply
+      const toResponse = (reply) => (isResponse(reply) ? reply : response(reply))
+      return reply instanceof Promise ? reply.then(toResponse) : toResponse(reply)
+    }
+
+    fetchMock = {
+      calls,
+      // on(method?, pattern, reply, { times }?): `reply` is a response, a
+      // body, or a function of the request returning either
+      on(...args) {
+        const method = args.length >= 3 && typeof args[0] === 'string' && /^[A-Za-z*]+$/.test(args[0])
+          ? args.shift().toUpperCase()
+          : '*'
+        const [pattern, reply, options = {}] = args
+        routes.push({ method, pattern, reply, times: options.times })
+        return fetchMock
+      },
+      reset() {
+        routes.length = 0
+        calls.length = 0
+      },
+      restore() {
+        globalThis.fetch = original
+        fetchMock = null
+      },
+    }
+    globalThis.fetch = mockFetch
+    return fetchMock
+  }
+
+  let clock = null
+
+  // Fake `setTimeout`, `setInterval`, `setImmediate` and `Date`. Timers only
+  // fire when `tick` or `runAll` moves the virtual clock past them, in the
+  // order they are due.
+  const timers = {
+    install({ now = 0 } = {}) {
+      if (clock) throw new Error('Fake timers are already installed')
+      const names = ['setTimeout', 'clearTimeout', 'setInterval', 'clearInterval', 'setImmediate', 'clearImmediate', 'Date']
+      const saved = names.map((name) => [name, Object.getOwnPropertyDescriptor(globalThis, name)])
+      clock = { now: Number(now), pending: new Map(), nextId: 1, saved }
+
+      const RealDate = saved.find(([name]) => name === 'Date')[1].value
+      const currentTime = () => (clock ? clock.now : RealDate.now())
+      // A function rather than a class, since `Date()` without `new` is
+      // allowed and returns the current time as a string
+      const FakeDate = function Date(...args) {
+        if (!new.target) return new RealDate(currentTime()).toString()
+        return Reflect.construct(RealDate, args.length === 0 ? [currentTime()] : args, new.target)
+      }
+      Object.setPrototypeOf(FakeDate, RealDate)
+      FakeDate.prototype = RealDate.prototype
+      FakeDate.now = currentTime
+
+      const schedule = (callback, delay, args, interval) => {
+        if (typeof callback !== 'function') throw new TypeError('The callback must be a function')
+        const id = clock.nextId++
+        const wait = Math.max(0, Number(delay) || 0)
+        clock.pending.set(id, { callback, args, at: clock.now + wait, interval: interval ? Math.max(1, wait) : null })
+        return id
+      }
+      const clear = (id) => clock?.pending.delete(id)
+
+      globalThis.setTimeout = (callback, delay, ...args) => schedule(callback, delay, args, false)
+      globalThis.setInterval = (callback, delay, ...args) => schedule(callback, delay, args, true)
+      globalThis.setImmediate = (callback, ...args) => schedule(callback, 0, args, false)
+      globalThis.clearTimeout = globalThis.clearInterval = globalThis.clearImmediate = clear
+      globalThis.Date = FakeDate
+      return timers
+    },
+
+    // Advance the clock by `ms`, firing every timer due on the way
+    tick(ms = 0) {
+      const until = installed().now + Math.max(0, Number(ms) || 0)
+      let next
+      while ((next = due(until))) fire(next)
+      clock.now = until
+      return timers
+    },
+
+    // Fire timers until none are left, moving the clock to each one
+    runAll({ limit = 1000 } = {}) {
+      installed()
+      for (let fired = 0; ; fired++) {
+        const next = due(Infinity)
+        if (!next) return timers
+        if (fired >= limit) throw new Error('Timers still pending after ' + limit + ' runs, is an interval never cleared?')
+        fire(next)
+      }
+    },
+
+    now: () => installed().now,
+    pending: () => installed().pending.size,
+
+    restore() {
+      if (!clock) return
+      for (const [name, descriptor] of clock.saved) {
+        if (descriptor) Object.defineProperty(globalThis, name, descriptor)
+        else delete globalThis[name]
+      }
+      clock = null
+    },
+  }
+
+  function installed() {
+    if (!clock) throw new Error('Fake timers are not installed, call mock.timers.install() first')
+    return clock
+  }
+
+  // The earliest timer due by `until`, ties going to the oldest
+  function due(until) {
+    let next = null
+    for (const [id, timer] of clock.pending) {
+      if (timer.at <= until && (!next || timer.at < next[1].at)) next = [id, timer]
+    }
+    return next
+  }
+
+  function fire([id, timer]) {
+    clock.now = Math.max(clock.now, timer.at)
+    if (timer.interval === null) clock.pending.delete(id)
+    else timer.at += timer.interval
+    timer.callback(...timer.args)
+  }
+
+  function restoreAll() {
+    for (const spy of [...active]) spy.mock.restore()
+    fetchMock?.restore()
+    timers.restore()
+  }
+
+  return { fn, method, fetch, response, timers, restoreAll }
+})
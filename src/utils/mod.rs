@@ -1,3 +1,4 @@
+pub mod assert;
 pub mod console;
 pub mod fetch;
 pub mod fs;
@@ -8,12 +9,4 @@ pub mod process;
 pub mod prompt;
 pub mod uuid;
 
-pub use console::setup_console;
-pub use fetch::setup_fetch;
-pub use fs::setup_fs;
-pub use http::setup_http;
-pub use password::setup_password;
-pub use path::setup_path;
-pub use process::setup_process;
-pub use prompt::setup_prompt;
-pub use uuid::setup_uuid;
+pub use process::setup_process_state;
@@ -1,45 +1,6 @@
 use rusty_v8 as v8;
 
-pub fn setup_password(scope: &mut v8::HandleScope) {
-    let global = scope.get_current_context().global(scope);
-
-    // Get or create Rode object
-    let rode_key = v8::String::new(scope, "Rode").unwrap();
-    let rode_obj = if let Some(existing) = global.get(scope, rode_key.into()) {
-        existing.to_object(scope).unwrap()
-    } else {
-        let new_obj = v8::Object::new(scope);
-        global.set(scope, rode_key.into(), new_obj.into());
-        new_obj
-    };
-
-    // Create password object
-    let password_obj = v8::Object::new(scope);
-    let password_key = v8::String::new(scope, "password").unwrap();
-    rode_obj.set(scope, password_key.into(), password_obj.into());
-
-    // password.hash(password, rounds?) - Hash a password with bcrypt
-    let hash_key = v8::String::new(scope, "hash").unwrap();
-    let hash_func = v8::Function::new(scope, password_hash).unwrap();
-    password_obj.set(scope, hash_key.into(), hash_func.into());
-
-    // password.verify(password, hash) - Verify a password against a hash
-    let verify_key = v8::String::new(scope, "verify").unwrap();
-    let verify_func = v8::Function::new(scope, password_verify).unwrap();
-    password_obj.set(scope, verify_key.into(), verify_func.into());
-
-    // password.strength(password) - Check password strength
-    let strength_key = v8::String::new(scope, "strength").unwrap();
-    let strength_func = v8::Function::new(scope, password_strength).unwrap();
-    password_obj.set(scope, strength_key.into(), strength_func.into());
-
-    // password.generate(length?, options?) - Generate a secure password
-    let generate_key = v8::String::new(scope, "generate").unwrap();
-    let generate_func = v8::Function::new(scope, password_generate).unwrap();
-    password_obj.set(scope, generate_key.into(), generate_func.into());
-}
-
-fn password_hash(
+pub fn password_hash(
     scope: &mut v8::HandleScope,
     args: v8::FunctionCallbackArguments,
     mut rv: v8::ReturnValue,
@@ -78,7 +39,7 @@ fn password_hash(
     }
 }
 
-fn password_verify(
+pub fn password_verify(
     scope: &mut v8::HandleScope,
     args: v8::FunctionCallbackArguments,
     mut rv: v8::ReturnValue,
@@ -119,7 +80,7 @@ fn password_verify(
     }
 }
 
-fn password_strength(
+pub fn password_strength(
     scope: &mut v8::HandleScope,
     args: v8::FunctionCallbackArguments,
     mut rv: v8::ReturnValue,
@@ -167,7 +128,7 @@ fn password_strength(
     rv.set(result_obj.into());
 }
 
-fn password_generate(
+pub fn password_generate(
     scope: &mut v8::HandleScope,
     args: v8::FunctionCallbackArguments,
     mut rv: v8::ReturnValue,
@@ -1,78 +1,7 @@
 use rusty_v8 as v8;
 use std::path::{Path, PathBuf};
 
-pub fn setup_path(scope: &mut v8::HandleScope) {
-    let global = scope.get_current_context().global(scope);
-
-    // Get or create Rode object
-    let rode_key = v8::String::new(scope, "Rode").unwrap();
-    let rode_obj = if let Some(existing) = global.get(scope, rode_key.into()) {
-        existing.to_object(scope).unwrap()
-    } else {
-        let new_obj = v8::Object::new(scope);
-        global.set(scope, rode_key.into(), new_obj.into());
-        new_obj
-    };
-
-    // Create path object
-    let path_obj = v8::Object::new(scope);
-    let path_key = v8::String::new(scope, "path").unwrap();
-    rode_obj.set(scope, path_key.into(), path_obj.into());
-
-    // path.join(...paths)
-    let join_key = v8::String::new(scope, "join").unwrap();
-    let join_func = v8::Function::new(scope, path_join).unwrap();
-    path_obj.set(scope, join_key.into(), join_func.into());
-
-    // path.resolve(...paths)
-    let resolve_key = v8::String::new(scope, "resolve").unwrap();
-    let resolve_func = v8::Function::new(scope, path_resolve).unwrap();
-    path_obj.set(scope, resolve_key.into(), resolve_func.into());
-
-    // path.dirname(path)
-    let dirname_key = v8::String::new(scope, "dirname").unwrap();
-    let dirname_func = v8::Function::new(scope, path_dirname).unwrap();
-    path_obj.set(scope, dirname_key.into(), dirname_func.into());
-
-    // path.basename(path, ext?)
-    let basename_key = v8::String::new(scope, "basename").unwrap();
-    let basename_func = v8::Function::new(scope, path_basename).unwrap();
-    path_obj.set(scope, basename_key.into(), basename_func.into());
-
-    // path.extname(path)
-    let extname_key = v8::String::new(scope, "extname").unwrap();
-    let extname_func = v8::Function::new(scope, path_extname).unwrap();
-    path_obj.set(scope, extname_key.into(), extname_func.into());
-
-    // path.isAbsolute(path)
-    let is_absolute_key = v8::String::new(scope, "isAbsolute").unwrap();
-    let is_absolute_func = v8::Function::new(scope, path_is_absolute).unwrap();
-    path_obj.set(scope, is_absolute_key.into(), is_absolute_func.into());
-
-    // path.normalize(path)
-    let normalize_key = v8::String::new(scope, "normalize").unwrap();
-    let normalize_func = v8::Function::new(scope, path_normalize).unwrap();
-    path_obj.set(scope, normalize_key.into(), normalize_func.into());
-
-    // path.relative(from, to)
-    let relative_key = v8::String::new(scope, "relative").unwrap();
-    let relative_func = v8::Function::new(scope, path_relative).unwrap();
-    path_obj.set(scope, relative_key.into(), relative_func.into());
-
-    // path.sep (path separator)
-    let sep_key = v8::String::new(scope, "sep").unwrap();
-    let sep_value = if cfg!(windows) { "\\" } else { "/" };
-    let sep_str = v8::String::new(scope, sep_value).unwrap();
-    path_obj.set(scope, sep_key.into(), sep_str.into());
-
-    // path.delimiter (PATH delimiter)
-    let delimiter_key = v8::String::new(scope, "delimiter").unwrap();
-    let delimiter_value = if cfg!(windows) { ";" } else { ":" };
-    let delimiter_str = v8::String::new(scope, delimiter_value).unwrap();
-    path_obj.set(scope, delimiter_key.into(), delimiter_str.into());
-}
-
-fn path_join(
+pub fn path_join(
     scope: &mut v8::HandleScope,
     args: v8::FunctionCallbackArguments,
     mut rv: v8::ReturnValue,
@@ -93,7 +22,7 @@ fn path_join(
     rv.set(result_str.into());
 }
 
-fn path_resolve(
+pub fn path_resolve(
     scope: &mut v8::HandleScope,
     args: v8::FunctionCallbackArguments,
     mut rv: v8::ReturnValue,
@@ -123,7 +52,7 @@ fn path_resolve(
     rv.set(result_str.into());
 }
 
-fn path_dirname(
+pub fn path_dirname(
     scope: &mut v8::HandleScope,
     args: v8::FunctionCallbackArguments,
     mut rv: v8::ReturnValue,
@@ -144,7 +73,7 @@ fn path_dirname(
     }
 }
 
-fn path_basename(
+pub fn path_basename(
     scope: &mut v8::HandleScope,
     args: v8::FunctionCallbackArguments,
     mut rv: v8::ReturnValue,
@@ -179,7 +108,7 @@ fn path_basename(
     }
 }
 
-fn path_extname(
+pub fn path_extname(
     scope: &mut v8::HandleScope,
     args: v8::FunctionCallbackArguments,
     mut rv: v8::ReturnValue,
@@ -202,7 +131,7 @@ fn path_extname(
     }
 }
 
-fn path_is_absolute(
+pub fn path_is_absolute(
     scope: &mut v8::HandleScope,
     args: v8::FunctionCallbackArguments,
     mut rv: v8::ReturnValue,
@@ -222,7 +151,7 @@ fn path_is_absolute(
     }
 }
 
-fn path_normalize(
+pub fn path_normalize(
     scope: &mut v8::HandleScope,
     args: v8::FunctionCallbackArguments,
     mut rv: v8::ReturnValue,
@@ -243,7 +172,7 @@ fn path_normalize(
     }
 }
 
-fn path_relative(
+pub fn path_relative(
     scope: &mut v8::HandleScope,
     args: v8::FunctionCallbackArguments,
     mut rv: v8::ReturnValue,
@@ -1,27 +1,30 @@
 use rusty_v8 as v8;
 use std::env;
 use std::process;
+use std::sync::OnceLock;
 
-pub fn setup_process(scope: &mut v8::HandleScope) {
-    let global = scope.get_current_context().global(scope);
+/// Arguments after the script name, as parsed by the CLI
+static SCRIPT_ARGS: OnceLock<Vec<String>> = OnceLock::new();
+
+/// Set what `Rode.args` contains; called once before any script runs
+pub fn set_script_args(args: Vec<String>) {
+    let _ = SCRIPT_ARGS.set(args);
+}
 
-    // Get or create Rode object
+/// `Rode.args`, `Rode.argv` and `Rode.env`, which belong to this process and
+/// so are set on every context rather than kept in the startup snapshot
+pub fn setup_process_state(scope: &mut v8::HandleScope) {
+    let global = scope.get_current_context().global(scope);
     let rode_key = v8::String::new(scope, "Rode").unwrap();
-    let rode_obj = if let Some(existing) = global.get(scope, rode_key.into()) {
-        existing.to_object(scope).unwrap()
-    } else {
-        let new_obj = v8::Object::new(scope);
-        global.set(scope, rode_key.into(), new_obj.into());
-        new_obj
+    let Some(rode_obj) = global
+        .get(scope, rode_key.into())
+        .and_then(|rode| rode.to_object(scope))
+    else {
+        return;
     };
 
-    // Rode.exit(code?) - Exit the process with optional exit code
-    let exit_key = v8::String::new(scope, "exit").unwrap();
-    let exit_func = v8::Function::new(scope, rode_exit).unwrap();
-    rode_obj.set(scope, exit_key.into(), exit_func.into());
-
-    // Rode.args - Array of command line arguments (excluding program name)
-    let args: Vec<String> = env::args().skip(1).collect();
+    // Rode.args - Array of the script's own arguments (excluding rode flags)
+    let args: &[String] = SCRIPT_ARGS.get().map(Vec::as_slice).unwrap_or_default();
     let args_array = v8::Array::new(scope, args.len() as i32);
     for (i, arg) in args.iter().enumerate() {
         let index = v8::Number::new(scope, i as f64);
@@ -42,9 +45,13 @@ pub fn setup_process(scope: &mut v8::HandleScope) {
     let argv_key = v8::String::new(scope, "argv").unwrap();
     rode_obj.set(scope, argv_key.into(), argv_array.into());
 
-    // Rode.env - Object containing environment variables
+    // Rode.env - Object containing environment variables allowed by rode.json
     let env_obj = v8::Object::new(scope);
+    let config = crate::config::current();
     for (key, value) in env::vars() {
+        if config.is_some_and(|config| !config.allows_env(&key)) {
+            continue;
+        }
         let env_key = v8::String::new(scope, &key).unwrap();
         let env_value = v8::String::new(scope, &value).unwrap();
         env_obj.set(scope, env_key.into(), env_value.into());
@@ -53,7 +60,7 @@ pub fn setup_process(scope: &mut v8::HandleScope) {
     rode_obj.set(scope, env_key.into(), env_obj.into());
 }
 
-fn rode_exit(
+pub fn rode_exit(
     scope: &mut v8::HandleScope,
     args: v8::FunctionCallbackArguments,
     _rv: v8::ReturnValue,
@@ -65,6 +72,34 @@ fn rode_exit(
         0
     };
 
-    // Exit the process
+    // Exit the process, after writing what --cpu-prof collected
+    crate::profiler::finish_cpu_profile();
     process::exit(exit_code);
 }
+
+pub fn rode_write_heap_snapshot(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    mut rv: v8::ReturnValue,
+) {
+    let path =
+        (!args.get(0).is_null_or_undefined()).then(|| args.get(0).to_rust_string_lossy(scope));
+    let path = crate::profiler::heap_snapshot_path(path.as_deref());
+    let written = match crate::config::current() {
+        Some(config) => config.check_path(&path.to_string_lossy(), true),
+        None => Ok(()),
+    }
+    .and_then(|_| crate::profiler::write_heap_snapshot(scope, &path));
+
+    match written {
+        Ok(path) => {
+            let path = v8::String::new(scope, &path.to_string_lossy()).unwrap();
+            rv.set(path.into());
+        }
+        Err(message) => {
+            let message = v8::String::new(scope, &message).unwrap();
+            let error = v8::Exception::error(scope, message);
+            scope.throw_exception(error);
+        }
+    }
+}
@@ -1,21 +1,7 @@
 use rusty_v8 as v8;
 use std::io::{self, Write};
 
-pub fn setup_prompt(scope: &mut v8::HandleScope) {
-    let global = scope.get_current_context().global(scope);
-
-    // Add prompt function to global scope
-    let prompt_key = v8::String::new(scope, "prompt").unwrap();
-    let prompt_func = v8::Function::new(scope, prompt_function).unwrap();
-    global.set(scope, prompt_key.into(), prompt_func.into());
-
-    // Add alert function to global scope
-    let alert_key = v8::String::new(scope, "alert").unwrap();
-    let alert_func = v8::Function::new(scope, alert_function).unwrap();
-    global.set(scope, alert_key.into(), alert_func.into());
-}
-
-fn prompt_function(
+pub fn prompt_function(
     scope: &mut v8::HandleScope,
     args: v8::FunctionCallbackArguments,
     mut rv: v8::ReturnValue,
@@ -82,7 +68,7 @@ fn prompt_function(
     }
 }
 
-fn alert_function(
+pub fn alert_function(
     scope: &mut v8::HandleScope,
     args: v8::FunctionCallbackArguments,
     mut rv: v8::ReturnValue,
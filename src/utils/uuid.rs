@@ -1,61 +1,7 @@
 use rusty_v8 as v8;
 use std::fmt::Write;
 
-pub fn setup_uuid(scope: &mut v8::HandleScope) {
-    let global = scope.get_current_context().global(scope);
-
-    // Get or create Rode object
-    let rode_key = v8::String::new(scope, "Rode").unwrap();
-    let rode_obj = if let Some(existing) = global.get(scope, rode_key.into()) {
-        existing.to_object(scope).unwrap()
-    } else {
-        let new_obj = v8::Object::new(scope);
-        global.set(scope, rode_key.into(), new_obj.into());
-        new_obj
-    };
-
-    // Create uuid object
-    let uuid_obj = v8::Object::new(scope);
-    let uuid_key = v8::String::new(scope, "uuid").unwrap();
-    rode_obj.set(scope, uuid_key.into(), uuid_obj.into());
-
-    // uuid.v4() - Generate random UUID v4
-    let v4_key = v8::String::new(scope, "v4").unwrap();
-    let v4_func = v8::Function::new(scope, uuid_v4).unwrap();
-    uuid_obj.set(scope, v4_key.into(), v4_func.into());
-
-    // uuid.v1() - Generate time-based UUID v1
-    let v1_key = v8::String::new(scope, "v1").unwrap();
-    let v1_func = v8::Function::new(scope, uuid_v1).unwrap();
-    uuid_obj.set(scope, v1_key.into(), v1_func.into());
-
-    // uuid.v7() - Generate time-ordered UUID v7
-    let v7_key = v8::String::new(scope, "v7").unwrap();
-    let v7_func = v8::Function::new(scope, uuid_v7).unwrap();
-    uuid_obj.set(scope, v7_key.into(), v7_func.into());
-
-    // uuid.nil() - Get nil UUID (all zeros)
-    let nil_key = v8::String::new(scope, "nil").unwrap();
-    let nil_func = v8::Function::new(scope, uuid_nil).unwrap();
-    uuid_obj.set(scope, nil_key.into(), nil_func.into());
-
-    // uuid.parse(uuid) - Parse UUID string to validate
-    let parse_key = v8::String::new(scope, "parse").unwrap();
-    let parse_func = v8::Function::new(scope, uuid_parse).unwrap();
-    uuid_obj.set(scope, parse_key.into(), parse_func.into());
-
-    // uuid.validate(uuid) - Validate UUID string
-    let validate_key = v8::String::new(scope, "validate").unwrap();
-    let validate_func = v8::Function::new(scope, uuid_validate).unwrap();
-    uuid_obj.set(scope, validate_key.into(), validate_func.into());
-
-    // uuid.version(uuid) - Get UUID version
-    let version_key = v8::String::new(scope, "version").unwrap();
-    let version_func = v8::Function::new(scope, uuid_version).unwrap();
-    uuid_obj.set(scope, version_key.into(), version_func.into());
-}
-
-fn uuid_v4(
+pub fn uuid_v4(
     scope: &mut v8::HandleScope,
     _args: v8::FunctionCallbackArguments,
     mut rv: v8::ReturnValue,
@@ -65,7 +11,7 @@ fn uuid_v4(
     rv.set(result_str.into());
 }
 
-fn uuid_v1(
+pub fn uuid_v1(
     scope: &mut v8::HandleScope,
     _args: v8::FunctionCallbackArguments,
     mut rv: v8::ReturnValue,
@@ -75,7 +21,7 @@ fn uuid_v1(
     rv.set(result_str.into());
 }
 
-fn uuid_v7(
+pub fn uuid_v7(
     scope: &mut v8::HandleScope,
     _args: v8::FunctionCallbackArguments,
     mut rv: v8::ReturnValue,
@@ -85,7 +31,7 @@ fn uuid_v7(
     rv.set(result_str.into());
 }
 
-fn uuid_nil(
+pub fn uuid_nil(
     scope: &mut v8::HandleScope,
     _args: v8::FunctionCallbackArguments,
     mut rv: v8::ReturnValue,
@@ -95,7 +41,7 @@ fn uuid_nil(
     rv.set(result_str.into());
 }
 
-fn uuid_parse(
+pub fn uuid_parse(
     scope: &mut v8::HandleScope,
     args: v8::FunctionCallbackArguments,
     mut rv: v8::ReturnValue,
@@ -121,7 +67,7 @@ fn uuid_parse(
     }
 }
 
-fn uuid_validate(
+pub fn uuid_validate(
     scope: &mut v8::HandleScope,
     args: v8::FunctionCallbackArguments,
     mut rv: v8::ReturnValue,
@@ -143,7 +89,7 @@ fn uuid_validate(
     }
 }
 
-fn uuid_version(
+pub fn uuid_version(
     scope: &mut v8::HandleScope,
     args: v8::FunctionCallbackArguments,
     mut rv: v8::ReturnValue,
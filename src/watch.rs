@@ -0,0 +1,227 @@
+/// File tracking for `--watch`
+/// Watch mode follows the entry script, every module it loaded through
+/// require(), the env files, rode.json and tsconfig.json, plus any
+/// `--watch-path`. Files are watched through their parent directories so
+/// editors that save by writing a new file and renaming it are still seen.
+use crate::config;
+use crate::glob::Glob;
+use notify::event::ModifyKind;
+use notify::{EventKind, RecursiveMode, Watcher};
+use std::collections::BTreeSet;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub struct WatchSet {
+    entry: PathBuf,
+    /// Env files, config files and `--watch-path` files, watched on every run
+    fixed: BTreeSet<PathBuf>,
+    /// Modules the script loaded through require()
+    modules: BTreeSet<PathBuf>,
+    /// Files whose changes restart the script
+    files: BTreeSet<PathBuf>,
+    /// `--watch-path` directories, watched recursively
+    trees: BTreeSet<PathBuf>,
+    /// `--watch-ignore` globs, relative to the current directory
+    ignore: Vec<Glob>,
+    cwd: PathBuf,
+    /// Directories currently subscribed to, and whether recursively
+    subscribed: BTreeSet<(PathBuf, bool)>,
+}
+
+impl WatchSet {
+    pub fn new(
+        entry: &Path,
+        watch_paths: &[String],
+        ignore: &[String],
+    ) -> Result<WatchSet, String> {
+        let cwd = env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+        let ignore = ignore
+            .iter()
+            .map(|pattern| Glob::new(pattern))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut fixed = BTreeSet::new();
+        let mut trees = BTreeSet::new();
+        for path in watch_paths {
+            let path = absolute(&cwd.join(path));
+            if path.is_dir() {
+                trees.insert(path);
+            } else {
+                fixed.insert(path);
+            }
+        }
+
+        match config::current().and_then(|config| config.env_files.as_ref()) {
+            Some(files) => fixed.extend(files.iter().map(|file| absolute(file))),
+            None => fixed.extend(
+                crate::env_parser::DEFAULT_ENV_FILES
+                    .iter()
+                    .map(|file| absolute(&cwd.join(file))),
+            ),
+        }
+        if let Some(config) = config::current() {
+            fixed.insert(absolute(&config.path));
+        }
+
+        let entry = absolute(entry);
+        if let Some(tsconfig) = crate::tsconfig::find_path(entry.parent().unwrap_or(&cwd)) {
+            fixed.insert(absolute(&tsconfig));
+        }
+
+        let mut watch = WatchSet {
+            entry,
+            fixed,
+            modules: BTreeSet::new(),
+            files: BTreeSet::new(),
+            trees,
+            ignore,
+            cwd,
+            subscribed: BTreeSet::new(),
+        };
+        watch.set_loaded_files(BTreeSet::new());
+        Ok(watch)
+    }
+
+    /// Replace the module graph with the files the last run loaded
+    pub fn set_loaded_files(&mut self, loaded: BTreeSet<PathBuf>) {
+        self.modules = loaded.iter().map(|file| absolute(file)).collect();
+        self.files = self.modules.clone();
+        self.files.insert(self.entry.clone());
+        self.files.extend(self.fixed.iter().cloned());
+    }
+
+    /// Subscribe to the directories the current files live in, dropping
+    /// the ones no longer needed
+    pub fn subscribe(&mut self, watcher: &mut impl Watcher) {
+        let wanted = self.subscriptions();
+
+        for (dir, _) in self.subscribed.difference(&wanted) {
+            let _ = watcher.unwatch(dir);
+        }
+        for (dir, recursive) in wanted.difference(&self.subscribed) {
+            let mode = if *recursive {
+                RecursiveMode::Recursive
+            } else {
+                RecursiveMode::NonRecursive
+            };
+            if let Err(err) = watcher.watch(dir, mode) {
+                eprintln!("Warning: Cannot watch {}: {}", dir.display(), err);
+            }
+        }
+
+        self.subscribed = wanted;
+    }
+
+    fn subscriptions(&self) -> BTreeSet<(PathBuf, bool)> {
+        let mut wanted: BTreeSet<(PathBuf, bool)> = self
+            .files
+            .iter()
+            .filter_map(|file| file.parent())
+            .filter(|dir| dir.is_dir())
+            .map(|dir| (dir.to_path_buf(), false))
+            .collect();
+        wanted.extend(self.trees.iter().map(|dir| (dir.clone(), true)));
+
+        // rode.json `watch.include` globs can match anywhere in the project
+        if let Some(config) = config::current().filter(|config| !config.watch.include.is_empty()) {
+            wanted.insert((absolute(&config.dir), true));
+        }
+
+        // A recursive watch already covers its subdirectories
+        let trees: Vec<PathBuf> = wanted
+            .iter()
+            .filter(|(_, recursive)| *recursive)
+            .map(|(dir, _)| dir.clone())
+            .collect();
+        wanted.retain(|(dir, recursive)| {
+            *recursive || !trees.iter().any(|tree| dir.starts_with(tree))
+        });
+        wanted
+    }
+
+    /// Whether a change to `path` should restart the script
+    pub fn is_relevant(&self, path: &Path) -> bool {
+        let relative = path.strip_prefix(&self.cwd).unwrap_or(path);
+        if self.ignore.iter().any(|glob| glob.matches(relative)) {
+            return false;
+        }
+        if let Some(config) = config::current() {
+            if config.watch_excludes(path) {
+                return false;
+            }
+            if config.watch_includes(path) {
+                return true;
+            }
+        }
+
+        self.files.contains(path) || self.trees.iter().any(|tree| path.starts_with(tree))
+    }
+
+    /// Whether a change to `path` can be handed to a `--hot` run: it is a
+    /// module the script required, not the entry script or a config file
+    pub fn is_hot_swappable(&self, path: &Path) -> bool {
+        self.modules.contains(path) && path != self.entry && !self.fixed.contains(path)
+    }
+}
+
+/// Changes to file contents or names; access and metadata events are noise
+pub fn is_change(kind: &EventKind) -> bool {
+    match kind {
+        EventKind::Create(_) | EventKind::Remove(_) => true,
+        EventKind::Modify(ModifyKind::Metadata(_)) => false,
+        EventKind::Modify(_) => true,
+        _ => false,
+    }
+}
+
+/// Canonical path when the file exists, or its canonical directory joined
+/// with the name, so paths compare equal to the ones the watcher reports
+fn absolute(path: &Path) -> PathBuf {
+    if let Ok(canonical) = fs::canonicalize(path) {
+        return canonical;
+    }
+    match (path.parent(), path.file_name()) {
+        (Some(dir), Some(name)) => fs::canonicalize(dir)
+            .map(|dir| dir.join(name))
+            .unwrap_or_else(|_| path.to_path_buf()),
+        _ => path.to_path_buf(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_watch_set() {
+        let dir = env::temp_dir().join(format!("rode-watch-{}", std::process::id()));
+        fs::create_dir_all(dir.join("lib")).unwrap();
+        fs::create_dir_all(dir.join("assets")).unwrap();
+        let dir = fs::canonicalize(&dir).unwrap();
+        fs::write(dir.join("main.js"), "").unwrap();
+        fs::write(dir.join("lib/util.js"), "").unwrap();
+
+        let mut watch = WatchSet::new(
+            &dir.join("main.js"),
+            &[dir.join("assets").to_string_lossy().to_string()],
+            &["**/*.tmp".to_string()],
+        )
+        .unwrap();
+        watch.set_loaded_files(BTreeSet::from([dir.join("lib/util.js")]));
+
+        assert!(watch.is_relevant(&dir.join("main.js")));
+        assert!(watch.is_relevant(&dir.join("lib/util.js")));
+        assert!(!watch.is_relevant(&dir.join("lib/other.js")));
+        assert!(watch.is_relevant(&dir.join("assets/logo.svg")));
+        assert!(watch.is_hot_swappable(&dir.join("lib/util.js")));
+        assert!(!watch.is_hot_swappable(&dir.join("main.js")));
+        assert!(!watch.is_relevant(&dir.join("lib/util.js.tmp")));
+        let subscriptions = watch.subscriptions();
+        assert!(subscriptions.contains(&(dir.join("lib"), false)));
+        assert!(subscriptions.contains(&(dir.clone(), false)));
+        assert!(subscriptions.contains(&(dir.join("assets"), true)));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,368 @@
+/// Just enough HTTP and WebSocket (RFC 6455) for the debugger server
+/// Only the server side: it reads a request head, answers the upgrade
+/// handshake, reads messages a client sends (masked, maybe fragmented) and
+/// writes unmasked, unfragmented ones back.
+use std::io::{self, BufRead, Read, Write};
+
+/// Appended to the client's key to prove the server speaks WebSocket
+const HANDSHAKE_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// Messages from a client beyond this are refused
+const MAX_MESSAGE_SIZE: u64 = 256 * 1024 * 1024;
+
+const OP_CONTINUATION: u8 = 0x0;
+const OP_TEXT: u8 = 0x1;
+const OP_BINARY: u8 = 0x2;
+const OP_CLOSE: u8 = 0x8;
+const OP_PING: u8 = 0x9;
+const OP_PONG: u8 = 0xA;
+
+pub struct Request {
+    pub path: String,
+    /// Header names are lowercase
+    pub headers: Vec<(String, String)>,
+}
+
+impl Request {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The client's key, when this asks to upgrade to a WebSocket
+    pub fn websocket_key(&self) -> Option<&str> {
+        let upgrade = self.header("upgrade")?;
+        if !upgrade.eq_ignore_ascii_case("websocket") {
+            return None;
+        }
+        self.header("sec-websocket-key")
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Message {
+    Text(String),
+    Binary(Vec<u8>),
+    Ping(Vec<u8>),
+    Pong,
+    Close,
+}
+
+/// Read an HTTP request line and headers, leaving any body unread
+pub fn read_request(reader: &mut impl BufRead) -> io::Result<Request> {
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let path = line
+        .split_whitespace()
+        .nth(1)
+        .ok_or_else(|| invalid("malformed request line"))?
+        .to_string();
+
+    let mut headers = Vec::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(invalid("connection closed in the headers"));
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            headers.push((name.trim().to_ascii_lowercase(), value.trim().to_string()));
+        }
+    }
+    Ok(Request { path, headers })
+}
+
+/// Send a complete HTTP response and close the exchange
+pub fn write_response(
+    writer: &mut impl Write,
+    status: &str,
+    content_type: &str,
+    body: &str,
+) -> io::Result<()> {
+    write!(
+        writer,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    )?;
+    writer.flush()
+}
+
+/// Accept the upgrade that `key` asked for
+pub fn write_handshake(writer: &mut impl Write, key: &str) -> io::Result<()> {
+    write!(
+        writer,
+        "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+        accept_key(key)
+    )?;
+    writer.flush()
+}
+
+fn accept_key(key: &str) -> String {
+    let digest = sha1(format!("{}{}", key.trim(), HANDSHAKE_GUID).as_bytes());
+    crate::source_map::base64_encode(&digest)
+}
+
+/// Reads messages from a client, joining fragments
+pub struct MessageReader<R> {
+    inner: R,
+    /// Opcode and data of a message still missing fragments
+    partial: Option<(u8, Vec<u8>)>,
+}
+
+impl<R: Read> MessageReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            partial: None,
+        }
+    }
+
+    pub fn read_message(&mut self) -> io::Result<Message> {
+        loop {
+            let (fin, opcode, payload) = read_frame(&mut self.inner)?;
+            match opcode {
+                // Control frames can arrive between the fragments of a message
+                OP_CLOSE => return Ok(Message::Close),
+                OP_PING => return Ok(Message::Ping(payload)),
+                OP_PONG => return Ok(Message::Pong),
+                OP_CONTINUATION => match &mut self.partial {
+                    Some((_, data)) => data.extend_from_slice(&payload),
+                    None => return Err(invalid("continuation without a message")),
+                },
+                OP_TEXT | OP_BINARY if self.partial.is_none() => {
+                    self.partial = Some((opcode, payload))
+                }
+                _ => return Err(invalid("unexpected opcode")),
+            }
+            if let Some((_, data)) = &self.partial
+                && data.len() as u64 > MAX_MESSAGE_SIZE
+            {
+                return Err(invalid("message too large"));
+            }
+            if fin && let Some((opcode, data)) = self.partial.take() {
+                return Ok(match opcode {
+                    OP_TEXT => Message::Text(
+                        String::from_utf8(data).map_err(|_| invalid("text is not UTF-8"))?,
+                    ),
+                    _ => Message::Binary(data),
+                });
+            }
+        }
+    }
+}
+
+fn read_frame(reader: &mut impl Read) -> io::Result<(bool, u8, Vec<u8>)> {
+    let mut head = [0u8; 2];
+    reader.read_exact(&mut head)?;
+    let fin = head[0] & 0x80 != 0;
+    let opcode = head[0] & 0x0F;
+    let masked = head[1] & 0x80 != 0;
+    let len = match head[1] & 0x7F {
+        126 => {
+            let mut len = [0u8; 2];
+            reader.read_exact(&mut len)?;
+            u16::from_be_bytes(len) as u64
+        }
+        127 => {
+            let mut len = [0u8; 8];
+            reader.read_exact(&mut len)?;
+            u64::from_be_bytes(len)
+        }
+        len => len as u64,
+    };
+    if len > MAX_MESSAGE_SIZE {
+        return Err(invalid("frame too large"));
+    }
+
+    let mut mask = [0u8; 4];
+    if masked {
+        reader.read_exact(&mut mask)?;
+    }
+    let mut payload = vec![0u8; len as usize];
+    reader.read_exact(&mut payload)?;
+    if masked {
+        for (i, byte) in payload.iter_mut().enumerate() {
+            *byte ^= mask[i % 4];
+        }
+    }
+    Ok((fin, opcode, payload))
+}
+
+pub fn write_text(writer: &mut impl Write, text: &str) -> io::Result<()> {
+    write_frame(writer, OP_TEXT, text.as_bytes())
+}
+
+pub fn write_pong(writer: &mut impl Write, payload: &[u8]) -> io::Result<()> {
+    write_frame(writer, OP_PONG, payload)
+}
+
+pub fn write_close(writer: &mut impl Write) -> io::Result<()> {
+    write_frame(writer, OP_CLOSE, &[])
+}
+
+fn write_frame(writer: &mut impl Write, opcode: u8, payload: &[u8]) -> io::Result<()> {
+    let mut frame = Vec::with_capacity(payload.len() + 10);
+    frame.push(0x80 | opcode);
+    match payload.len() {
+        len if len < 126 => frame.push(len as u8),
+        len if len <= u16::MAX as usize => {
+            frame.push(126);
+            frame.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        len => {
+            frame.push(127);
+            frame.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+    }
+    frame.extend_from_slice(payload);
+    writer.write_all(&frame)?;
+    writer.flush()
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// SHA-1, which the handshake requires; not for anything secret
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut h: [u32; 5] = [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476, 0xC3D2E1F0];
+
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&((data.len() as u64) * 8).to_be_bytes());
+
+    for block in message.chunks(64) {
+        let mut w = [0u32; 80];
+        for (i, word) in block.chunks(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e] = h;
+        for (i, word) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5A827999),
+                20..=39 => (b ^ c ^ d, 0x6ED9EBA1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1BBCDC),
+                _ => (b ^ c ^ d, 0xCA62C1D6),
+            };
+            let temp = a
+                .rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(*word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+        for (state, value) in h.iter_mut().zip([a, b, c, d, e]) {
+            *state = state.wrapping_add(value);
+        }
+    }
+
+    let mut digest = [0u8; 20];
+    for (bytes, word) in digest.chunks_mut(4).zip(h) {
+        bytes.copy_from_slice(&word.to_be_bytes());
+    }
+    digest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::BufReader;
+    use std::net::{TcpListener, TcpStream};
+    use std::thread;
+
+    #[test]
+    fn test_handshake() {
+        assert_eq!(
+            sha1(b"abc")
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect::<String>(),
+            "a9993e364706816aba3e25717850c26c9cd0d89d"
+        );
+        // The example from RFC 6455
+        assert_eq!(
+            accept_key("dGhlIHNhbXBsZSBub25jZQ=="),
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+    }
+
+    /// A local client upgrades, sends a masked fragmented message and a
+    /// ping, and reads the server's text frame back
+    #[test]
+    fn test_client_round_trip() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut writer = stream.try_clone().unwrap();
+            let mut reader = BufReader::new(stream);
+            let request = read_request(&mut reader).unwrap();
+            assert_eq!(request.path, "/session");
+            write_handshake(&mut writer, request.websocket_key().unwrap()).unwrap();
+            let mut reader = MessageReader::new(reader);
+            let first = reader.read_message().unwrap();
+            let second = reader.read_message().unwrap();
+            write_text(&mut writer, &"x".repeat(300)).unwrap();
+            (first, second)
+        });
+
+        let mut client = TcpStream::connect(address).unwrap();
+        client
+            .write_all(
+                b"GET /session HTTP/1.1\r\nHost: x\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\r\n",
+            )
+            .unwrap();
+        let mask = [1, 2, 3, 4];
+        let masked = |text: &[u8]| -> Vec<u8> {
+            text.iter()
+                .enumerate()
+                .map(|(i, b)| b ^ mask[i % 4])
+                .collect()
+        };
+        let mut frames = vec![OP_TEXT, 0x80 | 5];
+        frames.extend(mask);
+        frames.extend(masked(b"{\"id\""));
+        frames.extend([0x80 | OP_PING, 0x80]);
+        frames.extend(mask);
+        frames.extend([0x80 | OP_CONTINUATION, 0x80 | 3]);
+        frames.extend(mask);
+        frames.extend(masked(b":1}"));
+        client.write_all(&frames).unwrap();
+
+        let mut reader = BufReader::new(client);
+        let mut head = String::new();
+        while !head.ends_with("\r\n\r\n") {
+            reader.read_line(&mut head).unwrap();
+        }
+        assert!(head.starts_with("HTTP/1.1 101"));
+        assert!(head.contains("Sec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n"));
+        assert_eq!(
+            MessageReader::new(reader).read_message().unwrap(),
+            Message::Text("x".repeat(300))
+        );
+
+        let (first, second) = server.join().unwrap();
+        assert_eq!(first, Message::Ping(Vec::new()));
+        assert_eq!(second, Message::Text("{\"id\":1}".to_string()));
+    }
+}